@@ -0,0 +1,37 @@
+//! Generate the `data/egm96-5.dac` of the `bundled-geoid` feature from
+//! the 15' EGM96 `WW15MGH.DAC` of the NGA, keeping each 5 degrees
+//!
+//! cargo run --example egm96_grid -- WW15MGH.DAC
+
+use std::fs;
+
+/// Rows and columns of the 15' grid, without the 360 degrees column
+const ROWS: usize = 721;
+const COLS: usize = 1440;
+/// 5 degrees on the 15' grid
+const STRIDE: usize = 20;
+
+fn main() -> Result<(), String> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or("Usage: egm96_grid <WW15MGH.DAC>")?;
+    let bytes = fs::read(&path).map_err(|e| format!("Failed on read {}: {}", path, e))?;
+    if bytes.len() != ROWS * COLS * 2 {
+        return Err(format!(
+            "Expected the 15' grid of {} bytes, found {}",
+            ROWS * COLS * 2,
+            bytes.len()
+        ));
+    }
+
+    let mut grid = vec![];
+    for row in (0..ROWS).step_by(STRIDE) {
+        for col in (0..COLS).step_by(STRIDE) {
+            let at = (row * COLS + col) * 2;
+            grid.extend_from_slice(&bytes[at..at + 2]);
+        }
+    }
+
+    fs::create_dir_all("data").map_err(|e| e.to_string())?;
+    fs::write("data/egm96-5.dac", grid).map_err(|e| e.to_string())
+}
@@ -0,0 +1,418 @@
+//! Discovery and loading of the config files, like `.loc2gpx.yaml`
+//!
+//! A config can `include:` other files, like the field mappings shared
+//! across projects, by a path or a list of paths relative to it. The
+//! included files are merged first, in order, and the keys of the
+//! config override theirs, merging the nested mappings.
+//!
+//! The `profiles` of a config, like one for each source, are selected
+//! by name and merged over the `defaults` section, so they only
+//! set what is different from the common config.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+
+/// Name of the config file, on the current and the home directories
+pub const CONFIG_FILE: &str = ".loc2gpx.yaml";
+
+/// Key of the included files
+const INCLUDE: &str = "include";
+/// Section of the keys shared by the profiles
+const DEFAULTS: &str = "defaults";
+/// Section of the named profiles
+const PROFILES: &str = "profiles";
+
+/// Finds and parses the config of a run
+#[derive(Clone, Debug, Default)]
+pub struct ConfigLoader {
+    provided: Option<PathBuf>,
+    search: Option<Vec<PathBuf>>,
+    profile: Option<String>,
+}
+
+/// Config parsed by the loader and what happened on its discovery
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadedConfig<T> {
+    pub config: T,
+    /// File of the config, or `None` for the defaults
+    pub path: Option<PathBuf>,
+    /// Files ignored on the search, like the ones with a wrong name
+    pub warnings: Vec<String>,
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Config file required by the user, instead of the search
+    pub fn provided<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.provided = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Profile of the config merged over its `defaults` section
+    pub fn profile(&mut self, name: &str) -> &mut Self {
+        self.profile = Some(name.to_string());
+        self
+    }
+
+    /// Files searched instead of the default ones, in order of priority
+    pub fn search_path<P: AsRef<Path>>(&mut self, paths: &[P]) -> &mut Self {
+        self.search = Some(paths.iter().map(|p| p.as_ref().to_path_buf()).collect());
+        self
+    }
+
+    /// Searched files, in order of priority. By default the `.loc2gpx.yaml`
+    /// of the current directory, the `location2gpx/config.yaml` of the user
+    /// config directory, like `$XDG_CONFIG_HOME`, and the `~/.loc2gpx.yaml`
+    pub fn candidates(&self) -> Vec<PathBuf> {
+        if let Some(search) = &self.search {
+            return search.clone();
+        }
+
+        let mut candidates = vec![PathBuf::from(CONFIG_FILE)];
+
+        if let Some(config) = dirs::config_dir() {
+            candidates.push(config.join("location2gpx").join("config.yaml"));
+        }
+
+        if let Some(home) = dirs::home_dir() {
+            candidates.push(home.join(CONFIG_FILE));
+        }
+
+        candidates
+    }
+
+    /// Parse the provided config or the first one found. Without
+    /// files, the default config is returned with a warning
+    pub fn load<T>(&self) -> Result<LoadedConfig<T>, String>
+    where
+        T: DeserializeOwned + Default,
+    {
+        if let Some(provided) = &self.provided {
+            let yaml = fs::read_to_string(provided)
+                .map_err(|e| format!("Failed on read the config {}: {}", provided.display(), e))?;
+            return Ok(LoadedConfig {
+                config: parse_file(&yaml, provided, self.profile.as_deref())?,
+                path: Some(provided.clone()),
+                warnings: vec![],
+            });
+        }
+
+        let mut warnings = vec![];
+        let candidates = self.candidates();
+
+        for path in &candidates {
+            match fs::read_to_string(path) {
+                Ok(yaml) => {
+                    return Ok(LoadedConfig {
+                        config: parse_file(&yaml, path, self.profile.as_deref())?,
+                        path: Some(path.clone()),
+                        warnings,
+                    })
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    let misnamed = path.with_extension("yml");
+                    if misnamed.exists() {
+                        warnings.push(format!(
+                            "Config {} ignored, the file must be named {}",
+                            misnamed.display(),
+                            CONFIG_FILE
+                        ));
+                    }
+                }
+                Err(e) => warnings.push(format!(
+                    "Config {} ignored, failed on read it: {}",
+                    path.display(),
+                    e
+                )),
+            }
+        }
+
+        if let Some(profile) = &self.profile {
+            return Err(format!("Profile `{}` not found, no config loaded", profile));
+        }
+
+        let searched: Vec<_> = candidates.iter().map(|p| p.display().to_string()).collect();
+        warnings.push(format!(
+            "No config found on {}, using the defaults",
+            searched.join(", ")
+        ));
+
+        Ok(LoadedConfig {
+            config: T::default(),
+            path: None,
+            warnings,
+        })
+    }
+}
+
+/// Parse the config, failing on the unknown keys and the invalid values
+pub fn parse<T: DeserializeOwned>(yaml: &str, path: &Path) -> Result<T, String> {
+    serde_yaml::from_str(yaml).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Parse the config of the file with its includes and the profile.
+/// Without them, the errors keep the line of the config
+fn parse_file<T: DeserializeOwned>(
+    yaml: &str,
+    path: &Path,
+    profile: Option<&str>,
+) -> Result<T, String> {
+    let value: Value = parse(yaml, path)?;
+    let sections = [INCLUDE, DEFAULTS, PROFILES];
+    if profile.is_none() && sections.iter().all(|key| value.get(key).is_none()) {
+        return parse(yaml, path);
+    }
+
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let merged = include(value, path, &mut vec![canonical])?;
+    let merged = select_profile(merged, path, profile)?;
+
+    T::deserialize(merged).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+}
+
+/// Merge the `defaults`, the keys out of the sections and the profile
+fn select_profile(mut value: Value, path: &Path, profile: Option<&str>) -> Result<Value, String> {
+    let (defaults, profiles) = match value.as_mapping_mut() {
+        Some(mapping) => (mapping.remove(DEFAULTS), mapping.remove(PROFILES)),
+        None => (None, None),
+    };
+
+    let mut merged = Value::Mapping(Mapping::new());
+    if let Some(defaults) = defaults {
+        merge(&mut merged, defaults);
+    }
+    merge(&mut merged, value);
+
+    if let Some(name) = profile {
+        let selected = profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .ok_or_else(|| {
+                let available: Vec<_> = profiles
+                    .iter()
+                    .filter_map(|profiles| profiles.as_mapping())
+                    .flat_map(|profiles| profiles.keys())
+                    .filter_map(|key| key.as_str())
+                    .collect();
+                format!(
+                    "Profile `{}` not found on the config {}, available: {}",
+                    name,
+                    path.display(),
+                    available.join(", ")
+                )
+            })?;
+        merge(&mut merged, selected.clone());
+    }
+
+    Ok(merged)
+}
+
+/// Merge the included files of the config under its keys
+fn include(mut value: Value, path: &Path, stack: &mut Vec<PathBuf>) -> Result<Value, String> {
+    let includes = match value.as_mapping_mut().and_then(|m| m.remove(INCLUDE)) {
+        None => vec![],
+        Some(Value::String(file)) => vec![file],
+        Some(Value::Sequence(files)) => files
+            .into_iter()
+            .map(|file| match file {
+                Value::String(file) => Ok(file),
+                _ => Err(format!(
+                    "Invalid config {}: the includes must be paths",
+                    path.display()
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => {
+            return Err(format!(
+                "Invalid config {}: `include` must be a path or a list of paths",
+                path.display()
+            ))
+        }
+    };
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut merged = Value::Mapping(Mapping::new());
+
+    for file in includes {
+        let included = dir.join(file);
+        let read_error = |e: std::io::Error| {
+            format!(
+                "Failed on read the config {}, included by {}: {}",
+                included.display(),
+                path.display(),
+                e
+            )
+        };
+
+        // The same file by other paths, like `./base.yaml` or a link
+        let canonical = fs::canonicalize(&included).map_err(read_error)?;
+        if stack.contains(&canonical) {
+            return Err(format!(
+                "Config {} includes itself by {}",
+                included.display(),
+                path.display()
+            ));
+        }
+
+        let yaml = fs::read_to_string(&included).map_err(read_error)?;
+        let value: Value = parse(&yaml, &included)?;
+
+        stack.push(canonical);
+        let value = include(value, &included, stack)?;
+        stack.pop();
+
+        merge(&mut merged, value);
+    }
+
+    merge(&mut merged, value);
+
+    Ok(merged)
+}
+
+/// Override the keys of the base by the ones of the value, merging the
+/// nested mappings. Null values, like an empty key, keep the base
+fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Mapping(base), Value::Mapping(value)) => {
+            for (key, value) in value {
+                match base.get_mut(&key) {
+                    Some(current) => merge(current, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (_, Value::Null) => {}
+        (base, value) => *base = value,
+    }
+}
+
+#[test]
+fn load_provided() -> Result<(), String> {
+    use crate::TrackOptions;
+
+    let dir = std::env::temp_dir().join("loc2gpx-config-provided");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join("fleet.yaml");
+    fs::write(&path, "dedup: true").map_err(|e| e.to_string())?;
+
+    let loaded = ConfigLoader::new().provided(&path).load::<TrackOptions>()?;
+    assert!(loaded.config.dedup);
+    assert_eq!(Some(path.clone()), loaded.path);
+    assert!(loaded.warnings.is_empty());
+
+    fs::write(&path, "dedupe: true").map_err(|e| e.to_string())?;
+    let err = ConfigLoader::new()
+        .provided(&path)
+        .load::<TrackOptions>()
+        .unwrap_err();
+    assert!(err.contains("unknown field `dedupe`"));
+
+    let err = ConfigLoader::new()
+        .provided(dir.join("missing.yaml"))
+        .load::<TrackOptions>()
+        .unwrap_err();
+    assert!(err.starts_with("Failed on read the config"));
+
+    Ok(())
+}
+
+#[test]
+fn load_includes() -> Result<(), String> {
+    use crate::TrackOptions;
+
+    let dir = std::env::temp_dir().join("loc2gpx-config-includes");
+    fs::create_dir_all(dir.join("shared")).map_err(|e| e.to_string())?;
+    let write = |name: &str, yaml: &str| fs::write(dir.join(name), yaml).map_err(|e| e.to_string());
+
+    write(
+        "shared/base.yaml",
+        "dedup: true\nmax_duration: 10\nlabels:\n  every: 5",
+    )?;
+    write(
+        "fleet.yaml",
+        "include: shared/base.yaml\nmax_duration: 20\nlabels:\n  name: '{device}'",
+    )?;
+
+    let loaded = ConfigLoader::new()
+        .search_path(&[dir.join("missing.yaml"), dir.join("fleet.yaml")])
+        .load::<TrackOptions>()?;
+    assert_eq!(Some(dir.join("fleet.yaml")), loaded.path);
+    assert!(loaded.config.dedup);
+    assert_eq!(Some(20), loaded.config.max_duration);
+    let labels = loaded.config.labels.ok_or("Labels not merged")?;
+    assert_eq!("{device}", labels.name);
+    assert_eq!(5, labels.every);
+
+    write("loop.yaml", "include: [fleet.yaml, loop.yaml]")?;
+    let err = ConfigLoader::new()
+        .provided(dir.join("loop.yaml"))
+        .load::<TrackOptions>()
+        .unwrap_err();
+    assert!(err.contains("includes itself"));
+
+    write("alias.yaml", "include: shared/../alias.yaml")?;
+    let err = ConfigLoader::new()
+        .provided(dir.join("alias.yaml"))
+        .load::<TrackOptions>()
+        .unwrap_err();
+    assert!(err.contains("includes itself"));
+
+    write("typo.yaml", "include: shared/base.yaml\ndedupe: false")?;
+    let err = ConfigLoader::new()
+        .provided(dir.join("typo.yaml"))
+        .load::<TrackOptions>()
+        .unwrap_err();
+    assert!(err.contains("unknown field `dedupe`"));
+
+    Ok(())
+}
+
+#[test]
+fn load_profiles() -> Result<(), String> {
+    use crate::TrackOptions;
+
+    let dir = std::env::temp_dir().join("loc2gpx-config-profiles");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join("fleet.yaml");
+    fs::write(
+        &path,
+        "defaults:\n  dedup: true\n  labels:\n    every: 5\nprofiles:\n  csv:\n    labels:\n      name: '{number}'\n  mongo:",
+    )
+    .map_err(|e| e.to_string())?;
+
+    let loaded = ConfigLoader::new()
+        .provided(&path)
+        .profile("csv")
+        .load::<TrackOptions>()?;
+    assert!(loaded.config.dedup);
+    let labels = loaded.config.labels.ok_or("Labels not merged")?;
+    assert_eq!("{number}", labels.name);
+    assert_eq!(5, labels.every);
+
+    let loaded = ConfigLoader::new()
+        .provided(&path)
+        .profile("mongo")
+        .load::<TrackOptions>()?;
+    assert_eq!(Some(5), loaded.config.labels.map(|l| l.every));
+
+    let loaded = ConfigLoader::new().provided(&path).load::<TrackOptions>()?;
+    assert!(loaded.config.dedup);
+
+    let err = ConfigLoader::new()
+        .provided(&path)
+        .profile("flespi")
+        .load::<TrackOptions>()
+        .unwrap_err();
+    assert!(err.ends_with("available: csv, mongo"));
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Append of new tracks on the ones of an existing document
+
+use std::collections::HashSet;
+
+use gpx::Track;
+
+use super::writer::TimePrecision;
+
+/// Name and times of the first and last points of the track
+type TrackKey = (Option<String>, Option<String>, Option<String>);
+
+/// Key of the track, with the times on the precision of the written ones
+fn key(track: &Track, precision: TimePrecision) -> Result<TrackKey, String> {
+    let mut times = track
+        .segments
+        .iter()
+        .flat_map(|s| s.points.iter())
+        .filter_map(|p| p.time);
+    let first = times.next();
+    let last = times.next_back().or(first);
+
+    let format = |time: Option<String>| time.map(|t| precision.format(&t)).transpose();
+
+    let first = first.map(|t| t.format()).transpose();
+    let last = last.map(|t| t.format()).transpose();
+
+    Ok((
+        track.name.clone(),
+        format(first.map_err(|e| e.to_string())?)?,
+        format(last.map_err(|e| e.to_string())?)?,
+    ))
+}
+
+/// Add the tracks after the existing ones, skipping the ones with the
+/// same name and time span of an existing track, compared with the times
+/// precision of the written documents. The tracks are numbered again and
+/// the number of the skipped ones is also returned
+pub fn append_tracks(
+    existing: Vec<Track>,
+    tracks: Vec<Track>,
+    precision: TimePrecision,
+) -> Result<(Vec<Track>, usize), String> {
+    let mut keys = HashSet::new();
+    for track in &existing {
+        keys.insert(key(track, precision)?);
+    }
+
+    let mut appended = existing;
+    let mut skipped = 0;
+    for track in tracks {
+        if keys.insert(key(&track, precision)?) {
+            appended.push(track);
+        } else {
+            skipped += 1;
+        }
+    }
+
+    for (track, number) in appended.iter_mut().zip(1..) {
+        track.number = Some(number);
+    }
+
+    Ok((appended, skipped))
+}
+
+#[test]
+fn append_new_tracks() -> Result<(), String> {
+    use super::model::{TrackModel, TrackPoint};
+    use geo::Point;
+    use time::macros::datetime;
+
+    let track = |name: &str, hour: u8| {
+        let time = datetime!(2021-05-24 0:00 UTC).replace_hour(hour).unwrap();
+        Track::from(TrackModel {
+            name: Some(name.to_string()),
+            segments: vec![vec![TrackPoint::basic(Point::new(-48.87, -26.31), time)]],
+            ..Default::default()
+        })
+    };
+
+    let (tracks, skipped) = append_tracks(
+        vec![track("125", 8), track("126", 9)],
+        vec![track("125", 8), track("125", 10)],
+        TimePrecision::Auto,
+    )?;
+
+    assert_eq!(1, skipped);
+    let names: Vec<_> = tracks
+        .iter()
+        .map(|t| (t.name.clone().unwrap_or_default(), t.number))
+        .collect();
+    assert_eq!(
+        vec![
+            ("125".to_string(), Some(1)),
+            ("126".to_string(), Some(2)),
+            ("125".to_string(), Some(3))
+        ],
+        names
+    );
+
+    Ok(())
+}
+
+#[test]
+fn append_with_time_precision() -> Result<(), String> {
+    use super::model::{TrackModel, TrackPoint};
+    use geo::Point;
+    use time::macros::datetime;
+
+    let track = |time| {
+        Track::from(TrackModel {
+            name: Some("125".to_string()),
+            segments: vec![vec![TrackPoint::basic(Point::new(-48.87, -26.31), time)]],
+            ..Default::default()
+        })
+    };
+    // Existing track read from a document written with milliseconds
+    let existing = || vec![track(datetime!(2021-05-24 8:00:00.250 UTC))];
+    let new = || vec![track(datetime!(2021-05-24 8:00:00.250_123 UTC))];
+
+    let (tracks, skipped) = append_tracks(existing(), new(), TimePrecision::Milliseconds)?;
+    assert_eq!((1, 1), (tracks.len(), skipped));
+    let (tracks, skipped) = append_tracks(existing(), new(), TimePrecision::Auto)?;
+    assert_eq!((1, 1), (tracks.len(), skipped));
+
+    // Existing track written with whole seconds
+    let existing = vec![track(datetime!(2021-05-24 8:00 UTC))];
+    let (tracks, skipped) = append_tracks(existing, new(), TimePrecision::Seconds)?;
+    assert_eq!((1, 1), (tracks.len(), skipped));
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! Coordinate reference systems of the sources, converted to WGS84
+
+use geo::Point;
+
+use super::position::DevicePosition;
+use super::transform::PositionTransform;
+
+/// Semi-major axis of the WGS84 and GRS80 ellipsoids, in meters
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const WGS84_FLATTENING: f64 = 1.0 / 298.257_223_563;
+const GRS80_FLATTENING: f64 = 1.0 / 298.257_222_101;
+/// Scale factor on the central meridian of the UTM zones
+const UTM_SCALE: f64 = 0.9996;
+
+/// Reference system of the coordinates of a source
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crs {
+    /// Longitude and latitude, like WGS84 and SIRGAS 2000
+    Geographic,
+    /// Web Mercator meters, of the map tiles
+    WebMercator,
+    /// Easting and northing of an UTM zone
+    Utm {
+        zone: u8,
+        south: bool,
+        flattening: f64,
+    },
+}
+
+impl Crs {
+    /// System of the EPSG code, like 31982 for SIRGAS 2000 / UTM zone 22S.
+    /// The datums of the supported codes are within a meter of WGS84
+    pub fn from_epsg(code: u32) -> Result<Self, String> {
+        let utm = |zone: u32, south: bool, flattening: f64| Crs::Utm {
+            zone: zone as u8,
+            south,
+            flattening,
+        };
+
+        match code {
+            // WGS84, SIRGAS 2000, NAD83 and ETRS89
+            4326 | 4674 | 4269 | 4258 => Ok(Crs::Geographic),
+            3857 | 900913 => Ok(Crs::WebMercator),
+            32601..=32660 => Ok(utm(code - 32600, false, WGS84_FLATTENING)),
+            32701..=32760 => Ok(utm(code - 32700, true, WGS84_FLATTENING)),
+            // SIRGAS 2000, zones 11N to 22N and 17S to 25S
+            31965..=31976 => Ok(utm(code - 31954, false, GRS80_FLATTENING)),
+            31977..=31985 => Ok(utm(code - 31960, true, GRS80_FLATTENING)),
+            // NAD83, zones 1N to 23N
+            26901..=26923 => Ok(utm(code - 26900, false, GRS80_FLATTENING)),
+            // ETRS89, zones 28N to 38N
+            25828..=25838 => Ok(utm(code - 25800, false, GRS80_FLATTENING)),
+            _ => Err(format!("EPSG:{} not supported", code)),
+        }
+    }
+
+    /// Longitude and latitude of the point
+    pub fn to_wgs84(&self, point: Point) -> Point {
+        match *self {
+            Crs::Geographic => point,
+            Crs::WebMercator => Point::new(
+                (point.x() / SEMI_MAJOR_AXIS).to_degrees(),
+                (point.y() / SEMI_MAJOR_AXIS).sinh().atan().to_degrees(),
+            ),
+            Crs::Utm {
+                zone,
+                south,
+                flattening,
+            } => utm_to_geographic(point, zone, south, flattening),
+        }
+    }
+}
+
+impl PositionTransform for Crs {
+    fn transform(&self, dpos: &mut DevicePosition) {
+        dpos.pos.coordinates = self.to_wgs84(dpos.pos.coordinates);
+    }
+}
+
+/// Inverse of the Transverse Mercator projection, by the series of
+/// Snyder's Map Projections: A Working Manual, millimeters inside a zone
+fn utm_to_geographic(point: Point, zone: u8, south: bool, flattening: f64) -> Point {
+    let e2 = flattening * (2.0 - flattening);
+    let ep2 = e2 / (1.0 - e2);
+
+    let x = point.x() - 500_000.0;
+    let y = match south {
+        true => point.y() - 10_000_000.0,
+        false => point.y(),
+    };
+
+    // Footprint latitude
+    let mu = y
+        / UTM_SCALE
+        / (SEMI_MAJOR_AXIS * (1.0 - e2 / 4.0 - 3.0 * e2.powi(2) / 64.0 - 5.0 * e2.powi(3) / 256.0));
+    let e1 = (1.0 - (1.0 - e2).sqrt()) / (1.0 + (1.0 - e2).sqrt());
+    let phi = mu
+        + (3.0 * e1 / 2.0 - 27.0 * e1.powi(3) / 32.0) * (2.0 * mu).sin()
+        + (21.0 * e1.powi(2) / 16.0 - 55.0 * e1.powi(4) / 32.0) * (4.0 * mu).sin()
+        + (151.0 * e1.powi(3) / 96.0) * (6.0 * mu).sin()
+        + (1097.0 * e1.powi(4) / 512.0) * (8.0 * mu).sin();
+
+    let (sin, cos, tan) = (phi.sin(), phi.cos(), phi.tan());
+    let c = ep2 * cos * cos;
+    let t = tan * tan;
+    let n = SEMI_MAJOR_AXIS / (1.0 - e2 * sin * sin).sqrt();
+    let r = SEMI_MAJOR_AXIS * (1.0 - e2) / (1.0 - e2 * sin * sin).powf(1.5);
+    let d = x / (n * UTM_SCALE);
+
+    let latitude = phi
+        - (n * tan / r)
+            * (d.powi(2) / 2.0
+                - (5.0 + 3.0 * t + 10.0 * c - 4.0 * c * c - 9.0 * ep2) * d.powi(4) / 24.0
+                + (61.0 + 90.0 * t + 298.0 * c + 45.0 * t * t - 252.0 * ep2 - 3.0 * c * c)
+                    * d.powi(6)
+                    / 720.0);
+    let longitude = (d - (1.0 + 2.0 * t + c) * d.powi(3) / 6.0
+        + (5.0 - 2.0 * c + 28.0 * t - 3.0 * c * c + 8.0 * ep2 + 24.0 * t * t) * d.powi(5) / 120.0)
+        / cos;
+
+    let central_meridian = f64::from(zone) * 6.0 - 183.0;
+
+    Point::new(
+        central_meridian + longitude.to_degrees(),
+        latitude.to_degrees(),
+    )
+}
+
+#[test]
+fn epsg_conversions() -> Result<(), String> {
+    use crate::geoutil::distance;
+
+    // CN Tower, on the UTM zone 17N
+    let tower = Crs::from_epsg(32617)?.to_wgs84(Point::new(630084.0, 4833438.0));
+    assert!(
+        distance(tower, Point::new(-79.387139, 43.642567)) < 1.0,
+        "{:?}",
+        tower
+    );
+
+    // Central meridian of the SIRGAS 2000 / UTM zone 22S
+    let center = Crs::from_epsg(31982)?.to_wgs84(Point::new(500000.0, 7090000.0));
+    assert!((center.x() - -51.0).abs() < 1e-9, "{:?}", center);
+    assert!((center.y() - -26.3099).abs() < 1e-4, "{:?}", center);
+
+    let mercator = Crs::from_epsg(3857)?.to_wgs84(Point::new(-5440183.5, -3037526.7));
+    assert!((mercator.x() - -48.87).abs() < 1e-4, "{:?}", mercator);
+    assert!((mercator.y() - -26.31).abs() < 1e-4, "{:?}", mercator);
+
+    let point = Point::new(-48.87, -26.31);
+    assert_eq!(point, Crs::from_epsg(4674)?.to_wgs84(point));
+
+    assert!(Crs::from_epsg(2193).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+//! Destination of the output files, like `exports/{device}/{date}.gpx`
+
+use std::collections::BTreeMap;
+
+use gpx::Track;
+use time::macros::format_description;
+use time::UtcOffset;
+
+use super::model::TrackModel;
+use super::period::CalendarPeriod;
+
+/// Placeholders of the destinations, replaced for each track
+const PLACEHOLDERS: [&str; 4] = ["{device}", "{date}", "{track}", "{period}"];
+
+/// Path of the output files, with placeholders of the tracks:
+/// `{device}`, `{date}` of the first point, `{track}` name and
+/// the calendar `{period}` of the split. The tracks with the same
+/// path are written together
+#[derive(Clone, Debug, PartialEq)]
+pub struct DestinationTemplate {
+    template: String,
+    period: Option<CalendarPeriod>,
+    offset: UtcOffset,
+}
+
+impl DestinationTemplate {
+    pub fn new(template: &str) -> Self {
+        Self {
+            template: template.to_string(),
+            period: None,
+            offset: UtcOffset::UTC,
+        }
+    }
+
+    /// Period of the `{period}` placeholder
+    pub fn period(&mut self, period: CalendarPeriod) -> &mut Self {
+        self.period = Some(period);
+        self
+    }
+
+    /// Offset of the `{date}` and `{period}` placeholders
+    pub fn offset(&mut self, offset: UtcOffset) -> &mut Self {
+        self.offset = offset;
+        self
+    }
+
+    /// If the template has any placeholder
+    pub fn is_templated(&self) -> bool {
+        PLACEHOLDERS.iter().any(|p| self.template.contains(p))
+    }
+
+    /// Path of the track, or `None` for the tracks without points
+    pub fn resolve(&self, track: &TrackModel) -> Result<Option<String>, String> {
+        let first = match track.points().next() {
+            Some(point) => point.time.to_offset(self.offset),
+            None => return Ok(None),
+        };
+
+        let mut path = self
+            .template
+            .replace("{device}", &path_safe(&track.device))
+            .replace("{track}", &path_safe(track.name.as_deref().unwrap_or("")));
+
+        if path.contains("{date}") {
+            let date = first
+                .format(format_description!("[year]-[month]-[day]"))
+                .map_err(|e| e.to_string())?;
+            path = path.replace("{date}", &date);
+        }
+
+        if path.contains("{period}") {
+            let period = self
+                .period
+                .ok_or("The `{period}` of the destination needs a split by period")?;
+            path = path.replace("{period}", &period.name(first));
+        }
+
+        Ok(Some(path))
+    }
+
+    /// Group the tracks by their paths. The tracks are numbered
+    /// again inside of each file
+    pub fn group(&self, models: Vec<TrackModel>) -> Result<BTreeMap<String, Vec<Track>>, String> {
+        let mut groups: BTreeMap<String, Vec<Track>> = BTreeMap::new();
+
+        for model in models {
+            if let Some(path) = self.resolve(&model)? {
+                groups.entry(path).or_default().push(Track::from(model));
+            }
+        }
+
+        for tracks in groups.values_mut() {
+            for (track, number) in tracks.iter_mut().zip(1..) {
+                track.number = Some(number);
+            }
+        }
+
+        Ok(groups)
+    }
+}
+
+/// Value of a placeholder without the path separators
+fn path_safe(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[test]
+fn resolve_destinations() -> Result<(), String> {
+    use super::model::TrackPoint;
+    use geo::Point;
+    use time::macros::{datetime, offset};
+
+    let track = |device: &str, name: &str, time| TrackModel {
+        device: device.to_string(),
+        name: Some(name.to_string()),
+        segments: vec![vec![TrackPoint::basic(Point::new(-48.87, -26.31), time)]],
+        ..Default::default()
+    };
+
+    let mut template = DestinationTemplate::new("exports/{device}/{date}.gpx");
+    assert!(template.is_templated());
+    assert!(!DestinationTemplate::new("fleet.gpx").is_templated());
+
+    let files = template.group(vec![
+        track("AA251", "125", datetime!(2021-05-24 8:00 UTC)),
+        track("AA251", "126", datetime!(2021-05-24 9:00 UTC)),
+        track("BB/7", "125", datetime!(2021-05-25 1:00 UTC)),
+        TrackModel::default(),
+    ])?;
+    let paths: Vec<_> = files.keys().cloned().collect();
+    assert_eq!(
+        vec![
+            "exports/AA251/2021-05-24.gpx".to_string(),
+            "exports/BB_7/2021-05-25.gpx".to_string()
+        ],
+        paths
+    );
+    let numbers: Vec<_> = files["exports/AA251/2021-05-24.gpx"]
+        .iter()
+        .map(|t| t.number)
+        .collect();
+    assert_eq!(vec![Some(1), Some(2)], numbers);
+
+    template.offset(offset!(-3));
+    assert_eq!(
+        Some("exports/BB_7/2021-05-24.gpx".to_string()),
+        template.resolve(&track("BB/7", "125", datetime!(2021-05-25 1:00 UTC)))?
+    );
+
+    let mut template = DestinationTemplate::new("{period}/{track}.gpx");
+    assert!(template
+        .resolve(&track("AA251", "125", datetime!(2021-05-24 8:00 UTC)))
+        .is_err());
+    template.period(CalendarPeriod::Month);
+    assert_eq!(
+        Some("2021-05/125.gpx".to_string()),
+        template.resolve(&track("AA251", "125", datetime!(2021-05-24 8:00 UTC)))?
+    );
+
+    Ok(())
+}
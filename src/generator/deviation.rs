@@ -0,0 +1,261 @@
+//! Deviation of the tracks from the reference lines of their routes
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+use geo::{Closest, ClosestPoint, LineString, Point};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use super::model::TrackModel;
+use crate::geoutil::distance;
+
+/// Reference lines of the routes, like for transit compliance checks
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviationOptions {
+    /// GPX or GeoJSON file with the line of each route
+    pub references: BTreeMap<String, String>,
+    /// Distance from the reference, in meters, of the off-route points
+    pub threshold_m: f64,
+}
+
+impl Default for DeviationOptions {
+    fn default() -> Self {
+        Self {
+            references: BTreeMap::new(),
+            threshold_m: 50.0,
+        }
+    }
+}
+
+/// Deviation of a track from the reference of its route
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteDeviation {
+    pub device: String,
+    pub route: String,
+    pub track: Option<String>,
+    /// Max distance of the points from the reference, in meters
+    pub max_m: f64,
+    /// Mean distance of the points from the reference, in meters
+    pub mean_m: f64,
+    /// Runs of points farther than the threshold
+    pub off_route: Vec<OffRoute>,
+}
+
+/// Run of consecutive points off the route
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffRoute {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub points: usize,
+    /// Max distance of the points from the reference, in meters
+    pub max_m: f64,
+}
+
+impl DeviationOptions {
+    /// Lines of the reference files
+    pub fn load_references(&self) -> Result<BTreeMap<String, LineString>, String> {
+        self.references
+            .iter()
+            .map(|(route, path)| {
+                let line = read_line(Path::new(path))
+                    .map_err(|e| format!("Failed on read the reference of {}: {}", route, e))?;
+                Ok((route.clone(), line))
+            })
+            .collect()
+    }
+
+    /// Deviation of the track from the reference line
+    pub fn measure(
+        &self,
+        reference: &LineString,
+        route: &str,
+        track: &TrackModel,
+    ) -> RouteDeviation {
+        let mut deviation = RouteDeviation {
+            device: track.device.clone(),
+            route: route.to_string(),
+            track: track.name.clone(),
+            max_m: 0.0,
+            mean_m: 0.0,
+            off_route: vec![],
+        };
+
+        let mut count = 0;
+        let mut off: Option<OffRoute> = None;
+        for point in track.points() {
+            let meters = match reference.closest_point(&point.coordinates) {
+                Closest::Intersection(p) | Closest::SinglePoint(p) => {
+                    distance(point.coordinates, p)
+                }
+                Closest::Indeterminate => continue,
+            };
+
+            count += 1;
+            deviation.mean_m += meters;
+            deviation.max_m = deviation.max_m.max(meters);
+
+            if meters > self.threshold_m {
+                let run = off.get_or_insert(OffRoute {
+                    start: point.time,
+                    end: point.time,
+                    points: 0,
+                    max_m: 0.0,
+                });
+                run.end = point.time;
+                run.points += 1;
+                run.max_m = run.max_m.max(meters);
+            } else if let Some(run) = off.take() {
+                deviation.off_route.push(run);
+            }
+        }
+        deviation.off_route.extend(off);
+
+        if count > 0 {
+            deviation.mean_m /= count as f64;
+        }
+
+        deviation
+    }
+}
+
+/// Line of the GPX tracks, or routes, or of the GeoJSON
+/// LineString, MultiLineString or their features
+fn read_line(path: &Path) -> Result<LineString, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    let points: Vec<Point> = if extension.eq_ignore_ascii_case("gpx") {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let gpx = gpx::read(BufReader::new(file)).map_err(|e| e.to_string())?;
+
+        let tracks: Vec<Point> = gpx
+            .tracks
+            .iter()
+            .flat_map(|t| &t.segments)
+            .flat_map(|s| &s.points)
+            .map(|p| p.point())
+            .collect();
+        if tracks.is_empty() {
+            gpx.routes
+                .iter()
+                .flat_map(|r| &r.points)
+                .map(|p| p.point())
+                .collect()
+        } else {
+            tracks
+        }
+    } else {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let value: Value = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+        geojson_points(&value)?
+    };
+
+    if points.len() < 2 {
+        return Err("The reference has no line".to_string());
+    }
+
+    Ok(points.into_iter().collect())
+}
+
+fn geojson_points(value: &Value) -> Result<Vec<Point>, String> {
+    let position = |p: &Value| match (
+        p.get(0).and_then(Value::as_f64),
+        p.get(1).and_then(Value::as_f64),
+    ) {
+        (Some(lng), Some(lat)) => Ok(Point::new(lng, lat)),
+        _ => Err("Invalid GeoJSON coordinates".to_string()),
+    };
+    let positions = |c: &Value| -> Result<Vec<Point>, String> {
+        c.as_array().into_iter().flatten().map(position).collect()
+    };
+    let coordinates = value.get("coordinates").unwrap_or(&Value::Null);
+
+    match value.get("type").and_then(Value::as_str) {
+        Some("LineString") => positions(coordinates),
+        Some("MultiLineString") => Ok(coordinates
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(positions)
+            .collect::<Result<Vec<_>, _>>()?
+            .concat()),
+        Some("Feature") => geojson_points(value.get("geometry").unwrap_or(&Value::Null)),
+        Some("FeatureCollection") => {
+            for feature in value
+                .get("features")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let points = geojson_points(feature)?;
+                if !points.is_empty() {
+                    return Ok(points);
+                }
+            }
+            Ok(vec![])
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+#[test]
+fn measure_deviations() -> Result<(), String> {
+    use super::model::TrackPoint;
+    use time::{macros::datetime, Duration};
+
+    let dir = std::env::temp_dir().join(format!("l2g-deviation-{}", std::process::id()));
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join("route-10.geojson");
+    fs::write(
+        &path,
+        r#"{"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[-48.87, -26.31], [-48.80, -26.31]]}}"#,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut options = DeviationOptions::default();
+    options
+        .references
+        .insert("10".to_string(), path.display().to_string());
+    let references = options.load_references()?;
+    fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    // 0.001 degree of latitude is about 111m
+    let points = [-26.31, -26.3102, -26.312, -26.313, -26.3101, -26.3115];
+    let track = TrackModel {
+        device: "bus 1".to_string(),
+        name: Some("10".to_string()),
+        segments: vec![points
+            .iter()
+            .zip(0..)
+            .map(|(lat, i)| {
+                TrackPoint::basic(
+                    Point::new(-48.85, *lat),
+                    datetime!(2021-05-24 8:00 UTC) + Duration::minutes(i),
+                )
+            })
+            .collect()],
+        ..Default::default()
+    };
+
+    let deviation = options.measure(&references["10"], "10", &track);
+    assert_eq!("bus 1", deviation.device);
+    assert!((deviation.max_m - 333.6).abs() < 1.0, "{}", deviation.max_m);
+    assert!(
+        (deviation.mean_m - 125.7).abs() < 1.0,
+        "{}",
+        deviation.mean_m
+    );
+    assert_eq!(2, deviation.off_route.len());
+    assert_eq!(2, deviation.off_route[0].points);
+    assert_eq!(datetime!(2021-05-24 8:02 UTC), deviation.off_route[0].start);
+    assert_eq!(datetime!(2021-05-24 8:03 UTC), deviation.off_route[0].end);
+    assert_eq!(1, deviation.off_route[1].points);
+
+    assert!(read_line(Path::new("missing.gpx")).is_err());
+
+    Ok(())
+}
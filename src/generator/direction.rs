@@ -0,0 +1,140 @@
+//! Direction of travel along the routes
+
+use std::collections::BTreeMap;
+
+use geo::Point;
+use serde::{Deserialize, Serialize};
+
+use super::model::TrackModel;
+use crate::geoutil::distance;
+
+/// Direction of travel detection
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectionOptions {
+    /// Reference line of the routes, as `[lng, lat]` points in
+    /// the outbound direction. The routes without one use their
+    /// first recorded run as reference
+    pub references: BTreeMap<String, Vec<[f64; 2]>>,
+    /// Routes with the runs always reversed, like the ones recorded
+    /// backwards, before detecting their direction
+    pub reverse: BTreeMap<String, bool>,
+    /// Reverse the inbound runs, so all the runs of a route follow its
+    /// reference, like to build a canonical geometry of the route
+    pub normalize: bool,
+}
+
+/// Direction of a run compared to the route reference
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Outbound,
+    Inbound,
+}
+
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Outbound => "outbound",
+            Self::Inbound => "inbound",
+        }
+    }
+
+    /// Direction of the run going from `start` to `end`
+    pub fn detect(start: Point, end: Point, reference: (Point, Point)) -> Self {
+        let (rstart, rend) = reference;
+
+        let same = distance(start, rstart) + distance(end, rend);
+        let reversed = distance(start, rend) + distance(end, rstart);
+
+        if reversed < same {
+            Self::Inbound
+        } else {
+            Self::Outbound
+        }
+    }
+}
+
+impl DirectionOptions {
+    /// Reverse the tracks of the `reverse` routes, append the direction on
+    /// the names of the tracks and, with `normalize`, reverse the inbound
+    /// ones. The tracks are given with their route names
+    pub fn apply(&self, tracks: &mut [(String, TrackModel)]) {
+        for (route, track) in tracks.iter_mut() {
+            if self.reverse.get(route).copied().unwrap_or(false) {
+                track.reverse();
+            }
+        }
+
+        let mut references: BTreeMap<String, (Point, Point)> = self
+            .references
+            .iter()
+            .filter_map(|(route, line)| {
+                let start = line.first()?;
+                let end = line.last()?;
+                Some((
+                    route.clone(),
+                    (Point::new(start[0], start[1]), Point::new(end[0], end[1])),
+                ))
+            })
+            .collect();
+
+        // First recorded run of each route without a reference
+        let start_time = |track: &TrackModel| track.points().next().map(|p| p.time);
+        let mut runs: Vec<&(String, TrackModel)> = tracks
+            .iter()
+            .filter(|(route, _)| !references.contains_key(route))
+            .collect();
+        runs.sort_by_key(|(_, track)| start_time(track));
+        for (route, track) in runs {
+            if let Some(ends) = run_ends(track) {
+                references.entry(route.clone()).or_insert(ends);
+            }
+        }
+
+        for (route, track) in tracks.iter_mut() {
+            let direction = match (references.get(route), run_ends(track)) {
+                (Some(reference), Some((start, end))) => Direction::detect(start, end, *reference),
+                _ => continue,
+            };
+
+            track.name = track
+                .name
+                .as_ref()
+                .map(|n| format!("{} {}", n, direction.name()));
+
+            if self.normalize && direction == Direction::Inbound {
+                track.reverse();
+            }
+        }
+    }
+}
+
+/// First and last points of the track
+fn run_ends(track: &TrackModel) -> Option<(Point, Point)> {
+    let first = track.points().next()?;
+    let last = track.points().last()?;
+
+    Some((first.coordinates, last.coordinates))
+}
+
+#[test]
+fn detect_direction() {
+    let reference = (Point::new(-48.87, -26.31), Point::new(-48.80, -26.25));
+
+    assert_eq!(
+        Direction::Outbound,
+        Direction::detect(
+            Point::new(-48.869, -26.309),
+            Point::new(-48.81, -26.26),
+            reference
+        )
+    );
+    assert_eq!(
+        Direction::Inbound,
+        Direction::detect(
+            Point::new(-48.81, -26.26),
+            Point::new(-48.869, -26.309),
+            reference
+        )
+    );
+}
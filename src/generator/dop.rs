@@ -0,0 +1,110 @@
+//! Adaptive filter of the position jumps, by the dilution of precision
+
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use super::filter::PointFilter;
+use super::model::TrackPoint;
+use crate::geoutil::distance;
+
+/// Drop the points too far from the previous one, with a limit
+/// adapted to the HDOP of each fix
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DopFilter {
+    /// Max speed between the points, in m/s, of a fix with the reference HDOP
+    pub max_speed: f64,
+    /// HDOP of a regular fix. The limit is wider for the better
+    /// fixes and tighter for the worse ones, up to 4 times
+    pub reference_hdop: f64,
+}
+
+impl Default for DopFilter {
+    fn default() -> Self {
+        Self {
+            max_speed: 55.0, // ~200 km/h
+            reference_hdop: 2.0,
+        }
+    }
+}
+
+impl DopFilter {
+    /// Max distance, in m, accepted from the previous point. Without
+    /// the HDOP, the limit of the reference HDOP is used
+    pub fn max_distance(&self, elapsed: Duration, hdop: Option<f64>) -> f64 {
+        let scale = match hdop {
+            Some(hdop) if hdop > 0.0 => (self.reference_hdop / hdop).clamp(0.25, 4.0),
+            _ => 1.0,
+        };
+
+        self.max_speed * elapsed.as_seconds_f64() * scale
+    }
+
+    fn jump(&self, prev: &TrackPoint, current: &TrackPoint) -> f64 {
+        distance(prev.coordinates, current.coordinates)
+    }
+
+    fn limit(&self, prev: &TrackPoint, current: &TrackPoint) -> f64 {
+        self.max_distance(current.time - prev.time, current.hdop)
+    }
+}
+
+impl PointFilter for DopFilter {
+    fn keep(&self, prev: Option<&TrackPoint>, current: &TrackPoint) -> bool {
+        prev.is_none_or(|prev| self.jump(prev, current) <= self.limit(prev, current))
+    }
+
+    fn reason(&self, prev: Option<&TrackPoint>, current: &TrackPoint) -> String {
+        match prev {
+            Some(prev) => format!(
+                "Jump of {:.0}m over the limit of {:.0}m for the HDOP, position dropped",
+                self.jump(prev, current),
+                self.limit(prev, current)
+            ),
+            None => "Position dropped by the DOP filter".to_string(),
+        }
+    }
+}
+
+#[test]
+fn adaptive_jumps() {
+    use super::filter::apply_filters;
+    use super::report::Report;
+    use geo::Point;
+    use time::macros::datetime;
+
+    let filter = DopFilter::default();
+    assert_eq!(550.0, filter.max_distance(Duration::seconds(10), None));
+    assert_eq!(
+        1100.0,
+        filter.max_distance(Duration::seconds(10), Some(1.0))
+    );
+    assert_eq!(220.0, filter.max_distance(Duration::seconds(10), Some(5.0)));
+    assert_eq!(
+        137.5,
+        filter.max_distance(Duration::seconds(10), Some(50.0))
+    );
+
+    // ~800m jumps every 10s
+    let start = datetime!(2021-05-24 0:00 UTC);
+    let mut points = vec![];
+    for (i, hdop) in [Some(1.0), Some(0.8), Some(6.0), None, Some(1.0)]
+        .into_iter()
+        .enumerate()
+    {
+        let mut point = TrackPoint::basic(
+            Point::new(-48.87 + i as f64 * 0.008, -26.31),
+            start + Duration::seconds(i as i64 * 10),
+        );
+        point.hdop = hdop;
+        points.push(point);
+    }
+
+    let mut report = Report::default();
+    apply_filters(&[&filter], &mut points, "dev 1", &mut report);
+    assert_eq!(2, report.entries.len());
+    assert_eq!(start + Duration::seconds(20), report.entries[0].time);
+    assert_eq!(start + Duration::seconds(30), report.entries[1].time);
+    // The last one is ~2400m after the kept one, but 30s later with a good fix
+    assert_eq!(3, points.len());
+}
@@ -0,0 +1,177 @@
+//! Elevation from the GNSS and barometric altitudes
+
+use gpx::Waypoint;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+/// Altitude used as the elevation of the points
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElevationSource {
+    /// The GNSS altitude
+    #[default]
+    Gnss,
+    /// The barometric altitude, or the GNSS one when missing
+    Barometric,
+    /// The barometric altitude, with its drift corrected
+    /// by the mean difference to the GNSS altitudes
+    Fused,
+}
+
+/// Elevation configurations
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElevationOptions {
+    pub source: ElevationSource,
+    /// Period, in seconds, around each point used to
+    /// compute the drift of the barometric altitude
+    pub drift_window: u32,
+}
+
+impl Default for ElevationOptions {
+    fn default() -> Self {
+        Self {
+            source: ElevationSource::Gnss,
+            drift_window: 600, // 10 minutes
+        }
+    }
+}
+
+impl ElevationOptions {
+    /// Set the elevation of the points, sorted by time, with their
+    /// GNSS altitudes and the `(time, barometric altitude)` samples
+    pub fn apply(&self, samples: &[(OffsetDateTime, Option<f64>)], points: &mut [Waypoint]) {
+        match self.source {
+            ElevationSource::Gnss => {}
+            ElevationSource::Barometric => {
+                for ((_, baro), wp) in samples.iter().zip(points.iter_mut()) {
+                    if baro.is_some() {
+                        wp.elevation = *baro;
+                    }
+                }
+            }
+            ElevationSource::Fused => self.fuse(samples, points),
+        }
+    }
+
+    fn fuse(&self, samples: &[(OffsetDateTime, Option<f64>)], points: &mut [Waypoint]) {
+        // Cumulative sums of the GNSS - barometric differences
+        let mut sums = vec![(0.0, 0)];
+        for ((_, baro), wp) in samples.iter().zip(points.iter()) {
+            let (sum, count) = sums[sums.len() - 1];
+            sums.push(match (wp.elevation, baro) {
+                (Some(gnss), Some(baro)) => (sum + gnss - baro, count + 1),
+                _ => (sum, count),
+            });
+        }
+        let (total, total_count) = sums[sums.len() - 1];
+
+        let half = Duration::seconds(self.drift_window.into()) / 2;
+        let (mut lo, mut hi) = (0, 0);
+        for (i, (time, baro)) in samples.iter().enumerate() {
+            while samples[lo].0 < *time - half {
+                lo += 1;
+            }
+            while hi < samples.len() && samples[hi].0 <= *time + half {
+                hi += 1;
+            }
+
+            let baro = match baro {
+                Some(baro) => baro,
+                None => continue,
+            };
+
+            let (sum, count) = (sums[hi].0 - sums[lo].0, sums[hi].1 - sums[lo].1);
+            let drift = if count > 0 {
+                sum / count as f64
+            } else if total_count > 0 {
+                total / total_count as f64
+            } else {
+                0.0
+            };
+
+            points[i].elevation = Some(baro + drift);
+        }
+    }
+}
+
+#[test]
+fn fuse_elevations() {
+    use geo::Point;
+    use time::macros::datetime;
+
+    let start = datetime!(2021-05-24 0:00 UTC);
+    let samples: Vec<_> = [Some(100.0), Some(101.0), None, Some(103.0), Some(110.0)]
+        .into_iter()
+        .enumerate()
+        .map(|(i, baro)| (start + Duration::minutes(i as i64 * 10), baro))
+        .collect();
+    let points = || -> Vec<Waypoint> {
+        [Some(120.0), Some(118.0), Some(125.0), None, Some(130.0)]
+            .into_iter()
+            .map(|ele| {
+                let mut wp = Waypoint::new(Point::new(-48.87, -26.31));
+                wp.elevation = ele;
+                wp
+            })
+            .collect()
+    };
+    let elevations = |wps: Vec<Waypoint>| wps.iter().map(|wp| wp.elevation).collect::<Vec<_>>();
+
+    let mut wps = points();
+    ElevationOptions::default().apply(&samples, &mut wps);
+    assert_eq!(elevations(points()), elevations(wps));
+
+    let mut wps = points();
+    let options = ElevationOptions {
+        source: ElevationSource::Barometric,
+        ..Default::default()
+    };
+    options.apply(&samples, &mut wps);
+    assert_eq!(
+        vec![
+            Some(100.0),
+            Some(101.0),
+            Some(125.0),
+            Some(103.0),
+            Some(110.0)
+        ],
+        elevations(wps)
+    );
+
+    let mut wps = points();
+    let options = ElevationOptions {
+        source: ElevationSource::Fused,
+        drift_window: 1200,
+    };
+    options.apply(&samples, &mut wps);
+    assert_eq!(
+        vec![
+            // drift of the first two points: (20 + 17) / 2
+            Some(118.5),
+            Some(119.5),
+            Some(125.0),
+            Some(123.0),
+            Some(130.0)
+        ],
+        elevations(wps)
+    );
+
+    let mut wps = points();
+    let options = ElevationOptions {
+        source: ElevationSource::Fused,
+        drift_window: 60,
+    };
+    options.apply(&samples, &mut wps);
+    assert_eq!(
+        vec![
+            Some(120.0),
+            Some(118.0),
+            Some(125.0),
+            // no drift around, so the mean of the track: (20 + 17 + 20) / 3
+            Some(122.0),
+            Some(130.0)
+        ],
+        elevations(wps)
+    );
+}
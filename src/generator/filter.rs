@@ -0,0 +1,62 @@
+//! Cleaning rules of the track points
+
+use super::model::TrackPoint;
+use super::report::{RejectStage, Report};
+
+/// Rule to drop or change the points of the tracks, like the built-in
+/// sanity limits and DOP filter, registered on the `Tracker` or on
+/// the `SourceToTracks`
+pub trait PointFilter: Send + Sync {
+    /// If the point is kept, given the previous kept point
+    fn keep(&self, _prev: Option<&TrackPoint>, _current: &TrackPoint) -> bool {
+        true
+    }
+
+    /// Reason of the dropped point, written on the report
+    fn reason(&self, _prev: Option<&TrackPoint>, _current: &TrackPoint) -> String {
+        "Position dropped by a filter".to_string()
+    }
+
+    /// Change the kept point, returning the description of each change made
+    fn transform(&self, _point: &mut TrackPoint) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Run each filter over the points, in order, registering
+/// on the report the dropped and the changed ones
+pub fn apply_filters(
+    filters: &[&dyn PointFilter],
+    points: &mut Vec<TrackPoint>,
+    device: &str,
+    report: &mut Report,
+) {
+    for filter in filters {
+        let mut kept: Vec<TrackPoint> = Vec::with_capacity(points.len());
+
+        for mut point in points.drain(..) {
+            if !filter.keep(kept.last(), &point) {
+                let reason = filter.reason(kept.last(), &point);
+                report
+                    .reject(
+                        device.to_string(),
+                        point.time,
+                        point.coordinates,
+                        RejectStage::Filter,
+                        reason.clone(),
+                    )
+                    .source_index = point.source_index;
+                report.add(device.to_string(), point.time, reason);
+                continue;
+            }
+
+            for change in filter.transform(&mut point) {
+                report.add(device.to_string(), point.time, change);
+            }
+
+            kept.push(point);
+        }
+
+        *points = kept;
+    }
+}
@@ -0,0 +1,206 @@
+//! Geoid heights, to convert the ellipsoidal heights of
+//! the raw GNSS data to orthometric elevations
+//!
+//! The builds with the `bundled-geoid` feature embed a 5 degrees
+//! EGM96 grid, `data/egm96-5.dac`, generated from the NGA file by
+//! the `egm96_grid` example. The other builds require a grid file.
+
+use std::fs;
+
+use geo::geometry::Point;
+use gpx::Waypoint;
+use serde::{Deserialize, Serialize};
+
+/// Steps, in degrees, of the supported global grids
+const GRID_STEPS: &[f64] = &[0.25, 0.5, 1.0, 2.0, 5.0];
+
+/// EGM96 grid of 5 degrees, subsampled from the `WW15MGH.DAC`
+#[cfg(feature = "bundled-geoid")]
+const BUNDLED_GRID: &[u8] = include_bytes!("../../data/egm96-5.dac");
+
+/// Geoid configurations
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoidOptions {
+    /// Path of an EGM96 grid file, on the `WW15MGH.DAC` format,
+    /// downloaded from the NGA. Default: the bundled grid
+    pub grid_file: Option<String>,
+}
+
+impl GeoidOptions {
+    /// Grid of the file, or the bundled one
+    pub fn load(&self) -> Result<GeoidGrid, String> {
+        match &self.grid_file {
+            Some(path) => GeoidGrid::open(path),
+            None => GeoidGrid::bundled(),
+        }
+    }
+}
+
+/// Global grid of geoid heights above the WGS84 ellipsoid
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeoidGrid {
+    /// Spacing of the grid, in degrees
+    step: f64,
+    rows: usize,
+    cols: usize,
+    /// In m, by rows from the north pole and columns from
+    /// the 0 meridian to the east, without the 360 degrees column
+    heights: Vec<f64>,
+}
+
+impl GeoidGrid {
+    /// Grid with the `step` spacing, with the rows from 90 to -90 and
+    /// the columns from 0 degrees of longitude to the east, like the
+    /// `WW15MGH.DAC` ones. A repeated 360 degrees column is dropped
+    pub fn new(step: f64, mut heights: Vec<f64>) -> Result<Self, String> {
+        let rows = (180.0 / step).round() as usize + 1;
+        let cols = (360.0 / step).round() as usize;
+
+        if rows * (cols + 1) == heights.len() {
+            heights = heights
+                .chunks_exact(cols + 1)
+                .flat_map(|row| &row[..cols])
+                .copied()
+                .collect();
+        } else if rows * cols != heights.len() {
+            return Err(format!(
+                "Geoid grid of {} degrees needs {} heights, found {}",
+                step,
+                rows * cols,
+                heights.len()
+            ));
+        }
+
+        Ok(Self {
+            step,
+            rows,
+            cols,
+            heights,
+        })
+    }
+
+    /// Load the grid from the EGM96 `WW15MGH.DAC` format, big-endian
+    /// 16 bits heights in cm. The spacing is detected by the size
+    pub fn from_dac(bytes: &[u8]) -> Result<Self, String> {
+        let heights: Vec<f64> = bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_be_bytes([b[0], b[1]]) as f64 / 100.0)
+            .collect();
+
+        let step = GRID_STEPS
+            .iter()
+            .find(|step| {
+                let rows = (180.0 / *step) as usize + 1;
+                let cols = (360.0 / *step) as usize;
+                rows * cols == heights.len() || rows * (cols + 1) == heights.len()
+            })
+            .ok_or(format!("Unknown geoid grid with {} heights", heights.len()))?;
+
+        Self::new(*step, heights)
+    }
+
+    /// Load the grid from a `WW15MGH.DAC` file
+    pub fn open(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Failed on read the geoid grid: {}", e))?;
+
+        Self::from_dac(&bytes)
+    }
+
+    /// The 5 degrees EGM96 grid of the builds with the `bundled-geoid` feature
+    pub fn bundled() -> Result<Self, String> {
+        #[cfg(feature = "bundled-geoid")]
+        return Self::from_dac(BUNDLED_GRID);
+
+        #[cfg(not(feature = "bundled-geoid"))]
+        Err("No geoid grid bundled on this build, set the grid_file".to_string())
+    }
+
+    /// Geoid height at the coordinates, in m, with
+    /// bilinear interpolation of the grid
+    pub fn height(&self, coordinates: Point) -> f64 {
+        let row = ((90.0 - coordinates.y()) / self.step).clamp(0.0, (self.rows - 1) as f64);
+        let col = coordinates.x().rem_euclid(360.0) / self.step;
+
+        let r0 = (row.floor() as usize).min(self.rows - 2);
+        let c0 = (col.floor() as usize).min(self.cols - 1);
+        // East of the last column, the values wrap to the 0 meridian
+        let c1 = (c0 + 1) % self.cols;
+        let (fr, fc) = (row - r0 as f64, col - c0 as f64);
+
+        let at = |r: usize, c: usize| self.heights[r * self.cols + c];
+        let north = at(r0, c0) * (1.0 - fc) + at(r0, c1) * fc;
+        let south = at(r0 + 1, c0) * (1.0 - fc) + at(r0 + 1, c1) * fc;
+
+        north * (1.0 - fr) + south * fr
+    }
+
+    /// Convert the ellipsoidal height of the point to
+    /// orthometric elevation, keeping the geoid height
+    pub fn correct(&self, wp: &mut Waypoint) {
+        let geoid = self.height(wp.point());
+
+        wp.elevation = wp.elevation.map(|el| el - geoid);
+        wp.geoidheight = Some(geoid);
+    }
+}
+
+#[test]
+fn geoid_heights() -> Result<(), String> {
+    // 90 degrees grid: 3 rows and 4 columns, like the DAC files
+    #[rustfmt::skip]
+    let heights = vec![
+        10.0, 10.0, 10.0, 10.0,
+        0.0, 20.0, 40.0, 20.0,
+        -10.0, -10.0, -10.0, -10.0,
+    ];
+    assert!(GeoidGrid::new(90.0, heights[1..].to_vec()).is_err());
+    let grid = GeoidGrid::new(90.0, heights.clone())?;
+
+    assert_eq!(0.0, grid.height(Point::new(0.0, 0.0)));
+    assert_eq!(10.0, grid.height(Point::new(45.0, 0.0)));
+    assert_eq!(20.0, grid.height(Point::new(-90.0, 0.0)));
+    assert_eq!(15.0, grid.height(Point::new(90.0, 45.0)));
+    assert_eq!(-10.0, grid.height(Point::new(10.0, -90.0)));
+    // Between the last column and the 0 meridian
+    assert_eq!(10.0, grid.height(Point::new(315.0, 0.0)));
+    assert_eq!(10.0, grid.height(Point::new(-45.0, 0.0)));
+
+    // With the repeated 360 degrees column
+    let repeated: Vec<f64> = heights
+        .chunks_exact(4)
+        .flat_map(|row| [row, &row[..1]].concat())
+        .collect();
+    assert_eq!(grid, GeoidGrid::new(90.0, repeated)?);
+
+    let mut wp = Waypoint::new(Point::new(45.0, 0.0));
+    wp.elevation = Some(30.0);
+    grid.correct(&mut wp);
+    assert_eq!(Some(20.0), wp.elevation);
+    assert_eq!(Some(10.0), wp.geoidheight);
+
+    // 5 degrees grid shaped like the 0.25 degrees WW15MGH.DAC,
+    // of 721 x 1440, without the 360 degrees column
+    let mut dac = vec![];
+    for _ in 0..37 {
+        for col in 0..72i16 {
+            dac.extend_from_slice(&(col * 100).to_be_bytes());
+        }
+    }
+    let grid = GeoidGrid::from_dac(&dac)?;
+    assert_eq!(71.0, grid.height(Point::new(355.0, -26.31)));
+    assert_eq!(35.5, grid.height(Point::new(357.5, 10.0)));
+    assert_eq!(0.0, grid.height(Point::new(0.0, 10.0)));
+    assert!(GeoidGrid::from_dac(&dac[2..]).is_err());
+
+    let options = GeoidOptions {
+        grid_file: Some("missing.dac".to_string()),
+    };
+    assert!(options.load().is_err());
+    #[cfg(feature = "bundled-geoid")]
+    assert_eq!(GeoidGrid::bundled()?, GeoidOptions::default().load()?);
+    #[cfg(not(feature = "bundled-geoid"))]
+    assert!(GeoidOptions::default().load().is_err());
+
+    Ok(())
+}
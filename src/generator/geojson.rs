@@ -0,0 +1,87 @@
+//! GeoJSON output of the tracks, for the map tools without GPX support
+
+use gpx::Track;
+use serde_json::{json, Value};
+
+/// FeatureCollection with a MultiLineString of each track, one line per
+/// segment. The point times are on the `coordTimes` property, as the
+/// `togeojson` converters do
+pub fn tracks_geojson(tracks: &[Track]) -> Result<Value, String> {
+    let mut features = vec![];
+
+    for track in tracks {
+        let mut lines = vec![];
+        let mut times = vec![];
+        for segment in &track.segments {
+            lines.push(
+                segment
+                    .points
+                    .iter()
+                    .map(|p| match p.elevation {
+                        Some(ele) => json!([p.point().x(), p.point().y(), ele]),
+                        None => json!([p.point().x(), p.point().y()]),
+                    })
+                    .collect::<Vec<Value>>(),
+            );
+            times.push(
+                segment
+                    .points
+                    .iter()
+                    .map(|p| p.time.map(|t| t.format()).transpose())
+                    .collect::<Result<Vec<Option<String>>, _>>()
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "MultiLineString", "coordinates": lines },
+            "properties": {
+                "name": track.name,
+                "description": track.description,
+                "source": track.source,
+                "type": track._type,
+                "number": track.number,
+                "coordTimes": times,
+            },
+        }));
+    }
+
+    Ok(json!({ "type": "FeatureCollection", "features": features }))
+}
+
+#[test]
+fn geojson_tracks() -> Result<(), String> {
+    use geo::Point;
+    use gpx::{TrackSegment, Waypoint};
+    use time::macros::datetime;
+
+    let mut point = Waypoint::new(Point::new(-48.87, -26.31));
+    point.time = Some(datetime!(2021-05-24 8:00 UTC).into());
+    point.elevation = Some(12.0);
+    let mut segment = TrackSegment::new();
+    segment.points.push(point);
+    segment
+        .points
+        .push(Waypoint::new(Point::new(-48.86, -26.30)));
+
+    let mut track = Track::new();
+    track.name = Some("dev 1".to_string());
+    track.number = Some(1);
+    track.segments.push(segment);
+
+    let geojson = tracks_geojson(&[track])?;
+    let feature = &geojson["features"][0];
+    assert_eq!("MultiLineString", feature["geometry"]["type"]);
+    assert_eq!(
+        json!([[[-48.87, -26.31, 12.0], [-48.86, -26.30]]]),
+        feature["geometry"]["coordinates"]
+    );
+    assert_eq!("dev 1", feature["properties"]["name"]);
+    assert_eq!(
+        json!([["2021-05-24T08:00:00Z", null]]),
+        feature["properties"]["coordTimes"]
+    );
+
+    Ok(())
+}
@@ -1,22 +1,76 @@
 //! GPX generator API
 
+use std::io::Write;
+
+use geo::Rect;
 use gpx::{Gpx, GpxVersion, Track};
 
+use super::manifest::Manifest;
+use super::writer::{write_gpx_manifest, OutputOptions};
+use crate::geoutil;
+
 pub struct GpxGenerator {
     pub tracks: Vec<Track>,
+    pub output: OutputOptions,
+    /// Settings of the run, written with the `manifest` output option
+    pub manifest: Option<Manifest>,
 }
 
 impl GpxGenerator {
     pub fn empty() -> Self {
-        Self { tracks: vec![] }
+        Self {
+            tracks: vec![],
+            output: OutputOptions::default(),
+            manifest: None,
+        }
     }
 
-    pub fn generate(self) -> Result<Gpx, String> {
-        let mut gpx: Gpx = Default::default();
-        gpx.version = GpxVersion::Gpx11;
-        gpx.creator = Some("location2gpx".to_string());
+    /// Bounding box of all the points of the tracks, if any
+    pub fn bounds(&self) -> Option<Rect> {
+        geoutil::tracks_bounds(&self.tracks)
+    }
+
+    pub fn generate(mut self) -> Result<Gpx, String> {
+        let mut gpx = Gpx {
+            version: GpxVersion::Gpx11,
+            creator: Some(self.output.creator()),
+            ..Default::default()
+        };
+
+        if !self.output.symbols.is_empty() {
+            for wp in self
+                .tracks
+                .iter_mut()
+                .flat_map(|t| &mut t.segments)
+                .flat_map(|s| &mut s.points)
+            {
+                if let Some(symbol) = wp._type.as_ref().and_then(|t| self.output.symbols.get(t)) {
+                    wp.symbol = Some(symbol.clone());
+                }
+            }
+        }
+
+        if self.output.waypoints {
+            gpx.waypoints = self
+                .tracks
+                .iter()
+                .flat_map(|t| &t.segments)
+                .flat_map(|s| &s.points)
+                .filter(|wp| wp.name.is_some())
+                .cloned()
+                .collect();
+        }
         gpx.tracks = self.tracks;
 
         Ok(gpx)
     }
+
+    /// Generate and write the GPX document
+    pub fn write<W: Write>(self, writer: W) -> Result<(), String> {
+        let output = self.output.clone();
+        let manifest = self.manifest.clone().filter(|_| output.manifest);
+        let doc = self.generate()?;
+
+        write_gpx_manifest(&doc, &output, manifest.as_ref(), writer)
+    }
 }
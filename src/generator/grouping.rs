@@ -0,0 +1,102 @@
+//! Grouping of the positions in tracks
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use time::macros::format_description;
+use time::Date;
+
+use super::position::DevicePosition;
+
+/// Key of the tracks, besides the device
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupingStrategy {
+    /// A single track for each device, named with its ID
+    Device,
+    /// By route, or by day for the positions without one
+    #[default]
+    DeviceRoute,
+    /// By day, ignoring the routes
+    DeviceDay,
+    /// By the `group_by` field of the source, like a trip ID, also used
+    /// as the track name. The positions without one are grouped by route
+    Field,
+}
+
+impl GroupingStrategy {
+    /// Name of the track of the position and, for the routes split
+    /// by day, the day of the position
+    pub fn key(
+        &self,
+        pos: &DevicePosition,
+        continuous_routes: bool,
+    ) -> Result<(Arc<str>, Option<Date>), String> {
+        let day = || {
+            pos.pos
+                .time
+                .format(format_description!("[year]-[month]-[day]"))
+                .map(Arc::from)
+                .map_err(|e| e.to_string())
+        };
+
+        match (self, &pos.route_name) {
+            (Self::Device, _) => Ok((pos.device_id.clone(), None)),
+            (Self::Field, _) => match &pos.group {
+                Some(group) => Ok((group.as_str().into(), None)),
+                None => Self::DeviceRoute.key(pos, continuous_routes),
+            },
+            (Self::DeviceRoute, Some(route)) if continuous_routes => Ok((route.clone(), None)),
+            (Self::DeviceRoute, Some(route)) => Ok((route.clone(), Some(pos.pos.time.date()))),
+            (Self::DeviceRoute, None) | (Self::DeviceDay, _) => Ok((day()?, None)),
+        }
+    }
+}
+
+#[test]
+fn grouping_keys() -> Result<(), String> {
+    use geo::Point;
+    use time::macros::{date, datetime};
+
+    let mut pos = DevicePosition::basic(
+        "dev 1",
+        Point::new(-48.87, -26.31),
+        datetime!(2021-05-24 8:00 UTC),
+    );
+
+    assert_eq!(
+        ("dev 1".into(), None),
+        GroupingStrategy::Device.key(&pos, false)?
+    );
+    assert_eq!(
+        ("2021-05-24".into(), None),
+        GroupingStrategy::DeviceRoute.key(&pos, false)?
+    );
+
+    assert_eq!(
+        ("2021-05-24".into(), None),
+        GroupingStrategy::Field.key(&pos, false)?
+    );
+
+    pos.route_name = Some("125".into());
+    assert_eq!(
+        ("125".into(), Some(date!(2021 - 05 - 24))),
+        GroupingStrategy::DeviceRoute.key(&pos, false)?
+    );
+    assert_eq!(
+        ("125".into(), None),
+        GroupingStrategy::DeviceRoute.key(&pos, true)?
+    );
+    assert_eq!(
+        ("2021-05-24".into(), None),
+        GroupingStrategy::DeviceDay.key(&pos, false)?
+    );
+
+    pos.group = Some("trip 42".to_string());
+    assert_eq!(
+        ("trip 42".into(), None),
+        GroupingStrategy::Field.key(&pos, false)?
+    );
+
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! Density of the positions on a grid, instead of the tracks
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use geo::{coord, Rect};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::position::DevicePosition;
+use crate::geoutil::METERS_PER_DEGREE;
+
+/// Grid of the heatmap
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeatmapOptions {
+    /// Size of the cells, in meters
+    pub cell_m: f64,
+    /// Cells with less positions than this are left out
+    pub min_count: usize,
+}
+
+impl Default for HeatmapOptions {
+    fn default() -> Self {
+        Self {
+            cell_m: 100.0,
+            min_count: 1,
+        }
+    }
+}
+
+/// Positions of a cell of the grid
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeatCell {
+    pub count: usize,
+    pub devices: BTreeSet<String>,
+}
+
+/// Positions counted by cells of about the same size in meters
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    options: HeatmapOptions,
+    /// Cells by their row and column
+    cells: BTreeMap<(i64, i64), HeatCell>,
+}
+
+impl Heatmap {
+    pub fn new(options: &HeatmapOptions) -> Self {
+        Self {
+            options: options.clone(),
+            cells: BTreeMap::new(),
+        }
+    }
+
+    /// Count the position on its cell
+    pub fn add(&mut self, dpos: &DevicePosition) {
+        let point = dpos.pos.coordinates;
+        let lat_step = self.lat_step();
+        let row = (point.y() / lat_step).floor() as i64;
+        let col = (point.x() / self.lng_step(row)).floor() as i64;
+
+        let cell = self.cells.entry((row, col)).or_default();
+        cell.count += 1;
+        if !cell.devices.contains(&*dpos.device_id) {
+            cell.devices.insert(dpos.device_id.to_string());
+        }
+    }
+
+    /// Area and positions of the cells with at least the `min_count`
+    pub fn cells(&self) -> impl Iterator<Item = (Rect, &HeatCell)> {
+        self.cells
+            .iter()
+            .filter(|(_, cell)| cell.count >= self.options.min_count)
+            .map(|((row, col), cell)| {
+                let (lat_step, lng_step) = (self.lat_step(), self.lng_step(*row));
+                let min = coord! { x: *col as f64 * lng_step, y: *row as f64 * lat_step };
+                let max = coord! { x: min.x + lng_step, y: min.y + lat_step };
+                (Rect::new(min, max), cell)
+            })
+    }
+
+    /// GeoJSON FeatureCollection with a polygon of each cell. The `density`
+    /// is the count relative to the most visited cell, from 0 to 1
+    pub fn to_geojson(&self) -> Value {
+        let max = self.cells().map(|(_, cell)| cell.count).max().unwrap_or(1);
+
+        let features: Vec<Value> = self
+            .cells()
+            .map(|(rect, cell)| {
+                let (min, max_corner) = (rect.min(), rect.max());
+                json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[
+                            [min.x, min.y],
+                            [max_corner.x, min.y],
+                            [max_corner.x, max_corner.y],
+                            [min.x, max_corner.y],
+                            [min.x, min.y],
+                        ]],
+                    },
+                    "properties": {
+                        "count": cell.count,
+                        "devices": cell.devices.len(),
+                        "density": cell.count as f64 / max as f64,
+                    },
+                })
+            })
+            .collect();
+
+        json!({ "type": "FeatureCollection", "features": features })
+    }
+
+    fn lat_step(&self) -> f64 {
+        self.options.cell_m.max(1.0) / METERS_PER_DEGREE
+    }
+
+    /// Longitude degrees of the cells of the row, wider near the poles
+    fn lng_step(&self, row: i64) -> f64 {
+        let lat_step = self.lat_step();
+        let center = ((row as f64 + 0.5) * lat_step).to_radians();
+
+        (lat_step / center.cos().max(0.01)).min(360.0)
+    }
+}
+
+#[test]
+fn count_density() -> Result<(), String> {
+    use geo::Point;
+    use time::macros::datetime;
+
+    let at = |device: &str, lng: f64, lat: f64| {
+        DevicePosition::basic(
+            device.to_string(),
+            Point::new(lng, lat),
+            datetime!(2021-05-24 8:00 UTC),
+        )
+    };
+
+    let mut heatmap = Heatmap::new(&HeatmapOptions::default());
+    heatmap.add(&at("dev 1", -48.87001, -26.31001));
+    heatmap.add(&at("dev 1", -48.87002, -26.31002));
+    heatmap.add(&at("dev 2", -48.87003, -26.31003));
+    heatmap.add(&at("dev 2", -48.86, -26.31));
+
+    let cells: Vec<(Rect, &HeatCell)> = heatmap.cells().collect();
+    assert_eq!(2, cells.len());
+    let (rect, busy) = cells
+        .iter()
+        .find(|(_, cell)| cell.count == 3)
+        .ok_or("No cell with 3 positions")?;
+    assert_eq!(2, busy.devices.len());
+    // About 100m wide and tall
+    let width = crate::geoutil::distance(rect.min().into(), (rect.max().x, rect.min().y).into());
+    let height = crate::geoutil::distance(rect.min().into(), (rect.min().x, rect.max().y).into());
+    assert!((width - 100.0).abs() < 1.0, "{}", width);
+    assert!((height - 100.0).abs() < 1.0, "{}", height);
+
+    let geojson = heatmap.to_geojson();
+    assert_eq!(2, geojson["features"].as_array().map_or(0, |f| f.len()));
+    let densities: Vec<f64> = geojson["features"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|f| f["properties"]["density"].as_f64())
+        .collect();
+    assert!(densities.contains(&1.0));
+
+    let mut sparse = Heatmap::new(&HeatmapOptions {
+        min_count: 2,
+        ..Default::default()
+    });
+    sparse.add(&at("dev 1", -48.86, -26.31));
+    assert_eq!(0, sparse.cells().count());
+
+    Ok(())
+}
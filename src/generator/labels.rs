@@ -0,0 +1,108 @@
+//! Names of the track points, like for the check-in style data
+
+use serde::{Deserialize, Serialize};
+use time::macros::format_description;
+
+use super::model::TrackModel;
+
+/// Names and descriptions of the points, with the `{device}`,
+/// `{track}`, `{time}` and `{number}` placeholders
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PointLabels {
+    pub name: String,
+    pub description: Option<String>,
+    /// Label each Nth point of the track, 1 for all of them
+    pub every: usize,
+}
+
+impl Default for PointLabels {
+    fn default() -> Self {
+        Self {
+            name: "{device} {time}".to_string(),
+            description: None,
+            every: 1,
+        }
+    }
+}
+
+impl PointLabels {
+    /// Name the points of the track, keeping the names of the events
+    pub fn apply(&self, device: &str, track: &mut TrackModel) -> Result<(), String> {
+        let name = track.name.clone().unwrap_or_default();
+        let every = self.every.max(1);
+
+        for (i, point) in track
+            .segments
+            .iter_mut()
+            .flatten()
+            .enumerate()
+            .filter(|(i, point)| i % every == 0 && point.event.is_none())
+        {
+            let time = point
+                .time
+                .format(format_description!(
+                    "[year]-[month]-[day] [hour]:[minute]:[second]"
+                ))
+                .map_err(|e| e.to_string())?;
+            let fill = |template: &str| {
+                template
+                    .replace("{device}", device)
+                    .replace("{track}", &name)
+                    .replace("{time}", &time)
+                    .replace("{number}", &(i + 1).to_string())
+            };
+
+            point.name = Some(fill(&self.name));
+            point.description = self.description.as_deref().map(fill);
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn label_points() -> Result<(), String> {
+    use super::model::TrackPoint;
+    use geo::Point;
+    use time::{macros::datetime, Duration};
+
+    let points: Vec<TrackPoint> = (0..5)
+        .map(|i| {
+            TrackPoint::basic(
+                Point::new(-48.87, -26.31),
+                datetime!(2021-05-24 8:00 UTC) + Duration::minutes(i),
+            )
+        })
+        .collect();
+    let mut track = TrackModel {
+        name: Some("check-ins".to_string()),
+        segments: vec![points[..3].to_vec(), points[3..].to_vec()],
+        ..Default::default()
+    };
+
+    let labels = PointLabels {
+        description: Some("#{number} of {track}".to_string()),
+        every: 2,
+        ..Default::default()
+    };
+    labels.apply("dev 1", &mut track)?;
+
+    let names: Vec<Option<String>> = track.points().map(|p| p.name.clone()).collect();
+    assert_eq!(
+        vec![
+            Some("dev 1 2021-05-24 08:00:00".to_string()),
+            None,
+            Some("dev 1 2021-05-24 08:02:00".to_string()),
+            None,
+            Some("dev 1 2021-05-24 08:04:00".to_string()),
+        ],
+        names
+    );
+    assert_eq!(
+        Some("#5 of check-ins".to_string()),
+        track.segments[1][1].description
+    );
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Settings of a run, embedded on the generated documents
+
+use serde::Serialize;
+use serde_yaml::Value;
+use time::format_description::well_known::Rfc3339;
+
+use crate::sources::TimeRange;
+
+/// Effective source, period and configuration used to build
+/// a document, so an archived file tells how it was produced
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Manifest {
+    /// Crate version
+    pub version: String,
+    /// Kind and location of the positions, without secrets
+    pub source: String,
+    /// RFC3339 start of the period, if any
+    pub since: Option<String>,
+    /// RFC3339 end of the period, if any
+    pub until: Option<String>,
+    /// Fields, filters, segments and output configs
+    pub settings: Value,
+}
+
+impl Manifest {
+    pub fn new(source: &str, range: &TimeRange) -> Result<Self, String> {
+        let format = |time: Option<time::OffsetDateTime>| {
+            time.map(|tm| tm.format(&Rfc3339))
+                .transpose()
+                .map_err(|e| e.to_string())
+        };
+
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            source: source.to_string(),
+            since: format(range.start)?,
+            until: format(range.end)?,
+            settings: Value::Null,
+        })
+    }
+
+    /// Attach the configuration
+    pub fn settings<T: Serialize>(&mut self, settings: &T) -> Result<&mut Self, String> {
+        self.settings = serde_yaml::to_value(settings)
+            .map_err(|e| format!("Failed on serialize the settings: {}", e))?;
+
+        Ok(self)
+    }
+
+    /// YAML text of the manifest
+    pub fn to_yaml(&self) -> Result<String, String> {
+        serde_yaml::to_string(self).map_err(|e| format!("Failed on serialize the manifest: {}", e))
+    }
+}
@@ -1,8 +1,36 @@
 //! Generation core
 
+pub mod append;
+pub mod crs;
+pub mod destination;
+pub mod deviation;
+pub mod direction;
+pub mod dop;
+pub mod elevation;
+pub mod filter;
+pub mod geoid;
+pub mod geojson;
 pub mod gpx;
+pub mod grouping;
+pub mod heatmap;
+pub mod labels;
+pub mod manifest;
+pub mod model;
+pub mod period;
 pub mod position;
+pub mod preview;
+pub mod quality;
+pub mod report;
+pub mod sanity;
+pub mod sentinel;
+pub mod simplify;
+pub mod smoothing;
+pub mod spill;
 pub mod tracker;
+pub mod transform;
+pub mod trips;
+pub mod validate;
+pub mod writer;
 
 #[cfg(test)]
 pub mod tests;
@@ -0,0 +1,186 @@
+//! Model of the tracks built by the tracker, converted to the output formats
+
+use geo::{LineString, Point, Rect};
+use gpx::{Link, Track, TrackSegment, Waypoint};
+use time::OffsetDateTime;
+
+use crate::geoutil;
+
+/// Point of a track, with the values of the position after the corrections
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackPoint {
+    pub coordinates: Point,
+    pub time: OffsetDateTime,
+    /// Elevation, in m
+    pub elevation: Option<f64>,
+    /// Speed, in m/s
+    pub speed: Option<f64>,
+    /// Satellites in view
+    pub satellites: Option<u64>,
+    /// Horizontal dilution of precision
+    pub hdop: Option<f64>,
+    /// Height of the geoid above the ellipsoid, in m
+    pub geoid_height: Option<f64>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Event of the device on the point, written as the waypoint type
+    pub event: Option<String>,
+    /// Index of the position on the ones given to the tracker
+    pub source_index: Option<usize>,
+}
+
+impl TrackPoint {
+    /// Point only with the coordinates and the time
+    pub fn basic(coordinates: Point, time: OffsetDateTime) -> Self {
+        Self {
+            coordinates,
+            time,
+            elevation: None,
+            speed: None,
+            satellites: None,
+            hdop: None,
+            geoid_height: None,
+            name: None,
+            description: None,
+            event: None,
+            source_index: None,
+        }
+    }
+
+    /// Point of the waypoint, with the time of its position
+    pub fn from_waypoint(wp: &Waypoint, time: OffsetDateTime) -> Self {
+        Self {
+            coordinates: wp.point(),
+            time,
+            elevation: wp.elevation,
+            speed: wp.speed,
+            satellites: wp.sat,
+            hdop: wp.hdop,
+            geoid_height: wp.geoidheight,
+            name: wp.name.clone(),
+            description: wp.description.clone(),
+            event: wp._type.clone(),
+            source_index: None,
+        }
+    }
+
+    /// GPX waypoint of the point
+    pub fn waypoint(&self) -> Waypoint {
+        self.clone().into_waypoint()
+    }
+
+    /// GPX waypoint of the point, moving its texts
+    pub fn into_waypoint(self) -> Waypoint {
+        let mut wp = Waypoint::new(self.coordinates);
+        wp.time = Some(self.time.into());
+        wp.elevation = self.elevation;
+        wp.speed = self.speed;
+        wp.sat = self.satellites;
+        wp.hdop = self.hdop;
+        wp.geoidheight = self.geoid_height;
+        wp.name = self.name;
+        wp.description = self.description;
+        wp._type = self.event;
+
+        wp
+    }
+}
+
+/// Track of a device, with its segments of points
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackModel {
+    /// Device of the positions, not written on the GPX
+    pub device: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Data source, eg.: track app
+    pub source: Option<String>,
+    /// Activity, eg.: cycling, driving
+    pub track_type: Option<String>,
+    /// Position of the track in the document
+    pub number: Option<u32>,
+    pub links: Vec<Link>,
+    pub segments: Vec<Vec<TrackPoint>>,
+}
+
+impl TrackModel {
+    /// Coordinates of the segment
+    pub fn line(segment: &[TrackPoint]) -> LineString {
+        segment.iter().map(|p| p.coordinates).collect()
+    }
+
+    /// All the points of the track
+    pub fn points(&self) -> impl Iterator<Item = &TrackPoint> {
+        self.segments.iter().flatten()
+    }
+
+    /// Bounding box of the points, if any
+    pub fn bounds(&self) -> Option<Rect> {
+        geoutil::bounds(self.points().map(|p| p.coordinates))
+    }
+
+    /// Reverse the order of the segments and of their points. The
+    /// points keep their times, so they are on the descending order
+    pub fn reverse(&mut self) {
+        self.segments.reverse();
+        for segment in self.segments.iter_mut() {
+            segment.reverse();
+        }
+    }
+}
+
+impl From<TrackModel> for Track {
+    fn from(model: TrackModel) -> Self {
+        let mut track = Track::new();
+        track.name = model.name;
+        track.description = model.description;
+        track.source = model.source;
+        track._type = model.track_type;
+        track.number = model.number;
+        track.links = model.links;
+
+        for segment in model.segments {
+            let mut tseg = TrackSegment::new();
+            tseg.points = segment.into_iter().map(TrackPoint::into_waypoint).collect();
+            track.segments.push(tseg);
+        }
+
+        track
+    }
+}
+
+#[test]
+fn model_to_gpx() {
+    use time::macros::datetime;
+
+    let point = TrackPoint {
+        coordinates: Point::new(-48.8702222, -26.31832),
+        time: datetime!(2021-05-24 0:00:00.250 UTC),
+        elevation: Some(12.0),
+        speed: Some(3.5),
+        satellites: Some(9),
+        hdop: Some(0.9),
+        geoid_height: Some(1.5),
+        name: Some("check-in".to_string()),
+        description: None,
+        event: Some("ignition_on".to_string()),
+        source_index: None,
+    };
+    let model = TrackModel {
+        name: Some("running in joinville".to_string()),
+        number: Some(2),
+        segments: vec![vec![point.clone()], vec![]],
+        ..Default::default()
+    };
+    assert_eq!(1, model.points().count());
+
+    let track = Track::from(model);
+    assert_eq!(Some("running in joinville".to_string()), track.name);
+    assert_eq!(Some(2), track.number);
+    assert_eq!(2, track.segments.len());
+
+    let wp = &track.segments[0].points[0];
+    assert_eq!(point.coordinates, wp.point());
+    assert_eq!(Some(datetime!(2021-05-24 0:00:00.250 UTC).into()), wp.time);
+    assert_eq!(point, TrackPoint::from_waypoint(wp, point.time));
+}
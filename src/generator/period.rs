@@ -0,0 +1,96 @@
+//! Calendar periods, used to split the tracks for archives
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use gpx::Track;
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Calendar period of the tracks
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarPeriod {
+    /// ISO 8601 week, like `2021-W21`
+    Week,
+    /// Month, like `2021-05`
+    Month,
+}
+
+impl CalendarPeriod {
+    /// Name of the period of the time
+    pub fn name(&self, time: OffsetDateTime) -> String {
+        match self {
+            Self::Week => {
+                let (year, week, _) = time.to_iso_week_date();
+                format!("{}-W{:02}", year, week)
+            }
+            Self::Month => format!("{}-{:02}", time.year(), time.month() as u8),
+        }
+    }
+}
+
+impl FromStr for CalendarPeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            _ => Err(format!("Unknown period `{}`, use `week` or `month`", s)),
+        }
+    }
+}
+
+/// Group the tracks by the period of their first point. The
+/// tracks are numbered again inside of each period
+pub fn group_by_period(
+    tracks: Vec<Track>,
+    period: CalendarPeriod,
+) -> Result<BTreeMap<String, Vec<Track>>, String> {
+    let mut groups: BTreeMap<String, Vec<Track>> = BTreeMap::new();
+
+    for track in tracks {
+        let first = track
+            .segments
+            .iter()
+            .find_map(|s| s.points.first())
+            .and_then(|p| p.time);
+        let name = match first {
+            Some(time) => {
+                let time = time.format().map_err(|e| e.to_string())?;
+                let time = OffsetDateTime::parse(&time, &Rfc3339).map_err(|e| e.to_string())?;
+                period.name(time)
+            }
+            None => continue,
+        };
+
+        groups.entry(name).or_default().push(track);
+    }
+
+    for tracks in groups.values_mut() {
+        for (track, number) in tracks.iter_mut().zip(1..) {
+            track.number = Some(number);
+        }
+    }
+
+    Ok(groups)
+}
+
+#[test]
+fn period_names() {
+    use time::macros::datetime;
+
+    let week = CalendarPeriod::Week;
+    assert_eq!("2021-W21", week.name(datetime!(2021-05-24 0:00 UTC)));
+    assert_eq!("2020-W53", week.name(datetime!(2021-01-03 23:59 UTC)));
+    assert_eq!("2021-W01", week.name(datetime!(2021-01-04 0:00 UTC)));
+
+    let month = CalendarPeriod::Month;
+    assert_eq!("2021-05", month.name(datetime!(2021-05-24 0:00 UTC)));
+    assert_eq!("2021-12", month.name(datetime!(2021-12-31 23:59 UTC)));
+
+    assert_eq!(Ok(CalendarPeriod::Week), "week".parse());
+    assert!("year".parse::<CalendarPeriod>().is_err());
+}
@@ -1,9 +1,13 @@
 //! Position definition
 
+use std::collections::HashSet;
+use std::sync::Arc;
+
 use geo::geometry::Point;
 use time::OffsetDateTime;
 
 /// Raw version of a recorded position
+#[derive(Clone, Debug)]
 pub struct RawPosition {
     pub coordinates: Point,
     pub time: OffsetDateTime,
@@ -13,6 +17,14 @@ pub struct RawPosition {
     pub precision: Option<f64>,
     /// in m
     pub altitude: Option<f64>,
+    /// Barometric altitude, in m
+    pub baro_altitude: Option<f64>,
+    /// Satellites in view
+    pub satellites: Option<u64>,
+    /// Horizontal dilution of precision
+    pub hdop: Option<f64>,
+    /// Event of the device on the position, like `ignition_on`
+    pub event: Option<String>,
 }
 
 impl RawPosition {
@@ -23,28 +35,111 @@ impl RawPosition {
             speed: None,
             precision: None,
             altitude: None,
+            baro_altitude: None,
+            satellites: None,
+            hdop: None,
+            event: None,
         }
     }
 }
 
 /// Position with device and other context datas
+#[derive(Clone, Debug)]
 pub struct DevicePosition {
-    /// Device unique ID
-    pub device_id: String,
+    /// Device unique ID, shared by the positions of the device
+    pub device_id: Arc<str>,
     pub pos: RawPosition,
     /// Route numer or name
-    pub route_name: Option<String>,
-    /// Tracker app or software
-    pub tracker: Option<String>,
+    pub route_name: Option<Arc<str>>,
+    /// Tracker app, software or model
+    pub tracker: Option<Arc<str>>,
+    /// Friendly name of the device
+    pub device_name: Option<String>,
+    /// Plate of the vehicle with the device
+    pub vehicle_plate: Option<String>,
+    /// Activity, eg.: cycling, driving
+    pub track_type: Option<String>,
+    /// Trip ID, job number or other group of the source
+    pub group: Option<String>,
+    /// Record of the source with the position, kept for debugging
+    #[cfg(feature = "raw")]
+    pub raw: Option<serde_json::Value>,
 }
 
 impl DevicePosition {
-    pub fn basic(device_id: String, coordinates: Point, time: OffsetDateTime) -> Self {
+    pub fn basic(device_id: impl Into<Arc<str>>, coordinates: Point, time: OffsetDateTime) -> Self {
         Self {
-            device_id,
+            device_id: device_id.into(),
             pos: RawPosition::basic(coordinates, time),
             route_name: None,
             tracker: None,
+            device_name: None,
+            vehicle_plate: None,
+            track_type: None,
+            group: None,
+            #[cfg(feature = "raw")]
+            raw: None,
         }
     }
+
+    /// Keep the record of the source, on the builds with the `raw` feature
+    pub fn keep_raw(&mut self, _record: impl FnOnce() -> serde_json::Value) {
+        #[cfg(feature = "raw")]
+        {
+            self.raw = Some(_record());
+        }
+    }
+}
+
+/// Shared copies of the repeated names of the positions, like the device
+/// IDs, so the sources allocate each name once
+#[derive(Clone, Debug, Default)]
+pub struct Names(HashSet<Arc<str>>);
+
+impl Names {
+    /// Shared copy of the name
+    pub fn get(&mut self, name: &str) -> Arc<str> {
+        if let Some(shared) = self.0.get(name) {
+            return shared.clone();
+        }
+
+        let shared: Arc<str> = Arc::from(name);
+        self.0.insert(shared.clone());
+        shared
+    }
+
+    /// Add the names of the other ones, like the ones of a worker
+    /// thread, keeping the shared copies already known
+    pub fn merge(&mut self, other: Names) {
+        self.0.extend(other.0);
+    }
+
+    /// Replace the names of the position with their shared copies
+    pub fn share(&mut self, dpos: &mut DevicePosition) {
+        dpos.device_id = self.get(&dpos.device_id);
+        dpos.route_name = dpos.route_name.as_deref().map(|route| self.get(route));
+        dpos.tracker = dpos.tracker.as_deref().map(|tracker| self.get(tracker));
+    }
+}
+
+#[test]
+fn shared_names() {
+    let mut names = Names::default();
+    let first = names.get("imei-351");
+    let again = names.get("imei-351");
+    assert!(Arc::ptr_eq(&first, &again));
+    assert_eq!("imei-351", &*again);
+    assert!(!Arc::ptr_eq(&first, &names.get("imei-352")));
+
+    // Names of a worker, merged back
+    let mut worker = names.clone();
+    let mut dpos = DevicePosition::basic(
+        worker.get("imei-353"),
+        Point::new(-48.87, -26.31),
+        OffsetDateTime::UNIX_EPOCH,
+    );
+    let other = names.get("imei-353");
+    names.merge(worker);
+    names.share(&mut dpos);
+    assert!(Arc::ptr_eq(&other, &dpos.device_id));
 }
@@ -0,0 +1,17 @@
+//! Preview of a run, before building the tracks
+
+/// Counts of the positions and of what a run would produce
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preview {
+    /// Positions fetched from the source
+    pub positions: usize,
+    /// Duplicated positions, dropped with the `dedup` option
+    pub duplicates: usize,
+    pub devices: usize,
+    /// Routes, or days, of each device
+    pub routes: usize,
+    pub tracks: usize,
+    /// Estimated segments, by the time slots of the positions. The
+    /// filters may drop some of them on the real run
+    pub segments: usize,
+}
@@ -0,0 +1,345 @@
+//! Quality metrics of the devices positions
+
+use std::collections::BTreeMap;
+
+use time::{Duration, OffsetDateTime};
+
+use super::position::DevicePosition;
+use super::sentinel::Sentinels;
+use crate::TimeRange;
+
+/// Quality of the positions reported by a device
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceQuality {
+    /// Positions fetched from the source
+    pub positions: usize,
+    /// Positions with a fix: coordinates that are not a sentinel
+    /// and, when reported, some satellites in view
+    pub fixes: usize,
+    /// Longest period without positions
+    pub longest_gap: Duration,
+    /// Points written on the tracks
+    pub emitted: usize,
+    /// Periods without positions longer than the gap threshold
+    pub gaps: Vec<Gap>,
+    /// Sum and count of the reported accuracies
+    accuracy: (f64, usize),
+}
+
+impl DeviceQuality {
+    /// Rate of the positions with a fix, from 0 to 1
+    pub fn fix_rate(&self) -> f64 {
+        rate(self.fixes, self.positions)
+    }
+
+    /// Rate of the positions not written on the
+    /// tracks, like the dropped or simplified ones
+    pub fn filtered_rate(&self) -> f64 {
+        rate(self.positions.saturating_sub(self.emitted), self.positions)
+    }
+
+    /// Mean of the accuracy reported by the device, in m
+    pub fn mean_accuracy(&self) -> Option<f64> {
+        let (sum, count) = self.accuracy;
+
+        (count > 0).then(|| sum / count as f64)
+    }
+
+    /// Add the metrics of other period of the device
+    pub fn merge(&mut self, other: &DeviceQuality) {
+        self.positions += other.positions;
+        self.fixes += other.fixes;
+        self.longest_gap = self.longest_gap.max(other.longest_gap);
+        self.emitted += other.emitted;
+        self.gaps.extend(other.gaps.iter().cloned());
+        self.accuracy.0 += other.accuracy.0;
+        self.accuracy.1 += other.accuracy.1;
+    }
+}
+
+/// Period without positions of a device, like when the tracker was off
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gap {
+    /// Time of the last position before the gap, or the window start
+    pub start: OffsetDateTime,
+    /// Time of the first position after the gap, or the window end
+    pub end: OffsetDateTime,
+}
+
+impl Gap {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+fn rate(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    part as f64 / total as f64
+}
+
+/// Quality of the positions of each device, without the emitted points
+pub fn analyze(
+    positions: &[DevicePosition],
+    sentinels: &Sentinels,
+) -> BTreeMap<String, DeviceQuality> {
+    let mut devices: BTreeMap<&str, Vec<&DevicePosition>> = BTreeMap::new();
+    for dpos in positions {
+        devices.entry(&dpos.device_id).or_default().push(dpos);
+    }
+
+    devices
+        .into_iter()
+        .map(|(device, mut dev_pos)| {
+            dev_pos.sort_by_key(|dpos| dpos.pos.time);
+
+            let mut quality = DeviceQuality {
+                positions: dev_pos.len(),
+                ..Default::default()
+            };
+
+            for dpos in &dev_pos {
+                let fix = !sentinels.is_missing(dpos.pos.coordinates)
+                    && dpos.pos.satellites.is_none_or(|sat| sat > 0);
+                if fix {
+                    quality.fixes += 1;
+                }
+
+                if let Some(precision) = dpos.pos.precision {
+                    quality.accuracy.0 += precision;
+                    quality.accuracy.1 += 1;
+                }
+            }
+
+            quality.longest_gap = dev_pos
+                .windows(2)
+                .map(|w| w[1].pos.time - w[0].pos.time)
+                .max()
+                .unwrap_or_default();
+
+            (device.to_string(), quality)
+        })
+        .collect()
+}
+
+/// Periods of each device without positions longer than the threshold,
+/// inside of the window. The window ends are also gap ends, so the devices
+/// that stopped reporting before the window end are flagged
+pub fn find_gaps(
+    positions: &[DevicePosition],
+    range: TimeRange,
+    threshold: Duration,
+) -> BTreeMap<String, Vec<Gap>> {
+    let mut devices: BTreeMap<&str, Vec<OffsetDateTime>> = BTreeMap::new();
+    for dpos in positions {
+        devices
+            .entry(&dpos.device_id)
+            .or_default()
+            .push(dpos.pos.time);
+    }
+
+    devices
+        .into_iter()
+        .map(|(device, mut times)| {
+            times.sort();
+
+            let mut bounds = vec![];
+            bounds.extend(range.start);
+            bounds.extend(times);
+            bounds.extend(range.end);
+
+            let gaps = bounds
+                .windows(2)
+                .map(|w| Gap {
+                    start: w[0],
+                    end: w[1],
+                })
+                .filter(|gap| gap.duration() > threshold)
+                .collect();
+
+            (device.to_string(), gaps)
+        })
+        .collect()
+}
+
+/// Quality and gaps of the devices added by parts of their positions,
+/// like the spilled groups, without all of them in memory. The gaps
+/// inside of a part are merged with the ones between the parts
+#[derive(Debug, Default)]
+pub struct QualityParts {
+    threshold: Option<Duration>,
+    devices: BTreeMap<String, DeviceParts>,
+}
+
+#[derive(Debug, Default)]
+struct DeviceParts {
+    quality: DeviceQuality,
+    /// First and last times of each part
+    spans: Vec<(OffsetDateTime, OffsetDateTime)>,
+    /// Gaps inside of the parts
+    gaps: Vec<Gap>,
+}
+
+impl QualityParts {
+    pub fn new(threshold: Option<Duration>) -> Self {
+        Self {
+            threshold,
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// Add the positions of a part
+    pub fn add(&mut self, positions: &[DevicePosition], sentinels: &Sentinels) {
+        let mut gaps = match self.threshold {
+            Some(threshold) => find_gaps(positions, TimeRange::default(), threshold),
+            None => BTreeMap::new(),
+        };
+
+        for (device, quality) in analyze(positions, sentinels) {
+            let times = positions
+                .iter()
+                .filter(|dpos| *dpos.device_id == device)
+                .map(|dpos| dpos.pos.time);
+            let span = times.clone().min().zip(times.max());
+
+            let parts = self.devices.entry(device.clone()).or_default();
+            parts.quality.merge(&quality);
+            parts.spans.extend(span);
+            parts.gaps.extend(gaps.remove(&device).unwrap_or_default());
+        }
+    }
+
+    /// Quality of each device, with the gaps between the parts and
+    /// the window ends
+    pub fn finish(self, range: TimeRange) -> BTreeMap<String, DeviceQuality> {
+        self.devices
+            .into_iter()
+            .map(|(device, mut parts)| {
+                parts.spans.sort();
+                let first = parts.spans.first().map(|(start, _)| *start);
+
+                let mut between = vec![];
+                let mut last: Option<OffsetDateTime> = None;
+                for (start, end) in parts.spans {
+                    if let Some(last) = last.filter(|last| start > *last) {
+                        between.push(Gap {
+                            start: last,
+                            end: start,
+                        });
+                    }
+                    last = Some(last.map_or(end, |last| last.max(end)));
+                }
+
+                let mut quality = parts.quality;
+                if let Some(longest) = between.iter().map(Gap::duration).max() {
+                    quality.longest_gap = quality.longest_gap.max(longest);
+                }
+
+                if let Some(threshold) = self.threshold {
+                    let mut gaps = parts.gaps;
+                    gaps.extend(between);
+                    gaps.extend(
+                        range
+                            .start
+                            .zip(first)
+                            .map(|(start, end)| Gap { start, end }),
+                    );
+                    gaps.extend(last.zip(range.end).map(|(start, end)| Gap { start, end }));
+                    gaps.retain(|gap| gap.duration() > threshold);
+                    gaps.sort_by_key(|gap| gap.start);
+                    quality.gaps = gaps;
+                }
+
+                (device, quality)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn device_quality() {
+    use geo::Point;
+    use time::macros::datetime;
+
+    let start = datetime!(2021-05-24 0:00 UTC);
+    let mut positions = vec![];
+    for (i, minutes) in [0, 1, 6, 7].iter().enumerate() {
+        let mut dpos = DevicePosition::basic(
+            "dev 1".to_string(),
+            Point::new(-48.87, -26.31),
+            start + Duration::minutes(*minutes),
+        );
+        dpos.pos.precision = Some(i as f64 * 2.0);
+        positions.push(dpos);
+    }
+    positions[1].pos.coordinates = Point::new(0.0, 0.0);
+    positions[2].pos.satellites = Some(0);
+    positions.push(DevicePosition::basic(
+        "dev 2".to_string(),
+        Point::new(-48.87, -26.31),
+        start,
+    ));
+
+    let sentinels = Sentinels {
+        coordinates: vec![[0.0, 0.0]],
+        ..Default::default()
+    };
+    let mut devices = analyze(&positions, &sentinels);
+    assert_eq!(2, devices.len());
+
+    let dev1 = devices.get_mut("dev 1").unwrap();
+    assert_eq!(4, dev1.positions);
+    assert_eq!(0.5, dev1.fix_rate());
+    assert_eq!(Some(3.0), dev1.mean_accuracy());
+    assert_eq!(Duration::minutes(5), dev1.longest_gap);
+    dev1.emitted = 1;
+    assert_eq!(0.75, dev1.filtered_rate());
+
+    let dev2 = &devices["dev 2"];
+    assert_eq!(1.0, dev2.fix_rate());
+    assert_eq!(None, dev2.mean_accuracy());
+    assert_eq!(Duration::ZERO, dev2.longest_gap);
+}
+
+#[test]
+fn reporting_gaps() {
+    use geo::Point;
+    use time::macros::datetime;
+
+    let start = datetime!(2021-05-24 0:00 UTC);
+    let positions: Vec<DevicePosition> =
+        [("dev 1", 10), ("dev 1", 20), ("dev 1", 95), ("dev 2", 5)]
+            .iter()
+            .map(|(device, minutes)| {
+                DevicePosition::basic(
+                    device.to_string(),
+                    Point::new(-48.87, -26.31),
+                    start + Duration::minutes(*minutes),
+                )
+            })
+            .collect();
+
+    let range = TimeRange::between(start, start + Duration::minutes(100));
+    let gaps = find_gaps(&positions, range, Duration::minutes(30));
+    assert_eq!(
+        vec![Gap {
+            start: start + Duration::minutes(20),
+            end: start + Duration::minutes(95),
+        }],
+        gaps["dev 1"]
+    );
+    assert_eq!(Duration::minutes(75), gaps["dev 1"][0].duration());
+    assert_eq!(
+        vec![Gap {
+            start: start + Duration::minutes(5),
+            end: start + Duration::minutes(100),
+        }],
+        gaps["dev 2"]
+    );
+
+    let gaps = find_gaps(&positions, TimeRange::all(), Duration::minutes(30));
+    assert_eq!(1, gaps["dev 1"].len());
+    assert!(gaps["dev 2"].is_empty());
+}
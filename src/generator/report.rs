@@ -0,0 +1,130 @@
+//! Generation report API
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use geo::{Coord, Point, Rect};
+use time::OffsetDateTime;
+
+use super::deviation::RouteDeviation;
+use super::quality::DeviceQuality;
+use crate::geoutil;
+
+/// Events worth to report during the tracks generation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub entries: Vec<ReportEntry>,
+    /// Duplicated positions dropped
+    pub duplicates: usize,
+    /// Quality of the positions of each device
+    pub quality: BTreeMap<String, DeviceQuality>,
+    /// Bounding box of the points of the generated tracks
+    pub bounds: Option<Rect>,
+    /// Deviation of the tracks from the reference lines of their routes
+    pub deviations: Vec<RouteDeviation>,
+    /// Positions dropped by the cleaning of the tracks
+    pub rejects: Vec<Rejection>,
+    /// Records of the source that failed to parse, with the reason
+    pub skipped: Vec<String>,
+}
+
+/// Something that happened with a position
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportEntry {
+    /// Device name, number...
+    pub device: String,
+    /// Position time
+    pub time: OffsetDateTime,
+    pub message: String,
+}
+
+/// Position dropped by the cleaning of the tracks
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    pub device: String,
+    pub time: OffsetDateTime,
+    pub coordinates: Point,
+    pub stage: RejectStage,
+    pub reason: String,
+    /// Index of the position on the ones given to the tracker
+    pub source_index: Option<usize>,
+    /// Record of the source with the position
+    #[cfg(feature = "raw")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// Step of the generation that dropped a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectStage {
+    /// Duplicated device and time
+    Dedup,
+    /// Coordinates with a sentinel value
+    Sentinel,
+    /// Sanity limits, DOP filter or a custom filter
+    Filter,
+}
+
+impl fmt::Display for RejectStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RejectStage::Dedup => "dedup",
+            RejectStage::Sentinel => "sentinel",
+            RejectStage::Filter => "filter",
+        })
+    }
+}
+
+impl Report {
+    /// Register a new entry
+    pub fn add(&mut self, device: String, time: OffsetDateTime, message: String) {
+        self.entries.push(ReportEntry {
+            device,
+            time,
+            message,
+        });
+    }
+
+    /// Register a dropped position, returning its rejection
+    pub fn reject(
+        &mut self,
+        device: String,
+        time: OffsetDateTime,
+        coordinates: Point,
+        stage: RejectStage,
+        reason: String,
+    ) -> &mut Rejection {
+        self.rejects.push(Rejection {
+            device,
+            time,
+            coordinates,
+            stage,
+            reason,
+            source_index: None,
+            #[cfg(feature = "raw")]
+            raw: None,
+        });
+        self.rejects.last_mut().expect("rejection just pushed")
+    }
+
+    /// Move the entries of other report into this one
+    pub fn merge(&mut self, other: Report) {
+        self.entries.extend(other.entries);
+        self.duplicates += other.duplicates;
+        self.deviations.extend(other.deviations);
+        self.rejects.extend(other.rejects);
+        self.skipped.extend(other.skipped);
+        for (device, quality) in other.quality {
+            self.quality.entry(device).or_default().merge(&quality);
+        }
+        let corners = [self.bounds, other.bounds]
+            .into_iter()
+            .flatten()
+            .flat_map(|rect| [rect.min(), rect.max()])
+            .map(Coord::into);
+        self.bounds = geoutil::bounds(corners);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.duplicates == 0 && self.skipped.is_empty()
+    }
+}
@@ -0,0 +1,128 @@
+//! Sanity limits of the emitted values
+
+use gpx::Waypoint;
+use serde::{Deserialize, Serialize};
+
+use super::filter::PointFilter;
+use super::model::TrackPoint;
+
+/// What to do with a value out of the limits
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SanityAction {
+    /// Replace the value by the closest limit
+    Clamp,
+    /// Remove the value from the point
+    Drop,
+}
+
+/// Caps for absurd values reported by broken devices
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SanityLimits {
+    /// Max speed allowed, in m/s
+    pub max_speed: Option<f64>,
+    /// Min elevation allowed, in m
+    pub min_elevation: Option<f64>,
+    /// Max elevation allowed, in m
+    pub max_elevation: Option<f64>,
+    pub action: SanityAction,
+}
+
+impl Default for SanityLimits {
+    fn default() -> Self {
+        Self {
+            max_speed: None,
+            min_elevation: None,
+            max_elevation: None,
+            action: SanityAction::Drop,
+        }
+    }
+}
+
+impl SanityLimits {
+    /// Apply the limits over the point values, returning
+    /// the description of each change made
+    pub fn apply(&self, wp: &mut Waypoint) -> Vec<String> {
+        self.limit(&mut wp.speed, &mut wp.elevation)
+    }
+
+    fn limit(&self, speed: &mut Option<f64>, elevation: &mut Option<f64>) -> Vec<String> {
+        let mut changes = vec![];
+
+        if let (Some(value), Some(_)) = (*speed, self.max_speed) {
+            if let Some(fixed) = self.fix(value, Some(0.0), self.max_speed) {
+                changes.push(self.describe("speed", value, fixed));
+                *speed = fixed;
+            }
+        }
+
+        if let Some(value) = *elevation {
+            if let Some(fixed) = self.fix(value, self.min_elevation, self.max_elevation) {
+                changes.push(self.describe("elevation", value, fixed));
+                *elevation = fixed;
+            }
+        }
+
+        changes
+    }
+
+    /// New value for a out of limits one, `None` if the value is fine
+    fn fix(&self, value: f64, min: Option<f64>, max: Option<f64>) -> Option<Option<f64>> {
+        if min.is_none() && max.is_none() {
+            return None;
+        }
+
+        let mut limited = value;
+        if let Some(min) = min {
+            limited = limited.max(min);
+        }
+        if let Some(max) = max {
+            limited = limited.min(max);
+        }
+
+        if limited == value {
+            return None;
+        }
+
+        match self.action {
+            SanityAction::Clamp => Some(Some(limited)),
+            SanityAction::Drop => Some(None),
+        }
+    }
+
+    fn describe(&self, field: &str, value: f64, fixed: Option<f64>) -> String {
+        match fixed {
+            Some(f) => format!(
+                "The {} {} is out of the limits, clamped to {}",
+                field, value, f
+            ),
+            None => format!("The {} {} is out of the limits, dropped", field, value),
+        }
+    }
+}
+
+impl PointFilter for SanityLimits {
+    fn transform(&self, point: &mut TrackPoint) -> Vec<String> {
+        self.limit(&mut point.speed, &mut point.elevation)
+    }
+}
+
+#[test]
+fn parse_sanity_limits() -> Result<(), String> {
+    let yaml = "\nmax_speed: 83.3\nmin_elevation: -500\naction: clamp";
+
+    let sl: SanityLimits = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+
+    assert_eq!(
+        SanityLimits {
+            max_speed: Some(83.3),
+            min_elevation: Some(-500.0),
+            max_elevation: None,
+            action: SanityAction::Clamp,
+        },
+        sl
+    );
+
+    Ok(())
+}
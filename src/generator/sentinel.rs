@@ -0,0 +1,56 @@
+//! Sentinel values reported by trackers without a fix
+
+use geo::geometry::Point;
+use gpx::Waypoint;
+use serde::{Deserialize, Serialize};
+
+/// Values to treat as missing, like the `0,0` of trackers
+/// without a fix or the `-1` and `9999` of some firmwares
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sentinels {
+    /// Coordinates, as `[lng, lat]`, that drop the position
+    pub coordinates: Vec<[f64; 2]>,
+    /// Speeds removed from the points
+    pub speed: Vec<f64>,
+    /// Elevations removed from the points
+    pub elevation: Vec<f64>,
+}
+
+impl Sentinels {
+    /// If the coordinates are a sentinel, so the position has no fix
+    pub fn is_missing(&self, coordinates: Point) -> bool {
+        self.coordinates
+            .iter()
+            .any(|[lng, lat]| *lng == coordinates.x() && *lat == coordinates.y())
+    }
+
+    /// Remove the sentinel values from the point
+    pub fn clear(&self, wp: &mut Waypoint) {
+        if wp.speed.is_some_and(|sp| self.speed.contains(&sp)) {
+            wp.speed = None;
+        }
+
+        if wp.elevation.is_some_and(|el| self.elevation.contains(&el)) {
+            wp.elevation = None;
+        }
+    }
+}
+
+#[test]
+fn parse_sentinels() -> Result<(), String> {
+    let yaml = "\ncoordinates: [[0, 0]]\nspeed: [-1]\nelevation: [9999, -1]";
+
+    let st: Sentinels = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+
+    assert_eq!(
+        Sentinels {
+            coordinates: vec![[0.0, 0.0]],
+            speed: vec![-1.0],
+            elevation: vec![9999.0, -1.0],
+        },
+        st
+    );
+
+    Ok(())
+}
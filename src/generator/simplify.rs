@@ -0,0 +1,264 @@
+//! Simplification of the segments
+
+use std::cmp::Ordering;
+
+use geo::{BoundingRect, LineString, SimplifyVwIdx};
+
+use super::model::TrackPoint;
+use crate::geoutil::{distance, METERS_PER_DEGREE};
+
+/// Indexes of the points kept by the Visvalingam-Whyatt simplification,
+/// with the tolerance in meters. The points are projected in meters around
+/// the mean latitude of the segment, so the same tolerance works on any
+/// region, and the ones with a triangle area smaller than the square of
+/// the tolerance are removed
+pub fn simplify_metric(points: &[TrackPoint], tolerance: f64) -> Vec<usize> {
+    project(points).simplify_vw_idx(&(tolerance * tolerance))
+}
+
+/// Indexes of the points kept by the Visvalingam-Whyatt simplification
+/// with the smallest tolerance that reduces the segment to the target
+/// count of points, or less. The ends of the segment are always kept
+pub fn simplify_to_count(points: &[TrackPoint], target: usize) -> Vec<usize> {
+    if points.len() <= target {
+        return (0..points.len()).collect();
+    }
+
+    let line = project(points);
+    let rect = match line.bounding_rect() {
+        Some(rect) => rect,
+        None => return vec![],
+    };
+
+    // No triangle is larger than the square of the diagonal
+    let mut low = 0.0;
+    let mut high = rect.width().hypot(rect.height());
+    let mut keep = line.simplify_vw_idx(&(high * high));
+
+    for _ in 0..50 {
+        let tolerance = (low + high) / 2.0;
+        let candidate = line.simplify_vw_idx(&(tolerance * tolerance));
+        if candidate.len() <= target {
+            high = tolerance;
+            keep = candidate;
+        } else {
+            low = tolerance;
+        }
+    }
+
+    keep
+}
+
+/// Points in meters around the mean latitude of the segment
+fn project(points: &[TrackPoint]) -> LineString {
+    if points.is_empty() {
+        return LineString::new(vec![]);
+    }
+
+    let latitude = points.iter().map(|p| p.coordinates.y()).sum::<f64>() / points.len() as f64;
+    let scale = latitude.to_radians().cos();
+
+    points
+        .iter()
+        .map(|p| {
+            (
+                p.coordinates.x() * METERS_PER_DEGREE * scale,
+                p.coordinates.y() * METERS_PER_DEGREE,
+            )
+        })
+        .collect()
+}
+
+/// Indexes of the points farther than the distance, in meters, from the
+/// previous kept point, dropping the jitter of the stopped devices. The
+/// ends of the segment are always kept
+pub fn thin_by_distance(points: &[TrackPoint], meters: f64) -> Vec<usize> {
+    let mut keep: Vec<usize> = vec![];
+
+    for (i, point) in points.iter().enumerate() {
+        match keep.last() {
+            Some(last) if distance(points[*last].coordinates, point.coordinates) < meters => {}
+            _ => keep.push(i),
+        }
+    }
+
+    let last = points.len().saturating_sub(1);
+    if !points.is_empty() && keep.last() != Some(&last) {
+        keep.push(last);
+    }
+
+    keep
+}
+
+/// Keep only the points of the indexes, moving them in place
+/// instead of copying the kept ones to a new segment
+pub fn retain_indexes(points: &mut Vec<TrackPoint>, keep: &mut Vec<usize>) {
+    keep.sort_unstable();
+    keep.dedup();
+
+    let mut next = keep.iter().peekable();
+    let mut i = 0;
+    points.retain(|_| {
+        let kept = next.next_if_eq(&&i).is_some();
+        i += 1;
+        kept
+    });
+}
+
+/// Indexes of the points with the extreme values of the segment: the max
+/// speed, the max and min elevations and the ends of the longest time gap
+pub fn extremes(points: &[TrackPoint]) -> Vec<usize> {
+    let mut indexes = vec![];
+
+    let by_value = |value: fn(&TrackPoint) -> Option<f64>, max: bool| {
+        points
+            .iter()
+            .enumerate()
+            .filter_map(|(i, wp)| value(wp).map(|v| (i, v)))
+            .reduce(|a, b| match (max, b.1.total_cmp(&a.1)) {
+                (true, Ordering::Greater) | (false, Ordering::Less) => b,
+                _ => a,
+            })
+            .map(|(i, _)| i)
+    };
+
+    indexes.extend(by_value(|wp| wp.speed, true));
+    indexes.extend(by_value(|wp| wp.elevation, true));
+    indexes.extend(by_value(|wp| wp.elevation, false));
+
+    let gap = points
+        .windows(2)
+        .enumerate()
+        .max_by_key(|(_, w)| w[1].time - w[0].time)
+        .map(|(i, _)| i);
+    if let Some(i) = gap {
+        indexes.extend([i, i + 1]);
+    }
+
+    indexes.sort_unstable();
+    indexes.dedup();
+
+    indexes
+}
+
+#[test]
+fn metric_tolerance() {
+    use geo::Point;
+    use time::macros::datetime;
+
+    // 5m off of the line between points 200m apart,
+    // so a triangle of 500m²
+    for latitude in [-26.31_f64, 60.0] {
+        let meters = METERS_PER_DEGREE * latitude.to_radians().cos();
+        let points: Vec<TrackPoint> = [(0.0, 0.0), (100.0, 5.0), (200.0, 0.0), (300.0, 0.0)]
+            .iter()
+            .map(|(x, y)| {
+                TrackPoint::basic(
+                    Point::new(-48.87 + x / meters, latitude + y / METERS_PER_DEGREE),
+                    datetime!(2021-05-24 0:00 UTC),
+                )
+            })
+            .collect();
+
+        assert_eq!(vec![0, 1, 2, 3], simplify_metric(&points, 10.0));
+        assert_eq!(vec![0, 3], simplify_metric(&points, 30.0));
+    }
+}
+
+#[test]
+fn target_count() {
+    use geo::Point;
+    use time::macros::datetime;
+
+    let points: Vec<TrackPoint> = (0..100)
+        .map(|i| {
+            let i = f64::from(i);
+            TrackPoint::basic(
+                Point::new(-48.87 + i * 0.001, -26.31 + (i / 5.0).sin() * 0.002),
+                datetime!(2021-05-24 0:00 UTC),
+            )
+        })
+        .collect();
+
+    for target in [2, 10, 25, 60] {
+        let keep = simplify_to_count(&points, target);
+        assert!(keep.len() <= target);
+        assert!(keep.len() + 3 >= target, "{} of {}", keep.len(), target);
+        assert_eq!(Some(&0), keep.first());
+        assert_eq!(Some(&99), keep.last());
+    }
+
+    assert_eq!(100, simplify_to_count(&points, 500).len());
+}
+
+#[test]
+fn segment_extremes() {
+    use geo::Point;
+    use time::{macros::datetime, Duration};
+
+    let start = datetime!(2021-05-24 0:00 UTC);
+    let mut points = vec![];
+    for (i, (speed, elevation, minutes)) in [
+        (Some(3.0), Some(10.0), 0),
+        (Some(9.0), Some(12.0), 1),
+        (None, Some(40.0), 2),
+        (Some(2.0), Some(5.0), 3),
+        (Some(9.0), None, 8),
+        (Some(1.0), Some(7.0), 9),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut point = TrackPoint::basic(
+            Point::new(-48.87 + i as f64 * 0.001, -26.31),
+            start + Duration::minutes(minutes),
+        );
+        point.speed = speed;
+        point.elevation = elevation;
+        points.push(point);
+    }
+
+    assert_eq!(vec![1, 2, 3, 4], extremes(&points));
+    assert!(extremes(&[]).is_empty());
+}
+
+#[test]
+fn min_distance() {
+    use geo::Point;
+    use time::macros::datetime;
+
+    // 0.0001 degree of latitude is about 11m
+    let points: Vec<TrackPoint> = [0.0, 0.00002, 0.0001, 0.00011, 0.0003, 0.00031]
+        .iter()
+        .map(|y| {
+            TrackPoint::basic(
+                Point::new(-48.87, -26.31 + y),
+                datetime!(2021-05-24 0:00 UTC),
+            )
+        })
+        .collect();
+
+    assert_eq!(vec![0, 2, 4, 5], thin_by_distance(&points, 10.0));
+    assert_eq!(vec![0, 4, 5], thin_by_distance(&points, 20.0));
+    assert_eq!(vec![0, 1, 2, 3, 4, 5], thin_by_distance(&points, 0.0));
+    assert!(thin_by_distance(&[], 10.0).is_empty());
+}
+
+#[test]
+fn retain_in_place() {
+    use geo::Point;
+    use time::{macros::datetime, Duration};
+
+    let mut points: Vec<TrackPoint> = (0..6)
+        .map(|i| {
+            TrackPoint::basic(
+                Point::new(-48.87 + i as f64 * 0.001, -26.31),
+                datetime!(2021-05-24 0:00 UTC) + Duration::minutes(i),
+            )
+        })
+        .collect();
+    let expected = vec![points[0].clone(), points[3].clone(), points[5].clone()];
+
+    retain_indexes(&mut points, &mut vec![5, 0, 3, 3, 9]);
+    assert_eq!(expected, points);
+}
@@ -0,0 +1,76 @@
+//! De-noising of the track coordinates
+
+use geo::Point;
+
+use super::model::TrackPoint;
+
+/// Replace the coordinates by the rolling median of the window centered on
+/// each point, removing single point spikes without lagging the track.
+/// Near the ends the window shrinks, so the first and last points are kept
+pub fn median_filter(points: &mut [TrackPoint], window: usize) {
+    let half = window / 2;
+    if half == 0 {
+        return;
+    }
+
+    let original: Vec<Point> = points.iter().map(|p| p.coordinates).collect();
+
+    for (i, point) in points.iter_mut().enumerate() {
+        let half = half.min(i).min(original.len() - 1 - i);
+        let around = &original[i - half..=i + half];
+
+        let x = median(around.iter().map(|p| p.x()).collect());
+        let y = median(around.iter().map(|p| p.y()).collect());
+        point.coordinates = Point::new(x, y);
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+#[test]
+fn median_spikes() {
+    use time::macros::datetime;
+
+    let coordinates = [
+        (-48.870, -26.310),
+        (-48.869, -26.310),
+        (-48.500, -26.100), // spike
+        (-48.867, -26.310),
+        (-48.866, -26.311),
+    ];
+    let mut points: Vec<TrackPoint> = coordinates
+        .iter()
+        .map(|(x, y)| TrackPoint::basic(Point::new(*x, *y), datetime!(2021-05-24 0:00 UTC)))
+        .collect();
+
+    median_filter(&mut points, 3);
+    let filtered: Vec<(f64, f64)> = points
+        .iter()
+        .map(|p| (p.coordinates.x(), p.coordinates.y()))
+        .collect();
+    assert_eq!(
+        vec![
+            (-48.870, -26.310),
+            (-48.869, -26.310),
+            (-48.867, -26.310),
+            (-48.866, -26.310),
+            (-48.866, -26.311),
+        ],
+        filtered
+    );
+
+    let mut single = points[..1].to_vec();
+    median_filter(&mut single, 5);
+    assert_eq!(points[0], single[0]);
+
+    assert_eq!(2.5, median(vec![4.0, 1.0, 2.0, 3.0]));
+}
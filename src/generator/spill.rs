@@ -0,0 +1,356 @@
+//! Positions of the groups spilled to temporary files, for the
+//! machines with little RAM
+//!
+//! The positions are spilled while the source fetches them, so only
+//! the largest group must fit in memory, when its track is built.
+//! The sources reading all the records at once, like the GPX files,
+//! still keep them in memory during the fetch.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use geo::Point;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime, UtcOffset};
+
+use super::position::{DevicePosition, Names, RawPosition};
+
+/// Limit of the positions kept in memory while fetching them
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpillOptions {
+    /// Positions kept in memory, the others are written on
+    /// temporary files until the build of their tracks
+    pub max_positions: usize,
+    /// Directory of the temporary files. Default: the system one
+    pub dir: Option<String>,
+}
+
+impl Default for SpillOptions {
+    fn default() -> Self {
+        Self {
+            max_positions: 1_000_000,
+            dir: None,
+        }
+    }
+}
+
+/// Positions of a group, the oldest ones on a temporary file
+#[derive(Debug, Default)]
+pub struct GroupPositions {
+    start: Option<OffsetDateTime>,
+    file: Option<PathBuf>,
+    memory: Vec<DevicePosition>,
+}
+
+impl From<Vec<DevicePosition>> for GroupPositions {
+    fn from(memory: Vec<DevicePosition>) -> Self {
+        Self {
+            start: memory.iter().map(|dpos| dpos.pos.time).min(),
+            file: None,
+            memory,
+        }
+    }
+}
+
+impl GroupPositions {
+    /// Time of the first position
+    pub fn start(&self) -> Option<OffsetDateTime> {
+        self.start
+    }
+
+    /// All the positions of the group, in the order they were added,
+    /// removing the temporary file
+    pub fn load(self) -> Result<Vec<DevicePosition>, String> {
+        let mut positions = match &self.file {
+            Some(path) => {
+                let positions = read_positions(path)?;
+                fs::remove_file(path).map_err(|e| e.to_string())?;
+                positions
+            }
+            None => vec![],
+        };
+        positions.extend(self.memory);
+
+        Ok(positions)
+    }
+}
+
+/// Directory of the temporary files, removed when dropped
+#[derive(Debug)]
+pub struct SpillDir(PathBuf);
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Groups the positions, writing all the groups on their temporary
+/// files when the positions in memory reach the limit
+pub struct Spiller<K> {
+    options: SpillOptions,
+    dir: Option<SpillDir>,
+    groups: BTreeMap<K, GroupPositions>,
+    buffered: usize,
+    files: usize,
+}
+
+impl<K: Ord> Spiller<K> {
+    pub fn new(options: &SpillOptions) -> Self {
+        Self {
+            options: options.clone(),
+            dir: None,
+            groups: BTreeMap::new(),
+            buffered: 0,
+            files: 0,
+        }
+    }
+
+    /// Add the position on its group
+    pub fn push(&mut self, key: K, dpos: DevicePosition) -> Result<(), String> {
+        let group = self.groups.entry(key).or_default();
+        let time = dpos.pos.time;
+        group.start = Some(group.start.map_or(time, |start| start.min(time)));
+        group.memory.push(dpos);
+
+        self.buffered += 1;
+        if self.buffered >= self.options.max_positions.max(1) {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    /// Groups ordered by their keys, with the directory of their files
+    pub fn finish(self) -> (Option<SpillDir>, Vec<(K, GroupPositions)>) {
+        (self.dir, self.groups.into_iter().collect())
+    }
+
+    /// Move the positions in memory to the files of their groups
+    fn spill(&mut self) -> Result<(), String> {
+        let dir = match &self.dir {
+            Some(dir) => dir.0.clone(),
+            None => {
+                let base = match &self.options.dir {
+                    Some(dir) => PathBuf::from(dir),
+                    None => std::env::temp_dir(),
+                };
+                let nanos = OffsetDateTime::now_utc().unix_timestamp_nanos();
+                let dir = base.join(format!("location2gpx-{}-{}", std::process::id(), nanos));
+                fs::create_dir_all(&dir).map_err(|e| {
+                    format!(
+                        "Failed on create the spill directory {}: {}",
+                        dir.display(),
+                        e
+                    )
+                })?;
+                self.dir = Some(SpillDir(dir.clone()));
+                dir
+            }
+        };
+
+        for group in self.groups.values_mut() {
+            if group.memory.is_empty() {
+                continue;
+            }
+
+            let path = group.file.get_or_insert_with(|| {
+                self.files += 1;
+                dir.join(format!("group-{}.jsonl", self.files))
+            });
+            write_positions(path, group.memory.drain(..))?;
+            group.memory.shrink_to_fit();
+        }
+        self.buffered = 0;
+
+        Ok(())
+    }
+}
+
+/// Line of a spilled position
+#[derive(Deserialize, Serialize)]
+struct SpilledPosition {
+    device_id: String,
+    x: f64,
+    y: f64,
+    seconds: i64,
+    nanos: i64,
+    offset: i32,
+    speed: Option<f64>,
+    precision: Option<f64>,
+    altitude: Option<f64>,
+    baro_altitude: Option<f64>,
+    satellites: Option<u64>,
+    hdop: Option<f64>,
+    event: Option<String>,
+    route_name: Option<String>,
+    tracker: Option<String>,
+    device_name: Option<String>,
+    vehicle_plate: Option<String>,
+    track_type: Option<String>,
+    group: Option<String>,
+    #[cfg(feature = "raw")]
+    raw: Option<serde_json::Value>,
+}
+
+impl From<DevicePosition> for SpilledPosition {
+    fn from(dpos: DevicePosition) -> Self {
+        let time = dpos.pos.time;
+        Self {
+            device_id: dpos.device_id.to_string(),
+            x: dpos.pos.coordinates.x(),
+            y: dpos.pos.coordinates.y(),
+            seconds: time.unix_timestamp(),
+            nanos: time.nanosecond().into(),
+            offset: time.offset().whole_seconds(),
+            speed: dpos.pos.speed,
+            precision: dpos.pos.precision,
+            altitude: dpos.pos.altitude,
+            baro_altitude: dpos.pos.baro_altitude,
+            satellites: dpos.pos.satellites,
+            hdop: dpos.pos.hdop,
+            event: dpos.pos.event,
+            route_name: dpos.route_name.as_deref().map(str::to_string),
+            tracker: dpos.tracker.as_deref().map(str::to_string),
+            device_name: dpos.device_name,
+            vehicle_plate: dpos.vehicle_plate,
+            track_type: dpos.track_type,
+            group: dpos.group,
+            #[cfg(feature = "raw")]
+            raw: dpos.raw,
+        }
+    }
+}
+
+impl SpilledPosition {
+    fn into_position(self, names: &mut Names) -> Result<DevicePosition, String> {
+        let offset = UtcOffset::from_whole_seconds(self.offset).map_err(|e| e.to_string())?;
+        let time = OffsetDateTime::from_unix_timestamp(self.seconds).map_err(|e| e.to_string())?
+            + Duration::nanoseconds(self.nanos);
+
+        Ok(DevicePosition {
+            device_id: names.get(&self.device_id),
+            pos: RawPosition {
+                coordinates: Point::new(self.x, self.y),
+                time: time.to_offset(offset),
+                speed: self.speed,
+                precision: self.precision,
+                altitude: self.altitude,
+                baro_altitude: self.baro_altitude,
+                satellites: self.satellites,
+                hdop: self.hdop,
+                event: self.event,
+            },
+            route_name: self.route_name.map(|route| names.get(&route)),
+            tracker: self.tracker.map(|tracker| names.get(&tracker)),
+            device_name: self.device_name,
+            vehicle_plate: self.vehicle_plate,
+            track_type: self.track_type,
+            group: self.group,
+            #[cfg(feature = "raw")]
+            raw: self.raw,
+        })
+    }
+}
+
+fn write_positions(
+    path: &Path,
+    positions: impl Iterator<Item = DevicePosition>,
+) -> Result<(), String> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed on open the spill file {}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+
+    for dpos in positions {
+        serde_json::to_writer(&mut writer, &SpilledPosition::from(dpos))
+            .map_err(|e| e.to_string())?;
+        writer.write_all(b"\n").map_err(|e| e.to_string())?;
+    }
+
+    writer
+        .flush()
+        .map_err(|e| format!("Failed on write the spill file {}: {}", path.display(), e))
+}
+
+fn read_positions(path: &Path) -> Result<Vec<DevicePosition>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed on open the spill file {}: {}", path.display(), e))?;
+
+    let mut names = Names::default();
+    BufReader::new(file)
+        .lines()
+        .map(|line| {
+            let line = line.map_err(|e| e.to_string())?;
+            let spilled: SpilledPosition =
+                serde_json::from_str(&line).map_err(|e| e.to_string())?;
+            spilled.into_position(&mut names)
+        })
+        .collect()
+}
+
+#[test]
+fn spill_groups() -> Result<(), String> {
+    use time::macros::datetime;
+
+    let at = |device: &str, minute: i64| {
+        let mut dpos = DevicePosition::basic(
+            device.to_string(),
+            Point::new(-48.87, -26.31),
+            datetime!(2021-05-24 8:00 -3) + Duration::minutes(minute),
+        );
+        dpos.pos.speed = Some(minute as f64);
+        dpos.route_name = Some("10".into());
+        dpos
+    };
+
+    let mut spiller = Spiller::new(&SpillOptions {
+        max_positions: 2,
+        ..Default::default()
+    });
+    for (device, minute) in [
+        ("dev 2", 3),
+        ("dev 1", 0),
+        ("dev 1", 1),
+        ("dev 2", 4),
+        ("dev 1", 2),
+    ] {
+        spiller.push(device, at(device, minute))?;
+    }
+
+    let (dir, groups) = spiller.finish();
+    let dir = dir.ok_or("Nothing spilled")?;
+    assert!(dir.0.exists());
+    assert_eq!(
+        vec!["dev 1", "dev 2"],
+        groups.iter().map(|(k, _)| *k).collect::<Vec<_>>()
+    );
+
+    let mut loaded = vec![];
+    for (_, group) in groups {
+        let start = group.start();
+        let positions = group.load()?;
+        assert_eq!(start, positions.first().map(|dpos| dpos.pos.time));
+        loaded.extend(positions);
+    }
+
+    let minutes: Vec<Option<f64>> = loaded.iter().map(|dpos| dpos.pos.speed).collect();
+    assert_eq!(
+        vec![Some(0.0), Some(1.0), Some(2.0), Some(3.0), Some(4.0)],
+        minutes
+    );
+    assert_eq!(datetime!(2021-05-24 8:03 -3), loaded[3].pos.time);
+    assert_eq!(-3, loaded[3].pos.time.offset().whole_hours());
+    assert_eq!(Some("10"), loaded[3].route_name.as_deref());
+
+    let path = dir.0.clone();
+    drop(dir);
+    assert!(!path.exists());
+
+    Ok(())
+}
@@ -1,12 +1,26 @@
 use std::fs;
 
 use geo::Point;
-use time::{macros::datetime, OffsetDateTime};
+use time::{macros::datetime, Duration};
 
+use super::direction::DirectionOptions;
+use super::filter::PointFilter;
 use super::gpx::GpxGenerator;
+use super::manifest::Manifest;
+use super::model::TrackPoint;
+use super::period::{group_by_period, CalendarPeriod};
 use super::position::{DevicePosition, RawPosition};
-use super::tracker::{SourceToTracks, TrackSegmentOptions, Tracker};
-use crate::PositionsSource;
+use super::preview::Preview;
+use super::report::{RejectStage, Report};
+use super::sanity::{SanityAction, SanityLimits};
+use super::sentinel::Sentinels;
+use super::tracker::{
+    BucketAlignment, PipelineStage, SourceToTracks, TrackOptions, TrackOrder, TrackSegmentOptions,
+    Tracker,
+};
+use super::validate::check_gpx_structure;
+use super::writer::{write_gpx, OutputOptions, SpeedOutput, SpeedUnit, TimePrecision};
+use crate::{PositionsSource, TimeRange};
 
 #[test]
 fn simple_track() -> Result<(), String> {
@@ -111,15 +125,144 @@ fn simple_gpx() -> Result<(), String> {
     Ok(())
 }
 
+#[test]
+fn gpx_writer() -> Result<(), String> {
+    let p1 = RawPosition::basic(
+        Point::new(-48.8702222, -26.31832),
+        datetime!(2021-05-24 0:00 UTC),
+    );
+    let p2 = RawPosition::basic(
+        Point::new(-48.8619776, -26.3185919),
+        datetime!(2021-05-24 0:02 UTC),
+    );
+    let p3 = RawPosition::basic(
+        Point::new(-48.8619871, -26.3185861),
+        datetime!(2021-05-24 0:04 UTC),
+    );
+
+    let track = Tracker::new("my dev 1".to_string(), "running in joinville".to_string())
+        .build(vec![&p1, &p2, &p3])?;
+
+    let mut gpx = GpxGenerator::empty();
+    gpx.tracks.push(track);
+
+    let mut bdoc: Vec<u8> = Vec::new();
+    gpx.write(&mut bdoc)?;
+    let doc = String::from_utf8(bdoc).map_err(|e| e.to_string())?;
+
+    let edoc = fs::read_to_string("samples/simple.gpx").map_err(|e| e.to_string())?;
+
+    assert_eq!(
+        edoc.lines().collect::<String>(),
+        doc.lines().collect::<String>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn source2tracks_order() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut pos = vec![];
+
+            pos.push({
+                let mut p = DevicePosition::basic(
+                    "dev 1".to_string(),
+                    Point::new(-48.8702222, -26.31832),
+                    datetime!(2021-05-24 0:05 UTC),
+                );
+                p.route_name = Some("B".into());
+                p
+            });
+            pos.push({
+                let mut p = DevicePosition::basic(
+                    "dev 2".to_string(),
+                    Point::new(-48.8619871, -26.3385861),
+                    datetime!(2021-05-24 0:02 UTC),
+                );
+                p.route_name = Some("A".into());
+                p
+            });
+            pos.push({
+                let mut p = DevicePosition::basic(
+                    "dev 3".to_string(),
+                    Point::new(-48.8619871, -26.3385861),
+                    datetime!(2021-05-24 0:01 UTC),
+                );
+                p.route_name = Some("C".into());
+                p
+            });
+
+            Ok(pos)
+        }
+    }
+
+    let descriptions = |tracks: &Vec<gpx::Track>| -> Vec<(Option<u32>, String)> {
+        tracks
+            .iter()
+            .map(|t| (t.number, t.description.clone().unwrap_or_default()))
+            .collect()
+    };
+
+    let mut generator = SourceToTracks::new();
+    let (tracks, _) = generator.run(TestSource {}, TimeRange::all())?;
+    assert_eq!(
+        vec![
+            (Some(1), "Tracked by `dev 1`".to_string()),
+            (Some(2), "Tracked by `dev 2`".to_string()),
+            (Some(3), "Tracked by `dev 3`".to_string()),
+        ],
+        descriptions(&tracks)
+    );
+
+    let (tracks, _) = generator
+        .configure_tracks(&TrackOptions {
+            order: TrackOrder::Route,
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(
+        vec![
+            (Some(1), "Tracked by `dev 2`".to_string()),
+            (Some(2), "Tracked by `dev 1`".to_string()),
+            (Some(3), "Tracked by `dev 3`".to_string()),
+        ],
+        descriptions(&tracks)
+    );
+
+    let (tracks, _) = generator
+        .configure_tracks(&TrackOptions {
+            order: TrackOrder::StartTime,
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(
+        vec![
+            (Some(1), "Tracked by `dev 3`".to_string()),
+            (Some(2), "Tracked by `dev 2`".to_string()),
+            (Some(3), "Tracked by `dev 1`".to_string()),
+        ],
+        descriptions(&tracks)
+    );
+
+    let mut gpx = GpxGenerator::empty();
+    gpx.tracks = tracks;
+    let mut bdoc: Vec<u8> = Vec::new();
+    write_gpx(&gpx.generate()?, &mut bdoc)?;
+    let doc = String::from_utf8(bdoc).map_err(|e| e.to_string())?;
+    assert!(doc.contains("<number>3</number>"));
+    assert_eq!(0, check_gpx_structure(doc.as_bytes())?.len());
+
+    Ok(())
+}
+
 #[test]
 fn source2tracks() -> Result<(), String> {
     struct TestSource {}
     impl PositionsSource for TestSource {
-        fn fetch(
-            &mut self,
-            _start: OffsetDateTime,
-            _end: OffsetDateTime,
-        ) -> Result<Vec<DevicePosition>, String> {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
             let mut pos = vec![];
 
             pos.push(DevicePosition::basic(
@@ -179,11 +322,7 @@ fn source2tracks() -> Result<(), String> {
 fn source2tracks_with_rotes() -> Result<(), String> {
     struct TestSource {}
     impl PositionsSource for TestSource {
-        fn fetch(
-            &mut self,
-            _start: OffsetDateTime,
-            _end: OffsetDateTime,
-        ) -> Result<Vec<DevicePosition>, String> {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
             let mut pos = vec![];
 
             pos.push({
@@ -192,8 +331,8 @@ fn source2tracks_with_rotes() -> Result<(), String> {
                     Point::new(-48.8702222, -26.31832),
                     datetime!(2021-05-24 0:00 UTC),
                 );
-                p.route_name = Some("125".to_string());
-                p.tracker = Some("my app".to_string());
+                p.route_name = Some("125".into());
+                p.tracker = Some("my app".into());
                 p
             });
             pos.push({
@@ -202,8 +341,8 @@ fn source2tracks_with_rotes() -> Result<(), String> {
                     Point::new(-48.8702222, -23.31832),
                     datetime!(2021-05-24 0:00 UTC),
                 );
-                p.route_name = Some("125".to_string());
-                p.tracker = Some("my app".to_string());
+                p.route_name = Some("125".into());
+                p.tracker = Some("my app".into());
                 p
             });
             pos.push({
@@ -212,8 +351,8 @@ fn source2tracks_with_rotes() -> Result<(), String> {
                     Point::new(-48.8702222, -22.31832),
                     datetime!(2021-05-24 0:00 UTC),
                 );
-                p.route_name = Some("123".to_string());
-                p.tracker = Some("my app".to_string());
+                p.route_name = Some("123".into());
+                p.tracker = Some("my app".into());
                 p
             });
             pos.push({
@@ -222,8 +361,8 @@ fn source2tracks_with_rotes() -> Result<(), String> {
                     Point::new(-48.3702222, -26.31832),
                     datetime!(2021-05-24 0:00 UTC),
                 );
-                p.route_name = Some("125".to_string());
-                p.tracker = Some("my app".to_string());
+                p.route_name = Some("125".into());
+                p.tracker = Some("my app".into());
                 p
             });
 
@@ -267,6 +406,53 @@ fn source2tracks_with_rotes() -> Result<(), String> {
     Ok(())
 }
 
+#[test]
+fn source2tracks_with_types() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut pos = vec![];
+
+            pos.push(DevicePosition::basic(
+                "dev 1".to_string(),
+                Point::new(-48.8702222, -26.31832),
+                datetime!(2021-05-24 0:00 UTC),
+            ));
+            pos.push({
+                let mut p = DevicePosition::basic(
+                    "dev 2".to_string(),
+                    Point::new(-48.8619871, -26.3385861),
+                    datetime!(2021-05-24 0:02 UTC),
+                );
+                p.track_type = Some("running".to_string());
+                p
+            });
+
+            Ok(pos)
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            track_type: Some("cycling".to_string()),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(2, tracks.len());
+    assert_eq!(Some("cycling".to_string()), tracks[0]._type);
+    assert_eq!(Some("running".to_string()), tracks[1]._type);
+
+    let tracks = SourceToTracks::build(
+        TestSource {},
+        datetime!(2021-05-24 0:00 UTC),
+        datetime!(2022-05-24 0:00 UTC),
+        TrackSegmentOptions::default(),
+    )?;
+    assert_eq!(None, tracks[0]._type);
+
+    Ok(())
+}
+
 #[test]
 fn speed_and_elevation_info() -> Result<(), String> {
     let mut p1 = RawPosition::basic(
@@ -316,7 +502,7 @@ fn time_segmented_track() -> Result<(), String> {
         .iter()
         .map(|tm| RawPosition::basic(Point::new(-48.8702222, -26.31832), *tm))
         .collect();
-    let pos = raw.iter().map(|p| p).collect();
+    let pos = raw.iter();
     let track =
         Tracker::new("my dev 1".to_string(), "running in joinville".to_string()).build(pos)?;
     assert_eq!(5, track.segments.len());
@@ -379,9 +565,371 @@ fn time_segmented_track() -> Result<(), String> {
     Ok(())
 }
 
+#[test]
+fn segments_alignment() -> Result<(), String> {
+    let raw: Vec<RawPosition> = [4, 5, 6, 8, 9, 31, 34]
+        .iter()
+        .map(|minutes| {
+            RawPosition::basic(
+                Point::new(-48.8702222, -26.31832),
+                datetime!(2021-05-24 0:00 UTC) + Duration::minutes(*minutes),
+            )
+        })
+        .collect();
+
+    let lengths = |alignment: BucketAlignment| -> Result<Vec<usize>, String> {
+        let op = TrackSegmentOptions {
+            alignment,
+            ..Default::default()
+        };
+        let track = Tracker::new("my dev 1".to_string(), "running in joinville".to_string())
+            .configure_segments(&op)
+            .build(&raw)?;
+
+        Ok(track.segments.iter().map(|s| s.points.len()).collect())
+    };
+
+    assert_eq!(vec![1, 4, 2], lengths(BucketAlignment::Epoch)?);
+    assert_eq!(vec![4, 1, 1, 1], lengths(BucketAlignment::FirstPoint)?);
+    assert_eq!(vec![4, 1, 2], lengths(BucketAlignment::Sliding)?);
+
+    Ok(())
+}
+
+#[test]
+fn start_time_interleaved_trips() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let times = vec![
+                ("dev 1", datetime!(2021-05-24 8:00 UTC)),
+                ("dev 1", datetime!(2021-05-24 8:01 UTC)),
+                ("dev 2", datetime!(2021-05-24 10:00 UTC)),
+                ("dev 2", datetime!(2021-05-24 10:01 UTC)),
+                ("dev 1", datetime!(2021-05-24 12:00 UTC)),
+                ("dev 1", datetime!(2021-05-24 12:01 UTC)),
+            ];
+
+            Ok(times
+                .into_iter()
+                .map(|(dev, tm)| {
+                    DevicePosition::basic(dev.to_string(), Point::new(-48.8702222, -26.31832), tm)
+                })
+                .collect())
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            max_duration: Some(3600),
+            order: TrackOrder::StartTime,
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+
+    let tracks: Vec<(Option<u32>, Option<String>, Option<String>)> = tracks
+        .into_iter()
+        .map(|t| (t.number, t.name, t.description))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                Some(1),
+                Some("2021-05-24 08:00-08:01".to_string()),
+                Some("Tracked by `dev 1`".to_string())
+            ),
+            (
+                Some(2),
+                Some("2021-05-24".to_string()),
+                Some("Tracked by `dev 2`".to_string())
+            ),
+            (
+                Some(3),
+                Some("2021-05-24 12:00-12:01".to_string()),
+                Some("Tracked by `dev 1`".to_string())
+            ),
+        ],
+        tracks
+    );
+
+    Ok(())
+}
+
+#[test]
+fn split_segments_into_tracks() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let times = vec![
+                datetime!(2021-05-24 0:00 UTC),
+                datetime!(2021-05-24 0:01 UTC),
+                datetime!(2021-05-24 0:17 UTC),
+                datetime!(2021-05-24 1:21 UTC),
+            ];
+
+            Ok(times
+                .into_iter()
+                .map(|tm| {
+                    DevicePosition::basic(
+                        "dev 1".to_string(),
+                        Point::new(-48.8702222, -26.31832),
+                        tm,
+                    )
+                })
+                .collect())
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            split_segments: true,
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(3, tracks.len());
+    assert_eq!(Some("2021-05-24 #1".to_string()), tracks[0].name);
+    assert_eq!(Some(1), tracks[0].number);
+    assert_eq!(2, tracks[0].segments[0].points.len());
+    assert_eq!(Some("2021-05-24 #3".to_string()), tracks[2].name);
+    assert_eq!(Some(3), tracks[2].number);
+    assert_eq!(1, tracks[2].segments.len());
+    assert_eq!(
+        Some("Tracked by `dev 1`".to_string()),
+        tracks[2].description
+    );
+
+    Ok(())
+}
+
+#[test]
+fn route_direction_names() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let west = Point::new(-48.87, -26.31);
+            let east = Point::new(-48.80, -26.30);
+            let runs = vec![
+                ("bus 1", west, east, datetime!(2021-05-24 7:00 UTC)),
+                ("bus 2", east, west, datetime!(2021-05-24 6:00 UTC)),
+                ("bus 3", west, east, datetime!(2021-05-24 8:00 UTC)),
+            ];
+
+            let mut pos = vec![];
+            for (device, from, to, time) in runs {
+                for (point, time) in [(from, time), (to, time + Duration::minutes(30))] {
+                    let mut p = DevicePosition::basic(device.to_string(), point, time);
+                    p.route_name = Some("10".into());
+                    pos.push(p);
+                }
+            }
+
+            Ok(pos)
+        }
+    }
+
+    let names = |tracks: Vec<gpx::Track>| -> Vec<String> {
+        tracks
+            .into_iter()
+            .map(|t| t.name.unwrap_or_default())
+            .collect()
+    };
+
+    // The first run, of the `bus 2`, is the reference
+    let mut generator = SourceToTracks::new();
+    let (tracks, _) = generator
+        .configure_tracks(&TrackOptions {
+            direction: Some(DirectionOptions::default()),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(
+        vec!["10 inbound", "10 outbound", "10 inbound"],
+        names(tracks)
+    );
+
+    let mut direction = DirectionOptions::default();
+    direction.references.insert(
+        "10".to_string(),
+        vec![[-48.87, -26.31], [-48.83, -26.30], [-48.80, -26.30]],
+    );
+    let (tracks, _) = generator
+        .configure_tracks(&TrackOptions {
+            direction: Some(direction),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(
+        vec!["10 outbound", "10 inbound", "10 outbound"],
+        names(tracks)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reverse_route_runs() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let west = Point::new(-48.87, -26.31);
+            let east = Point::new(-48.80, -26.30);
+            let runs = vec![
+                ("bus 1", "10", west, east, datetime!(2021-05-24 7:00 UTC)),
+                ("bus 2", "10", east, west, datetime!(2021-05-24 8:00 UTC)),
+                ("bus 3", "20", east, west, datetime!(2021-05-24 9:00 UTC)),
+            ];
+
+            let mut pos = vec![];
+            for (device, route, from, to, time) in runs {
+                for (point, time) in [(from, time), (to, time + Duration::minutes(30))] {
+                    let mut p = DevicePosition::basic(device.to_string(), point, time);
+                    p.route_name = Some(route.into());
+                    pos.push(p);
+                }
+            }
+
+            Ok(pos)
+        }
+    }
+
+    let mut direction = DirectionOptions {
+        normalize: true,
+        ..Default::default()
+    };
+    direction.reverse.insert("20".to_string(), true);
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            direction: Some(direction),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+
+    let ends = |track: &gpx::Track| -> (f64, f64) {
+        let points: Vec<_> = track.segments.iter().flat_map(|s| &s.points).collect();
+        (points[0].point().x(), points[points.len() - 1].point().x())
+    };
+    assert_eq!(Some("10 outbound".to_string()), tracks[0].name);
+    assert_eq!((-48.87, -48.80), ends(&tracks[0]));
+    // Inbound run, normalized to the outbound direction
+    assert_eq!(Some("10 inbound".to_string()), tracks[1].name);
+    assert_eq!((-48.87, -48.80), ends(&tracks[1]));
+    // Recorded backwards, so it is its own outbound reference
+    assert_eq!(Some("20 outbound".to_string()), tracks[2].name);
+    assert_eq!((-48.87, -48.80), ends(&tracks[2]));
+
+    Ok(())
+}
+
+#[test]
+fn dedup_positions() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut pos = vec![];
+
+            // Same period exported twice
+            for _ in 0..2 {
+                for device in ["dev 1", "dev 2"] {
+                    pos.push(DevicePosition::basic(
+                        device.to_string(),
+                        Point::new(-48.8702222, -26.31832),
+                        datetime!(2021-05-24 0:00 UTC),
+                    ));
+                    pos.push(DevicePosition::basic(
+                        device.to_string(),
+                        Point::new(-48.8619776, -26.3185919),
+                        datetime!(2021-05-24 0:01 UTC),
+                    ));
+                }
+            }
+
+            Ok(pos)
+        }
+    }
+
+    let (tracks, report) = SourceToTracks::new().run(TestSource {}, TimeRange::all())?;
+    assert_eq!(4, tracks[0].segments[0].points.len());
+    assert_eq!(0, report.duplicates);
+
+    let (tracks, report) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            dedup: true,
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(2, tracks.len());
+    assert_eq!(2, tracks[0].segments[0].points.len());
+    assert_eq!(2, tracks[1].segments[0].points.len());
+    assert_eq!(4, report.duplicates);
+    assert_eq!(4, report.rejects.len());
+    assert!(report
+        .rejects
+        .iter()
+        .all(|reject| reject.stage == RejectStage::Dedup));
+
+    let quality = &report.quality["dev 1"];
+    assert_eq!(4, quality.positions);
+    assert_eq!(2, quality.emitted);
+    assert_eq!(0.5, quality.filtered_rate());
+
+    Ok(())
+}
+
+#[test]
+fn split_long_tracks() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let times = vec![
+                datetime!(2021-05-24 8:00 UTC),
+                datetime!(2021-05-24 11:59 UTC),
+                datetime!(2021-05-24 12:00 UTC),
+                datetime!(2021-05-24 15:30 UTC),
+                datetime!(2021-05-24 16:10 UTC),
+            ];
+
+            Ok(times
+                .into_iter()
+                .map(|tm| {
+                    DevicePosition::basic(
+                        "dev 1".to_string(),
+                        Point::new(-48.8702222, -26.31832),
+                        tm,
+                    )
+                })
+                .collect())
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            max_duration: Some(4 * 3600),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(3, tracks.len());
+    assert_eq!(Some("2021-05-24 08:00-11:59".to_string()), tracks[0].name);
+    assert_eq!(Some("2021-05-24 12:00-15:30".to_string()), tracks[1].name);
+    assert_eq!(Some("2021-05-24 16:10-16:10".to_string()), tracks[2].name);
+    assert_eq!(2, tracks[1].segments.len());
+    assert_eq!(Some(3), tracks[2].number);
+
+    // Short tracks keep their names
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            max_duration: Some(12 * 3600),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(1, tracks.len());
+    assert_eq!(Some("2021-05-24".to_string()), tracks[0].name);
+
+    Ok(())
+}
+
 #[test]
 fn simplify_track() -> Result<(), String> {
-    let locs = vec![
+    let locs = [
         Point::new(5.0, 2.0),
         Point::new(3.0, 8.0),
         Point::new(6.0, 20.0),
@@ -393,9 +941,11 @@ fn simplify_track() -> Result<(), String> {
         .iter()
         .map(|loc| RawPosition::basic(*loc, datetime!(2021-05-24 0:00 UTC)))
         .collect();
-    let pos = raw.iter().map(|p| p).collect();
-    let mut op = TrackSegmentOptions::default();
-    op.vw_tolerance = Some(30.0);
+    let pos = raw.iter();
+    let op = TrackSegmentOptions {
+        vw_tolerance: Some(30.0),
+        ..Default::default()
+    };
     let track = Tracker::new("my dev 1".to_string(), "running in joinville".to_string())
         .configure_segments(&op)
         .build(pos)?;
@@ -409,3 +959,1292 @@ fn simplify_track() -> Result<(), String> {
 
     Ok(())
 }
+
+#[test]
+fn sanity_limits() -> Result<(), String> {
+    let mut p1 = RawPosition::basic(
+        Point::new(-48.8702222, -26.31832),
+        datetime!(2021-05-24 0:00 UTC),
+    );
+    p1.speed = Some(9999.0);
+    p1.altitude = Some(-15000.0);
+    let mut p2 = RawPosition::basic(
+        Point::new(-48.8619776, -26.3185919),
+        datetime!(2021-05-24 0:02 UTC),
+    );
+    p2.speed = Some(7.0);
+    p2.altitude = Some(50.0);
+
+    let mut op = TrackSegmentOptions {
+        sanity: SanityLimits {
+            max_speed: Some(83.3),
+            min_elevation: Some(-500.0),
+            max_elevation: Some(9000.0),
+            action: SanityAction::Drop,
+        },
+        ..Default::default()
+    };
+
+    let mut report = Report::default();
+    let track = Tracker::new("my dev 1".to_string(), "running in joinville".to_string())
+        .configure_segments(&op)
+        .build_with_report(vec![&p1, &p2], &mut report)?;
+
+    let segment = &track.segments[0];
+    assert_eq!(2, segment.points.len());
+    assert_eq!(None, segment.points[0].speed);
+    assert_eq!(None, segment.points[0].elevation);
+    assert_eq!(Some(7.0), segment.points[1].speed);
+    assert_eq!(Some(50.0), segment.points[1].elevation);
+    assert_eq!(2, report.entries.len());
+    assert_eq!("my dev 1", report.entries[0].device);
+    assert_eq!(p1.time, report.entries[0].time);
+
+    op.sanity.action = SanityAction::Clamp;
+
+    let mut report = Report::default();
+    let track = Tracker::new("my dev 1".to_string(), "running in joinville".to_string())
+        .configure_segments(&op)
+        .build_with_report(vec![&p1, &p2], &mut report)?;
+
+    let segment = &track.segments[0];
+    assert_eq!(Some(83.3), segment.points[0].speed);
+    assert_eq!(Some(-500.0), segment.points[0].elevation);
+    assert_eq!(2, report.entries.len());
+
+    Ok(())
+}
+
+#[test]
+fn custom_point_filters() -> Result<(), String> {
+    use std::sync::Arc;
+
+    /// Drops the points with few satellites and rounds the speeds
+    struct Satellites(u64);
+    impl PointFilter for Satellites {
+        fn keep(&self, _prev: Option<&TrackPoint>, current: &TrackPoint) -> bool {
+            current.satellites.is_none_or(|sat| sat >= self.0)
+        }
+
+        fn reason(&self, _prev: Option<&TrackPoint>, _current: &TrackPoint) -> String {
+            "Few satellites".to_string()
+        }
+
+        fn transform(&self, point: &mut TrackPoint) -> Vec<String> {
+            point.speed = point.speed.map(f64::round);
+            vec![]
+        }
+    }
+
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut pos = vec![];
+            for (minutes, sat) in [(0, 9), (1, 2), (2, 7)] {
+                let mut p = DevicePosition::basic(
+                    "dev 1".to_string(),
+                    Point::new(-48.8702222, -26.31832),
+                    datetime!(2021-05-24 0:00 UTC) + Duration::minutes(minutes),
+                );
+                p.pos.satellites = Some(sat);
+                p.pos.speed = Some(5.4);
+                pos.push(p);
+            }
+
+            Ok(pos)
+        }
+    }
+
+    let (tracks, report) = SourceToTracks::new()
+        .filter(Arc::new(Satellites(4)))
+        .run(TestSource {}, TimeRange::all())?;
+
+    let segment = &tracks[0].segments[0];
+    assert_eq!(2, segment.points.len());
+    assert_eq!(Some(9), segment.points[0].sat);
+    assert_eq!(Some(5.0), segment.points[0].speed);
+    assert_eq!(Some(7), segment.points[1].sat);
+    assert_eq!(1, report.entries.len());
+    assert_eq!("Few satellites", report.entries[0].message);
+    assert_eq!(RejectStage::Filter, report.rejects[0].stage);
+    assert_eq!("Few satellites", report.rejects[0].reason);
+
+    Ok(())
+}
+
+#[test]
+fn sentinel_values() -> Result<(), String> {
+    let mut p1 = RawPosition::basic(Point::new(0.0, 0.0), datetime!(2021-05-24 0:00 UTC));
+    p1.speed = Some(10.0);
+    let mut p2 = RawPosition::basic(
+        Point::new(-48.8619776, -26.3185919),
+        datetime!(2021-05-24 0:02 UTC),
+    );
+    p2.speed = Some(-1.0);
+    p2.altitude = Some(9999.0);
+    let mut p3 = RawPosition::basic(
+        Point::new(-48.8619871, -26.3185861),
+        datetime!(2021-05-24 0:04 UTC),
+    );
+    p3.speed = Some(7.0);
+    p3.altitude = Some(50.0);
+
+    let op = TrackSegmentOptions {
+        sentinels: Sentinels {
+            coordinates: vec![[0.0, 0.0]],
+            speed: vec![-1.0],
+            elevation: vec![9999.0],
+        },
+        ..Default::default()
+    };
+
+    let mut report = Report::default();
+    let track = Tracker::new("my dev 1".to_string(), "running in joinville".to_string())
+        .configure_segments(&op)
+        .build_with_report(vec![&p1, &p2, &p3], &mut report)?;
+
+    let segment = &track.segments[0];
+    assert_eq!(2, segment.points.len());
+    assert_eq!(p2.coordinates, segment.points[0].point());
+    assert_eq!(None, segment.points[0].speed);
+    assert_eq!(None, segment.points[0].elevation);
+    assert_eq!(Some(7.0), segment.points[1].speed);
+    assert_eq!(Some(50.0), segment.points[1].elevation);
+    assert_eq!(1, report.entries.len());
+    assert_eq!(p1.time, report.entries[0].time);
+    assert_eq!(1, report.rejects.len());
+    assert_eq!(RejectStage::Sentinel, report.rejects[0].stage);
+    assert_eq!(p1.coordinates, report.rejects[0].coordinates);
+
+    Ok(())
+}
+
+#[test]
+fn gpx_schema_validation() -> Result<(), String> {
+    let edoc = fs::read_to_string("samples/simple.gpx").map_err(|e| e.to_string())?;
+
+    let violations = check_gpx_structure(edoc.as_bytes())?;
+    assert_eq!(0, violations.len());
+
+    let doc = r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1" creator="location2gpx">
+  <trk>
+    <desc>Tracked by `my dev 1`</desc>
+    <name>running in joinville</name>
+    <trkseg>
+      <trkpt lat="-26.31832" lon="-248.8702222">
+        <time>2021-05-24T00:00:00Z</time>
+        <speed>7</speed>
+      </trkpt>
+      <trkpt lat="-26.3185919">
+        <ele>NaN</ele>
+        <extensions><speed>7</speed></extensions>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+    let violations = check_gpx_structure(doc.as_bytes())?;
+    assert_eq!(
+        vec![
+            "line 5: element `name` out of order in `trk`",
+            "line 7: invalid value `-248.8702222` for attribute `lon` of `trkpt`",
+            "line 9: element `speed` not allowed in `trkpt`",
+            "line 11: attribute `lon` required in `trkpt`",
+            "line 12: invalid value `NaN` for `ele`",
+        ],
+        violations
+    );
+
+    Ok(())
+}
+
+#[test]
+fn speed_outputs() -> Result<(), String> {
+    let mut p1 = RawPosition::basic(
+        Point::new(-48.8702222, -26.31832),
+        datetime!(2021-05-24 0:00 UTC),
+    );
+    p1.speed = Some(5.0);
+    let p2 = RawPosition::basic(
+        Point::new(-48.8619776, -26.3185919),
+        datetime!(2021-05-24 0:02 UTC),
+    );
+
+    let track = Tracker::new("my dev 1".to_string(), "running in joinville".to_string())
+        .build(vec![&p1, &p2])?;
+
+    let write = |output: OutputOptions| -> Result<String, String> {
+        let mut gpx = GpxGenerator::empty();
+        gpx.tracks.push(track.clone());
+        gpx.output = output;
+
+        let mut bdoc: Vec<u8> = Vec::new();
+        gpx.write(&mut bdoc)?;
+        String::from_utf8(bdoc).map_err(|e| e.to_string())
+    };
+
+    let doc = write(OutputOptions::default())?;
+    assert!(!doc.contains("speed"));
+
+    let doc = write(OutputOptions {
+        speed: vec![SpeedOutput::TrackPointExtension, SpeedOutput::Custom],
+        speed_unit: SpeedUnit::KilometersPerHour,
+        ..Default::default()
+    })?;
+    assert!(
+        doc.contains(r#"xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v2""#)
+    );
+    assert!(doc.contains("<gpxtpx:speed>5</gpxtpx:speed>"));
+    assert!(doc.contains(r#"<l2g:speed unit="km/h">18</l2g:speed>"#));
+    assert_eq!(1, doc.matches("<extensions>").count());
+    assert_eq!(0, check_gpx_structure(doc.as_bytes())?.len());
+
+    let doc = write(OutputOptions {
+        speed: vec![SpeedOutput::Element],
+        ..Default::default()
+    })?;
+    assert!(doc.contains("<speed>5</speed>"));
+    assert!(!doc.contains("<extensions>"));
+    assert_eq!(1, check_gpx_structure(doc.as_bytes())?.len());
+
+    Ok(())
+}
+
+#[test]
+fn reprocess_speeds() -> Result<(), String> {
+    use crate::sources::GpxSource;
+
+    let mut p1 = RawPosition::basic(
+        Point::new(-48.8702222, -26.31832),
+        datetime!(2021-05-24 0:00 UTC),
+    );
+    p1.speed = Some(5.0);
+    let p2 = RawPosition::basic(
+        Point::new(-48.8619776, -26.3185919),
+        datetime!(2021-05-24 0:02 UTC),
+    );
+
+    let track = Tracker::new("my dev 1".to_string(), "running in joinville".to_string())
+        .build(vec![&p1, &p2])?;
+
+    for (speed, speed_unit) in [
+        (SpeedOutput::Element, SpeedUnit::MetersPerSecond),
+        (SpeedOutput::TrackPointExtension, SpeedUnit::MetersPerSecond),
+        (SpeedOutput::Custom, SpeedUnit::KilometersPerHour),
+        (SpeedOutput::Custom, SpeedUnit::Knots),
+    ] {
+        let mut gpx = GpxGenerator::empty();
+        gpx.tracks.push(track.clone());
+        gpx.output = OutputOptions {
+            speed: vec![speed],
+            speed_unit,
+            ..Default::default()
+        };
+        let mut bdoc: Vec<u8> = Vec::new();
+        gpx.write(&mut bdoc)?;
+
+        let positions = GpxSource::new(&bdoc[..]).fetch(TimeRange::all())?;
+        assert_eq!(2, positions.len());
+        let speed = positions[0].pos.speed.ok_or("Speed not read")?;
+        assert!((speed - 5.0).abs() < 1e-9, "{:?}: {}", speed_unit, speed);
+        assert_eq!(None, positions[1].pos.speed);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn event_symbols() -> Result<(), String> {
+    let p1 = RawPosition::basic(
+        Point::new(-48.8702222, -26.31832),
+        datetime!(2021-05-24 0:00 UTC),
+    );
+    let mut p2 = RawPosition::basic(
+        Point::new(-48.8619776, -26.3185919),
+        datetime!(2021-05-24 0:02 UTC),
+    );
+    p2.event = Some("fuel".to_string());
+
+    let track = Tracker::new("my dev 1".to_string(), "fleet".to_string()).build(vec![&p1, &p2])?;
+
+    let mut gpx = GpxGenerator::empty();
+    gpx.tracks.push(track);
+    gpx.output = OutputOptions {
+        waypoints: true,
+        symbols: [("fuel".to_string(), "Gas Station".to_string())].into(),
+        ..Default::default()
+    };
+
+    let doc = gpx.generate()?;
+    assert_eq!(1, doc.waypoints.len());
+    assert_eq!(Some("Gas Station".to_string()), doc.waypoints[0].symbol);
+    let points = &doc.tracks[0].segments[0].points;
+    assert_eq!(None, points[0].symbol);
+    assert_eq!(Some("Gas Station".to_string()), points[1].symbol);
+
+    let mut bdoc: Vec<u8> = Vec::new();
+    write_gpx(&doc, &mut bdoc)?;
+    let text = String::from_utf8(bdoc).map_err(|e| e.to_string())?;
+    assert_eq!(2, text.matches("<sym>Gas Station</sym>").count());
+    assert_eq!(0, check_gpx_structure(text.as_bytes())?.len());
+
+    Ok(())
+}
+
+#[test]
+fn sub_second_times() -> Result<(), String> {
+    let raw: Vec<RawPosition> = (0..3)
+        .map(|i| {
+            RawPosition::basic(
+                Point::new(-48.8702222 + f64::from(i) * 0.00001, -26.31832),
+                datetime!(2021-05-24 0:00 UTC) + Duration::milliseconds(i64::from(i) * 100),
+            )
+        })
+        .collect();
+
+    let track =
+        Tracker::new("my dev 1".to_string(), "running in joinville".to_string()).build(&raw)?;
+    assert_eq!(3, track.segments[0].points.len());
+
+    let write = |time_precision: TimePrecision| -> Result<String, String> {
+        let mut gpx = GpxGenerator::empty();
+        gpx.tracks.push(track.clone());
+        gpx.output.time_precision = time_precision;
+
+        let mut bdoc: Vec<u8> = Vec::new();
+        gpx.write(&mut bdoc)?;
+        String::from_utf8(bdoc).map_err(|e| e.to_string())
+    };
+
+    let doc = write(TimePrecision::Auto)?;
+    assert!(doc.contains("<time>2021-05-24T00:00:00Z</time>"));
+    assert!(doc.contains("<time>2021-05-24T00:00:00.100Z</time>"));
+    assert!(doc.contains("<time>2021-05-24T00:00:00.200Z</time>"));
+    assert_eq!(0, check_gpx_structure(doc.as_bytes())?.len());
+
+    let doc = write(TimePrecision::Milliseconds)?;
+    assert!(doc.contains("<time>2021-05-24T00:00:00.000Z</time>"));
+
+    let doc = write(TimePrecision::Seconds)?;
+    assert_eq!(3, doc.matches("<time>2021-05-24T00:00:00Z</time>").count());
+
+    Ok(())
+}
+
+#[test]
+fn split_by_calendar_period() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let times = vec![
+                datetime!(2021-05-31 23:58 UTC),
+                datetime!(2021-05-31 23:59 UTC),
+                datetime!(2021-06-01 0:00 UTC),
+                datetime!(2021-06-01 0:01 UTC),
+            ];
+
+            Ok(times
+                .into_iter()
+                .map(|tm| {
+                    let mut p = DevicePosition::basic(
+                        "dev 1".to_string(),
+                        Point::new(-48.8702222, -26.31832),
+                        tm,
+                    );
+                    p.route_name = Some("10".into());
+                    p
+                })
+                .collect())
+        }
+    }
+
+    // The route continues on the next day
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            continuous_routes: true,
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(1, tracks.len());
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            continuous_routes: true,
+            split_by: Some(CalendarPeriod::Month),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(2, tracks.len());
+    assert_eq!(Some("10".to_string()), tracks[1].name);
+
+    let documents = group_by_period(tracks, CalendarPeriod::Month)?;
+    assert_eq!(
+        vec!["2021-05", "2021-06"],
+        documents.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(Some(1), documents["2021-06"][0].number);
+
+    Ok(())
+}
+
+#[test]
+fn route_aliases_names() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut pos = vec![];
+
+            for route in ["125", "126"] {
+                let mut p = DevicePosition::basic(
+                    "dev 1".to_string(),
+                    Point::new(-48.8702222, -26.31832),
+                    datetime!(2021-05-24 0:00 UTC),
+                );
+                p.route_name = Some(route.into());
+                pos.push(p);
+            }
+
+            Ok(pos)
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            route_aliases: [("125".to_string(), "Downtown – Harbor Express".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(
+        Some("Downtown – Harbor Express".to_string()),
+        tracks[0].name
+    );
+    assert_eq!(Some("126".to_string()), tracks[1].name);
+
+    Ok(())
+}
+
+#[test]
+fn device_descriptions() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut p1 = DevicePosition::basic(
+                "dev 1".to_string(),
+                Point::new(-48.8702222, -26.31832),
+                datetime!(2021-05-24 0:00 UTC),
+            );
+            p1.vehicle_plate = Some("MKZ-2041".to_string());
+            let mut p2 = p1.clone();
+            p2.device_id = "dev 2".into();
+            p2.device_name = Some("Van 3".to_string());
+            p2.vehicle_plate = None;
+
+            Ok(vec![p1, p2])
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new().run(TestSource {}, TimeRange::all())?;
+    assert_eq!(
+        Some("Tracked by `dev 1`, plate MKZ-2041".to_string()),
+        tracks[0].description
+    );
+    assert_eq!(
+        Some("Tracked by `Van 3`".to_string()),
+        tracks[1].description
+    );
+
+    Ok(())
+}
+
+#[test]
+fn creator_and_sources() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut p1 = DevicePosition::basic(
+                "dev 1".to_string(),
+                Point::new(-48.8702222, -26.31832),
+                datetime!(2021-05-24 0:00 UTC),
+            );
+            p1.tracker = Some("FMB920 03.27".into());
+            let mut p2 = p1.clone();
+            p2.device_id = "dev 2".into();
+            p2.tracker = None;
+
+            Ok(vec![p1, p2])
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new().run(TestSource {}, TimeRange::all())?;
+    assert_eq!(Some("FMB920 03.27".to_string()), tracks[0].source);
+    assert_eq!(None, tracks[1].source);
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            source: Some("{tracker} via location2gpx {version}".to_string()),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    let version = env!("CARGO_PKG_VERSION");
+    assert_eq!(
+        Some(format!("FMB920 03.27 via location2gpx {}", version)),
+        tracks[0].source
+    );
+    assert_eq!(
+        Some(format!("unknown tracker via location2gpx {}", version)),
+        tracks[1].source
+    );
+
+    let mut gpx = GpxGenerator::empty();
+    gpx.tracks = tracks;
+    gpx.output.creator = Some("location2gpx {version}".to_string());
+    let mut bdoc: Vec<u8> = Vec::new();
+    gpx.write(&mut bdoc)?;
+    let doc = String::from_utf8(bdoc).map_err(|e| e.to_string())?;
+    assert!(doc.contains(&format!(r#"creator="location2gpx {}""#, version)));
+    assert!(doc.contains("<src>FMB920 03.27 via location2gpx"));
+
+    Ok(())
+}
+
+#[test]
+fn run_manifest() -> Result<(), String> {
+    let p1 = RawPosition::basic(
+        Point::new(-48.8702222, -26.31832),
+        datetime!(2021-05-24 0:00 UTC),
+    );
+    let segments = TrackSegmentOptions {
+        simplify_tolerance_m: Some(5.0),
+        ..Default::default()
+    };
+    let track = Tracker::new("dev 1".to_string(), "route 1".to_string()).build(vec![&p1])?;
+
+    let range = TimeRange::between(
+        datetime!(2021-05-24 0:00 UTC),
+        datetime!(2021-05-25 0:00 UTC),
+    );
+    let mut manifest = Manifest::new("csv positions.csv", &range)?;
+    manifest.settings(&segments)?;
+
+    let write = |enabled: bool| -> Result<String, String> {
+        let mut gpx = GpxGenerator::empty();
+        gpx.tracks = vec![track.clone()];
+        gpx.output.manifest = enabled;
+        gpx.manifest = Some(manifest.clone());
+        let mut bdoc: Vec<u8> = Vec::new();
+        gpx.write(&mut bdoc)?;
+        String::from_utf8(bdoc).map_err(|e| e.to_string())
+    };
+
+    let doc = write(false)?;
+    assert!(!doc.contains("<metadata>"));
+
+    let doc = write(true)?;
+    assert!(doc.contains("<metadata>"));
+    assert!(doc.contains("<l2g:manifest>"));
+    assert!(doc.contains(&format!("version: {}", env!("CARGO_PKG_VERSION"))));
+    assert!(doc.contains("source: csv positions.csv"));
+    assert!(doc.contains("since: 2021-05-24T00:00:00Z"));
+    assert!(doc.contains("simplify_tolerance_m: 5.0"));
+    assert!(check_gpx_structure(doc.as_bytes())?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn canonical_output() -> Result<(), String> {
+    struct TestSource {
+        reversed: bool,
+    }
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut positions = vec![
+                DevicePosition::basic(
+                    "dev 1".to_string(),
+                    Point::new(-48.87022221234, -26.31832),
+                    datetime!(2021-05-24 0:00 UTC),
+                ),
+                DevicePosition::basic(
+                    "dev 1".to_string(),
+                    Point::new(-48.8619776, -26.3185919),
+                    datetime!(2021-05-24 0:00 UTC),
+                ),
+                DevicePosition::basic(
+                    "dev 2".to_string(),
+                    Point::new(-48.8619776, -26.3185919),
+                    datetime!(2021-05-24 0:01 UTC),
+                ),
+            ];
+            if self.reversed {
+                positions.reverse();
+            }
+
+            Ok(positions)
+        }
+    }
+
+    let write = |reversed: bool| -> Result<String, String> {
+        let (tracks, _) = SourceToTracks::new().run(TestSource { reversed }, TimeRange::all())?;
+        let mut gpx = GpxGenerator::empty();
+        gpx.tracks = tracks;
+        gpx.output.canonical = true;
+        let mut bdoc: Vec<u8> = Vec::new();
+        gpx.write(&mut bdoc)?;
+        String::from_utf8(bdoc).map_err(|e| e.to_string())
+    };
+
+    let doc = write(false)?;
+    assert_eq!(doc, write(true)?);
+    assert!(doc.contains(r#"lat="-26.3183200" lon="-48.8702222""#));
+    assert!(doc.contains(r#"lat="-26.3185919" lon="-48.8619776""#));
+
+    Ok(())
+}
+
+#[test]
+fn coordinate_precision() -> Result<(), String> {
+    let mut output = OutputOptions {
+        coordinate_precision: Some(5),
+        ..Default::default()
+    };
+    assert_eq!("-48.87022", output.coordinate(-48.87022221234));
+    assert_eq!("-26.3", output.coordinate(-26.3000012));
+    assert_eq!("10", output.coordinate(10.0));
+    assert_eq!("0", output.coordinate(-0.000001));
+
+    output.canonical = true;
+    assert_eq!("-26.30000", output.coordinate(-26.3000012));
+    assert_eq!("0.00000", output.coordinate(-0.000001));
+
+    output.coordinate_precision = None;
+    assert_eq!("-26.3000012", output.coordinate(-26.3000012));
+    assert_eq!("0.0000000", output.coordinate(-0.00000001));
+    assert_eq!("0.0000000", output.coordinate(-0.0));
+
+    output.canonical = false;
+    assert_eq!("0", output.coordinate(-0.0));
+
+    let p1 = RawPosition::basic(
+        Point::new(-48.87022221234, -26.31832),
+        datetime!(2021-05-24 0:00 UTC),
+    );
+    let mut gpx = GpxGenerator::empty();
+    gpx.tracks = vec![Tracker::new("dev 1".to_string(), "route 1".to_string()).build(vec![&p1])?];
+    gpx.output.coordinate_precision = Some(4);
+    let mut bdoc: Vec<u8> = Vec::new();
+    gpx.write(&mut bdoc)?;
+    let doc = String::from_utf8(bdoc).map_err(|e| e.to_string())?;
+    assert!(doc.contains(r#"lat="-26.3183" lon="-48.8702""#));
+
+    Ok(())
+}
+
+#[test]
+fn tracks_bounds() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            Ok(vec![
+                DevicePosition::basic(
+                    "dev 1".to_string(),
+                    Point::new(-48.8702222, -26.31832),
+                    datetime!(2021-05-24 0:00 UTC),
+                ),
+                DevicePosition::basic(
+                    "dev 2".to_string(),
+                    Point::new(-48.8619776, -26.3185919),
+                    datetime!(2021-05-24 0:01 UTC),
+                ),
+            ])
+        }
+    }
+
+    let (tracks, mut report) = SourceToTracks::new().run(TestSource {}, TimeRange::all())?;
+    let bounds = report.bounds.ok_or("Bounds not found")?;
+    assert_eq!(Point::new(-48.8702222, -26.3185919), bounds.min().into());
+    assert_eq!(Point::new(-48.8619776, -26.31832), bounds.max().into());
+
+    let mut gpx = GpxGenerator::empty();
+    gpx.tracks = tracks;
+    assert_eq!(Some(bounds), gpx.bounds());
+    assert_eq!(None, GpxGenerator::empty().bounds());
+
+    let other = Report {
+        bounds: Some(geo::Rect::new((-49.0, -26.0), (-48.9, -25.9))),
+        ..Default::default()
+    };
+    report.merge(other);
+    let merged = report.bounds.ok_or("Bounds not found")?;
+    assert_eq!(Point::new(-49.0, -26.3185919), merged.min().into());
+    assert_eq!(Point::new(-48.8619776, -25.9), merged.max().into());
+
+    Ok(())
+}
+
+#[test]
+fn pipeline_order() -> Result<(), String> {
+    // A nearly straight line, crossing two segments time slots
+    let raw: Vec<RawPosition> = (0..10)
+        .map(|i| {
+            let offset = if i % 2 == 0 { 0.0 } else { 0.00001 };
+            RawPosition::basic(
+                Point::new(-48.87 + f64::from(i) * 0.001, -26.31 + offset),
+                datetime!(2021-05-24 0:03 UTC) + Duration::minutes(i.into()),
+            )
+        })
+        .collect();
+
+    let build = |pipeline: Vec<PipelineStage>| -> Result<Vec<usize>, String> {
+        let op = TrackSegmentOptions {
+            simplify_tolerance_m: Some(20.0),
+            pipeline,
+            ..Default::default()
+        };
+        let track = Tracker::new("my dev 1".to_string(), "route 1".to_string())
+            .configure_segments(&op)
+            .build(&raw)?;
+
+        Ok(track.segments.iter().map(|s| s.points.len()).collect())
+    };
+
+    // Each segment keeps its ends
+    assert_eq!(
+        vec![2, 2, 2],
+        build(TrackSegmentOptions::default().pipeline)?
+    );
+
+    // Only the ends of the track are left to the segments
+    assert_eq!(
+        vec![1, 1],
+        build(vec![
+            PipelineStage::Filter,
+            PipelineStage::Smooth,
+            PipelineStage::Simplify,
+            PipelineStage::Segment,
+        ])?
+    );
+
+    assert_eq!(
+        Err("The pipeline needs the `segment` stage once, found 0 times".to_string()),
+        build(vec![
+            PipelineStage::Filter,
+            PipelineStage::Smooth,
+            PipelineStage::Simplify,
+        ])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn routes_by_day() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let pos = [
+                datetime!(2021-05-24 23:50 UTC),
+                datetime!(2021-05-24 23:58 UTC),
+                datetime!(2021-05-25 0:05 UTC),
+            ]
+            .into_iter()
+            .map(|time| {
+                let mut p = DevicePosition::basic(
+                    "dev 1".to_string(),
+                    Point::new(-48.8702222, -26.31832),
+                    time,
+                );
+                p.route_name = Some("125".into());
+                p
+            })
+            .collect();
+
+            Ok(pos)
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            continuous_routes: true,
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+    assert_eq!(1, tracks.len());
+    assert_eq!(Some("125".to_string()), tracks[0].name);
+
+    let (tracks, _) = SourceToTracks::new().run(TestSource {}, TimeRange::all())?;
+    assert_eq!(2, tracks.len());
+    assert_eq!(Some("125 2021-05-24".to_string()), tracks[0].name);
+    assert_eq!(Some("125 2021-05-25".to_string()), tracks[1].name);
+    let points: usize = tracks[0].segments.iter().map(|s| s.points.len()).sum();
+    assert_eq!(2, points);
+
+    Ok(())
+}
+
+#[test]
+fn run_preview() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            let mut pos = vec![];
+            for (device, route, minutes) in [
+                ("dev 1", "A", 0),
+                ("dev 1", "A", 0),
+                ("dev 1", "A", 7),
+                ("dev 1", "B", 20),
+                ("dev 2", "A", 3),
+                ("dev 2", "A", 6),
+            ] {
+                let mut p = DevicePosition::basic(
+                    device.to_string(),
+                    Point::new(-48.8702222, -26.31832),
+                    datetime!(2021-05-24 0:00 UTC) + Duration::minutes(minutes),
+                );
+                p.route_name = Some(route.into());
+                pos.push(p);
+            }
+
+            Ok(pos)
+        }
+    }
+
+    let preview = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            dedup: true,
+            ..Default::default()
+        })
+        .preview(TestSource {}, TimeRange::all())?;
+    assert_eq!(
+        Preview {
+            positions: 6,
+            duplicates: 1,
+            devices: 2,
+            routes: 3,
+            tracks: 3,
+            segments: 5,
+        },
+        preview
+    );
+
+    // Same segments of the export, with the alignment of the slots
+    let mut generator = SourceToTracks::new();
+    generator.configure_segments(&TrackSegmentOptions {
+        alignment: BucketAlignment::FirstPoint,
+        ..Default::default()
+    });
+    let preview = generator.preview(TestSource {}, TimeRange::all())?;
+    let (tracks, _) = generator.run(TestSource {}, TimeRange::all())?;
+    assert_eq!(4, preview.segments);
+    assert_eq!(
+        preview.segments,
+        tracks.iter().map(|t| t.segments.len()).sum::<usize>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn min_positions() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            Ok(vec![DevicePosition::basic(
+                "dev 1".to_string(),
+                Point::new(-48.8702222, -26.31832),
+                datetime!(2021-05-24 0:00 UTC),
+            )])
+        }
+    }
+
+    let mut generator = SourceToTracks::new();
+    generator.configure_tracks(&TrackOptions {
+        min_positions: Some(1),
+        ..Default::default()
+    });
+    assert_eq!(1, generator.run(TestSource {}, TimeRange::all())?.0.len());
+
+    generator.configure_tracks(&TrackOptions {
+        min_positions: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(
+        Some("The source returned 1 positions, less than the minimum of 2".to_string()),
+        generator.run(TestSource {}, TimeRange::all()).err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn spilled_groups() -> Result<(), String> {
+    use super::spill::SpillOptions;
+    use crate::sources::{SyntheticOptions, SyntheticSource};
+
+    let options = SyntheticOptions {
+        devices: 4,
+        points: 50,
+        ..Default::default()
+    };
+    let confs = TrackOptions {
+        order: TrackOrder::StartTime,
+        ..Default::default()
+    };
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&confs)
+        .run(SyntheticSource::new(options.clone()), TimeRange::all())?;
+
+    let (spilled, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            spill: Some(SpillOptions {
+                max_positions: 7,
+                ..Default::default()
+            }),
+            ..confs
+        })
+        .run(SyntheticSource::new(options), TimeRange::all())?;
+
+    assert_eq!(4, spilled.len());
+    assert_eq!(tracks, spilled);
+
+    Ok(())
+}
+
+#[test]
+fn spilled_fetch() -> Result<(), String> {
+    use super::spill::SpillOptions;
+
+    struct TestSource {
+        batches: Vec<Vec<DevicePosition>>,
+        whole: bool,
+    }
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            match self.whole {
+                true => Ok(self.batches.concat()),
+                false => Err("Fetched all the positions at once".to_string()),
+            }
+        }
+
+        fn fetch_batches(
+            &mut self,
+            _range: TimeRange,
+            each: &mut dyn FnMut(Vec<DevicePosition>) -> Result<(), String>,
+        ) -> Result<(), String> {
+            for batch in self.batches.drain(..) {
+                each(batch)?;
+            }
+            Ok(())
+        }
+    }
+
+    let position = |device: &str, minutes: i64| {
+        DevicePosition::basic(
+            device.to_string(),
+            Point::new(-48.87 + minutes as f64 * 0.001, -26.31),
+            datetime!(2021-05-24 8:00 UTC) + Duration::minutes(minutes),
+        )
+    };
+    // Gaps inside and between the batches, with a duplicate
+    let batches = vec![
+        vec![
+            position("dev 1", 180),
+            position("dev 2", 0),
+            position("dev 1", 0),
+        ],
+        vec![
+            position("dev 1", 5),
+            position("dev 2", 90),
+            position("dev 1", 5),
+        ],
+        vec![
+            position("dev 2", 95),
+            position("dev 1", 10),
+            position("dev 1", 185),
+        ],
+    ];
+    let range = TimeRange::between(
+        datetime!(2021-05-24 6:00 UTC),
+        datetime!(2021-05-24 13:00 UTC),
+    );
+    let confs = TrackOptions {
+        dedup: true,
+        gap_threshold: Some(3600),
+        ..Default::default()
+    };
+
+    let (tracks, report) = SourceToTracks::new().configure_tracks(&confs).run(
+        TestSource {
+            batches: batches.clone(),
+            whole: true,
+        },
+        range,
+    )?;
+
+    let (spilled, spilled_report) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            spill: Some(SpillOptions {
+                max_positions: 2,
+                ..Default::default()
+            }),
+            ..confs
+        })
+        .run(
+            TestSource {
+                batches,
+                whole: false,
+            },
+            range,
+        )?;
+
+    assert_eq!(2, spilled.len());
+    assert_eq!(tracks, spilled);
+    assert_eq!(1, spilled_report.duplicates);
+    assert_eq!(report.quality, spilled_report.quality);
+    assert_eq!(report.entries, spilled_report.entries);
+    assert_eq!(3, spilled_report.quality["dev 1"].gaps.len());
+
+    Ok(())
+}
+
+#[test]
+fn time_pushdown() -> Result<(), String> {
+    struct TestSource {
+        pushdown: bool,
+    }
+    impl PositionsSource for TestSource {
+        // The period is ignored
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            Ok([0, 1, 2, 3]
+                .into_iter()
+                .map(|hour| {
+                    DevicePosition::basic(
+                        "dev 1".to_string(),
+                        Point::new(-48.8702222, -26.31832),
+                        datetime!(2021-05-24 0:00 UTC) + Duration::hours(hour),
+                    )
+                })
+                .collect())
+        }
+
+        fn supports_time_pushdown(&self) -> bool {
+            self.pushdown
+        }
+    }
+
+    let range = TimeRange::between(
+        datetime!(2021-05-24 1:00 UTC),
+        datetime!(2021-05-24 3:00 UTC),
+    );
+
+    let (tracks, _) = SourceToTracks::new().run(TestSource { pushdown: false }, range)?;
+    let points: Vec<_> = tracks[0].segments.iter().flat_map(|s| &s.points).collect();
+    assert_eq!(2, points.len());
+    assert_eq!(Some(datetime!(2021-05-24 1:00 UTC).into()), points[0].time);
+    assert_eq!(Some(datetime!(2021-05-24 2:00 UTC).into()), points[1].time);
+
+    // Trusted, without filtering again
+    let (tracks, _) = SourceToTracks::new().run(TestSource { pushdown: true }, range)?;
+    let points: usize = tracks[0].segments.iter().map(|s| s.points.len()).sum();
+    assert_eq!(4, points);
+
+    Ok(())
+}
+
+#[test]
+fn position_transforms() -> Result<(), String> {
+    use super::transform::PositionTransform;
+    use std::sync::Arc;
+
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            Ok((0..3)
+                .map(|i| {
+                    let mut dpos = DevicePosition::basic(
+                        format!("imei-35{}", i % 2),
+                        Point::new(-48.87, -26.31),
+                        datetime!(2021-05-24 0:00 UTC) + Duration::minutes(i),
+                    );
+                    // In km/h
+                    dpos.pos.speed = Some(36.0);
+                    dpos
+                })
+                .collect())
+        }
+    }
+
+    struct DatumShift(f64);
+    impl PositionTransform for DatumShift {
+        fn transform(&self, dpos: &mut DevicePosition) {
+            let point = dpos.pos.coordinates;
+            dpos.pos.coordinates = Point::new(point.x() + self.0, point.y());
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new()
+        .transform(Arc::new(DatumShift(0.01)))
+        .transform(Arc::new(|dpos: &mut DevicePosition| {
+            dpos.device_id = "truck 1".into();
+            dpos.pos.speed = dpos.pos.speed.map(|kmh| kmh / 3.6);
+        }))
+        .run(TestSource {}, TimeRange::all())?;
+
+    assert_eq!(1, tracks.len());
+    assert_eq!(
+        Some("Tracked by `truck 1`".to_string()),
+        tracks[0].description
+    );
+    let points: Vec<_> = tracks[0].segments.iter().flat_map(|s| &s.points).collect();
+    assert_eq!(3, points.len());
+    assert_eq!(Point::new(-48.86, -26.31), points[0].point());
+    assert_eq!(Some(10.0), points[0].speed);
+
+    Ok(())
+}
+
+#[test]
+fn projected_source() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            // SIRGAS 2000 / UTM zone 22S, on the central meridian
+            Ok((0..3)
+                .map(|i| {
+                    DevicePosition::basic(
+                        "imei-351".to_string(),
+                        Point::new(500000.0, 7090000.0 + i as f64 * 100.0),
+                        datetime!(2021-05-24 0:00 UTC) + Duration::minutes(i),
+                    )
+                })
+                .collect())
+        }
+    }
+
+    let (tracks, _) = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            crs: Some(31982),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all())?;
+
+    let points: Vec<_> = tracks[0].segments.iter().flat_map(|s| &s.points).collect();
+    assert_eq!(3, points.len());
+    assert!((points[0].point().x() - -51.0).abs() < 1e-9);
+    assert!((points[0].point().y() - -26.3099).abs() < 1e-4);
+
+    let unknown = SourceToTracks::new()
+        .configure_tracks(&TrackOptions {
+            crs: Some(2193),
+            ..Default::default()
+        })
+        .run(TestSource {}, TimeRange::all());
+    assert!(unknown.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tracks_per_device() -> Result<(), String> {
+    struct TestSource {}
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            Ok((0..6)
+                .map(|i| {
+                    let mut dpos = DevicePosition::basic(
+                        format!("imei-35{}", i % 2),
+                        Point::new(-48.87 + i as f64 * 0.001, -26.31),
+                        datetime!(2021-05-24 0:00 UTC) + Duration::hours(i),
+                    );
+                    dpos.route_name = Some(format!("{}", i % 3).into());
+                    dpos
+                })
+                .collect())
+        }
+    }
+
+    let devices = SourceToTracks::new().build_per_device(TestSource {}, TimeRange::all())?;
+
+    assert_eq!(
+        vec!["imei-350", "imei-351"],
+        devices.keys().collect::<Vec<_>>()
+    );
+    let tracks = devices.get("imei-350").ok_or("No tracks of imei-350")?;
+    assert_eq!(3, tracks.len());
+    assert!(tracks
+        .iter()
+        .all(|t| t.description == Some("Tracked by `imei-350`".to_string())));
+    let points: usize = devices
+        .values()
+        .flatten()
+        .flat_map(|t| &t.segments)
+        .map(|s| s.points.len())
+        .sum();
+    assert_eq!(6, points);
+
+    Ok(())
+}
+
+#[test]
+fn track_from_device_positions() -> Result<(), String> {
+    let positions: Vec<DevicePosition> = [(-48.8702222, 0), (-48.8619776, 2), (-48.8619871, 4)]
+        .into_iter()
+        .map(|(lng, minute)| {
+            DevicePosition::basic(
+                "my dev 1".to_string(),
+                Point::new(lng, -26.31832),
+                datetime!(2021-05-24 0:00 UTC) + Duration::minutes(minute),
+            )
+        })
+        .collect();
+
+    let tracker = Tracker::new("my dev 1".to_string(), "running in joinville".to_string());
+    let track = tracker.build_from_device_positions(&positions)?;
+    assert_eq!(Some("running in joinville".to_string()), track.name);
+    assert_eq!(1, track.segments.len());
+    assert_eq!(3, track.segments[0].points.len());
+
+    let raw = positions.iter().map(|dpos| &dpos.pos);
+    assert_eq!(tracker.build(raw)?, track);
+    let owned: Vec<RawPosition> = positions.into_iter().map(|dpos| dpos.pos).collect();
+    assert_eq!(tracker.build(owned)?, track);
+
+    Ok(())
+}
+
+#[test]
+fn skipped_source_records() -> Result<(), String> {
+    struct TestSource {
+        skipped: Vec<String>,
+    }
+    impl PositionsSource for TestSource {
+        fn fetch(&mut self, _range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+            self.skipped
+                .push("Error with doc {}: Time field not found".to_string());
+
+            Ok(vec![DevicePosition::basic(
+                "dev 1",
+                Point::new(-48.8702222, -26.31832),
+                datetime!(2021-05-24 0:00 UTC),
+            )])
+        }
+
+        fn take_skipped(&mut self) -> Vec<String> {
+            std::mem::take(&mut self.skipped)
+        }
+    }
+
+    let (tracks, report) =
+        SourceToTracks::new().run(TestSource { skipped: vec![] }, TimeRange::all())?;
+    assert_eq!(1, tracks.len());
+    assert_eq!(
+        vec!["Error with doc {}: Time field not found".to_string()],
+        report.skipped
+    );
+    assert!(!report.is_empty());
+
+    Ok(())
+}
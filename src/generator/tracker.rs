@@ -1,14 +1,39 @@
 //! Track generator API
 
-use std::collections::BTreeMap;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::sync::Arc;
 
 use geo::SimplifyVwIdx;
-use gpx::{Track, TrackSegment, Waypoint};
-use serde::Deserialize;
-use time::{macros::format_description, OffsetDateTime};
+use gpx::{Link, Track, Waypoint};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
 
+use super::crs::Crs;
+use super::deviation::DeviationOptions;
+use super::direction::DirectionOptions;
+use super::dop::DopFilter;
+use super::elevation::ElevationOptions;
+use super::filter::{self, PointFilter};
+use super::geoid::{GeoidGrid, GeoidOptions};
+use super::grouping::GroupingStrategy;
+use super::labels::PointLabels;
+use super::model::{TrackModel, TrackPoint};
+use super::period::CalendarPeriod;
 use super::position::{DevicePosition, RawPosition};
-use crate::PositionsSource;
+use super::preview::Preview;
+use super::quality;
+use super::report::{RejectStage, Report, ReportEntry};
+use super::sanity::SanityLimits;
+use super::sentinel::Sentinels;
+use super::simplify;
+use super::smoothing;
+use super::spill::{GroupPositions, SpillDir, SpillOptions, Spiller};
+use super::transform::PositionTransform;
+use super::trips::{split_by_stops, TripOptions};
+use crate::sources::fetch_range;
+use crate::{geoutil, PositionsSource, TimeRange};
 
 pub struct Tracker {
     /// Device name, number...
@@ -17,6 +42,20 @@ pub struct Tracker {
     name: String,
     /// Data source, eg.: track app
     source: Option<String>,
+    /// Details of the device, eg.: its friendly name
+    description: Option<String>,
+    /// Activity, eg.: cycling, driving
+    track_type: Option<String>,
+    /// Position of the track in the document
+    number: Option<u32>,
+    /// External references, eg.: device dashboard
+    links: Vec<Link>,
+    /// Converts the ellipsoidal heights to elevations
+    geoid: Option<Arc<GeoidGrid>>,
+    /// Cleaning rules, after the built-in ones
+    filters: Vec<Arc<dyn PointFilter>>,
+    /// Names of the points
+    labels: Option<PointLabels>,
     segment_confs: TrackSegmentOptions,
 }
 
@@ -27,6 +66,13 @@ impl Tracker {
             device,
             name,
             source: None,
+            description: None,
+            track_type: None,
+            number: None,
+            links: vec![],
+            geoid: None,
+            filters: vec![],
+            labels: None,
             segment_confs: TrackSegmentOptions::default(),
         }
     }
@@ -45,81 +91,767 @@ impl Tracker {
         self
     }
 
-    /// Build the track with the tracker params
-    pub fn build(&self, positions: Vec<&RawPosition>) -> Result<Track, String> {
-        let mut track = Track::new();
-        track.name = Some(self.name.clone());
-        track.description = Some(format!("Tracked by `{}`", self.device.clone()));
-        track.source = self.source.clone();
+    /// Description of the track, instead of the device ID
+    pub fn description(&mut self, description: String) -> &mut Self {
+        self.description = Some(description);
 
-        let mut positions = positions.clone();
-        positions.sort_by_key(|p| p.time);
+        self
+    }
+
+    /// Activity of the track, like cycling or driving
+    pub fn track_type(&mut self, track_type: String) -> &mut Self {
+        self.track_type = Some(track_type);
+
+        self
+    }
+
+    /// Number of the track in the document
+    pub fn number(&mut self, number: u32) -> &mut Self {
+        self.number = Some(number);
+
+        self
+    }
+
+    /// Add a link to an external reference of the track
+    pub fn link(&mut self, link: Link) -> &mut Self {
+        self.links.push(link);
+
+        self
+    }
+
+    /// Geoid grid used to convert the ellipsoidal
+    /// heights of the positions to elevations
+    pub fn geoid(&mut self, grid: Arc<GeoidGrid>) -> &mut Self {
+        self.geoid = Some(grid);
+
+        self
+    }
+
+    /// Add a cleaning rule of the points, run after the built-in ones
+    pub fn filter(&mut self, filter: Arc<dyn PointFilter>) -> &mut Self {
+        self.filters.push(filter);
+
+        self
+    }
+
+    /// Name the points of the track, like the check-ins
+    pub fn labels(&mut self, labels: PointLabels) -> &mut Self {
+        self.labels = Some(labels);
 
-        let mut segs: BTreeMap<i64, TrackSegment> = BTreeMap::new();
+        self
+    }
 
-        // We make small segments of tracks rounding
-        // the times to the closest 5min sloot
-        let max_time = self.segment_confs.max_duration as f64;
-        for poi in positions {
-            let key = ((poi.time.unix_timestamp() as f64 / max_time).floor() * max_time) as i64;
+    /// Build the track with the tracker params, from the owned or the
+    /// borrowed positions
+    pub fn build(
+        &self,
+        positions: impl IntoIterator<Item = impl Borrow<RawPosition>>,
+    ) -> Result<Track, String> {
+        self.build_with_report(positions, &mut Report::default())
+    }
 
-            let tseg = segs.entry(key).or_insert_with(|| TrackSegment::new());
+    /// Build the track with the tracker params, registering
+    /// on the report what was changed on the positions
+    pub fn build_with_report(
+        &self,
+        positions: impl IntoIterator<Item = impl Borrow<RawPosition>>,
+        report: &mut Report,
+    ) -> Result<Track, String> {
+        self.build_model(positions, report).map(Track::from)
+    }
+
+    /// Build the track of the positions of the device, like the
+    /// fetched ones, with the tracker params
+    pub fn build_from_device_positions(
+        &self,
+        positions: &[DevicePosition],
+    ) -> Result<Track, String> {
+        self.build_model_from_device_positions(positions, &mut Report::default())
+            .map(Track::from)
+    }
+
+    /// Build the model of the track of the positions of the device,
+    /// registering on the report what was changed on the positions,
+    /// with the source records of the dropped ones
+    pub fn build_model_from_device_positions(
+        &self,
+        positions: &[DevicePosition],
+        report: &mut Report,
+    ) -> Result<TrackModel, String> {
+        #[cfg(feature = "raw")]
+        let rejected = report.rejects.len();
+        let track = self.build_model(positions.iter().map(|dpos| &dpos.pos), report)?;
+
+        #[cfg(feature = "raw")]
+        for reject in &mut report.rejects[rejected..] {
+            reject.raw = reject
+                .source_index
+                .and_then(|i| positions.get(i))
+                .filter(|dpos| *dpos.device_id == reject.device)
+                .and_then(|dpos| dpos.raw.clone());
+        }
+
+        Ok(track)
+    }
+
+    /// Build the model of the track, before the output format, registering
+    /// on the report what was changed on the positions
+    pub fn build_model(
+        &self,
+        positions: impl IntoIterator<Item = impl Borrow<RawPosition>>,
+        report: &mut Report,
+    ) -> Result<TrackModel, String> {
+        let mut track = TrackModel {
+            device: self.device.clone(),
+            name: Some(self.name.clone()),
+            description: Some(
+                self.description
+                    .clone()
+                    .unwrap_or_else(|| format!("Tracked by `{}`", self.device)),
+            ),
+            source: self.source.clone(),
+            track_type: self.track_type.clone(),
+            number: self.number,
+            links: self.links.clone(),
+            segments: vec![],
+        };
+
+        let mut positions: Vec<_> = positions.into_iter().enumerate().collect();
+        positions.sort_by_key(|(_, p)| p.borrow().time);
+
+        let mut samples = vec![];
+        let mut points = vec![];
+        let mut indexes = vec![];
+        for (index, poi) in &positions {
+            let poi = poi.borrow();
+            if self.segment_confs.sentinels.is_missing(poi.coordinates) {
+                let reason = "The coordinates are a sentinel value, position dropped";
+                report
+                    .reject(
+                        self.device.clone(),
+                        poi.time,
+                        poi.coordinates,
+                        RejectStage::Sentinel,
+                        reason.to_string(),
+                    )
+                    .source_index = Some(*index);
+                report.add(self.device.clone(), poi.time, reason.to_string());
+                continue;
+            }
 
             let mut wp = Waypoint::new(poi.coordinates);
 
             wp.time = Some(poi.time.into());
             wp.elevation = poi.altitude;
             wp.speed = poi.speed;
+            wp.sat = poi.satellites;
+            wp.hdop = poi.hdop;
+            wp.name = poi.event.clone();
+            wp._type = poi.event.clone();
+
+            self.segment_confs.sentinels.clear(&mut wp);
+
+            if let Some(geoid) = &self.geoid {
+                geoid.correct(&mut wp);
+            }
 
-            tseg.points.push(wp);
+            let baro = poi
+                .baro_altitude
+                .filter(|el| !self.segment_confs.sentinels.elevation.contains(el));
+            samples.push((poi.time, baro));
+            indexes.push(*index);
+            points.push(wp);
         }
 
-        for (_, tseg) in segs {
-            if let Some(tol) = self.segment_confs.vw_tolerance {
-                let keep = tseg.linestring().simplify_vw_idx(&tol);
+        self.segment_confs.elevation.apply(&samples, &mut points);
 
-                let mut ntseg = TrackSegment::new();
+        let tpoints: Vec<TrackPoint> = samples
+            .into_iter()
+            .zip(points)
+            .zip(indexes)
+            .map(|(((time, _), wp), index)| TrackPoint {
+                source_index: Some(index),
+                ..TrackPoint::from_waypoint(&wp, time)
+            })
+            .collect();
 
-                for ipoint in keep {
-                    ntseg.points.push(tseg.points[ipoint].clone());
-                }
+        let mut filters: Vec<&dyn PointFilter> = vec![&self.segment_confs.sanity];
+        if let Some(dop) = &self.segment_confs.dop_filter {
+            filters.push(dop);
+        }
+        filters.extend(self.filters.iter().map(|f| f.as_ref()));
 
-                track.segments.push(ntseg);
-            } else {
-                track.segments.push(tseg);
+        // Until the segment stage, all the points are a single segment
+        let mut segments = vec![tpoints];
+        for stage in self.segment_confs.pipeline()? {
+            match stage {
+                PipelineStage::Filter => {
+                    for segment in &mut segments {
+                        filter::apply_filters(&filters, segment, &self.device, report);
+                    }
+                }
+                PipelineStage::Smooth => {
+                    if let Some(window) = self.segment_confs.median_window {
+                        for segment in &mut segments {
+                            smoothing::median_filter(segment, window);
+                        }
+                    }
+                }
+                PipelineStage::Segment => {
+                    segments = self.segment(segments.into_iter().flatten().collect());
+                }
+                PipelineStage::Simplify => {
+                    segments = segments.into_iter().map(|s| self.simplify(s)).collect();
+                }
             }
         }
+        track.segments = segments;
+
+        if let Some(labels) = &self.labels {
+            labels.apply(&self.device, &mut track)?;
+        }
 
         Ok(track)
     }
+
+    /// Split the points on the segments time slots
+    fn segment(&self, points: Vec<TrackPoint>) -> Vec<Vec<TrackPoint>> {
+        let mut segs: BTreeMap<i64, Vec<TrackPoint>> = BTreeMap::new();
+
+        let slots = segment_slots(&self.segment_confs, points.iter().map(|p| p.time));
+        for (key, point) in slots.into_iter().zip(points) {
+            segs.entry(key).or_default().push(point);
+        }
+
+        segs.into_values().collect()
+    }
+
+    /// Points of the segment kept by the simplification, if any
+    fn simplify(&self, mut tseg: Vec<TrackPoint>) -> Vec<TrackPoint> {
+        let confs = &self.segment_confs;
+        if let Some(meters) = confs.min_distance_m {
+            let mut keep = simplify::thin_by_distance(&tseg, meters);
+            simplify::retain_indexes(&mut tseg, &mut keep);
+        }
+
+        let mut keep = match (
+            confs.target_points,
+            confs.simplify_tolerance_m,
+            confs.vw_tolerance,
+        ) {
+            (Some(count), _, _) => simplify::simplify_to_count(&tseg, count),
+            (None, Some(meters), _) => simplify::simplify_metric(&tseg, meters),
+            (None, None, Some(tol)) => TrackModel::line(&tseg).simplify_vw_idx(&tol),
+            (None, None, None) => return tseg,
+        };
+
+        if confs.preserve_extremes {
+            keep.extend(simplify::extremes(&tseg));
+        }
+        simplify::retain_indexes(&mut tseg, &mut keep);
+
+        tseg
+    }
 }
 
+/// Device, route and, for the routes split by day, the day of a track
+type GroupKey = (Arc<str>, Arc<str>, Option<Date>);
+
+/// Groups of the positions, with the directory of the spilled ones
+type SpilledGroups = (Option<SpillDir>, Vec<(GroupKey, GroupPositions)>);
+
 /// Segments configurations
-#[derive(Clone, Debug, PartialEq, Deserialize)]
-#[serde(default)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct TrackSegmentOptions {
     /// Max segment duration in seconds
     pub max_duration: u16,
+    /// Start of the segments time slots
+    pub alignment: BucketAlignment,
     /// Tolerance value to simplify with Visvalingam-Whyatt algorithm
     pub vw_tolerance: Option<f64>,
+    /// Tolerance in meters to simplify with Visvalingam-Whyatt
+    /// algorithm, used instead of the `vw_tolerance`
+    pub simplify_tolerance_m: Option<f64>,
+    /// Simplify each segment to about this count of points, used
+    /// instead of the tolerances
+    pub target_points: Option<usize>,
+    /// Drop the points closer than this, in meters, to the previous kept
+    /// point. Applied before the simplification
+    pub min_distance_m: Option<f64>,
+    /// Keep on the simplification the points with the max speed, the max
+    /// and min elevations and the ones around the longest time gap
+    pub preserve_extremes: bool,
+    /// Drop the jumps over a limit adapted to the HDOP of the points
+    pub dop_filter: Option<DopFilter>,
+    /// Points of the rolling median over the coordinates, like 3 or 5,
+    /// to remove the single point spikes
+    pub median_window: Option<usize>,
+    /// Caps for the speed and elevation values
+    pub sanity: SanityLimits,
+    /// Values treated as missing
+    pub sentinels: Sentinels,
+    /// Source of the elevations, GNSS or barometric
+    pub elevation: ElevationOptions,
+    /// Order of the processing stages, with each one of them once
+    pub pipeline: Vec<PipelineStage>,
 }
 
 impl Default for TrackSegmentOptions {
     fn default() -> Self {
         Self {
             max_duration: 300, // 5 minutes
+            alignment: BucketAlignment::default(),
             vw_tolerance: None,
+            simplify_tolerance_m: None,
+            target_points: None,
+            min_distance_m: None,
+            preserve_extremes: false,
+            dop_filter: None,
+            median_window: None,
+            sanity: SanityLimits::default(),
+            sentinels: Sentinels::default(),
+            elevation: ElevationOptions::default(),
+            pipeline: vec![
+                PipelineStage::Filter,
+                PipelineStage::Smooth,
+                PipelineStage::Segment,
+                PipelineStage::Simplify,
+            ],
+        }
+    }
+}
+
+impl TrackSegmentOptions {
+    /// Stages of the pipeline, checking that each one is there once
+    pub fn pipeline(&self) -> Result<&[PipelineStage], String> {
+        let stages = [
+            PipelineStage::Filter,
+            PipelineStage::Smooth,
+            PipelineStage::Segment,
+            PipelineStage::Simplify,
+        ];
+        for stage in stages {
+            let count = self.pipeline.iter().filter(|st| **st == stage).count();
+            if count != 1 {
+                return Err(format!(
+                    "The pipeline needs the `{}` stage once, found {} times",
+                    format!("{:?}", stage).to_lowercase(),
+                    count
+                ));
+            }
+        }
+
+        Ok(&self.pipeline)
+    }
+}
+
+/// Processing stage of the points of a track
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStage {
+    /// Sanity limits, DOP filter and the custom filters
+    Filter,
+    /// Rolling median of the `median_window`
+    Smooth,
+    /// Split on the segments time slots
+    Segment,
+    /// Simplification of each segment, or of the whole
+    /// track when before the segment stage
+    Simplify,
+}
+
+/// Alignment of the segments time slots
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketAlignment {
+    /// Slots aligned to the Unix epoch, like 00:00-00:05
+    #[default]
+    Epoch,
+    /// Slots aligned to the time of the first point of the track
+    FirstPoint,
+    /// Each slot starts on the first point after the previous one
+    Sliding,
+}
+
+/// Order of the tracks in the document
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackOrder {
+    /// By device and then by route
+    #[default]
+    Device,
+    /// By route and then by device
+    Route,
+    /// By the time of the first point of each track, even the split ones
+    StartTime,
+}
+
+/// Tracks configurations
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackOptions {
+    /// Activity of the tracks, like cycling or driving, used
+    /// when the source does not provide one
+    pub track_type: Option<String>,
+    /// Order of the tracks, also used by their numbers
+    pub order: TrackOrder,
+    /// Links added on each track
+    pub links: Vec<LinkTemplate>,
+    /// Emit each segment as its own track, for the consumers
+    /// that join all the segments of a track
+    pub split_segments: bool,
+    /// Append the direction of travel, inbound or outbound, on the names,
+    /// also reversing the runs of the routes recorded backwards
+    pub direction: Option<DirectionOptions>,
+    /// Measure the deviation of the tracks from the reference lines
+    /// of their routes, reporting the off-route points
+    pub deviation: Option<DeviationOptions>,
+    /// Drop the positions with the same device and time of a previous one,
+    /// like the ones of overlapping exports
+    pub dedup: bool,
+    /// Max track duration in seconds, the longer ones are split
+    /// in many tracks named with their time spans
+    pub max_duration: Option<u32>,
+    /// Split the tracks at the stops of the devices, one track per
+    /// trip named with its time span, for the devices without routes
+    pub trips: Option<TripOptions>,
+    /// Convert the ellipsoidal heights of the source, like the
+    /// ones of raw GNSS data, to orthometric elevations
+    pub geoid: Option<GeoidOptions>,
+    /// Key of the tracks of each device
+    pub grouping: GroupingStrategy,
+    /// Keep a single track for each route of a device, instead of one
+    /// track per calendar day. The tracks of the routes with many days
+    /// are named with their days
+    pub continuous_routes: bool,
+    /// Split the tracks by calendar period, for the
+    /// archives with one document per period
+    pub split_by: Option<CalendarPeriod>,
+    /// Human names of the route IDs, used on the track names
+    pub route_aliases: BTreeMap<String, String>,
+    /// File with a `route,name` alias per line, overridden
+    /// by the `route_aliases` map
+    pub route_aliases_file: Option<String>,
+    /// Names of the points, written on the track points and, with the
+    /// `waypoints` output option, also as waypoints
+    pub labels: Option<PointLabels>,
+    /// Recorder on the track `source`, with the `{tracker}` and `{version}`
+    /// placeholders, like `{tracker} via location2gpx {version}`. Default:
+    /// the tracker app, software or model of the positions
+    pub source: Option<String>,
+    /// Fail the run when the source returns less positions than
+    /// this, instead of writing an empty or incomplete document
+    pub min_positions: Option<usize>,
+    /// Report the periods longer than this, in seconds, without
+    /// positions of a device inside of the requested window
+    pub gap_threshold: Option<u32>,
+    /// Keep a limit of positions in memory, writing the others on
+    /// temporary files while the source fetches them, until the build
+    /// of their tracks. The duplicates are dropped inside of each group
+    pub spill: Option<SpillOptions>,
+    /// EPSG code of the source coordinates, like 31982 for SIRGAS 2000 /
+    /// UTM zone 22S, converted to WGS84 before the grouping
+    pub crs: Option<u32>,
+}
+
+impl TrackOptions {
+    /// Route aliases of the file and of the map
+    pub fn load_route_aliases(&self) -> Result<BTreeMap<String, String>, String> {
+        let mut aliases = match &self.route_aliases_file {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|e| format!("Failed on read the route aliases file: {}", e))?;
+                parse_route_aliases(&content)?
+            }
+            None => BTreeMap::new(),
+        };
+
+        aliases.extend(self.route_aliases.clone());
+
+        Ok(aliases)
+    }
+}
+
+/// Parse the `route,name` lines, skipping the empty ones and the `#` comments
+fn parse_route_aliases(content: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut aliases = BTreeMap::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (route, name) = line
+            .split_once(',')
+            .ok_or(format!("Invalid route alias at line {}", i + 1))?;
+        let unquote = |v: &str| v.trim().trim_matches('"').to_string();
+
+        aliases.insert(unquote(route), unquote(name));
+    }
+
+    Ok(aliases)
+}
+
+/// Link with the `{device}` and `{route}` placeholders
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkTemplate {
+    /// URL, eg.: `https://fleet.example.com/device/{device}`
+    pub href: String,
+    pub text: Option<String>,
+    /// Mime type of the content
+    #[serde(rename = "type")]
+    pub link_type: Option<String>,
+}
+
+impl LinkTemplate {
+    /// Link of the track, with the values of the placeholders
+    pub fn fill(&self, device: &str, route: &str) -> Link {
+        let fill = |template: &str, encode: bool| {
+            let value = |v: &str| {
+                if encode {
+                    encode_url_value(v)
+                } else {
+                    v.to_string()
+                }
+            };
+
+            template
+                .replace("{device}", &value(device))
+                .replace("{route}", &value(route))
+        };
+
+        Link {
+            href: fill(&self.href, true),
+            text: self.text.as_ref().map(|t| fill(t, false)),
+            _type: self.link_type.clone(),
+        }
+    }
+}
+
+/// Split the positions in chunks with at most the duration, in seconds
+fn split_by_duration(mut positions: Vec<DevicePosition>, max: u32) -> Vec<Vec<DevicePosition>> {
+    positions.sort_by_key(|dpos| dpos.pos.time);
+
+    let max = Duration::seconds(max.into());
+    let mut chunks: Vec<Vec<DevicePosition>> = vec![];
+    for dpos in positions {
+        match chunks.last_mut() {
+            Some(chunk) if dpos.pos.time - chunk[0].pos.time < max => chunk.push(dpos),
+            _ => chunks.push(vec![dpos]),
         }
     }
+
+    chunks
+}
+
+/// Segment time slot of each of the times, on their order
+fn segment_slots(
+    confs: &TrackSegmentOptions,
+    times: impl IntoIterator<Item = OffsetDateTime>,
+) -> Vec<i64> {
+    // We make small segments of tracks rounding
+    // the times to the closest 5min sloot
+    let max_time = i64::from(confs.max_duration).max(1);
+    let mut first: Option<i64> = None;
+    let mut slot: Option<i64> = None;
+
+    times
+        .into_iter()
+        .map(|time| {
+            let ts = time.unix_timestamp();
+            match confs.alignment {
+                BucketAlignment::Epoch => ts.div_euclid(max_time) * max_time,
+                BucketAlignment::FirstPoint => {
+                    let first = *first.get_or_insert(ts);
+                    first + (ts - first).div_euclid(max_time) * max_time
+                }
+                BucketAlignment::Sliding => match slot {
+                    Some(start) if ts - start < max_time => start,
+                    _ => *slot.insert(ts),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Description with the friendly name and plate of the device, when known
+fn describe_device(dpos: &DevicePosition) -> Option<String> {
+    if dpos.device_name.is_none() && dpos.vehicle_plate.is_none() {
+        return None;
+    }
+
+    let name = dpos.device_name.as_deref().unwrap_or(&dpos.device_id);
+    let description = match &dpos.vehicle_plate {
+        Some(plate) => format!("Tracked by `{}`, plate {}", name, plate),
+        None => format!("Tracked by `{}`", name),
+    };
+
+    Some(description)
+}
+
+/// Split the positions by the calendar period of their times
+fn split_by_period(
+    positions: Vec<DevicePosition>,
+    period: CalendarPeriod,
+) -> Vec<Vec<DevicePosition>> {
+    let mut periods: BTreeMap<String, Vec<DevicePosition>> = BTreeMap::new();
+    for dpos in positions {
+        periods
+            .entry(period.name(dpos.pos.time))
+            .or_default()
+            .push(dpos);
+    }
+
+    periods.into_values().collect()
+}
+
+/// Same tracks whatever the order of the source, even with
+/// repeated times or duplicates
+fn sort_positions(positions: &mut [DevicePosition]) {
+    positions.sort_by(|a, b| {
+        (&a.device_id, a.pos.time)
+            .cmp(&(&b.device_id, b.pos.time))
+            .then(a.pos.coordinates.x().total_cmp(&b.pos.coordinates.x()))
+            .then(a.pos.coordinates.y().total_cmp(&b.pos.coordinates.y()))
+    });
+}
+
+/// Drop the positions of a device repeating a time, keeping the first
+fn dedup(positions: &mut Vec<DevicePosition>, report: &mut Report) {
+    let mut seen = HashSet::new();
+    let before = positions.len();
+    positions.retain(|dpos| {
+        let first = seen.insert((dpos.device_id.clone(), dpos.pos.time));
+        if !first {
+            report.reject(
+                dpos.device_id.to_string(),
+                dpos.pos.time,
+                dpos.pos.coordinates,
+                RejectStage::Dedup,
+                "Duplicated position of the device at the same time".to_string(),
+            );
+            #[cfg(feature = "raw")]
+            if let Some(reject) = report.rejects.last_mut() {
+                reject.raw = dpos.raw.clone();
+            }
+        }
+        first
+    });
+    report.duplicates += before - positions.len();
+}
+
+/// Period of the positions, like `08:00-11:59`. The dates
+/// are included when the period is not inside of the same day
+fn time_span(positions: &[DevicePosition]) -> Result<String, String> {
+    let start = positions.iter().map(|dpos| dpos.pos.time).min();
+    let end = positions.iter().map(|dpos| dpos.pos.time).max();
+    let (start, end) = match (start, end) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Ok(String::new()),
+    };
+
+    let span = if start.date() == end.date() {
+        let hour = format_description!("[hour]:[minute]");
+        format!(
+            "{}-{}",
+            start.format(hour).map_err(|e| e.to_string())?,
+            end.format(hour).map_err(|e| e.to_string())?
+        )
+    } else {
+        let full = format_description!("[year]-[month]-[day] [hour]:[minute]");
+        format!(
+            "{} - {}",
+            start.format(full).map_err(|e| e.to_string())?,
+            end.format(full).map_err(|e| e.to_string())?
+        )
+    };
+
+    Ok(span)
+}
+
+/// Track of each segment, with the segment number on the name
+fn split_segments(mut track: TrackModel) -> Vec<TrackModel> {
+    let segments = std::mem::take(&mut track.segments);
+
+    segments
+        .into_iter()
+        .zip(1..)
+        .map(|(segment, i)| {
+            let mut strack = track.clone();
+            strack.name = track.name.as_ref().map(|n| format!("{} #{}", n, i));
+            strack.segments.push(segment);
+            strack
+        })
+        .collect()
+}
+
+/// Percent encode the value to be used inside of an URL
+fn encode_url_value(value: &str) -> String {
+    let mut encoded = String::new();
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
 }
 
 /// Default tracks generator from source
-pub struct SourceToTracks {}
+#[derive(Default)]
+pub struct SourceToTracks {
+    track_confs: TrackOptions,
+    segment_confs: TrackSegmentOptions,
+    filters: Vec<Arc<dyn PointFilter>>,
+    transforms: Vec<Arc<dyn PositionTransform>>,
+}
 
 impl SourceToTracks {
+    /// Start a new generator with the default confs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Change the tracks confs
+    pub fn configure_tracks(&mut self, conf: &TrackOptions) -> &mut Self {
+        self.track_confs = conf.clone();
+
+        self
+    }
+
+    /// Change the segments confs
+    pub fn configure_segments(&mut self, conf: &TrackSegmentOptions) -> &mut Self {
+        self.segment_confs = conf.clone();
+
+        self
+    }
+
+    /// Add a cleaning rule of the points of all the
+    /// tracks, run after the built-in ones
+    pub fn filter(&mut self, filter: Arc<dyn PointFilter>) -> &mut Self {
+        self.filters.push(filter);
+
+        self
+    }
+
+    /// Add a change of the fetched positions, run before the
+    /// grouping in the order they were added
+    pub fn transform(&mut self, transform: Arc<dyn PositionTransform>) -> &mut Self {
+        self.transforms.push(transform);
+
+        self
+    }
+
     /// Run the source and build the tracks
     pub fn build<SU>(
-        mut source: SU,
+        source: SU,
         start: OffsetDateTime,
         end: OffsetDateTime,
         segment_confs: TrackSegmentOptions,
@@ -127,45 +859,496 @@ impl SourceToTracks {
     where
         SU: PositionsSource,
     {
-        let mut devices: BTreeMap<(String, String), Vec<DevicePosition>> = BTreeMap::new();
+        let (tracks, _) = Self::build_with_report(source, start, end, segment_confs)?;
+
+        Ok(tracks)
+    }
+
+    /// Run the source and build the tracks, also returning
+    /// the report of the generation
+    pub fn build_with_report<SU>(
+        source: SU,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        segment_confs: TrackSegmentOptions,
+    ) -> Result<(Vec<Track>, Report), String>
+    where
+        SU: PositionsSource,
+    {
+        Self::new()
+            .configure_segments(&segment_confs)
+            .run(source, TimeRange::between(start, end))
+    }
+
+    /// Run the source and build the tracks with the generator
+    /// confs, also returning the report of the generation
+    pub fn run<SU>(&self, source: SU, range: TimeRange) -> Result<(Vec<Track>, Report), String>
+    where
+        SU: PositionsSource,
+    {
+        let (models, report) = self.run_models(source, range)?;
+
+        Ok((models.into_iter().map(Track::from).collect(), report))
+    }
+
+    /// Run the source and build the tracks with the generator
+    /// confs, by the device of their positions
+    pub fn build_per_device<SU>(
+        &self,
+        source: SU,
+        range: TimeRange,
+    ) -> Result<BTreeMap<String, Vec<Track>>, String>
+    where
+        SU: PositionsSource,
+    {
+        let (models, _) = self.run_models(source, range)?;
+
+        let mut devices: BTreeMap<String, Vec<Track>> = BTreeMap::new();
+        for model in models {
+            devices
+                .entry(model.device.clone())
+                .or_default()
+                .push(Track::from(model));
+        }
+
+        Ok(devices)
+    }
+
+    /// Run the source and build the models of the tracks, before the
+    /// output format, also returning the report of the generation
+    pub fn run_models<SU>(
+        &self,
+        mut source: SU,
+        range: TimeRange,
+    ) -> Result<(Vec<TrackModel>, Report), String>
+    where
+        SU: PositionsSource,
+    {
+        let mut report = Report::default();
+        let mut routes = vec![];
         let mut tracks = vec![];
         let route_day_format = format_description!("[year]-[month]-[day]");
 
-        let positions = source.fetch(start, end)?;
+        let aliases = self.track_confs.load_route_aliases()?;
+        let references = match &self.track_confs.deviation {
+            Some(deviation) => deviation.load_references()?,
+            None => BTreeMap::new(),
+        };
 
-        for pos in positions {
-            let route = match pos.route_name.clone() {
-                Some(ro) => ro,
-                None => {
-                    let day = pos
-                        .pos
-                        .time
-                        .format(route_day_format)
-                        .map_err(|e| e.to_string())?;
-                    day
+        let geoid = match &self.track_confs.geoid {
+            Some(conf) => Some(Arc::new(conf.load()?)),
+            None => None,
+        };
+
+        let threshold = self
+            .track_confs
+            .gap_threshold
+            .map(|threshold| Duration::seconds(threshold.into()));
+
+        // The spilled positions are sorted, analyzed and deduplicated
+        // by group, when loaded
+        let (_spill_dir, groups, mut parts) = match &self.track_confs.spill {
+            Some(options) => {
+                let (count, (dir, groups)) = self.fetch_spilled(&mut source, range, options)?;
+                report.skipped = source.take_skipped();
+                self.check_min_positions(count)?;
+
+                (dir, groups, Some(quality::QualityParts::new(threshold)))
+            }
+            None => {
+                let mut positions = self.fetch(&mut source, range)?;
+                report.skipped = source.take_skipped();
+                self.check_min_positions(positions.len())?;
+
+                sort_positions(&mut positions);
+
+                report.quality = quality::analyze(&positions, &self.segment_confs.sentinels);
+                if let Some(threshold) = threshold {
+                    for (device, gaps) in quality::find_gaps(&positions, range, threshold) {
+                        report.quality.entry(device).or_default().gaps = gaps;
+                    }
                 }
-            };
-            let key = (pos.device_id.clone(), route);
 
-            let dev = devices.entry(key).or_insert(vec![]);
+                if self.track_confs.dedup {
+                    dedup(&mut positions, &mut report);
+                }
+
+                let groups = self
+                    .group(positions)?
+                    .into_iter()
+                    .map(|(key, dev_pos)| (key, GroupPositions::from(dev_pos)))
+                    .collect();
+
+                (None, groups, None)
+            }
+        };
+
+        // Routes of a device with many days, named with the day
+        let mut route_days: BTreeMap<(&Arc<str>, &Arc<str>), usize> = BTreeMap::new();
+        for ((device_id, route_name, day), _) in &groups {
+            if day.is_some() {
+                *route_days.entry((device_id, route_name)).or_default() += 1;
+            }
+        }
+        let dated_routes: HashSet<(Arc<str>, Arc<str>)> = route_days
+            .into_iter()
+            .filter(|(_, days)| *days > 1)
+            .map(|((device_id, route_name), _)| (device_id.clone(), route_name.clone()))
+            .collect();
+
+        for ((device_id, route_name, day), dev_pos) in groups {
+            let dated = dated_routes.contains(&(device_id.clone(), route_name.clone()));
+            let mut dev_pos = dev_pos.load()?;
+            if let Some(parts) = &mut parts {
+                sort_positions(&mut dev_pos);
+                parts.add(&dev_pos, &self.segment_confs.sentinels);
+                if self.track_confs.dedup {
+                    dedup(&mut dev_pos, &mut report);
+                }
+            }
+
+            for (spanned, dev_pos) in self.chunks(dev_pos) {
+                let mut name = match aliases.get(&*route_name) {
+                    Some(alias) => alias.clone(),
+                    None => route_name.to_string(),
+                };
+                if let Some(day) = day.filter(|_| dated) {
+                    let day = day.format(route_day_format).map_err(|e| e.to_string())?;
+                    name = format!("{} {}", name, day);
+                }
+                if spanned {
+                    name = format!("{} {}", name, time_span(&dev_pos)?);
+                }
+
+                let mut tracker = Tracker::new(device_id.to_string(), name);
+
+                match (&self.track_confs.source, &dev_pos[0].tracker) {
+                    (Some(template), trk) => {
+                        let trk = trk.as_deref().unwrap_or("unknown tracker");
+                        tracker.source(
+                            template
+                                .replace("{tracker}", trk)
+                                .replace("{version}", env!("CARGO_PKG_VERSION")),
+                        );
+                    }
+                    (None, Some(trk)) => {
+                        tracker.source(trk.to_string());
+                    }
+                    (None, None) => {}
+                }
+
+                if let Some(description) = describe_device(&dev_pos[0]) {
+                    tracker.description(description);
+                }
+
+                let track_type = dev_pos
+                    .iter()
+                    .find_map(|dpos| dpos.track_type.clone())
+                    .or_else(|| self.track_confs.track_type.clone());
+                if let Some(tt) = track_type {
+                    tracker.track_type(tt);
+                }
+
+                for link in &self.track_confs.links {
+                    tracker.link(link.fill(&device_id, &route_name));
+                }
+
+                if let Some(grid) = &geoid {
+                    tracker.geoid(grid.clone());
+                }
+
+                for filter in &self.filters {
+                    tracker.filter(filter.clone());
+                }
+
+                if let Some(labels) = &self.track_confs.labels {
+                    tracker.labels(labels.clone());
+                }
+
+                tracker.configure_segments(&self.segment_confs);
+
+                let track = tracker.build_model_from_device_positions(&dev_pos, &mut report)?;
+
+                let emitted = track.points().count();
+                report
+                    .quality
+                    .entry(device_id.to_string())
+                    .or_default()
+                    .emitted += emitted;
+
+                routes.push((route_name.to_string(), track));
+            }
+        }
+
+        if let Some(parts) = parts {
+            for (device, mut quality) in parts.finish(range) {
+                let entry = report.quality.entry(device).or_default();
+                quality.emitted = entry.emitted;
+                *entry = quality;
+            }
+        }
+
+        // The gaps are reported before the entries of the tracks
+        let gaps: Vec<_> = report
+            .quality
+            .iter()
+            .flat_map(|(device, quality)| {
+                quality.gaps.iter().map(|gap| ReportEntry {
+                    device: device.clone(),
+                    time: gap.start,
+                    message: format!("No positions for {}, until {}", gap.duration(), gap.end),
+                })
+            })
+            .collect();
+        report.entries.splice(0..0, gaps);
+
+        if let Some(direction) = &self.track_confs.direction {
+            direction.apply(&mut routes);
+        }
+
+        if let Some(options) = &self.track_confs.deviation {
+            for (route, track) in &routes {
+                let reference = match references.get(route) {
+                    Some(reference) => reference,
+                    None => continue,
+                };
+
+                let deviation = options.measure(reference, route, track);
+                for off in &deviation.off_route {
+                    report.add(
+                        deviation.device.clone(),
+                        off.start,
+                        format!(
+                            "{} points off the route {} until {}, up to {:.0}m from the reference",
+                            off.points, route, off.end, off.max_m
+                        ),
+                    );
+                }
+                report.deviations.push(deviation);
+            }
+        }
+
+        for (_, track) in routes {
+            if self.track_confs.split_segments && track.segments.len() > 1 {
+                tracks.extend(split_segments(track));
+            } else {
+                tracks.push(track);
+            }
+        }
+
+        // The groups were sorted by their start, but their trips and
+        // split tracks can interleave with the ones of other groups
+        if self.track_confs.order == TrackOrder::StartTime {
+            tracks.sort_by_cached_key(|track| {
+                let start = track.points().map(|p| p.time).min();
+                (start.is_none(), start)
+            });
+        }
+
+        for (track, number) in tracks.iter_mut().zip(1..) {
+            track.number = Some(number);
+        }
+
+        report.bounds = geoutil::bounds(
+            tracks
+                .iter()
+                .flat_map(|track| track.points())
+                .map(|p| p.coordinates),
+        );
+
+        Ok((tracks, report))
+    }
+
+    /// Positions of the source during the period, with the transforms
+    fn fetch<SU>(&self, source: &mut SU, range: TimeRange) -> Result<Vec<DevicePosition>, String>
+    where
+        SU: PositionsSource,
+    {
+        let crs = self.crs()?;
+        let mut positions = fetch_range(source, range)?;
+        self.apply_transforms(crs.as_ref(), &mut positions);
+
+        Ok(positions)
+    }
+
+    /// Spill the batches of the source while fetching them, with the
+    /// count of the positions and the ordered groups
+    fn fetch_spilled<SU>(
+        &self,
+        source: &mut SU,
+        range: TimeRange,
+        options: &SpillOptions,
+    ) -> Result<(usize, SpilledGroups), String>
+    where
+        SU: PositionsSource,
+    {
+        let crs = self.crs()?;
+        let pushdown = source.supports_time_pushdown();
+        let mut spiller = Spiller::new(options);
+        let mut count = 0;
+
+        source.fetch_batches(range, &mut |mut batch| {
+            if !pushdown {
+                batch.retain(|dpos| range.contains(dpos.pos.time));
+            }
+            self.apply_transforms(crs.as_ref(), &mut batch);
+
+            count += batch.len();
+            for dpos in batch {
+                spiller.push(self.group_key(&dpos)?, dpos)?;
+            }
+
+            Ok(())
+        })?;
+
+        let (dir, mut groups) = spiller.finish();
+        self.order_groups(&mut groups, GroupPositions::start);
+
+        Ok((count, (dir, groups)))
+    }
+
+    fn crs(&self) -> Result<Option<Crs>, String> {
+        self.track_confs.crs.map(Crs::from_epsg).transpose()
+    }
+
+    fn apply_transforms(&self, crs: Option<&Crs>, positions: &mut [DevicePosition]) {
+        if let Some(crs) = crs {
+            for dpos in positions.iter_mut() {
+                crs.transform(dpos);
+            }
+        }
+        for transform in &self.transforms {
+            for dpos in positions.iter_mut() {
+                transform.transform(dpos);
+            }
+        }
+    }
+
+    fn check_min_positions(&self, count: usize) -> Result<(), String> {
+        if let Some(min) = self.track_confs.min_positions {
+            if count < min {
+                return Err(format!(
+                    "The source returned {} positions, less than the minimum of {}",
+                    count, min
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count what the run would produce, only fetching and grouping
+    /// the positions, without building the tracks
+    pub fn preview<SU>(&self, mut source: SU, range: TimeRange) -> Result<Preview, String>
+    where
+        SU: PositionsSource,
+    {
+        let mut positions = self.fetch(&mut source, range)?;
+        let mut preview = Preview {
+            positions: positions.len(),
+            ..Default::default()
+        };
+
+        if self.track_confs.dedup {
+            let mut seen = HashSet::new();
+            positions.retain(|pos| seen.insert((pos.device_id.clone(), pos.pos.time)));
+            preview.duplicates = preview.positions - positions.len();
+        }
+
+        let mut devices = BTreeSet::new();
+        let mut routes = BTreeSet::new();
+        for ((device_id, route_name, _), dev_pos) in self.group(positions)? {
+            for (_, chunk) in self.chunks(dev_pos) {
+                let mut times: Vec<OffsetDateTime> =
+                    chunk.iter().map(|dpos| dpos.pos.time).collect();
+                times.sort();
+                let slots: BTreeSet<i64> = segment_slots(&self.segment_confs, times)
+                    .into_iter()
+                    .collect();
+                preview.tracks += 1;
+                preview.segments += slots.len();
+            }
+            routes.insert((device_id.clone(), route_name));
+            devices.insert(device_id);
+        }
+        preview.devices = devices.len();
+        preview.routes = routes.len();
+
+        Ok(preview)
+    }
+
+    /// Positions of each track key, on the tracks order
+    fn group(
+        &self,
+        positions: Vec<DevicePosition>,
+    ) -> Result<Vec<(GroupKey, Vec<DevicePosition>)>, String> {
+        let mut devices: BTreeMap<GroupKey, Vec<DevicePosition>> = BTreeMap::new();
+
+        for pos in positions {
+            let dev = devices.entry(self.group_key(&pos)?).or_insert(vec![]);
             dev.push(pos);
         }
 
-        for ((device_id, route_name), dev_pos) in devices {
-            let mut tracker = Tracker::new(device_id.clone(), route_name.clone());
+        let mut groups: Vec<_> = devices.into_iter().collect();
+        self.order_groups(&mut groups, |dev_pos| {
+            dev_pos.iter().map(|dpos| dpos.pos.time).min()
+        });
+
+        Ok(groups)
+    }
+
+    fn group_key(&self, pos: &DevicePosition) -> Result<GroupKey, String> {
+        let (route, day) = self
+            .track_confs
+            .grouping
+            .key(pos, self.track_confs.continuous_routes)?;
 
-            if let Some(trk) = &dev_pos[0].tracker {
-                tracker.source(trk.to_string());
+        Ok((pos.device_id.clone(), route, day))
+    }
+
+    /// Sort the groups, ordered by key, with the track order
+    fn order_groups<T>(
+        &self,
+        groups: &mut [(GroupKey, T)],
+        start: impl Fn(&T) -> Option<OffsetDateTime>,
+    ) {
+        match self.track_confs.order {
+            TrackOrder::Device => {}
+            TrackOrder::Route => groups.sort_by(|((da, ra, dya), _), ((db, rb, dyb), _)| {
+                (ra, da, dya).cmp(&(rb, db, dyb))
+            }),
+            TrackOrder::StartTime => {
+                groups.sort_by_key(|(key, positions)| (start(positions), key.clone()))
             }
+        }
+    }
 
-            tracker.configure_segments(&segment_confs);
+    /// Positions of each track of the group, split by calendar period,
+    /// trip and max duration, flagging the ones named by their time spans
+    fn chunks(&self, dev_pos: Vec<DevicePosition>) -> Vec<(bool, Vec<DevicePosition>)> {
+        let periods = match self.track_confs.split_by {
+            Some(period) => split_by_period(dev_pos, period),
+            None => vec![dev_pos],
+        };
 
-            let raw = dev_pos.iter().map(|dpos| &dpos.pos).collect();
-            let track = tracker.build(raw)?;
-            tracks.push(track);
+        let mut chunks = vec![];
+        for dev_pos in periods {
+            let trips = match &self.track_confs.trips {
+                Some(trips) => split_by_stops(dev_pos, trips),
+                None => vec![dev_pos],
+            };
+            let spanned: Vec<_> = match self.track_confs.max_duration {
+                Some(max) => trips
+                    .into_iter()
+                    .flat_map(|trip| split_by_duration(trip, max))
+                    .collect(),
+                None => trips,
+            };
+            let named = spanned.len() > 1;
+            chunks.extend(spanned.into_iter().map(|chunk| (named, chunk)));
         }
 
-        Ok(tracks)
+        chunks
     }
 }
 
@@ -173,27 +1356,62 @@ impl SourceToTracks {
 fn parse_track_seg_options() -> Result<(), String> {
     let yaml = "\nmax_duration: 300";
 
-    let tso: TrackSegmentOptions = serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?;
+    let tso: TrackSegmentOptions = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
 
     assert_eq!(
         TrackSegmentOptions {
             max_duration: 300,
-            vw_tolerance: None
+            vw_tolerance: None,
+            ..Default::default()
         },
         tso
     );
 
     let yaml = "\nmax_duration: 300\nvw_tolerance: 0.001";
 
-    let tso: TrackSegmentOptions = serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?;
+    let tso: TrackSegmentOptions = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
 
     assert_eq!(
         TrackSegmentOptions {
             max_duration: 300,
-            vw_tolerance: Some(0.001)
+            vw_tolerance: Some(0.001),
+            ..Default::default()
         },
         tso
     );
 
     Ok(())
 }
+
+#[test]
+fn parse_aliases() -> Result<(), String> {
+    let content = "# route,name\n125,\"Downtown – Harbor Express\"\n\n 7 , Airport, via Center\n";
+
+    let aliases = parse_route_aliases(content)?;
+    assert_eq!(2, aliases.len());
+    assert_eq!("Downtown – Harbor Express", aliases["125"]);
+    assert_eq!("Airport, via Center", aliases["7"]);
+
+    assert!(parse_route_aliases("125").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn fill_link_template() -> Result<(), String> {
+    let yaml =
+        "\nhref: https://fleet.example.com/device/{device}?route={route}\ntext: Device {device}";
+
+    let lt: LinkTemplate = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+
+    assert_eq!(
+        Link {
+            href: "https://fleet.example.com/device/AA%20251?route=2021-05-24".to_string(),
+            text: Some("Device AA 251".to_string()),
+            _type: None,
+        },
+        lt.fill("AA 251", "2021-05-24")
+    );
+
+    Ok(())
+}
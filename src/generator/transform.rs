@@ -0,0 +1,19 @@
+//! Changes of the positions of the sources
+
+use super::position::DevicePosition;
+
+/// Change of each fetched position, before the grouping, like a datum
+/// shift, a unit fix or a field rewrite, registered on the
+/// `SourceToTracks`. The closures over the positions are also accepted
+pub trait PositionTransform: Send + Sync {
+    fn transform(&self, dpos: &mut DevicePosition);
+}
+
+impl<F> PositionTransform for F
+where
+    F: Fn(&mut DevicePosition) + Send + Sync,
+{
+    fn transform(&self, dpos: &mut DevicePosition) {
+        self(dpos)
+    }
+}
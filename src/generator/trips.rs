@@ -0,0 +1,152 @@
+//! Trips of the devices without routes, split by their stops
+
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use super::position::DevicePosition;
+use crate::geoutil;
+
+/// Stops that end the trips
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TripOptions {
+    /// Stationary time, in seconds, that ends a trip
+    pub min_stop: u32,
+    /// Max speed of a stationary device, in m/s
+    pub max_speed: f64,
+    /// Max distance, in meters, from the place where the device stopped,
+    /// absorbing the GPS drift of the parked devices
+    pub radius_m: f64,
+}
+
+impl Default for TripOptions {
+    fn default() -> Self {
+        Self {
+            min_stop: 600,
+            max_speed: 0.5,
+            radius_m: 50.0,
+        }
+    }
+}
+
+impl TripOptions {
+    /// If the device is still stopped at the anchor position
+    fn stationary(&self, anchor: &DevicePosition, dpos: &DevicePosition) -> bool {
+        let slow = dpos.pos.speed.is_none_or(|speed| speed <= self.max_speed);
+        let near = geoutil::distance(anchor.pos.coordinates, dpos.pos.coordinates) <= self.radius_m;
+
+        slow && near
+    }
+}
+
+/// Split the positions in trips at the stops longer than the `min_stop`.
+/// The trips end at the arrival and the next ones start at the departure,
+/// so the positions between them are not part of any trip
+pub fn split_by_stops(
+    mut positions: Vec<DevicePosition>,
+    options: &TripOptions,
+) -> Vec<Vec<DevicePosition>> {
+    positions.sort_by_key(|dpos| dpos.pos.time);
+
+    let min_stop = Duration::seconds(options.min_stop.into());
+    let mut trips: Vec<Vec<DevicePosition>> = vec![vec![]];
+    // Positions since the last move, the first one is where the device stopped
+    let mut stop: Vec<DevicePosition> = vec![];
+
+    let close_stop = |trips: &mut Vec<Vec<DevicePosition>>, mut stop: Vec<DevicePosition>| {
+        let trip = trips.last_mut().expect("at least one trip");
+        match (stop.first(), stop.last()) {
+            (Some(arrival), Some(departure))
+                if departure.pos.time - arrival.pos.time >= min_stop =>
+            {
+                let departure = stop.pop().expect("departure of the stop");
+                // Already parked at the start of the positions
+                if !trip.is_empty() {
+                    trip.push(stop.swap_remove(0));
+                }
+                trips.push(vec![departure]);
+            }
+            _ => trip.append(&mut stop),
+        }
+    };
+
+    for dpos in positions {
+        match stop.first() {
+            Some(anchor) if options.stationary(anchor, &dpos) => stop.push(dpos),
+            _ => close_stop(&mut trips, std::mem::replace(&mut stop, vec![dpos])),
+        }
+    }
+
+    // Parked until the end of the positions
+    if let Some(departure) = stop.last() {
+        if departure.pos.time - stop[0].pos.time >= min_stop {
+            stop.truncate(1);
+        }
+    }
+    close_stop(&mut trips, stop);
+
+    trips.retain(|trip| !trip.is_empty());
+    trips
+}
+
+#[test]
+fn trips_by_stops() {
+    use geo::Point;
+    use time::macros::datetime;
+
+    let at = |minute: i64, lng: f64, speed: Option<f64>| {
+        let mut dpos = DevicePosition::basic(
+            "dev 1".to_string(),
+            Point::new(lng, -26.31),
+            datetime!(2021-05-24 8:00 UTC) + Duration::minutes(minute),
+        );
+        dpos.pos.speed = speed;
+        dpos
+    };
+
+    // 0.01 degree of longitude is about 1km here
+    let positions = vec![
+        at(0, -48.870, None),
+        at(5, -48.860, None),
+        // Parked with drift for an hour, then leaving
+        at(10, -48.850, Some(0.0)),
+        at(40, -48.8502, Some(0.2)),
+        at(70, -48.8501, None),
+        at(75, -48.840, Some(10.0)),
+        // Traffic light, a short stop
+        at(80, -48.830, Some(0.0)),
+        at(82, -48.830, Some(0.0)),
+        at(85, -48.820, Some(12.0)),
+    ];
+
+    let trips = split_by_stops(positions.clone(), &TripOptions::default());
+    let minutes = |trip: &Vec<DevicePosition>| -> Vec<i64> {
+        trip.iter()
+            .map(|dpos| (dpos.pos.time - positions[0].pos.time).whole_minutes())
+            .collect()
+    };
+    assert_eq!(2, trips.len());
+    assert_eq!(vec![0, 5, 10], minutes(&trips[0]));
+    assert_eq!(vec![70, 75, 80, 82, 85], minutes(&trips[1]));
+
+    // Slow, but moving away
+    let trips = split_by_stops(
+        vec![at(0, -48.870, Some(0.1)), at(30, -48.860, Some(0.1))],
+        &TripOptions::default(),
+    );
+    assert_eq!(1, trips.len());
+
+    // Parked at the start and at the end
+    let trips = split_by_stops(
+        vec![
+            at(0, -48.870, None),
+            at(30, -48.870, None),
+            at(35, -48.860, None),
+            at(40, -48.850, None),
+            at(90, -48.850, None),
+        ],
+        &TripOptions::default(),
+    );
+    assert_eq!(1, trips.len());
+    assert_eq!(vec![30, 35, 40], minutes(&trips[0]));
+}
@@ -0,0 +1,303 @@
+//! Structural subset check of the GPX 1.1 schema
+//!
+//! Not a validation against the XSD, only the elements sequences,
+//! required attributes and simple value types are the ones defined by
+//! the GPX 1.1 XSD: http://www.topografix.com/GPX/1/1/gpx.xsd
+//!
+//! Not covered: the fixed `version="1.1"`, the unknown attributes, the
+//! `anyURI` and `gYear` lexical rules, the namespace of the `extensions`
+//! content and the text of the complex elements.
+
+use std::io::Read;
+
+use time::format_description::well_known;
+use time::OffsetDateTime;
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+const GPX11_NS: &str = "http://www.topografix.com/GPX/1/1";
+
+/// Value types of the schema
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SimpleType {
+    Decimal,
+    Latitude,
+    Longitude,
+    Degrees,
+    DateTime,
+    NonNegativeInteger,
+    DgpsStation,
+    Fix,
+    Year,
+    Text,
+}
+
+const WPT: &[(&str, bool)] = &[
+    ("ele", false),
+    ("time", false),
+    ("magvar", false),
+    ("geoidheight", false),
+    ("name", false),
+    ("cmt", false),
+    ("desc", false),
+    ("src", false),
+    ("link", true),
+    ("sym", false),
+    ("type", false),
+    ("fix", false),
+    ("sat", false),
+    ("hdop", false),
+    ("vdop", false),
+    ("pdop", false),
+    ("ageofdgpsdata", false),
+    ("dgpsid", false),
+    ("extensions", false),
+];
+
+/// Children allowed, in the schema order, and if they can repeat
+fn children(element: &str) -> &'static [(&'static str, bool)] {
+    match element {
+        "gpx" => &[
+            ("metadata", false),
+            ("wpt", true),
+            ("rte", true),
+            ("trk", true),
+            ("extensions", false),
+        ],
+        "metadata" => &[
+            ("name", false),
+            ("desc", false),
+            ("author", false),
+            ("copyright", false),
+            ("link", true),
+            ("time", false),
+            ("keywords", false),
+            ("bounds", false),
+            ("extensions", false),
+        ],
+        "wpt" | "rtept" | "trkpt" => WPT,
+        "rte" => &[
+            ("name", false),
+            ("cmt", false),
+            ("desc", false),
+            ("src", false),
+            ("link", true),
+            ("number", false),
+            ("type", false),
+            ("extensions", false),
+            ("rtept", true),
+        ],
+        "trk" => &[
+            ("name", false),
+            ("cmt", false),
+            ("desc", false),
+            ("src", false),
+            ("link", true),
+            ("number", false),
+            ("type", false),
+            ("extensions", false),
+            ("trkseg", true),
+        ],
+        "trkseg" => &[("trkpt", true), ("extensions", false)],
+        "author" => &[("name", false), ("email", false), ("link", false)],
+        "link" => &[("text", false), ("type", false)],
+        "copyright" => &[("year", false), ("license", false)],
+        _ => &[],
+    }
+}
+
+/// Required attributes and their types
+fn attributes(element: &str) -> &'static [(&'static str, SimpleType)] {
+    match element {
+        "gpx" => &[("version", SimpleType::Text), ("creator", SimpleType::Text)],
+        "wpt" | "rtept" | "trkpt" => &[
+            ("lat", SimpleType::Latitude),
+            ("lon", SimpleType::Longitude),
+        ],
+        "email" => &[("id", SimpleType::Text), ("domain", SimpleType::Text)],
+        "link" => &[("href", SimpleType::Text)],
+        "copyright" => &[("author", SimpleType::Text)],
+        "bounds" => &[
+            ("minlat", SimpleType::Latitude),
+            ("minlon", SimpleType::Longitude),
+            ("maxlat", SimpleType::Latitude),
+            ("maxlon", SimpleType::Longitude),
+        ],
+        _ => &[],
+    }
+}
+
+/// Type of the text content of a child element
+fn content(parent: &str, element: &str) -> SimpleType {
+    match (parent, element) {
+        (_, "ele" | "geoidheight" | "hdop" | "vdop" | "pdop" | "ageofdgpsdata") => {
+            SimpleType::Decimal
+        }
+        (_, "magvar") => SimpleType::Degrees,
+        (_, "time") => SimpleType::DateTime,
+        (_, "sat" | "number") => SimpleType::NonNegativeInteger,
+        (_, "dgpsid") => SimpleType::DgpsStation,
+        (_, "fix") => SimpleType::Fix,
+        ("copyright", "year") => SimpleType::Year,
+        _ => SimpleType::Text,
+    }
+}
+
+fn valid_value(stype: SimpleType, value: &str) -> bool {
+    let value = value.trim();
+    match stype {
+        SimpleType::Decimal => value.parse::<f64>().is_ok_and(|v| v.is_finite()),
+        SimpleType::Latitude => value
+            .parse::<f64>()
+            .is_ok_and(|v| (-90.0..=90.0).contains(&v)),
+        SimpleType::Longitude => value
+            .parse::<f64>()
+            .is_ok_and(|v| (-180.0..180.0).contains(&v)),
+        SimpleType::Degrees => value
+            .parse::<f64>()
+            .is_ok_and(|v| (0.0..360.0).contains(&v)),
+        SimpleType::DateTime => OffsetDateTime::parse(value, &well_known::Rfc3339).is_ok(),
+        SimpleType::NonNegativeInteger => value.parse::<u64>().is_ok(),
+        SimpleType::DgpsStation => value.parse::<u16>().is_ok_and(|v| v <= 1023),
+        SimpleType::Fix => matches!(value, "none" | "2d" | "3d" | "dgps" | "pps"),
+        SimpleType::Year => value.parse::<i32>().is_ok(),
+        SimpleType::Text => true,
+    }
+}
+
+/// Element being validated
+struct Frame {
+    name: String,
+    parent: String,
+    /// Schema index of the last child found
+    last_child: Option<usize>,
+    text: String,
+}
+
+/// Check the structure of a GPX document against the subset of
+/// the GPX 1.1 schema, returning the violations found
+pub fn check_gpx_structure<R: Read>(source: R) -> Result<Vec<String>, String> {
+    let mut violations = vec![];
+    let mut stack: Vec<Frame> = vec![];
+    // Depth inside of `extensions`, where any content is allowed
+    let mut extensions = 0;
+
+    let mut reader = EventReader::new(source);
+    loop {
+        let event = reader
+            .next()
+            .map_err(|e| format!("Failed on read the GPX: {}", e))?;
+        let line = reader.position().row + 1;
+
+        match event {
+            XmlEvent::StartElement {
+                name,
+                attributes: attrs,
+                ..
+            } => {
+                if extensions > 0 {
+                    extensions += 1;
+                    continue;
+                }
+
+                let element = name.local_name.clone();
+
+                if name.namespace.as_deref() != Some(GPX11_NS) {
+                    violations.push(format!(
+                        "line {}: element `{}` is not in the GPX 1.1 namespace",
+                        line, element
+                    ));
+                }
+
+                match stack.last_mut() {
+                    None if element != "gpx" => {
+                        violations.push(format!("line {}: root element must be `gpx`", line));
+                    }
+                    None => {}
+                    Some(parent) => {
+                        let allowed = children(&parent.name);
+                        match allowed.iter().position(|(c, _)| *c == element) {
+                            None => violations.push(format!(
+                                "line {}: element `{}` not allowed in `{}`",
+                                line, element, parent.name
+                            )),
+                            Some(idx) => {
+                                if let Some(last) = parent.last_child {
+                                    if idx < last {
+                                        violations.push(format!(
+                                            "line {}: element `{}` out of order in `{}`",
+                                            line, element, parent.name
+                                        ));
+                                    } else if idx == last && !allowed[idx].1 {
+                                        violations.push(format!(
+                                            "line {}: element `{}` repeated in `{}`",
+                                            line, element, parent.name
+                                        ));
+                                    }
+                                }
+                                parent.last_child = Some(idx);
+                            }
+                        }
+                    }
+                }
+
+                for (attr, stype) in attributes(&element) {
+                    match attrs.iter().find(|a| a.name.local_name == *attr) {
+                        None => violations.push(format!(
+                            "line {}: attribute `{}` required in `{}`",
+                            line, attr, element
+                        )),
+                        Some(a) if !valid_value(*stype, &a.value) => violations.push(format!(
+                            "line {}: invalid value `{}` for attribute `{}` of `{}`",
+                            line, a.value, attr, element
+                        )),
+                        _ => {}
+                    }
+                }
+
+                if element == "extensions" {
+                    extensions = 1;
+                }
+
+                let parent = stack.last().map(|f| f.name.clone()).unwrap_or_default();
+                stack.push(Frame {
+                    name: element,
+                    parent,
+                    last_child: None,
+                    text: String::new(),
+                });
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) if extensions == 0 => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.text.push_str(&text);
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                if extensions > 1 {
+                    extensions -= 1;
+                    continue;
+                }
+                extensions = 0;
+
+                if let Some(frame) = stack.pop() {
+                    let stype = content(&frame.parent, &frame.name);
+                    if children(&frame.name).is_empty()
+                        && attributes(&frame.name).is_empty()
+                        && !valid_value(stype, &frame.text)
+                    {
+                        violations.push(format!(
+                            "line {}: invalid value `{}` for `{}`",
+                            line,
+                            frame.text.trim(),
+                            frame.name
+                        ));
+                    }
+                }
+            }
+            XmlEvent::EndDocument => break,
+            _ => {}
+        }
+    }
+
+    Ok(violations)
+}
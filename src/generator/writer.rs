@@ -0,0 +1,508 @@
+//! GPX 1.1 writer
+//!
+//! The `gpx` crate writer skips some elements of the schema, like
+//! the track `number`, so we write the documents by ourselves.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use super::manifest::Manifest;
+use gpx::{Gpx, GpxCopyright, Link, Metadata, Person, Route, Track, Waypoint};
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+const GPX11_NS: &str = "http://www.topografix.com/GPX/1/1";
+const GPXTPX_NS: &str = "http://www.garmin.com/xmlschemas/TrackPointExtension/v2";
+const L2G_NS: &str = "https://github.com/fernandobatels/location2gpx";
+
+/// Options of the written document
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputOptions {
+    /// Where write the speed of the points. GPX 1.1 has no
+    /// speed element, so by default the speed is not written
+    pub speed: Vec<SpeedOutput>,
+    /// Unit of the speed on the custom extension
+    pub speed_unit: SpeedUnit,
+    /// Precision of the times
+    pub time_precision: TimePrecision,
+    /// Also write the named track points as waypoints
+    pub waypoints: bool,
+    /// Symbol of the points of each event type, like
+    /// `fuel: Gas Station`, shown as icons by the GPS devices
+    pub symbols: BTreeMap<String, String>,
+    /// Embed the effective configuration of the run on the metadata
+    pub manifest: bool,
+    /// Byte-identical documents for the same positions, with the
+    /// coordinates always written with 7 decimals, or the ones of
+    /// the `coordinate_precision`
+    pub canonical: bool,
+    /// Round the latitudes and longitudes to these decimal places
+    pub coordinate_precision: Option<usize>,
+    /// Converter on the document `creator`, with the `{version}`
+    /// placeholder. Default: `location2gpx`
+    pub creator: Option<String>,
+}
+
+impl OutputOptions {
+    /// Text of the latitude or longitude, without the sign of the zeros
+    pub fn coordinate(&self, value: f64) -> String {
+        let text = match (self.coordinate_precision, self.canonical) {
+            (None, false) => value.to_string(),
+            (None, true) => format!("{:.7}", value),
+            (Some(precision), true) => format!("{:.*}", precision, value),
+            (Some(precision), false) => {
+                let fixed = format!("{:.*}", precision, value);
+                match fixed.contains('.') {
+                    true => fixed
+                        .trim_end_matches('0')
+                        .trim_end_matches('.')
+                        .to_string(),
+                    false => fixed,
+                }
+            }
+        };
+
+        match text.strip_prefix('-') {
+            Some(unsigned) if unsigned.chars().all(|c| c == '0' || c == '.') => {
+                unsigned.to_string()
+            }
+            _ => text,
+        }
+    }
+
+    /// Document creator, with the crate version on the placeholder
+    pub fn creator(&self) -> String {
+        match &self.creator {
+            Some(creator) => creator.replace("{version}", env!("CARGO_PKG_VERSION")),
+            None => "location2gpx".to_string(),
+        }
+    }
+}
+
+/// Precision of the written times
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimePrecision {
+    /// Milliseconds only on the times with a fraction of second
+    #[default]
+    Auto,
+    /// Whole seconds, truncating the fractions
+    Seconds,
+    /// Always with milliseconds, like `00:00:00.000Z`
+    Milliseconds,
+}
+
+impl TimePrecision {
+    /// Format the RFC 3339 time with the precision
+    pub fn format(&self, time: &str) -> Result<String, String> {
+        let time = OffsetDateTime::parse(time, &Rfc3339).map_err(|e| e.to_string())?;
+        let millis = time.millisecond();
+
+        let with_millis = match self {
+            Self::Auto => millis > 0,
+            Self::Seconds => false,
+            Self::Milliseconds => true,
+        };
+
+        let mut formatted = if with_millis {
+            time.format(format_description!(
+                "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]"
+            ))
+        } else {
+            time.format(format_description!(
+                "[year]-[month]-[day]T[hour]:[minute]:[second]"
+            ))
+        }
+        .map_err(|e| e.to_string())?;
+
+        if time.offset().is_utc() {
+            formatted.push('Z');
+        } else {
+            let offset = time
+                .offset()
+                .format(format_description!(
+                    "[offset_hour sign:mandatory]:[offset_minute]"
+                ))
+                .map_err(|e| e.to_string())?;
+            formatted.push_str(&offset);
+        }
+
+        Ok(formatted)
+    }
+}
+
+/// Element used to write the speed
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedOutput {
+    /// GPX 1.0 `<speed>`, in m/s. Not valid on the GPX 1.1 schema
+    Element,
+    /// Garmin `<gpxtpx:speed>` of the TrackPointExtension v2, in m/s
+    TrackPointExtension,
+    /// `<l2g:speed unit="...">`, in the configured unit
+    Custom,
+}
+
+/// Unit of the speed values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum SpeedUnit {
+    #[default]
+    #[serde(rename = "m/s")]
+    MetersPerSecond,
+    #[serde(rename = "km/h")]
+    KilometersPerHour,
+    #[serde(rename = "knots")]
+    Knots,
+}
+
+impl SpeedUnit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MetersPerSecond => "m/s",
+            Self::KilometersPerHour => "km/h",
+            Self::Knots => "knots",
+        }
+    }
+
+    /// Unit of the name, like on the `unit` of the `<l2g:speed>`
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::MetersPerSecond, Self::KilometersPerHour, Self::Knots]
+            .into_iter()
+            .find(|unit| unit.name() == name)
+    }
+
+    /// Convert the speed from m/s to this unit
+    pub fn from_mps(&self, speed: f64) -> f64 {
+        match self {
+            Self::MetersPerSecond => speed,
+            Self::KilometersPerHour => speed * 3.6,
+            Self::Knots => speed * 3600.0 / 1852.0,
+        }
+    }
+
+    /// Convert the speed from this unit to m/s
+    pub fn to_mps(&self, speed: f64) -> f64 {
+        match self {
+            Self::MetersPerSecond => speed,
+            Self::KilometersPerHour => speed / 3.6,
+            Self::Knots => speed * 1852.0 / 3600.0,
+        }
+    }
+}
+
+/// Write the document in the GPX 1.1 format
+pub fn write_gpx<W: Write>(doc: &Gpx, writer: W) -> Result<(), String> {
+    write_gpx_with(doc, &OutputOptions::default(), writer)
+}
+
+/// Write the document in the GPX 1.1 format with the given options
+pub fn write_gpx_with<W: Write>(
+    doc: &Gpx,
+    options: &OutputOptions,
+    writer: W,
+) -> Result<(), String> {
+    write_gpx_manifest(doc, options, None, writer)
+}
+
+/// Write the document in the GPX 1.1 format with the given options,
+/// and the manifest as a `<l2g:manifest>` metadata extension
+pub fn write_gpx_manifest<W: Write>(
+    doc: &Gpx,
+    options: &OutputOptions,
+    manifest: Option<&Manifest>,
+    writer: W,
+) -> Result<(), String> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(writer);
+
+    let creator = doc.creator.as_deref().unwrap_or("location2gpx");
+    let mut root = XmlEvent::start_element("gpx")
+        .attr("version", "1.1")
+        .attr("xmlns", GPX11_NS)
+        .attr("creator", creator);
+    if options.speed.contains(&SpeedOutput::TrackPointExtension) {
+        root = root.ns("gpxtpx", GPXTPX_NS);
+    }
+    if options.speed.contains(&SpeedOutput::Custom) || manifest.is_some() {
+        root = root.ns("l2g", L2G_NS);
+    }
+    event(&mut writer, root)?;
+
+    if doc.metadata.is_some() || manifest.is_some() {
+        let metadata = doc.metadata.clone().unwrap_or_default();
+        write_metadata(&mut writer, options, &metadata, manifest)?;
+    }
+    for wp in &doc.waypoints {
+        write_waypoint(&mut writer, options, "wpt", wp)?;
+    }
+    for route in &doc.routes {
+        write_route(&mut writer, options, route)?;
+    }
+    for track in &doc.tracks {
+        write_track(&mut writer, options, track)?;
+    }
+
+    event(&mut writer, XmlEvent::end_element())
+}
+
+fn event<'a, W, E>(writer: &mut EventWriter<W>, event: E) -> Result<(), String>
+where
+    W: Write,
+    E: Into<XmlEvent<'a>>,
+{
+    writer
+        .write(event)
+        .map_err(|e| format!("Failed on write the GPX: {}", e))
+}
+
+fn write_value<W: Write, T: ToString>(
+    writer: &mut EventWriter<W>,
+    element: &str,
+    value: &Option<T>,
+) -> Result<(), String> {
+    if let Some(value) = value {
+        event(writer, XmlEvent::start_element(element))?;
+        event(writer, XmlEvent::characters(&value.to_string()))?;
+        event(writer, XmlEvent::end_element())?;
+    }
+
+    Ok(())
+}
+
+fn write_time<W: Write, E: ToString>(
+    writer: &mut EventWriter<W>,
+    options: &OutputOptions,
+    time: Option<Result<String, E>>,
+) -> Result<(), String> {
+    let time = time
+        .transpose()
+        .map_err(|e| e.to_string())?
+        .map(|tm| options.time_precision.format(&tm))
+        .transpose()?;
+
+    write_value(writer, "time", &time)
+}
+
+fn write_metadata<W: Write>(
+    writer: &mut EventWriter<W>,
+    options: &OutputOptions,
+    metadata: &Metadata,
+    manifest: Option<&Manifest>,
+) -> Result<(), String> {
+    event(writer, XmlEvent::start_element("metadata"))?;
+    write_value(writer, "name", &metadata.name)?;
+    write_value(writer, "desc", &metadata.description)?;
+    if let Some(author) = &metadata.author {
+        write_person(writer, "author", author)?;
+    }
+    if let Some(copyright) = &metadata.copyright {
+        write_copyright(writer, copyright)?;
+    }
+    for link in &metadata.links {
+        write_link(writer, link)?;
+    }
+    write_time(writer, options, metadata.time.map(|tm| tm.format()))?;
+    write_value(writer, "keywords", &metadata.keywords)?;
+    if let Some(bounds) = &metadata.bounds {
+        event(
+            writer,
+            XmlEvent::start_element("bounds")
+                .attr("minlat", &options.coordinate(bounds.min().y))
+                .attr("minlon", &options.coordinate(bounds.min().x))
+                .attr("maxlat", &options.coordinate(bounds.max().y))
+                .attr("maxlon", &options.coordinate(bounds.max().x)),
+        )?;
+        event(writer, XmlEvent::end_element())?;
+    }
+    if let Some(manifest) = manifest {
+        event(writer, XmlEvent::start_element("extensions"))?;
+        write_value(writer, "l2g:manifest", &Some(manifest.to_yaml()?))?;
+        event(writer, XmlEvent::end_element())?;
+    }
+    event(writer, XmlEvent::end_element())
+}
+
+fn write_person<W: Write>(
+    writer: &mut EventWriter<W>,
+    element: &str,
+    person: &Person,
+) -> Result<(), String> {
+    event(writer, XmlEvent::start_element(element))?;
+    write_value(writer, "name", &person.name)?;
+    if let Some(email) = &person.email {
+        let (id, domain) = email
+            .split_once('@')
+            .ok_or(format!("Invalid email `{}`", email))?;
+        event(
+            writer,
+            XmlEvent::start_element("email")
+                .attr("id", id)
+                .attr("domain", domain),
+        )?;
+        event(writer, XmlEvent::end_element())?;
+    }
+    if let Some(link) = &person.link {
+        write_link(writer, link)?;
+    }
+    event(writer, XmlEvent::end_element())
+}
+
+fn write_copyright<W: Write>(
+    writer: &mut EventWriter<W>,
+    copyright: &GpxCopyright,
+) -> Result<(), String> {
+    let author = copyright.author.as_deref().unwrap_or_default();
+    event(
+        writer,
+        XmlEvent::start_element("copyright").attr("author", author),
+    )?;
+    write_value(writer, "year", &copyright.year)?;
+    write_value(writer, "license", &copyright.license)?;
+    event(writer, XmlEvent::end_element())
+}
+
+fn write_link<W: Write>(writer: &mut EventWriter<W>, link: &Link) -> Result<(), String> {
+    event(
+        writer,
+        XmlEvent::start_element("link").attr("href", &link.href),
+    )?;
+    write_value(writer, "text", &link.text)?;
+    write_value(writer, "type", &link._type)?;
+    event(writer, XmlEvent::end_element())
+}
+
+fn write_route<W: Write>(
+    writer: &mut EventWriter<W>,
+    options: &OutputOptions,
+    route: &Route,
+) -> Result<(), String> {
+    event(writer, XmlEvent::start_element("rte"))?;
+    write_value(writer, "name", &route.name)?;
+    write_value(writer, "cmt", &route.comment)?;
+    write_value(writer, "desc", &route.description)?;
+    write_value(writer, "src", &route.source)?;
+    for link in &route.links {
+        write_link(writer, link)?;
+    }
+    write_value(writer, "number", &route.number)?;
+    write_value(writer, "type", &route._type)?;
+    for wp in &route.points {
+        write_waypoint(writer, options, "rtept", wp)?;
+    }
+    event(writer, XmlEvent::end_element())
+}
+
+fn write_track<W: Write>(
+    writer: &mut EventWriter<W>,
+    options: &OutputOptions,
+    track: &Track,
+) -> Result<(), String> {
+    event(writer, XmlEvent::start_element("trk"))?;
+    write_value(writer, "name", &track.name)?;
+    write_value(writer, "cmt", &track.comment)?;
+    write_value(writer, "desc", &track.description)?;
+    write_value(writer, "src", &track.source)?;
+    for link in &track.links {
+        write_link(writer, link)?;
+    }
+    write_value(writer, "number", &track.number)?;
+    write_value(writer, "type", &track._type)?;
+    for segment in &track.segments {
+        event(writer, XmlEvent::start_element("trkseg"))?;
+        for wp in &segment.points {
+            write_waypoint(writer, options, "trkpt", wp)?;
+        }
+        event(writer, XmlEvent::end_element())?;
+    }
+    event(writer, XmlEvent::end_element())
+}
+
+fn write_waypoint<W: Write>(
+    writer: &mut EventWriter<W>,
+    options: &OutputOptions,
+    element: &str,
+    wp: &Waypoint,
+) -> Result<(), String> {
+    event(
+        writer,
+        XmlEvent::start_element(element)
+            .attr("lat", &options.coordinate(wp.point().y()))
+            .attr("lon", &options.coordinate(wp.point().x())),
+    )?;
+    write_value(writer, "ele", &wp.elevation)?;
+    write_time(writer, options, wp.time.map(|tm| tm.format()))?;
+    if options.speed.contains(&SpeedOutput::Element) {
+        write_value(writer, "speed", &wp.speed)?;
+    }
+    write_value(writer, "geoidheight", &wp.geoidheight)?;
+    write_value(writer, "name", &wp.name)?;
+    write_value(writer, "cmt", &wp.comment)?;
+    write_value(writer, "desc", &wp.description)?;
+    write_value(writer, "src", &wp.source)?;
+    for link in &wp.links {
+        write_link(writer, link)?;
+    }
+    write_value(writer, "sym", &wp.symbol)?;
+    write_value(writer, "type", &wp._type)?;
+    let fix = wp.fix.as_ref().map(|fix| match fix {
+        gpx::Fix::None => "none",
+        gpx::Fix::TwoDimensional => "2d",
+        gpx::Fix::ThreeDimensional => "3d",
+        gpx::Fix::DGPS => "dgps",
+        gpx::Fix::PPS => "pps",
+        gpx::Fix::Other(other) => other,
+    });
+    write_value(writer, "fix", &fix)?;
+    write_value(writer, "sat", &wp.sat)?;
+    write_value(writer, "hdop", &wp.hdop)?;
+    write_value(writer, "vdop", &wp.vdop)?;
+    write_value(writer, "pdop", &wp.pdop)?;
+    write_value(writer, "ageofdgpsdata", &wp.dgps_age)?;
+    write_value(writer, "dgpsid", &wp.dgpsid)?;
+    write_extensions(writer, options, wp)?;
+    event(writer, XmlEvent::end_element())
+}
+
+fn write_extensions<W: Write>(
+    writer: &mut EventWriter<W>,
+    options: &OutputOptions,
+    wp: &Waypoint,
+) -> Result<(), String> {
+    let speed = match wp.speed {
+        Some(speed) => speed,
+        None => return Ok(()),
+    };
+    let tpx = options.speed.contains(&SpeedOutput::TrackPointExtension);
+    let custom = options.speed.contains(&SpeedOutput::Custom);
+    if !tpx && !custom {
+        return Ok(());
+    }
+
+    event(writer, XmlEvent::start_element("extensions"))?;
+    if tpx {
+        event(
+            writer,
+            XmlEvent::start_element("gpxtpx:TrackPointExtension"),
+        )?;
+        write_value(writer, "gpxtpx:speed", &Some(speed))?;
+        event(writer, XmlEvent::end_element())?;
+    }
+    if custom {
+        let unit = options.speed_unit;
+        event(
+            writer,
+            XmlEvent::start_element("l2g:speed").attr("unit", unit.name()),
+        )?;
+        event(
+            writer,
+            XmlEvent::characters(&unit.from_mps(speed).to_string()),
+        )?;
+        event(writer, XmlEvent::end_element())?;
+    }
+    event(writer, XmlEvent::end_element())
+}
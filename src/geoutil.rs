@@ -0,0 +1,99 @@
+//! Geodesic helpers over points and tracks, in meters and degrees
+
+use geo::{Bearing, BoundingRect, HaversineDistance, MultiPoint, Point, Rect};
+use gpx::{Track, TrackSegment};
+
+/// Meters of a degree of latitude
+pub const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Haversine distance between the points, in meters
+pub fn distance(from: Point, to: Point) -> f64 {
+    from.haversine_distance(&to)
+}
+
+/// Initial bearing from a point to another, in degrees
+/// from the north, clockwise, between 0 and 360
+pub fn bearing(from: Point, to: Point) -> f64 {
+    from.bearing(to).rem_euclid(360.0)
+}
+
+/// Bounding box of the points, if any
+pub fn bounds<I: IntoIterator<Item = Point>>(points: I) -> Option<Rect> {
+    points.into_iter().collect::<MultiPoint>().bounding_rect()
+}
+
+/// Bounding box of all the points of the tracks, if any
+pub fn tracks_bounds(tracks: &[Track]) -> Option<Rect> {
+    bounds(
+        tracks
+            .iter()
+            .flat_map(|track| &track.segments)
+            .flat_map(|segment| &segment.points)
+            .map(|wp| wp.point()),
+    )
+}
+
+/// Length of the segment, in meters
+pub fn segment_length(segment: &TrackSegment) -> f64 {
+    segment
+        .points
+        .windows(2)
+        .map(|w| distance(w[0].point(), w[1].point()))
+        .sum()
+}
+
+/// Length of the track, in meters. The gaps
+/// between the segments are not included
+pub fn track_length(track: &Track) -> f64 {
+    track.segments.iter().map(segment_length).sum()
+}
+
+/// Length of each point since the start of the segment, in meters
+pub fn cumulative_length(segment: &TrackSegment) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut lengths = Vec::with_capacity(segment.points.len());
+
+    for (i, wp) in segment.points.iter().enumerate() {
+        if i > 0 {
+            total += distance(segment.points[i - 1].point(), wp.point());
+        }
+        lengths.push(total);
+    }
+
+    lengths
+}
+
+#[test]
+fn geodesic_helpers() {
+    use gpx::Waypoint;
+
+    let a = Point::new(-48.87, -26.31);
+    let b = Point::new(-48.87, -26.30);
+    let c = Point::new(-48.86, -26.30);
+
+    let d = distance(a, b);
+    assert!((d - 1111.9).abs() < 1.0, "{}", d);
+    assert_eq!(0.0, distance(a, a));
+
+    assert!(bearing(a, b).abs() < 1e-6);
+    assert!((bearing(b, c) - 90.0).abs() < 0.01);
+    assert!((bearing(b, a) - 180.0).abs() < 1e-6);
+    assert!((bearing(c, b) - 270.0).abs() < 0.01);
+
+    let mut segment = TrackSegment::new();
+    segment.points = vec![Waypoint::new(a), Waypoint::new(b), Waypoint::new(c)];
+    let lengths = cumulative_length(&segment);
+    assert_eq!(3, lengths.len());
+    assert_eq!(0.0, lengths[0]);
+    assert_eq!(d, lengths[1]);
+    assert_eq!(lengths[2], segment_length(&segment));
+
+    let mut track = Track::new();
+    track.segments = vec![segment.clone(), segment];
+    assert_eq!(lengths[2] * 2.0, track_length(&track));
+
+    let rect = tracks_bounds(&[track]).unwrap();
+    assert_eq!(Point::new(-48.87, -26.31), rect.min().into());
+    assert_eq!(Point::new(-48.86, -26.30), rect.max().into());
+    assert_eq!(None, tracks_bounds(&[]));
+}
@@ -1,9 +1,44 @@
 //! location2gpx - GPX generator from many location sources
 
+pub mod config;
 mod generator;
+pub mod geoutil;
+pub mod secrets;
 pub mod sources;
 
+pub use generator::append::append_tracks;
+pub use generator::crs::Crs;
+pub use generator::destination::DestinationTemplate;
+pub use generator::deviation::{DeviationOptions, OffRoute, RouteDeviation};
+pub use generator::direction::{Direction, DirectionOptions};
+pub use generator::dop::DopFilter;
+pub use generator::elevation::{ElevationOptions, ElevationSource};
+pub use generator::filter::PointFilter;
+pub use generator::geoid::{GeoidGrid, GeoidOptions};
+pub use generator::geojson::tracks_geojson;
 pub use generator::gpx::GpxGenerator;
-pub use generator::position::{DevicePosition, RawPosition};
-pub use generator::tracker::{SourceToTracks, TrackSegmentOptions, Tracker};
-pub use sources::{FieldsConfiguration, PositionsSource};
+pub use generator::grouping::GroupingStrategy;
+pub use generator::heatmap::{HeatCell, Heatmap, HeatmapOptions};
+pub use generator::labels::PointLabels;
+pub use generator::manifest::Manifest;
+pub use generator::model::{TrackModel, TrackPoint};
+pub use generator::period::{group_by_period, CalendarPeriod};
+pub use generator::position::{DevicePosition, Names, RawPosition};
+pub use generator::preview::Preview;
+pub use generator::quality::{DeviceQuality, Gap};
+pub use generator::report::{RejectStage, Rejection, Report, ReportEntry};
+pub use generator::sanity::{SanityAction, SanityLimits};
+pub use generator::sentinel::Sentinels;
+pub use generator::spill::SpillOptions;
+pub use generator::tracker::{
+    BucketAlignment, PipelineStage, SourceToTracks, TrackOptions, TrackOrder, TrackSegmentOptions,
+    Tracker,
+};
+pub use generator::transform::PositionTransform;
+pub use generator::trips::TripOptions;
+pub use generator::validate::check_gpx_structure;
+pub use generator::writer::{
+    write_gpx, write_gpx_manifest, write_gpx_with, OutputOptions, SpeedOutput, SpeedUnit,
+    TimePrecision,
+};
+pub use sources::{FieldAliases, FieldsConfiguration, IntervalMode, PositionsSource, TimeRange};
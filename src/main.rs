@@ -1,205 +1,1760 @@
 //! location2gpx cli - GPX generator from many location sources
 
 use std::fs::{self, File};
-use std::io::BufWriter;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
 
-use argopt::{cmd_group, subcmd};
-use bson::{doc, Document};
-use csv::Reader;
-use mongodb::sync::Client;
-use serde::Deserialize;
+use bson::{doc, Bson, Document};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use gpx::Track;
+use mongodb::sync::{Client, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use time::format_description::well_known;
-use time::OffsetDateTime;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime, PrimitiveDateTime, UtcOffset};
 
-use location2gpx::sources::{CsvSource, MongoDbSource};
-use location2gpx::{FieldsConfiguration, GpxGenerator, SourceToTracks, TrackSegmentOptions};
+use location2gpx::config::ConfigLoader;
+use location2gpx::secrets;
+use location2gpx::sources::{
+    fetch_range, read_gpx, AppleHealthSource, CsvLines, CsvOptions, CsvSource, DevicesLookup,
+    FlespiSource, GpxSource, MongoDbSource, MongoOptions, PlainTextSource, QueryPlan,
+    StravaActivity, StravaSource, SyntheticOptions, SyntheticSource,
+};
+use location2gpx::{
+    append_tracks, check_gpx_structure, group_by_period, tracks_geojson, Crs, DestinationTemplate,
+    FieldsConfiguration, GpxGenerator, Heatmap, HeatmapOptions, IntervalMode, Manifest,
+    OutputOptions, PositionTransform, PositionsSource, Report, SourceToTracks, TimeRange,
+    TrackOptions, TrackSegmentOptions,
+};
+
+/// Rows read to guess the CSV columns
+const SNIFF_ROWS: usize = 100;
 
 /// CLI of location2gpx - Convert your raw GPS data into a GPX file
-#[cmd_group(commands = [mongo,csv])]
-fn main() -> Result<(), String> {}
+#[derive(Parser)]
+#[command(name = "location2gpx", version)]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalOptions,
+    /// Validate the config and exit, showing the unknown keys and the invalid values
+    #[arg(long)]
+    check_config: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-/// Generate a GPX from a CSV file source
-#[subcmd]
-fn csv(
+/// Options shared by all the subcommands
+#[derive(Args)]
+#[command(next_help_heading = "Global options")]
+struct GlobalOptions {
+    /// Fields and segments configuration. Default: .loc2gpx.yaml, ~/.config/location2gpx/config.yaml, ~/.loc2gpx.yaml
+    #[arg(long, global = true)]
+    config: Option<String>,
+    /// Profile of the config, merged over its `defaults` section
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Show the loaded config and the written files
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// UTC offset, like `-03:00`, of the times without one and of the printed times
+    #[arg(long, global = true, default_value = "+00:00", allow_hyphen_values = true, value_parser = parse_offset)]
+    timezone: UtcOffset,
+    /// Output of the stats and preview commands, tab separated columns or JSON
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SourceKind {
+    Csv,
+    Flespi,
+    Mongo,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a GPX from a mongodb collection source
+    Mongo(MongoArgs),
+    /// Generate a GPX from a CSV file source
+    Csv(CsvArgs),
+    /// Generate a GPX from a Teltonika JSON messages file, as decoded by flespi
+    Flespi(FlespiArgs),
+    /// Generate a GPX from the activities of a Strava bulk export ZIP
+    ///
+    /// Only the GPX activities are read, also the gzipped ones. The FIT
+    /// and TCX files are not supported and their activities are skipped
+    Strava(StravaArgs),
+    /// Generate a GPX from the workout routes of an Apple Health export ZIP
+    AppleHealth(AppleHealthArgs),
+    /// Generate a GPX from a text file of `lat,lon[,time]` lines or `geo:` URIs
+    Text(TextArgs),
+    /// Generate a GPX from existing GPX files, with the filters of the config
+    Reprocess(ReprocessArgs),
+    /// Show the quality metrics of the devices of a source
+    Stats(StatsArgs),
+    /// Count the positions and the tracks of an export, without building them
+    Preview(PreviewArgs),
+    /// Write the density of the positions of a source on a grid, as GeoJSON
+    Heatmap(HeatmapArgs),
+    /// Time the generation of synthetic random walk tracks
+    #[command(hide = true)]
+    Bench(BenchArgs),
+    /// Print the completion script of the shell
+    Completions {
+        /// bash, elvish, fish, powershell or zsh
+        shell: Shell,
+    },
+    /// Print the man page
+    Man,
+}
+
+/// Period of the positions
+#[derive(Args)]
+struct PeriodOptions {
+    /// Only the positions since this time, RFC3339 format or without the offset of `--timezone`
+    #[arg(long)]
+    since: Option<String>,
+    /// Only the positions until this time, RFC3339 format or without the offset of `--timezone`
+    #[arg(long)]
+    until: Option<String>,
+}
+
+/// Options of the generated documents
+#[derive(Args)]
+struct ExportOptions {
+    #[command(flatten)]
+    period: PeriodOptions,
+    /// Check the generated file against a structural subset of the GPX 1.1 schema, not the XSD
+    #[arg(long)]
+    validate_output: bool,
+    /// One GPX per calendar period, named like `dest-2021-05.gpx`
+    #[arg(long, value_parser = ["week", "month"])]
+    split_by: Option<String>,
+    /// Fail, without writing the destination, when the source returns no positions
+    #[arg(long)]
+    fail_if_empty: bool,
+    /// Keep the existing destination files, skipping their tracks
+    #[arg(long, conflicts_with = "force")]
+    no_clobber: bool,
+    /// Add the tracks to the existing destination files, skipping the ones with the same name and time span
+    #[arg(long, conflicts_with_all = ["no_clobber", "force"])]
+    append: bool,
+    /// Overwrite the existing destination files, even with the `no_clobber` config
+    #[arg(long)]
+    force: bool,
+    /// Also write each document as GeoJSON, next to it with the `.geojson` extension
+    #[arg(long)]
+    also_geojson: bool,
+    /// Also write the quality stats of the run on this path, as the JSON of the stats command
+    #[arg(long)]
+    also_stats: Option<String>,
+    /// Write the positions dropped by the cleaning on this CSV, with the stage and the reason
+    #[arg(long)]
+    rejects: Option<String>,
+    /// Keep the source record of each position, written on the `--rejects` CSV
+    #[cfg(feature = "raw")]
+    #[arg(long)]
+    raw_records: bool,
+}
+
+#[derive(Args)]
+struct CsvArgs {
     /// CSV file source
     csv_path: String,
-    /// Start time, RFC3339 format
-    start: String,
-    /// End time, RFC3339 format
-    end: String,
-    /// GPX path file destination
+    /// GPX path file destination, or a template like `exports/{device}/{date}.gpx`.
+    /// Default: the `destinations` of the config
+    destination: Option<String>,
+    /// Guess the columns of the fields not found on the header
+    #[arg(long)]
+    sniff: bool,
+    /// Threads parsing the rows. Default: the `csv` config or the available cores
+    #[arg(long)]
+    threads: Option<usize>,
+    #[command(flatten)]
+    export: ExportOptions,
+}
+
+#[derive(Args)]
+struct FlespiArgs {
+    /// JSON file source, with an array of messages or one message per line
+    json_path: String,
+    /// GPX path file destination, or a template like `exports/{device}/{date}.gpx`.
+    /// Default: the `destinations` of the config
+    destination: Option<String>,
+    #[command(flatten)]
+    export: ExportOptions,
+}
+
+#[derive(Args)]
+struct StravaArgs {
+    /// ZIP file of the Strava bulk export
+    zip_path: String,
+    /// GPX path file destination, or a template like `exports/{device}/{date}.gpx`.
+    /// Default: the `destinations` of the config
+    destination: Option<String>,
+    #[command(flatten)]
+    export: ExportOptions,
+}
+
+#[derive(Args)]
+struct AppleHealthArgs {
+    /// ZIP file of the Health app export
+    zip_path: String,
+    /// GPX path file destination, or a template like `exports/{device}/{date}.gpx`.
+    /// Default: the `destinations` of the config
+    destination: Option<String>,
+    #[command(flatten)]
+    export: ExportOptions,
+}
+
+#[derive(Args)]
+struct TextArgs {
+    /// Text file source, with a position per line
+    text_path: String,
+    /// GPX path file destination, or a template like `exports/{device}/{date}.gpx`.
+    /// Default: the `destinations` of the config
+    destination: Option<String>,
+    /// Device of the positions. Default: the file name
+    #[arg(long)]
+    device: Option<String>,
+    /// Time of the first lines without a time, RFC3339 format or without the offset of `--timezone`
+    #[arg(long)]
+    start: Option<String>,
+    /// Seconds between the lines without a time
+    #[arg(long, default_value_t = 1)]
+    interval: u32,
+    #[command(flatten)]
+    export: ExportOptions,
+}
+
+#[derive(Args)]
+struct ReprocessArgs {
+    /// GPX files source, comma separated
+    gpx_paths: String,
+    /// GPX path file destination, or a template like `exports/{device}/{date}.gpx`.
+    /// Default: the `destinations` of the config
+    destination: Option<String>,
+    #[command(flatten)]
+    export: ExportOptions,
+}
+
+#[derive(Args)]
+struct MongoArgs {
+    /// Mongo connection string source, or a `env:VAR` or `keyring:service/account` reference
+    connection: String,
+    /// Mongo collection name, a comma separated list or a pattern like `positions_{year}_{month}`
+    collection: String,
+    /// GPX path file destination, or a template like `exports/{device}/{date}.gpx`.
+    /// Default: the `destinations` of the config
+    destination: Option<String>,
+    /// Print the query plan of each collection, without exporting
+    #[arg(long)]
+    explain: bool,
+    #[command(flatten)]
+    export: ExportOptions,
+}
+
+/// Source of the stats and preview commands
+#[derive(Args)]
+struct SourceOptions {
+    /// Source kind
+    #[arg(value_enum)]
+    kind: SourceKind,
+    /// CSV or JSON file path, or the mongo connection string
+    location: String,
+    /// Mongo collection name, a comma separated list or a pattern like `positions_{year}_{month}`
+    #[arg(long, required_if_eq("kind", "mongo"))]
+    collection: Option<String>,
+    #[command(flatten)]
+    period: PeriodOptions,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    #[command(flatten)]
+    source: SourceOptions,
+    /// List the periods longer than this, in seconds, without positions of a device
+    #[arg(long)]
+    gap_threshold: Option<u32>,
+}
+
+#[derive(Args)]
+struct PreviewArgs {
+    #[command(flatten)]
+    source: SourceOptions,
+}
+
+#[derive(Args)]
+struct HeatmapArgs {
+    #[command(flatten)]
+    source: SourceOptions,
+    /// GeoJSON path file destination
     destination: String,
-    /// Fields and segments configuration. Default: .loc2gpx.yaml, ~/.loc2gpx.yaml
-    #[opt(long)]
-    config: Option<String>,
-) -> Result<(), String> {
-    let start = OffsetDateTime::parse(&start, &well_known::Rfc3339)
-        .map_err(|e| format!("Failed on parse the start time: {}", e.to_string()))?;
-    let end = OffsetDateTime::parse(&end, &well_known::Rfc3339)
-        .map_err(|e| format!("Failed on parse the end time: {}", e.to_string()))?;
+    /// Size of the grid cells, in meters. Default: the `heatmap` config or 100
+    #[arg(long)]
+    cell_m: Option<f64>,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// GPX path file destination. Default: the document is discarded
+    destination: Option<String>,
+    #[arg(long, default_value_t = 10)]
+    devices: usize,
+    /// Positions of each device
+    #[arg(long, default_value_t = 10000)]
+    points: usize,
+    /// Seconds between the positions
+    #[arg(long, default_value_t = 5)]
+    interval: u32,
+    /// Max GPS error added to the positions, in meters
+    #[arg(long, default_value_t = 5.0)]
+    noise_m: f64,
+    /// Seed of the random walks
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+}
+
+fn main() -> Result<(), String> {
+    let cli = Cli::parse();
+    let global = &cli.global;
+
+    let command = match (cli.command, cli.check_config) {
+        (None, true) => return check_config(global),
+        (Some(command), false) => command,
+        (Some(_), true) => Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "the `--check-config` option can not be used with a command",
+            )
+            .exit(),
+        (None, false) => Cli::command()
+            .error(
+                ErrorKind::MissingSubcommand,
+                "a command or the `--check-config` option is required",
+            )
+            .exit(),
+    };
+
+    match command {
+        Command::Mongo(args) => mongo(args, global),
+        Command::Csv(args) => csv(args, global),
+        Command::Flespi(args) => flespi(args, global),
+        Command::Strava(args) => strava(args, global),
+        Command::AppleHealth(args) => apple_health(args, global),
+        Command::Text(args) => text(args, global),
+        Command::Reprocess(args) => reprocess(args, global),
+        Command::Stats(args) => stats(args, global),
+        Command::Preview(args) => preview(args, global),
+        Command::Heatmap(args) => heatmap(args, global),
+        Command::Bench(args) => bench(args, global),
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "location2gpx",
+                &mut io::stdout(),
+            );
+            Ok(())
+        }
+        Command::Man => clap_mangen::Man::new(Cli::command())
+            .render(&mut io::stdout())
+            .map_err(|e| format!("Failed on write the man page: {}", e)),
+    }
+}
+
+/// Generate a GPX from a CSV file source
+fn csv(args: CsvArgs, global: &GlobalOptions) -> Result<(), String> {
+    let mut configs = load_configs(global)?;
+    configs.csv.sniff |= args.sniff;
+    if let Some(threads) = args.threads {
+        configs.csv.threads = Some(threads);
+    }
+
+    #[allow(unused_mut)]
+    let mut source = csv_source(&args.csv_path, &configs)?;
+    #[cfg(feature = "raw")]
+    source.keep_raw(args.export.raw_records);
+
+    export(
+        source,
+        &format!("csv {}", args.csv_path),
+        destination(args.destination, &configs.destinations.csv, "csv")?,
+        args.export,
+        configs,
+        global,
+    )
+}
+
+/// Generate a GPX from a Teltonika JSON messages file, as decoded by flespi
+fn flespi(args: FlespiArgs, global: &GlobalOptions) -> Result<(), String> {
+    let json =
+        File::open(&args.json_path).map_err(|e| format!("Failed on open the JSON file: {}", e))?;
+
+    let configs = load_configs(global)?;
+
+    #[allow(unused_mut)]
+    let mut source = FlespiSource::new(BufReader::new(json));
+    #[cfg(feature = "raw")]
+    source.keep_raw(args.export.raw_records);
+
+    export(
+        source,
+        &format!("flespi {}", args.json_path),
+        destination(args.destination, &configs.destinations.flespi, "flespi")?,
+        args.export,
+        configs,
+        global,
+    )
+}
+
+/// Generate a GPX from the activities of a Strava bulk export ZIP
+fn strava(args: StravaArgs, global: &GlobalOptions) -> Result<(), String> {
+    let zip =
+        File::open(&args.zip_path).map_err(|e| format!("Failed on open the ZIP file: {}", e))?;
+
+    let configs = load_configs(global)?;
+
+    let source = StravaSource::new(BufReader::new(zip))?;
+    let unsupported: Vec<&StravaActivity> = source.unsupported().collect();
+    if !unsupported.is_empty() {
+        eprintln!(
+            "{} of {} activities skipped, only the GPX files are supported",
+            unsupported.len(),
+            source.activities().len()
+        );
+    }
+    if global.verbose {
+        for activity in unsupported {
+            eprintln!("Activity {} skipped: {}", activity.id, activity.file);
+        }
+    }
+
+    export(
+        source,
+        &format!("strava {}", args.zip_path),
+        destination(args.destination, &configs.destinations.strava, "strava")?,
+        args.export,
+        configs,
+        global,
+    )
+}
+
+/// Generate a GPX from the workout routes of an Apple Health export ZIP
+fn apple_health(args: AppleHealthArgs, global: &GlobalOptions) -> Result<(), String> {
+    let zip =
+        File::open(&args.zip_path).map_err(|e| format!("Failed on open the ZIP file: {}", e))?;
+
+    let configs = load_configs(global)?;
+
+    let source = AppleHealthSource::new(BufReader::new(zip))?;
+    if global.verbose {
+        println!("{} workouts with routes", source.workouts().len());
+    }
 
-    let destination = File::create(destination)
-        .map_err(|e| format!("Failed on create the destination file: {}", e.to_string()))?;
+    export(
+        source,
+        &format!("apple-health {}", args.zip_path),
+        destination(
+            args.destination,
+            &configs.destinations.apple_health,
+            "apple_health",
+        )?,
+        args.export,
+        configs,
+        global,
+    )
+}
+
+/// Generate a GPX from a text file of `lat,lon[,time]` lines or `geo:` URIs
+fn text(args: TextArgs, global: &GlobalOptions) -> Result<(), String> {
+    let file =
+        File::open(&args.text_path).map_err(|e| format!("Failed on open the text file: {}", e))?;
 
-    let csv = File::open(csv_path)
-        .map_err(|e| format!("Failed on open the CSV file: {}", e.to_string()))?;
-    let rcsv = Reader::from_reader(csv);
+    let configs = load_configs(global)?;
 
-    let (fields, op) = load_configs(config);
+    let mut source = PlainTextSource::new(file);
+    let device = match &args.device {
+        Some(device) => device.clone(),
+        None => Path::new(&args.text_path)
+            .file_stem()
+            .map_or("text".to_string(), |s| s.to_string_lossy().to_string()),
+    };
+    source.device(&device);
+    if let Some(start) = parse_time(args.start, "start", global.timezone)? {
+        source.untimed(start, args.interval);
+    }
+
+    export(
+        source,
+        &format!("text {}", args.text_path),
+        destination(args.destination, &configs.destinations.text, "text")?,
+        args.export,
+        configs,
+        global,
+    )
+}
+
+/// Generate a GPX from existing GPX files, with the filters of the config
+fn reprocess(args: ReprocessArgs, global: &GlobalOptions) -> Result<(), String> {
+    let mut files = vec![];
+    for path in args.gpx_paths.split(',') {
+        let file = File::open(path.trim())
+            .map_err(|e| format!("Failed on open the GPX file {}: {}", path, e))?;
+        files.push(BufReader::new(file));
+    }
+
+    let configs = load_configs(global)?;
+
+    let mut files = files.into_iter();
+    let mut source = GpxSource::new(files.next().ok_or("GPX files not provided")?);
+    for file in files {
+        source.add(file);
+    }
+
+    export(
+        source,
+        &format!("reprocess {}", args.gpx_paths),
+        destination(
+            args.destination,
+            &configs.destinations.reprocess,
+            "reprocess",
+        )?,
+        args.export,
+        configs,
+        global,
+    )
+}
+
+/// Generate a GPX from a mongodb collection source
+fn mongo(args: MongoArgs, global: &GlobalOptions) -> Result<(), String> {
+    let db = mongo_database(&args.connection)?;
 
-    let source = CsvSource::new(rcsv, Some(fields));
+    let configs = load_configs(global)?;
 
-    let tracks = SourceToTracks::build(source, start, end, op)?;
+    #[allow(unused_mut)]
+    let mut source = mongo_source(&db, &args.collection, &configs);
+    #[cfg(feature = "raw")]
+    source.keep_raw(args.export.raw_records);
 
+    if args.explain {
+        let range = parse_range(args.export.period, &configs, global)?;
+        let plans = source
+            .explain(&db, range)
+            .map_err(|e| secrets::redact(&e))?;
+        return print_plans(plans, global);
+    }
+
+    export(
+        source,
+        &format!("mongo {}", args.collection),
+        destination(args.destination, &configs.destinations.mongo, "mongo")?,
+        args.export,
+        configs,
+        global,
+    )
+    .map_err(|e| secrets::redact(&e))
+}
+
+/// Show the quality metrics of the devices of a source
+fn stats(args: StatsArgs, global: &GlobalOptions) -> Result<(), String> {
+    let mut configs = load_configs(global)?;
+    if args.gap_threshold.is_some() {
+        configs.tracks.gap_threshold = args.gap_threshold;
+    }
+
+    let args = args.source;
+    let range = parse_range(args.period, &configs, global)?;
+
+    match args.kind {
+        SourceKind::Csv => {
+            let source = csv_source(&args.location, &configs)?;
+            print_stats(source, range, &configs, global)
+        }
+        SourceKind::Flespi => {
+            let json = File::open(&args.location)
+                .map_err(|e| format!("Failed on open the JSON file: {}", e))?;
+            let source = FlespiSource::new(BufReader::new(json));
+            print_stats(source, range, &configs, global)
+        }
+        SourceKind::Mongo => {
+            let collection = args.collection.ok_or("Mongo collection not provided")?;
+            let db = mongo_database(&args.location)?;
+            let source = mongo_source(&db, &collection, &configs);
+            print_stats(source, range, &configs, global).map_err(|e| secrets::redact(&e))
+        }
+    }
+}
+
+/// Count the positions and the tracks of an export, without building them
+fn preview(args: PreviewArgs, global: &GlobalOptions) -> Result<(), String> {
+    let configs = load_configs(global)?;
+
+    let args = args.source;
+    let range = parse_range(args.period, &configs, global)?;
+
+    match args.kind {
+        SourceKind::Csv => {
+            let source = csv_source(&args.location, &configs)?;
+            print_preview(source, range, &configs, global)
+        }
+        SourceKind::Flespi => {
+            let json = File::open(&args.location)
+                .map_err(|e| format!("Failed on open the JSON file: {}", e))?;
+            let source = FlespiSource::new(BufReader::new(json));
+            print_preview(source, range, &configs, global)
+        }
+        SourceKind::Mongo => {
+            let collection = args.collection.ok_or("Mongo collection not provided")?;
+            let db = mongo_database(&args.location)?;
+            let source = mongo_source(&db, &collection, &configs);
+            print_preview(source, range, &configs, global).map_err(|e| secrets::redact(&e))
+        }
+    }
+}
+
+/// Write the density grid of the positions of a source
+fn heatmap(args: HeatmapArgs, global: &GlobalOptions) -> Result<(), String> {
+    let mut configs = load_configs(global)?;
+    if let Some(cell_m) = args.cell_m {
+        configs.heatmap.cell_m = cell_m;
+    }
+
+    let destination = args.destination;
+    let args = args.source;
+    let range = parse_range(args.period, &configs, global)?;
+
+    match args.kind {
+        SourceKind::Csv => {
+            let source = csv_source(&args.location, &configs)?;
+            write_heatmap(source, range, &destination, &configs, global)
+        }
+        SourceKind::Flespi => {
+            let json = File::open(&args.location)
+                .map_err(|e| format!("Failed on open the JSON file: {}", e))?;
+            let source = FlespiSource::new(BufReader::new(json));
+            write_heatmap(source, range, &destination, &configs, global)
+        }
+        SourceKind::Mongo => {
+            let collection = args.collection.ok_or("Mongo collection not provided")?;
+            let db = mongo_database(&args.location)?;
+            let source = mongo_source(&db, &collection, &configs);
+            write_heatmap(source, range, &destination, &configs, global)
+                .map_err(|e| secrets::redact(&e))
+        }
+    }
+}
+
+/// Generate synthetic tracks with the config, timing each step
+fn bench(args: BenchArgs, global: &GlobalOptions) -> Result<(), String> {
+    let configs = load_configs(global)?;
+    let options = SyntheticOptions {
+        devices: args.devices,
+        points: args.points,
+        interval: args.interval,
+        noise_m: args.noise_m,
+        seed: args.seed,
+        ..Default::default()
+    };
+
+    let started = Instant::now();
+    let positions = SyntheticSource::new(options.clone())
+        .fetch(TimeRange::all())?
+        .len();
+    let fetch = started.elapsed();
+
+    let started = Instant::now();
+    let (models, _) = SourceToTracks::new()
+        .configure_tracks(&configs.tracks)
+        .configure_segments(&configs.segments)
+        .run_models(SyntheticSource::new(options), TimeRange::all())?;
+    // The source is fetched again by the generation
+    let build = started.elapsed().saturating_sub(fetch);
+
+    let points: usize = models.iter().map(|model| model.points().count()).sum();
+    let tracks = models.len();
+
+    let started = Instant::now();
     let mut gpx = GpxGenerator::empty();
-    gpx.tracks = tracks;
+    gpx.tracks = models.into_iter().map(Track::from).collect();
+    gpx.output = configs.output.clone();
+    match &args.destination {
+        Some(destination) => {
+            let mut content = vec![];
+            gpx.write(&mut content)?;
+            write_file(destination, &content)?;
+        }
+        None => gpx.write(io::sink())?,
+    }
+    let write = started.elapsed();
 
-    let doc = gpx.generate()?;
+    let rate = positions as f64 / build.as_secs_f64().max(f64::EPSILON);
 
-    let mut writer = BufWriter::new(destination);
-    gpx::write(&doc, &mut writer).map_err(|e| e.to_string())?;
+    if global.format == OutputFormat::Json {
+        return print_json(json!({
+            "positions": positions,
+            "tracks": tracks,
+            "points": points,
+            "fetch_seconds": fetch.as_secs_f64(),
+            "build_seconds": build.as_secs_f64(),
+            "write_seconds": write.as_secs_f64(),
+            "positions_per_second": rate,
+        }));
+    }
+
+    println!("positions\t{}", positions);
+    println!("tracks\t{}", tracks);
+    println!("points\t{}", points);
+    println!("fetch\t{:.3}s", fetch.as_secs_f64());
+    println!("build\t{:.3}s", build.as_secs_f64());
+    println!("write\t{:.3}s", write.as_secs_f64());
+    println!("positions/s\t{:.0}", rate);
 
     Ok(())
 }
 
-/// Generate a GPX from a mongodb collection source
-#[subcmd]
-fn mongo(
-    /// Mongo connection string source
-    connection: String,
-    /// Mongo collection name
-    collection: String,
-    /// Start time, RFC3339 format
-    start: String,
-    /// End time, RFC3339 format
-    end: String,
-    /// GPX path file destination
-    destination: String,
-    /// Fields and segments configuration. Default: .loc2gpx.yaml, ~/.loc2gpx.yaml
-    #[opt(long)]
-    config: Option<String>,
-) -> Result<(), String> {
-    let start = OffsetDateTime::parse(&start, &well_known::Rfc3339)
-        .map_err(|e| format!("Failed on parse the start time: {}", e.to_string()))?;
-    let end = OffsetDateTime::parse(&end, &well_known::Rfc3339)
-        .map_err(|e| format!("Failed on parse the end time: {}", e.to_string()))?;
+/// Count the positions of the source on the grid and write it as GeoJSON
+fn write_heatmap<SU>(
+    mut source: SU,
+    range: TimeRange,
+    destination: &str,
+    configs: &Configs,
+    global: &GlobalOptions,
+) -> Result<(), String>
+where
+    SU: PositionsSource,
+{
+    let mut positions = fetch_range(&mut source, range)?;
+    if let Some(code) = configs.tracks.crs {
+        let crs = Crs::from_epsg(code)?;
+        for dpos in &mut positions {
+            crs.transform(dpos);
+        }
+    }
+
+    let mut heatmap = Heatmap::new(&configs.heatmap);
+    for dpos in &positions {
+        heatmap.add(dpos);
+    }
+
+    let content = serde_json::to_vec(&heatmap.to_geojson()).map_err(|e| e.to_string())?;
+    write_file(destination, &content)?;
+
+    if global.verbose {
+        println!(
+            "{} cells of {} positions written on {}",
+            heatmap.cells().count(),
+            positions.len(),
+            destination
+        );
+    }
+
+    Ok(())
+}
+
+/// Source of the CSV file, with the layout of the config
+fn csv_source(
+    path: &str,
+    configs: &Configs,
+) -> Result<CsvSource<CsvLines<BufReader<File>>>, String> {
+    let csv = File::open(path).map_err(|e| format!("Failed on open the CSV file: {}", e))?;
 
-    let destination = File::create(destination)
-        .map_err(|e| format!("Failed on create the destination file: {}", e.to_string()))?;
+    let mut source = configs.csv.source(csv, &configs.fields);
+
+    if configs.csv.sniff {
+        for sniffed in source.sniff(SNIFF_ROWS)? {
+            eprintln!(
+                "Field `{}` guessed as the column `{}`",
+                sniffed.field, sniffed.column
+            );
+        }
+    }
+
+    Ok(source)
+}
+
+/// Default database of the connection string, or of its secret reference
+fn mongo_database(connection: &str) -> Result<Database, String> {
+    let connection = secrets::resolve(connection)?;
 
     let client = Client::with_uri_str(connection)
-        .map_err(|e| format!("Failed on connect: {0}", e.to_string()))?;
-    let db = client
+        .map_err(|e| secrets::redact(&format!("Failed on connect: {0}", e)))?;
+
+    client
         .default_database()
-        .ok_or("Default database not provided")?;
-    let collection = db.collection::<Document>(&collection);
+        .ok_or("Default database not provided".to_string())
+}
 
-    let (fields, op) = load_configs(config);
+/// Source of the collection, with the devices lookup of the config
+fn mongo_source(db: &Database, collection: &str, configs: &Configs) -> MongoDbSource {
+    let fields = Some(configs.fields.clone());
+    let mut source = if collection.contains('{') {
+        MongoDbSource::with_pattern(db.clone(), collection, fields)
+    } else {
+        let collections = collection
+            .split(',')
+            .map(|name| db.collection::<Document>(name.trim()))
+            .collect();
+        MongoDbSource::with_collections(collections, fields)
+    };
 
-    let source = MongoDbSource::new(collection, Some(fields));
+    if let Some(lookup) = &configs.devices {
+        let devices = db.collection::<Document>(&lookup.collection);
+        source.lookup_devices(devices, lookup.clone());
+    }
+    source.options(configs.mongo.clone());
 
-    let tracks = SourceToTracks::build(source, start, end, op)?;
+    source
+}
 
-    let mut gpx = GpxGenerator::empty();
-    gpx.tracks = tracks;
+/// Show the stages and the indexes of the query plans
+fn print_plans(plans: Vec<QueryPlan>, global: &GlobalOptions) -> Result<(), String> {
+    if global.format == OutputFormat::Json {
+        let plans = plans
+            .into_iter()
+            .map(|plan| {
+                json!({
+                    "collection": plan.collection,
+                    "stages": plan.stages,
+                    "indexes": plan.indexes,
+                    "collection_scan": plan.is_collection_scan(),
+                    "explain": Bson::Document(plan.explain).into_relaxed_extjson(),
+                })
+            })
+            .collect();
+        return print_json(Value::Array(plans));
+    }
+
+    for plan in &plans {
+        println!("Collection {}", plan.collection);
+        println!("  Stages: {}", plan.stages.join(" <- "));
+        if plan.indexes.is_empty() {
+            println!("  Indexes: none");
+        } else {
+            println!("  Indexes: {}", plan.indexes.join(", "));
+        }
+        if plan.is_collection_scan() {
+            println!("  Warning: full collection scan, create an index on the time field");
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the tracks from the source and show the quality of each device
+fn print_stats<SU>(
+    source: SU,
+    range: TimeRange,
+    configs: &Configs,
+    global: &GlobalOptions,
+) -> Result<(), String>
+where
+    SU: PositionsSource,
+{
+    let (_, report) = SourceToTracks::new()
+        .configure_tracks(&configs.tracks)
+        .configure_segments(&configs.segments)
+        .run(source, range)?;
+
+    if global.format == OutputFormat::Json {
+        return print_json(stats_json(&report, global.timezone)?);
+    }
 
-    let doc = gpx.generate()?;
+    println!("device\tpositions\tfix rate\tmean accuracy\tlongest gap\tfiltered");
+    for (device, quality) in &report.quality {
+        let accuracy = match quality.mean_accuracy() {
+            Some(ac) => format!("{:.1}m", ac),
+            None => "-".to_string(),
+        };
+        println!(
+            "{}\t{}\t{:.1}%\t{}\t{}\t{:.1}%",
+            device,
+            quality.positions,
+            quality.fix_rate() * 100.0,
+            accuracy,
+            quality.longest_gap,
+            quality.filtered_rate() * 100.0
+        );
+    }
+
+    if report
+        .quality
+        .values()
+        .any(|quality| !quality.gaps.is_empty())
+    {
+        println!();
+        println!("device\tgap start\tgap end\tduration");
+        for (device, quality) in &report.quality {
+            for gap in &quality.gaps {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    device,
+                    gap.start.to_offset(global.timezone),
+                    gap.end.to_offset(global.timezone),
+                    gap.duration()
+                );
+            }
+        }
+    }
 
-    let mut writer = BufWriter::new(destination);
-    gpx::write(&doc, &mut writer).map_err(|e| e.to_string())?;
+    if let Some(bounds) = report.bounds {
+        println!();
+        println!(
+            "bounds\t{},{},{},{}",
+            bounds.min().x,
+            bounds.min().y,
+            bounds.max().x,
+            bounds.max().y
+        );
+    }
 
     Ok(())
 }
 
-/// Load the current config
-fn load_configs(provided: Option<String>) -> (FieldsConfiguration, TrackSegmentOptions) {
-    let mut options = vec![];
+/// Fetch and group the positions of the source and show the counts
+fn print_preview<SU>(
+    source: SU,
+    range: TimeRange,
+    configs: &Configs,
+    global: &GlobalOptions,
+) -> Result<(), String>
+where
+    SU: PositionsSource,
+{
+    let preview = SourceToTracks::new()
+        .configure_tracks(&configs.tracks)
+        .configure_segments(&configs.segments)
+        .preview(source, range)?;
 
-    if let Some(sprovided) = provided {
-        options.push(sprovided);
+    if global.format == OutputFormat::Json {
+        return print_json(json!({
+            "positions": preview.positions,
+            "duplicates": preview.duplicates,
+            "devices": preview.devices,
+            "routes": preview.routes,
+            "tracks": preview.tracks,
+            "segments": preview.segments,
+        }));
     }
 
-    options.push(".loc2gpx.yaml".to_string());
+    println!("positions\t{}", preview.positions);
+    if configs.tracks.dedup {
+        println!("duplicates\t{}", preview.duplicates);
+    }
+    println!("devices\t{}", preview.devices);
+    println!("routes\t{}", preview.routes);
+    println!("tracks\t{}", preview.tracks);
+    println!("segments\t~{}", preview.segments);
+
+    Ok(())
+}
+
+/// Quality metrics of the stats command, as JSON
+fn stats_json(report: &Report, timezone: UtcOffset) -> Result<Value, String> {
+    let time = |tm: OffsetDateTime| {
+        tm.to_offset(timezone)
+            .format(&well_known::Rfc3339)
+            .map_err(|e| e.to_string())
+    };
 
-    if let Some(home) = dirs::home_dir() {
-        if let Some(shome) = home.to_str() {
-            options.push(format!("{}/.loc2gpx.yaml", shome));
+    let mut devices = vec![];
+    for (device, quality) in &report.quality {
+        let mut gaps = vec![];
+        for gap in &quality.gaps {
+            gaps.push(json!({
+                "start": time(gap.start)?,
+                "end": time(gap.end)?,
+                "seconds": gap.duration().whole_seconds(),
+            }));
         }
+
+        devices.push(json!({
+            "device": device,
+            "positions": quality.positions,
+            "fix_rate": quality.fix_rate(),
+            "mean_accuracy": quality.mean_accuracy(),
+            "longest_gap_seconds": quality.longest_gap.whole_seconds(),
+            "filtered_rate": quality.filtered_rate(),
+            "gaps": gaps,
+        }));
+    }
+
+    let bounds = report.bounds.map(|bounds| {
+        [
+            bounds.min().x,
+            bounds.min().y,
+            bounds.max().x,
+            bounds.max().y,
+        ]
+    });
+
+    Ok(json!({ "devices": devices, "bounds": bounds }))
+}
+
+/// Write the value on the stdout, for the `--format json` option
+fn print_json(value: Value) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+    println!("{}", json);
+
+    Ok(())
+}
+
+/// Build the tracks from the source and write them on the destination
+fn export<SU>(
+    source: SU,
+    source_name: &str,
+    destination: String,
+    args: ExportOptions,
+    mut configs: Configs,
+    global: &GlobalOptions,
+) -> Result<(), String>
+where
+    SU: PositionsSource,
+{
+    configs.fail_if_empty(args.fail_if_empty);
+    configs.no_clobber = (configs.no_clobber || args.no_clobber) && !args.force && !args.append;
+    let range = parse_range(args.period, &configs, global)?;
+
+    let mut track_confs = configs.tracks.clone();
+    if let Some(period) = args.split_by {
+        track_confs.split_by = Some(period.parse()?);
     }
 
-    let mut yaml: Option<String> = None;
-    for fi in options {
-        if let Ok(s) = fs::read_to_string(fi) {
-            yaml = Some(s);
-            break;
+    let (models, report) = SourceToTracks::new()
+        .configure_tracks(&track_confs)
+        .configure_segments(&configs.segments)
+        .run_models(source, range)?;
+    print_report(&report, global.timezone);
+
+    if let Some(path) = &args.also_stats {
+        let stats = stats_json(&report, global.timezone)?;
+        let content = serde_json::to_vec_pretty(&stats).map_err(|e| e.to_string())?;
+        write_file(path, &content)?;
+        if global.verbose {
+            eprintln!("Stats written on {}", path);
+        }
+    }
+
+    if let Some(path) = &args.rejects {
+        write_file(path, &rejects_csv(&report, global.timezone)?)?;
+        if global.verbose {
+            eprintln!(
+                "{} rejected positions written on {}",
+                report.rejects.len(),
+                path
+            );
         }
     }
 
-    if let Some(s) = yaml {
-        if let Ok(conf) = serde_yaml::from_str::<Configs>(&s) {
-            return (conf.fields, conf.segments);
+    let mut manifest = Manifest::new(source_name, &range)?;
+    manifest.settings(&Configs {
+        tracks: track_confs.clone(),
+        ..configs.clone()
+    })?;
+
+    let mut template = DestinationTemplate::new(&destination);
+    template.offset(global.timezone);
+    if let Some(period) = track_confs.split_by {
+        template.period(period);
+    }
+
+    let documents = if template.is_templated() {
+        template.group(models)?.into_iter().collect()
+    } else {
+        let tracks = models.into_iter().map(Track::from).collect();
+        match track_confs.split_by {
+            Some(period) => group_by_period(tracks, period)?
+                .into_iter()
+                .map(|(name, tracks)| (period_destination(&destination, &name), tracks))
+                .collect(),
+            None => vec![(destination, tracks)],
+        }
+    };
+
+    for (destination, mut tracks) in documents {
+        if args.append && Path::new(&destination).exists() {
+            let skipped;
+            (tracks, skipped) = append_tracks(
+                read_tracks(&destination)?,
+                tracks,
+                configs.output.time_precision,
+            )?;
+            if skipped > 0 {
+                eprintln!("{} tracks already on {}, skipped", skipped, destination);
+            }
+        } else if configs.no_clobber && Path::new(&destination).exists() {
+            eprintln!("{} already exists, skipped", destination);
+            continue;
+        }
+
+        if template.is_templated() {
+            if let Some(dir) = Path::new(&destination).parent() {
+                fs::create_dir_all(dir).map_err(|e| {
+                    format!("Failed on create the directory {}: {}", dir.display(), e)
+                })?;
+            }
+        }
+
+        let geojson = match args.also_geojson {
+            true => Some(tracks_geojson(&tracks)?),
+            false => None,
+        };
+
+        let count = tracks.len();
+        write_document(
+            tracks,
+            &destination,
+            &configs,
+            &manifest,
+            args.validate_output,
+        )?;
+        if global.verbose {
+            eprintln!("{} tracks written on {}", count, destination);
+        }
+
+        if let Some(geojson) = geojson {
+            let path = Path::new(&destination).with_extension("geojson");
+            let path = path.to_string_lossy();
+            let content = serde_json::to_vec(&geojson).map_err(|e| e.to_string())?;
+            write_file(&path, &content)?;
+            if global.verbose {
+                eprintln!("{} tracks written on {}", count, path);
+            }
         }
     }
 
-    (
-        FieldsConfiguration::default(),
-        TrackSegmentOptions::default(),
+    Ok(())
+}
+
+/// Write the content on a temporary file and move it to the destination
+fn write_file(destination: &str, content: &[u8]) -> Result<(), String> {
+    let temp = temp_destination(destination);
+
+    let written = fs::write(&temp, content)
+        .map_err(|e| format!("Failed on write the destination file: {}", e))
+        .and_then(|_| {
+            fs::rename(&temp, destination)
+                .map_err(|e| format!("Failed on move the file to {}: {}", destination, e))
+        });
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    written
+}
+
+/// Write the tracks on the destination file. The document is written on
+/// a temporary file, moved to the destination only when complete and valid
+fn write_document(
+    tracks: Vec<Track>,
+    destination: &str,
+    configs: &Configs,
+    manifest: &Manifest,
+    validate_output: bool,
+) -> Result<(), String> {
+    let mut gpx = GpxGenerator::empty();
+    gpx.tracks = tracks;
+    gpx.output = configs.output.clone();
+    gpx.manifest = Some(manifest.clone());
+
+    let temp = temp_destination(destination);
+    let written = write_temp(gpx, &temp).and_then(|_| match validate_output {
+        true => check_output_structure(&temp),
+        false => Ok(()),
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    // The link fails when the destination was created meanwhile
+    let moved = match configs.no_clobber {
+        true => fs::hard_link(&temp, destination).and_then(|_| fs::remove_file(&temp)),
+        false => fs::rename(&temp, destination),
+    };
+    moved.map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        format!("Failed on move the file to {}: {}", destination, e)
+    })
+}
+
+/// Tracks of the existing destination, with the speeds written by the
+/// `speed` output, for the `--append` option
+fn read_tracks(destination: &str) -> Result<Vec<Track>, String> {
+    let file = File::open(destination)
+        .map_err(|e| format!("Failed on open the destination file: {}", e))?;
+    let gpx = read_gpx(BufReader::new(file))
+        .map_err(|e| format!("Failed on read the destination {}: {}", destination, e))?;
+
+    Ok(gpx.tracks)
+}
+
+/// Write and sync the document on the temporary file
+fn write_temp(gpx: GpxGenerator, temp: &str) -> Result<(), String> {
+    let file =
+        File::create(temp).map_err(|e| format!("Failed on create the destination file: {}", e))?;
+    let mut writer = BufWriter::new(file);
+    gpx.write(&mut writer)?;
+    writer.flush().map_err(|e| e.to_string())?;
+
+    writer
+        .get_ref()
+        .sync_all()
+        .map_err(|e| format!("Failed on sync the destination file: {}", e))
+}
+
+/// Hidden file next to the destination, so the rename is atomic
+fn temp_destination(destination: &str) -> String {
+    let path = Path::new(destination);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Destination of the argument, or of the config of the command
+fn destination(
+    provided: Option<String>,
+    configured: &Option<String>,
+    command: &str,
+) -> Result<String, String> {
+    provided.or_else(|| configured.clone()).ok_or_else(|| {
+        format!(
+            "Destination not provided, by the argument or the `destinations.{}` config",
+            command
+        )
+    })
+}
+
+/// Destination of the period, with its name before the extension
+fn period_destination(destination: &str, period: &str) -> String {
+    let path = Path::new(destination);
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, period, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, period),
+    };
+
+    path.with_file_name(file).to_string_lossy().to_string()
+}
+
+/// Period of the `--since` and `--until` times
+fn parse_range(
+    period: PeriodOptions,
+    configs: &Configs,
+    global: &GlobalOptions,
+) -> Result<TimeRange, String> {
+    Ok(TimeRange {
+        start: parse_time(period.since, "since", global.timezone)?,
+        end: parse_time(period.until, "until", global.timezone)?,
+        mode: configs.interval_mode,
+    })
+}
+
+/// Parse the optional time of a period end, RFC3339 or a local
+/// time, or day, with the offset of the `--timezone`
+fn parse_time(
+    time: Option<String>,
+    name: &str,
+    timezone: UtcOffset,
+) -> Result<Option<OffsetDateTime>, String> {
+    let tm = match time {
+        Some(tm) => tm,
+        None => return Ok(None),
+    };
+
+    let rfc = match OffsetDateTime::parse(&tm, &well_known::Rfc3339) {
+        Ok(time) => return Ok(Some(time)),
+        Err(e) => e,
+    };
+
+    let local = PrimitiveDateTime::parse(
+        &tm,
+        format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"),
     )
+    .or_else(|_| {
+        Date::parse(&tm, format_description!("[year]-[month]-[day]")).map(|day| day.midnight())
+    });
+
+    match local {
+        Ok(local) => Ok(Some(local.assume_offset(timezone))),
+        Err(_) => Err(format!("Failed on parse the {} time: {}", name, rfc)),
+    }
+}
+
+/// Parse the `--timezone` offset, like `-03:00` or `UTC`
+fn parse_offset(offset: &str) -> Result<UtcOffset, String> {
+    if offset.eq_ignore_ascii_case("utc") || offset == "Z" {
+        return Ok(UtcOffset::UTC);
+    }
+
+    UtcOffset::parse(
+        offset,
+        format_description!("[offset_hour sign:mandatory]:[offset_minute]"),
+    )
+    .map_err(|_| format!("Invalid offset `{}`, use like `-03:00` or `UTC`", offset))
+}
+
+/// Check the structure of the written GPX against the schema subset
+fn check_output_structure(path: &str) -> Result<(), String> {
+    let file =
+        File::open(path).map_err(|e| format!("Failed on open the destination file: {}", e))?;
+
+    let violations = check_gpx_structure(BufReader::new(file))?;
+    for violation in &violations {
+        eprintln!("{}", violation);
+    }
+
+    if !violations.is_empty() {
+        return Err(format!(
+            "The generated GPX has {} structural violations",
+            violations.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// CSV of the positions dropped by the cleaning, for audit
+fn rejects_csv(report: &Report, timezone: UtcOffset) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    #[allow(unused_mut)]
+    let mut header = vec!["device", "time", "longitude", "latitude", "stage", "reason"];
+    #[cfg(feature = "raw")]
+    header.push("raw");
+    writer.write_record(header).map_err(|e| e.to_string())?;
+
+    for reject in &report.rejects {
+        let time = reject
+            .time
+            .to_offset(timezone)
+            .format(&well_known::Rfc3339)
+            .map_err(|e| e.to_string())?;
+        #[allow(unused_mut)]
+        let mut record = vec![
+            reject.device.clone(),
+            time,
+            reject.coordinates.x().to_string(),
+            reject.coordinates.y().to_string(),
+            reject.stage.to_string(),
+            reject.reason.clone(),
+        ];
+        #[cfg(feature = "raw")]
+        record.push(
+            reject
+                .raw
+                .as_ref()
+                .map(Value::to_string)
+                .unwrap_or_default(),
+        );
+        writer.write_record(record).map_err(|e| e.to_string())?;
+    }
+
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+/// Show the report entries to the user
+fn print_report(report: &Report, timezone: UtcOffset) {
+    for entry in &report.entries {
+        eprintln!(
+            "{} at {}: {}",
+            entry.device,
+            entry.time.to_offset(timezone),
+            entry.message
+        );
+    }
+
+    if report.duplicates > 0 {
+        eprintln!("{} duplicated positions dropped", report.duplicates);
+    }
+
+    for skipped in &report.skipped {
+        eprintln!("Skipped: {}", skipped);
+    }
+
+    for deviation in &report.deviations {
+        eprintln!(
+            "{} on {}: {:.0}m max and {:.0}m mean deviation from the route {}, {} off-route runs",
+            deviation.device,
+            deviation.track.as_deref().unwrap_or(&deviation.route),
+            deviation.max_m,
+            deviation.mean_m,
+            deviation.route,
+            deviation.off_route.len()
+        );
+    }
+}
+
+/// Load the current config, showing the files ignored on the search
+fn load_configs(global: &GlobalOptions) -> Result<Configs, String> {
+    let loaded = config_loader(global).load::<Configs>()?;
+
+    for warning in &loaded.warnings {
+        eprintln!("{}", warning);
+    }
+    if let (true, Some(path)) = (global.verbose, &loaded.path) {
+        eprintln!("Config loaded from {}", path.display());
+    }
+
+    Ok(loaded.config)
 }
 
-#[derive(Clone, Debug, PartialEq, Deserialize)]
+/// Loader of the `--config`, or of the default files
+fn config_loader(global: &GlobalOptions) -> ConfigLoader {
+    let mut loader = ConfigLoader::new();
+    if let Some(path) = &global.config {
+        loader.provided(path);
+    }
+    if let Some(profile) = &global.profile {
+        loader.profile(profile);
+    }
+
+    loader
+}
+
+/// Validate the config, with the `--check-config` option
+fn check_config(global: &GlobalOptions) -> Result<(), String> {
+    let loaded = config_loader(global).load::<Configs>()?;
+
+    for warning in &loaded.warnings {
+        println!("{}", warning);
+    }
+    if let Some(path) = &loaded.path {
+        println!("Config {} is valid", path.display());
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 struct Configs {
     pub fields: FieldsConfiguration,
+    #[serde(default)]
+    pub tracks: TrackOptions,
     pub segments: TrackSegmentOptions,
+    /// If the `--until` time is part of the period
+    #[serde(default)]
+    pub interval_mode: IntervalMode,
+    #[serde(default)]
+    pub output: OutputOptions,
+    /// Metadata of the devices, for the mongo source
+    #[serde(default)]
+    pub devices: Option<DevicesLookup>,
+    /// Layout of the files, for the CSV source
+    #[serde(default)]
+    pub csv: CsvOptions,
+    /// Grid of the heatmap command
+    #[serde(default)]
+    pub heatmap: HeatmapOptions,
+    /// Query options, for the mongo source
+    #[serde(default)]
+    pub mongo: MongoOptions,
+    /// Destinations of the commands without one
+    #[serde(default)]
+    pub destinations: Destinations,
+    /// Keep the existing destination files, also the `--no-clobber` option
+    #[serde(default)]
+    pub no_clobber: bool,
+}
+
+/// Destination of each command, like `exports/{device}/{date}.gpx`
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct Destinations {
+    pub mongo: Option<String>,
+    pub csv: Option<String>,
+    pub flespi: Option<String>,
+    pub strava: Option<String>,
+    pub apple_health: Option<String>,
+    pub text: Option<String>,
+    pub reprocess: Option<String>,
+}
+
+impl Configs {
+    /// Require at least one position, with the `--fail-if-empty` option
+    fn fail_if_empty(&mut self, enabled: bool) {
+        if enabled {
+            self.tracks.min_positions = Some(self.tracks.min_positions.unwrap_or(0).max(1));
+        }
+    }
+}
+
+#[test]
+fn period_destinations() {
+    assert_eq!(
+        "out/fleet-2021-05.gpx",
+        period_destination("out/fleet.gpx", "2021-05")
+    );
+    assert_eq!("fleet-2021-W21", period_destination("fleet", "2021-W21"));
 }
 
 #[test]
 fn parse_configs() -> Result<(), String> {
+    use location2gpx::sources::EpochUnit;
+    use location2gpx::SpeedUnit;
+
     let yaml = "\nfields:\nsegments:";
 
-    let tso: Configs = serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?;
+    let tso: Configs = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
 
     assert_eq!(
         Configs {
             fields: FieldsConfiguration {
                 device_id: "device".to_string(),
                 time: "time".to_string(),
+                time_unit: EpochUnit::Auto,
                 route: "route".to_string(),
                 coordinates: "coordinates".to_string(),
                 speed: "speed".to_string(),
+                speed_unit: SpeedUnit::MetersPerSecond,
                 elevation: "elevation".to_string(),
+                baro_elevation: "baro_elevation".to_string(),
+                accuracy: "accuracy".to_string(),
+                hdop: "hdop".to_string(),
+                latitude: "latitude".to_string(),
+                longitude: "longitude".to_string(),
+                interval: "interval".to_string(),
+                track_type: "track_type".to_string(),
+                tracker: "tracker".to_string(),
+                group_by: "trip".to_string(),
                 flip_coordinates: false,
+                aliases: Default::default(),
             },
+            tracks: TrackOptions::default(),
             segments: TrackSegmentOptions {
                 max_duration: 300,
-                vw_tolerance: None
-            }
+                vw_tolerance: None,
+                ..Default::default()
+            },
+            interval_mode: IntervalMode::HalfOpen,
+            output: OutputOptions::default(),
+            devices: None,
+            csv: CsvOptions::default(),
+            heatmap: HeatmapOptions::default(),
+            mongo: MongoOptions::default(),
+            destinations: Destinations::default(),
+            no_clobber: false,
         },
         tso
     );
 
-    let yaml = "\nfields:\n  device_id: dev_id\nsegments:\n  max_duration: 600";
+    let yaml = "\nfields:\n  device_id: dev_id\ntracks:\n  track_type: cycling\nsegments:\n  max_duration: 600\ninterval_mode: closed";
 
-    let tso: Configs = serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?;
+    let tso: Configs = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
 
     assert_eq!(
         Configs {
             fields: FieldsConfiguration {
                 device_id: "dev_id".to_string(),
                 time: "time".to_string(),
+                time_unit: EpochUnit::Auto,
                 route: "route".to_string(),
                 coordinates: "coordinates".to_string(),
                 speed: "speed".to_string(),
+                speed_unit: SpeedUnit::MetersPerSecond,
                 elevation: "elevation".to_string(),
+                baro_elevation: "baro_elevation".to_string(),
+                accuracy: "accuracy".to_string(),
+                hdop: "hdop".to_string(),
+                latitude: "latitude".to_string(),
+                longitude: "longitude".to_string(),
+                interval: "interval".to_string(),
+                track_type: "track_type".to_string(),
+                tracker: "tracker".to_string(),
+                group_by: "trip".to_string(),
                 flip_coordinates: false,
+                aliases: Default::default(),
+            },
+            tracks: TrackOptions {
+                track_type: Some("cycling".to_string()),
+                ..Default::default()
             },
             segments: TrackSegmentOptions {
                 max_duration: 600,
-                vw_tolerance: None
-            }
+                vw_tolerance: None,
+                ..Default::default()
+            },
+            interval_mode: IntervalMode::Closed,
+            output: OutputOptions::default(),
+            devices: None,
+            csv: CsvOptions::default(),
+            heatmap: HeatmapOptions::default(),
+            mongo: MongoOptions::default(),
+            destinations: Destinations::default(),
+            no_clobber: false,
         },
         tso
     );
 
     Ok(())
 }
+
+#[test]
+fn parse_local_times() -> Result<(), String> {
+    use time::macros::datetime;
+
+    let tz = parse_offset("-03:00")?;
+
+    assert_eq!(
+        Some(datetime!(2021-05-24 8:00 -3)),
+        parse_time(Some("2021-05-24T08:00:00".to_string()), "since", tz)?
+    );
+    assert_eq!(
+        Some(datetime!(2021-05-24 0:00 -3)),
+        parse_time(Some("2021-05-24".to_string()), "since", tz)?
+    );
+    assert_eq!(
+        Some(datetime!(2021-05-24 8:00 UTC)),
+        parse_time(Some("2021-05-24T08:00:00Z".to_string()), "since", tz)?
+    );
+    assert!(parse_time(Some("24/05/2021".to_string()), "since", tz).is_err());
+
+    assert_eq!(UtcOffset::UTC, parse_offset("UTC")?);
+    assert!(parse_offset("America/Sao_Paulo").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn strict_configs() {
+    let yaml = "\nfields:\nsegments:\ntracks:\n  dedup: true\n  max_sped: 10";
+
+    let err = location2gpx::config::parse::<Configs>(yaml, Path::new("fleet.yaml")).unwrap_err();
+    assert!(err.starts_with("Invalid config fleet.yaml: tracks: unknown field `max_sped`"));
+    assert!(err.contains("line 6"));
+
+    let yaml = "\nfields:\nsegments:\n  max_duration: soon";
+
+    let err = location2gpx::config::parse::<Configs>(yaml, Path::new("fleet.yaml")).unwrap_err();
+    assert!(err.contains("segments.max_duration: invalid type"));
+}
+
+#[test]
+fn atomic_writes() -> Result<(), String> {
+    let dir = std::env::temp_dir().join("loc2gpx-atomic-writes");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let destination = dir.join("fleet.gpx").to_string_lossy().to_string();
+    let _ = fs::remove_file(&destination);
+
+    assert_eq!(
+        dir.join(format!(".fleet.gpx.{}.tmp", std::process::id()))
+            .to_string_lossy(),
+        temp_destination(&destination)
+    );
+
+    let manifest = Manifest::new("test", &TimeRange::all())?;
+    let mut configs = Configs {
+        no_clobber: true,
+        ..Default::default()
+    };
+
+    write_document(vec![], &destination, &configs, &manifest, true)?;
+    assert!(Path::new(&destination).exists());
+    assert!(!Path::new(&temp_destination(&destination)).exists());
+
+    let err = write_document(vec![], &destination, &configs, &manifest, false).unwrap_err();
+    assert!(err.starts_with("Failed on move the file"));
+    assert!(!Path::new(&temp_destination(&destination)).exists());
+
+    configs.no_clobber = false;
+    write_document(vec![], &destination, &configs, &manifest, false)?;
+
+    Ok(())
+}
+
+#[test]
+fn append_speeds() -> Result<(), String> {
+    use location2gpx::{SpeedOutput, SpeedUnit, TimePrecision};
+    use time::macros::datetime;
+
+    let dir = std::env::temp_dir().join("loc2gpx-append-speeds");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let destination = dir.join("fleet.gpx").to_string_lossy().to_string();
+
+    let track = |name: &str| {
+        let mut wp = gpx::Waypoint::new(geo::Point::new(-48.87, -26.31));
+        wp.time = Some(datetime!(2021-05-24 8:00 UTC).into());
+        wp.speed = Some(5.0);
+        let mut segment = gpx::TrackSegment::new();
+        segment.points.push(wp);
+        let mut track = Track::new();
+        track.name = Some(name.to_string());
+        track.segments.push(segment);
+        track
+    };
+
+    let manifest = Manifest::new("test", &TimeRange::all())?;
+    for speed in [SpeedOutput::Element, SpeedOutput::Custom] {
+        let configs = Configs {
+            output: OutputOptions {
+                speed: vec![speed],
+                speed_unit: SpeedUnit::KilometersPerHour,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        write_document(vec![track("125")], &destination, &configs, &manifest, false)?;
+
+        let (tracks, _) = append_tracks(
+            read_tracks(&destination)?,
+            vec![track("126")],
+            TimePrecision::Auto,
+        )?;
+        write_document(tracks, &destination, &configs, &manifest, false)?;
+
+        let speeds: Vec<_> = read_tracks(&destination)?
+            .iter()
+            .flat_map(|t| &t.segments)
+            .flat_map(|s| &s.points)
+            .map(|p| p.speed.map(|speed| (speed * 1e6).round() / 1e6))
+            .collect();
+        assert_eq!(vec![Some(5.0), Some(5.0)], speeds);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn rejects_audit() -> Result<(), String> {
+    use geo::Point;
+    use location2gpx::RejectStage;
+    use time::macros::datetime;
+
+    let mut report = Report::default();
+    report.reject(
+        "dev, 1".to_string(),
+        datetime!(2021-05-24 8:00 UTC),
+        Point::new(-48.87, -26.31),
+        RejectStage::Filter,
+        "Speed of 300 m/s".to_string(),
+    );
+
+    let csv =
+        String::from_utf8(rejects_csv(&report, UtcOffset::UTC)?).map_err(|e| e.to_string())?;
+    let expected = match cfg!(feature = "raw") {
+        true => "device,time,longitude,latitude,stage,reason,raw\n\"dev, 1\",2021-05-24T08:00:00Z,-48.87,-26.31,filter,Speed of 300 m/s,\n",
+        false => "device,time,longitude,latitude,stage,reason\n\"dev, 1\",2021-05-24T08:00:00Z,-48.87,-26.31,filter,Speed of 300 m/s\n",
+    };
+    assert_eq!(expected, csv);
+
+    Ok(())
+}
@@ -0,0 +1,236 @@
+//! Apple Health export source, with the routes of the workouts
+
+use std::io::{Read, Seek};
+use std::sync::Arc;
+
+use xml::reader::{EventReader, XmlEvent};
+use zip::ZipArchive;
+
+use super::gpx_file::{read_with_accuracies, track_positions};
+use super::{sort_by_time, PositionsSource, TimeRange};
+use crate::DevicePosition;
+
+/// Prefix of the workout types, like `HKWorkoutActivityTypeCycling`
+const WORKOUT_TYPE_PREFIX: &str = "HKWorkoutActivityType";
+
+/// Workout of the export with a route
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthWorkout {
+    /// Activity, like Cycling or Running
+    pub activity_type: Option<String>,
+    /// Device that recorded the workout, like the watch
+    pub source: Option<String>,
+    pub start: Option<String>,
+    /// Path of the route GPX, relative to the `export.xml`
+    pub route: String,
+}
+
+/// Workout routes of an Apple Health export ZIP
+///
+/// The workouts of the `export.xml` reference the GPX of their routes,
+/// read with the recording device, like the watch, as the device and the
+/// activity as the route and the track type
+pub struct AppleHealthSource<R>
+where
+    R: Read + Seek,
+{
+    archive: ZipArchive<R>,
+    /// Directory of the `export.xml`, the base of the routes
+    base: String,
+    workouts: Vec<HealthWorkout>,
+}
+
+impl<R> AppleHealthSource<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(rdr: R) -> Result<Self, String> {
+        let mut archive =
+            ZipArchive::new(rdr).map_err(|e| format!("Failed on open the ZIP archive: {}", e))?;
+
+        let index = archive
+            .file_names()
+            .filter(|name| *name == "export.xml" || name.ends_with("/export.xml"))
+            .min_by_key(|name| name.len())
+            .ok_or("The export.xml was not found on the archive")?
+            .to_string();
+        let base = index.trim_end_matches("export.xml").to_string();
+
+        let workouts = parse_workouts(
+            archive
+                .by_name(&index)
+                .map_err(|e| format!("Failed on read the export.xml: {}", e))?,
+        )?;
+
+        Ok(Self {
+            archive,
+            base,
+            workouts,
+        })
+    }
+
+    /// Workouts with a route
+    pub fn workouts(&self) -> &[HealthWorkout] {
+        &self.workouts
+    }
+}
+
+impl<R> PositionsSource for AppleHealthSource<R>
+where
+    R: Read + Seek,
+{
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+        let mut pos = vec![];
+
+        for workout in &self.workouts {
+            let path = format!("{}{}", self.base, workout.route.trim_start_matches('/'));
+            let file = self
+                .archive
+                .by_name(&path)
+                .map_err(|e| format!("Failed on read the route {}: {}", path, e))?;
+            let (doc, accuracies) = read_with_accuracies(file)
+                .map_err(|e| format!("Failed on read the GPX {}: {}", path, e))?;
+
+            let activity = workout
+                .activity_type
+                .clone()
+                .unwrap_or_else(|| "Workout".to_string());
+            let source: Option<Arc<str>> = workout.source.as_deref().map(Arc::from);
+            for (track, accuracies) in doc.tracks.iter().zip(&accuracies) {
+                for mut dpos in track_positions(track, &activity, range, accuracies)? {
+                    if let Some(source) = &source {
+                        dpos.device_id = source.clone();
+                    }
+                    dpos.track_type = workout.activity_type.clone();
+                    pos.push(dpos);
+                }
+            }
+        }
+
+        sort_by_time(&mut pos);
+
+        Ok(pos)
+    }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
+    }
+}
+
+/// Workouts of the export with a route, read in a single pass, since
+/// the exports of some years of records have gigabytes
+fn parse_workouts<T: Read>(export: T) -> Result<Vec<HealthWorkout>, String> {
+    let mut workouts = vec![];
+    let mut current: Option<HealthWorkout> = None;
+
+    for event in EventReader::new(export) {
+        match event.map_err(|e| format!("Failed on read the export.xml: {}", e))? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let attr = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|a| a.name.local_name == key)
+                        .map(|a| a.value.clone())
+                };
+
+                match name.local_name.as_str() {
+                    "Workout" => {
+                        current = Some(HealthWorkout {
+                            activity_type: attr("workoutActivityType")
+                                .map(|t| t.trim_start_matches(WORKOUT_TYPE_PREFIX).to_string()),
+                            source: attr("sourceName"),
+                            start: attr("startDate"),
+                            route: String::new(),
+                        })
+                    }
+                    "FileReference" => {
+                        if let (Some(workout), Some(path)) = (&mut current, attr("path")) {
+                            workout.route = path;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } if name.local_name == "Workout" => {
+                if let Some(workout) = current.take().filter(|w| !w.route.is_empty()) {
+                    workouts.push(workout);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(workouts)
+}
+
+#[test]
+fn workout_routes() -> Result<(), String> {
+    use std::io::{Cursor, Write};
+    use time::macros::datetime;
+    use zip::write::{FileOptions, ZipWriter};
+
+    let export = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE HealthData [
+<!ELEMENT HealthData (ExportDate,Me,(Record|Workout)*)>
+<!ATTLIST HealthData locale CDATA #REQUIRED>
+]>
+<HealthData locale="en_US">
+ <ExportDate value="2021-06-01 10:00:00 -0300"/>
+ <Me HKCharacteristicTypeIdentifierDateOfBirth=""/>
+ <Record type="HKQuantityTypeIdentifierStepCount" sourceName="iPhone" value="120"/>
+ <Workout workoutActivityType="HKWorkoutActivityTypeCycling" duration="2" sourceName="Watch" startDate="2021-05-24 05:00:00 -0300">
+  <MetadataEntry key="HKIndoorWorkout" value="0"/>
+  <WorkoutRoute sourceName="Watch">
+   <FileReference path="/workout-routes/route_2021-05-24_5.00am.gpx"/>
+  </WorkoutRoute>
+ </Workout>
+ <Workout workoutActivityType="HKWorkoutActivityTypeYoga" duration="30" sourceName="Watch" startDate="2021-05-24 18:00:00 -0300"/>
+</HealthData>"#;
+    let route = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="Apple Health Export" xmlns="http://www.topografix.com/GPX/1/1">
+ <trk><name>Route 2021-05-24 5:00am</name><trkseg>
+  <trkpt lon="-48.87" lat="-26.31"><ele>12.5</ele><time>2021-05-24T08:00:00Z</time><extensions><speed>4.2</speed><course>90.1</course><hAcc>3.5</hAcc><vAcc>2.1</vAcc></extensions></trkpt>
+  <trkpt lon="-48.869" lat="-26.31"><ele>12.7</ele><time>2021-05-24T08:01:00Z</time></trkpt>
+ </trkseg></trk>
+</gpx>"#;
+
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    for (path, content) in [
+        ("apple_health_export/export.xml", export),
+        (
+            "apple_health_export/workout-routes/route_2021-05-24_5.00am.gpx",
+            route,
+        ),
+    ] {
+        zip.start_file(path, FileOptions::default())
+            .map_err(|e| e.to_string())?;
+        zip.write_all(content.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+    let archive = zip.finish().map_err(|e| e.to_string())?;
+
+    let mut source = AppleHealthSource::new(archive)?;
+    assert_eq!(1, source.workouts().len());
+    assert_eq!(
+        Some("2021-05-24 05:00:00 -0300".to_string()),
+        source.workouts()[0].start
+    );
+
+    let positions = source.fetch(TimeRange::all())?;
+    assert_eq!(2, positions.len());
+    assert_eq!("Watch", &*positions[0].device_id);
+    assert_eq!(Some("Cycling"), positions[0].route_name.as_deref());
+    assert_eq!(Some("Cycling".to_string()), positions[0].track_type);
+    assert_eq!(datetime!(2021-05-24 8:00 UTC), positions[0].pos.time);
+    assert_eq!(Some(12.5), positions[0].pos.altitude);
+    assert_eq!(Some(4.2), positions[0].pos.speed);
+    assert_eq!(Some(3.5), positions[0].pos.precision);
+    assert_eq!(
+        (None, None),
+        (positions[1].pos.speed, positions[1].pos.precision)
+    );
+
+    Ok(())
+}
@@ -1,22 +1,39 @@
 //! CSV file source integration
 
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read};
+use std::num::NonZeroUsize;
+use std::thread;
 
-use csv::{Reader, StringRecord};
+use csv::{ByteRecord, Reader, ReaderBuilder, StringRecord};
 use geo::geometry::Point;
-use time::format_description::well_known;
-use time::OffsetDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use super::{FieldsConfiguration, PositionsSource};
-use crate::DevicePosition;
+use super::csv_sniff::{sniff_fields, SniffedField};
+use super::geometry::{expand_positions, line_times, parse_points};
+use super::numeric::parse_number;
+use super::{sort_by_time, FieldsConfiguration, PositionsSource, TimeRange};
+use crate::{DevicePosition, Names, SpeedUnit};
 
-/// MongoDB tracks source
+/// Rows read before each parallel parsing
+const BATCH_ROWS: usize = 100_000;
+
+/// CSV tracks source
 pub struct CsvSource<T>
 where
     T: Read,
 {
     rdr: Reader<T>,
     fields: FieldsConfiguration,
+    /// Rows already read, by the sniffing
+    buffered: Vec<StringRecord>,
+    /// Threads parsing the rows
+    threads: usize,
+    keep_raw: bool,
+    strict: bool,
+    trim: bool,
+    /// Shared names of all the rows
+    names: Names,
 }
 
 impl<T> CsvSource<T>
@@ -26,65 +43,355 @@ where
     pub fn new(rdr: Reader<T>, fields: Option<FieldsConfiguration>) -> Self {
         Self {
             rdr,
-            fields: match fields {
-                Some(f) => f,
-                None => FieldsConfiguration::default(),
+            fields: fields.unwrap_or_default(),
+            buffered: vec![],
+            threads: 1,
+            keep_raw: false,
+            strict: false,
+            trim: true,
+            names: Names::default(),
+        }
+    }
+
+    /// Keep the row of each position, by the header names, on
+    /// the builds with the `raw` feature
+    pub fn keep_raw(&mut self, keep: bool) -> &mut Self {
+        self.keep_raw = keep;
+
+        self
+    }
+
+    /// Parse the rows with many threads, keeping the order of the rows
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = threads.max(1);
+
+        self
+    }
+
+    /// Fail on the rows with more or less fields than the header, instead
+    /// of skipping the ones without the required fields. The reader must
+    /// be flexible, so these rows reach the source
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+
+        self
+    }
+
+    /// Remove the spaces around the fields, the default
+    pub fn trim(&mut self, trim: bool) -> &mut Self {
+        self.trim = trim;
+
+        self
+    }
+
+    /// Guess the columns of the fields not found on the header by
+    /// their names and by the values of the first rows, returning
+    /// the guessed ones
+    pub fn sniff(&mut self, rows: usize) -> Result<Vec<SniffedField>, String> {
+        let header = self
+            .rdr
+            .headers()
+            .map_err(|e| format!("Failed on read the header: {}", e))?
+            .clone();
+
+        while self.buffered.len() < rows {
+            let mut rec = StringRecord::new();
+            let more = self
+                .rdr
+                .read_record(&mut rec)
+                .map_err(|e| format!("Failed on read some row: {}", e))?;
+            if !more {
+                break;
+            }
+            self.buffered.push(rec);
+        }
+
+        Ok(sniff_fields(&header, &self.buffered, &mut self.fields))
+    }
+}
+
+/// Layout of the CSV files
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CsvOptions {
+    /// Leading lines before the header, like firmware banners
+    pub skip_lines: usize,
+    /// Prefix of the comment lines, ignored
+    pub comment: Option<String>,
+    /// Skip the rows without the required fields, instead of failing
+    /// on the rows with more or less fields than the header
+    pub flexible: bool,
+    /// Remove the spaces around the fields
+    pub trim: bool,
+    /// Guess the columns of the fields not found on the header
+    pub sniff: bool,
+    /// Threads parsing the rows. Default: the available cores
+    pub threads: Option<usize>,
+    /// Fields of a known export, used instead of the `fields` config
+    pub preset: Option<CsvPreset>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            skip_lines: 0,
+            comment: None,
+            flexible: false,
+            trim: true,
+            sniff: false,
+            threads: None,
+            preset: None,
+        }
+    }
+}
+
+/// Exports of other tools with known columns
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvPreset {
+    /// `tc_positions` table of Traccar, like the H2 export of
+    /// `CALL CSVWRITE('positions.csv', 'SELECT * FROM tc_positions')`
+    Traccar,
+}
+
+impl CsvPreset {
+    pub fn fields(&self) -> FieldsConfiguration {
+        match self {
+            Self::Traccar => FieldsConfiguration {
+                device_id: "deviceid".to_string(),
+                time: "fixtime".to_string(),
+                speed_unit: SpeedUnit::Knots,
+                elevation: "altitude".to_string(),
+                tracker: "protocol".to_string(),
+                ..Default::default()
             },
         }
     }
 }
 
+impl CsvOptions {
+    /// CSV reader of the input with the options
+    pub fn reader<R: Read>(&self, input: R) -> Reader<CsvLines<BufReader<R>>> {
+        let lines = CsvLines::new(BufReader::new(input), self.skip_lines, self.comment.clone());
+
+        ReaderBuilder::new()
+            .flexible(self.flexible)
+            .from_reader(lines)
+    }
+
+    /// Source of the input with the options, the fields of the preset
+    /// or the given ones, and the configured threads
+    pub fn source<R: Read>(
+        &self,
+        input: R,
+        fields: &FieldsConfiguration,
+    ) -> CsvSource<CsvLines<BufReader<R>>> {
+        let mut source = CsvSource::new(self.reader(input), Some(self.fields(fields)));
+        source
+            .threads(self.threads())
+            .strict(!self.flexible)
+            .trim(self.trim);
+
+        source
+    }
+
+    /// Fields of the preset, or the given ones
+    pub fn fields(&self, fields: &FieldsConfiguration) -> FieldsConfiguration {
+        match self.preset {
+            Some(preset) => preset.fields(),
+            None => fields.clone(),
+        }
+    }
+
+    /// Threads parsing the rows, the configured ones or the available cores
+    pub fn threads(&self) -> usize {
+        self.threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+    }
+}
+
+/// Input without the leading and the comment lines
+pub struct CsvLines<R: BufRead> {
+    inner: R,
+    skip: usize,
+    comment: Option<String>,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> CsvLines<R> {
+    pub fn new(inner: R, skip: usize, comment: Option<String>) -> Self {
+        Self {
+            inner,
+            skip,
+            comment: comment.filter(|c| !c.is_empty()),
+            line: vec![],
+            pos: 0,
+        }
+    }
+
+    fn is_comment(&self) -> bool {
+        match &self.comment {
+            Some(prefix) => self.line.trim_ascii_start().starts_with(prefix.as_bytes()),
+            None => false,
+        }
+    }
+}
+
+impl<R: BufRead> Read for CsvLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos >= self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+
+            if self.skip > 0 {
+                self.skip -= 1;
+                self.line.clear();
+            } else if self.is_comment() {
+                self.line.clear();
+            }
+        }
+
+        let size = buf.len().min(self.line.len() - self.pos);
+        buf[..size].copy_from_slice(&self.line[self.pos..self.pos + size]);
+        self.pos += size;
+
+        Ok(size)
+    }
+}
+
 impl<T> PositionsSource for CsvSource<T>
 where
     T: Read,
 {
-    fn fetch(
-        &mut self,
-        start: OffsetDateTime,
-        end: OffsetDateTime,
-    ) -> Result<Vec<DevicePosition>, String> {
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
         let mut pos = vec![];
+        self.fetch_batches(range, &mut |batch| {
+            pos.extend(batch);
+            Ok(())
+        })?;
+
+        sort_by_time(&mut pos);
 
+        Ok(pos)
+    }
+
+    /// Each batch of rows read, parsed by the threads
+    fn fetch_batches(
+        &mut self,
+        range: TimeRange,
+        each: &mut dyn FnMut(Vec<DevicePosition>) -> Result<(), String>,
+    ) -> Result<(), String> {
         let mut header = self
             .rdr
             .headers()
-            .map_err(|e| format!("Failed on read the header: {}", e.to_string()))?
+            .map_err(|e| format!("Failed on read the header: {}", e))?
             .clone();
         let header_idx = parse_header(&self.fields, &mut header)?;
 
-        let mut recs = self.rdr.records();
-        while let Some(row) = recs.next() {
-            let mut rec = row.map_err(|e| format!("Failed on read some row: {}", e.to_string()))?;
-
-            if rec.len() < 3 {
-                continue;
+        let buffered = std::mem::take(&mut self.buffered)
+            .into_iter()
+            .map(|rec| Ok(rec.into_byte_record()));
+        let mut rows = buffered.chain(self.rdr.byte_records());
+        loop {
+            let batch = rows
+                .by_ref()
+                .take(BATCH_ROWS)
+                .collect::<Result<Vec<ByteRecord>, _>>()
+                .map_err(|e| format!("Failed on read some row: {}", e))?;
+            if batch.is_empty() {
+                break;
             }
 
-            let row_pos = match parse_row(&header_idx, &self.fields, &mut rec) {
-                Ok(dpos) => Ok(dpos),
-                Err(e) => Err(format!("Error with row {:?}: {}", rec, e)),
-            }?;
-
-            if let Some(dpos) = row_pos {
-                if start <= dpos.pos.time && dpos.pos.time <= end {
-                    pos.push(dpos);
-                }
-            }
+            let rules = RowRules {
+                columns: header.len(),
+                strict: self.strict,
+                trim: self.trim,
+            };
+            let raw_names = self.keep_raw.then_some(&header);
+            let batch_pos = parse_rows(
+                &header_idx,
+                &self.fields,
+                rules,
+                raw_names,
+                batch,
+                &mut self.names,
+                self.threads,
+            )?;
+            each(
+                batch_pos
+                    .into_iter()
+                    .filter(|dpos| range.contains(dpos.pos.time))
+                    .collect(),
+            )?;
         }
 
-        Ok(pos)
+        Ok(())
+    }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
     }
 }
 
+/// Columns of the coordinates
+#[derive(Debug)]
+enum CoordinatesIndex {
+    /// Pair, GeoJSON, WKT or WKB
+    Text(usize),
+    /// Latitude and longitude columns
+    LatLng(usize, usize),
+}
+
 /// Field to index map
 #[derive(Debug)]
 struct FieldsIndex {
     device: usize,
-    coordinates: usize,
+    coordinates: CoordinatesIndex,
     time: usize,
     route: Option<usize>,
     speed: Option<usize>,
     elevation: Option<usize>,
+    baro_elevation: Option<usize>,
+    accuracy: Option<usize>,
+    hdop: Option<usize>,
+    interval: Option<usize>,
+    track_type: Option<usize>,
+    tracker: Option<usize>,
+    group: Option<usize>,
+}
+
+impl FieldsIndex {
+    /// Last index of the required fields
+    fn required(&self) -> usize {
+        let coordinates = match self.coordinates {
+            CoordinatesIndex::Text(i) => i,
+            CoordinatesIndex::LatLng(lat, lng) => lat.max(lng),
+        };
+
+        self.device.max(coordinates).max(self.time)
+    }
+}
+
+/// Checks of the rows
+#[derive(Clone, Copy, Debug)]
+struct RowRules {
+    /// Fields of the header
+    columns: usize,
+    strict: bool,
+    trim: bool,
+}
+
+/// Column name without the case and the spaces, so `Fix Time`
+/// matches `fix time` and `fixtime`
+pub(super) fn header_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
 }
 
 fn parse_header(
@@ -93,124 +400,223 @@ fn parse_header(
 ) -> Result<FieldsIndex, String> {
     header.trim();
 
-    let device = match header
-        .iter()
-        .position(|h| h.to_lowercase() == fields.device_id)
-    {
-        Some(p) => Ok(p),
-        None => Err("Device header not found"),
-    }?;
+    let keys: Vec<String> = header.iter().map(header_key).collect();
+    // The configured name first, then the aliases in their order
+    let position = |name: &str, aliases: &[String]| {
+        std::iter::once(name)
+            .chain(aliases.iter().map(String::as_str))
+            .find_map(|name| {
+                let name = header_key(name);
+                keys.iter().position(|key| *key == name)
+            })
+    };
+    let aliases = &fields.aliases;
 
-    let coordinates = match header
-        .iter()
-        .position(|h| h.to_lowercase() == fields.coordinates)
-    {
-        Some(p) => Ok(p),
-        None => Err("Coordinates header not found"),
-    }?;
+    let device =
+        position(&fields.device_id, &aliases.device_id).ok_or("Device header not found")?;
 
-    let time = match header.iter().position(|h| h.to_lowercase() == fields.time) {
-        Some(p) => Ok(p),
-        None => Err("Time header not found"),
+    let coordinates = match (
+        position(&fields.coordinates, &aliases.coordinates),
+        position(&fields.latitude, &aliases.latitude),
+        position(&fields.longitude, &aliases.longitude),
+    ) {
+        (Some(p), _, _) => Ok(CoordinatesIndex::Text(p)),
+        (None, Some(lat), Some(lng)) => Ok(CoordinatesIndex::LatLng(lat, lng)),
+        _ => Err("Coordinates header not found"),
     }?;
 
-    let route = header.iter().position(|h| h.to_lowercase() == fields.route);
-
-    let speed = header.iter().position(|h| h.to_lowercase() == fields.speed);
-
-    let elevation = header
-        .iter()
-        .position(|h| h.to_lowercase() == fields.elevation);
+    let time = position(&fields.time, &aliases.time).ok_or("Time header not found")?;
 
     Ok(FieldsIndex {
         device,
         coordinates,
         time,
-        route,
-        speed,
-        elevation,
+        route: position(&fields.route, &aliases.route),
+        speed: position(&fields.speed, &aliases.speed),
+        elevation: position(&fields.elevation, &aliases.elevation),
+        baro_elevation: position(&fields.baro_elevation, &aliases.baro_elevation),
+        accuracy: position(&fields.accuracy, &aliases.accuracy),
+        hdop: position(&fields.hdop, &aliases.hdop),
+        interval: position(&fields.interval, &aliases.interval),
+        track_type: position(&fields.track_type, &aliases.track_type),
+        tracker: position(&fields.tracker, &aliases.tracker),
+        group: position(&fields.group_by, &aliases.group_by),
     })
 }
 
+/// Positions of the rows, split between the threads and joined in the
+/// order of the rows. With the header names, the rows are kept as raw.
+/// Each thread works with a copy of the shared names, merged at the end
+fn parse_rows(
+    header: &FieldsIndex,
+    fields: &FieldsConfiguration,
+    rules: RowRules,
+    names: Option<&StringRecord>,
+    rows: Vec<ByteRecord>,
+    shared: &mut Names,
+    threads: usize,
+) -> Result<Vec<DevicePosition>, String> {
+    let parse = |rows: &[ByteRecord], shared: &mut Names| -> Result<Vec<DevicePosition>, String> {
+        let mut pos = vec![];
+        for rec in rows {
+            if rules.strict && rec.len() != rules.columns {
+                return Err(format!(
+                    "The row {} has {} fields, but the header has {}",
+                    rec.position().map_or(0, |p| p.record()),
+                    rec.len(),
+                    rules.columns
+                ));
+            }
+
+            // Ragged rows without the required fields
+            if rec.len() < 3 || rec.len() <= header.required() {
+                continue;
+            }
+
+            let mut row_pos = match parse_row(header, fields, rules.trim, rec, shared) {
+                Ok(row_pos) => row_pos,
+                Err(e) => return Err(format!("Error with row {:?}: {}", rec, e)),
+            };
+
+            if let Some(names) = names {
+                for dpos in &mut row_pos {
+                    dpos.keep_raw(|| {
+                        names
+                            .iter()
+                            .zip(rec.iter())
+                            .map(|(name, value)| {
+                                let value = String::from_utf8_lossy(value);
+                                match rules.trim {
+                                    true => (name, value.trim().to_string()),
+                                    false => (name, value.into_owned()),
+                                }
+                            })
+                            .collect::<Value>()
+                    });
+                }
+            }
+            pos.extend(row_pos);
+        }
+
+        Ok(pos)
+    };
+
+    if threads <= 1 || rows.len() < threads * 2 {
+        return parse(&rows, shared);
+    }
+
+    let parse = &parse;
+    let size = rows.len().div_ceil(threads);
+    let parsed = thread::scope(|scope| {
+        let workers: Vec<_> = rows
+            .chunks(size)
+            .map(|chunk| {
+                let mut names = shared.clone();
+                scope.spawn(move || parse(chunk, &mut names).map(|pos| (pos, names)))
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .map_err(|_| "The parsing of the rows failed".to_string())?
+            })
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+
+    let mut pos = vec![];
+    for (mut worker_pos, names) in parsed {
+        shared.merge(names);
+        for dpos in &mut worker_pos {
+            shared.share(dpos);
+        }
+        pos.extend(worker_pos);
+    }
+
+    Ok(pos)
+}
+
+/// Text of the field, borrowed from the row
+fn field(row: &ByteRecord, i: usize, trim: bool) -> Result<Option<&str>, String> {
+    match row.get(i) {
+        Some(raw) => std::str::from_utf8(raw)
+            .map(|text| Some(if trim { text.trim() } else { text }))
+            .map_err(|e| format!("Invalid UTF-8 on the column {}: {}", i + 1, e)),
+        None => Ok(None),
+    }
+}
+
 fn parse_row(
     header: &FieldsIndex,
     fields: &FieldsConfiguration,
-    row: &mut StringRecord,
-) -> Result<Option<DevicePosition>, String> {
-    row.trim();
+    trim: bool,
+    row: &ByteRecord,
+    shared: &mut Names,
+) -> Result<Vec<DevicePosition>, String> {
+    let text = |i: usize| field(row, i, trim);
+    let optional = |i: Option<usize>| -> Result<Option<&str>, String> {
+        match i {
+            Some(i) => Ok(text(i)?.filter(|d| !d.is_empty())),
+            None => Ok(None),
+        }
+    };
 
-    let device_id = match row.get(header.device) {
-        Some(d) => Ok(d.to_string()),
+    let device_id = match text(header.device)? {
+        Some(d) => Ok(shared.get(d)),
         None => Err("Device field not found"),
     }?;
 
-    let raw_coordinates = match row.get(header.coordinates) {
-        Some(d) => Ok(d.to_string()),
-        None => Err("Coordinates field not found"),
+    let points = match header.coordinates {
+        CoordinatesIndex::Text(i) => match text(i)? {
+            Some(d) => parse_points(d, fields),
+            None => Err("Coordinates field not found".to_string()),
+        },
+        CoordinatesIndex::LatLng(lat, lng) => match (text(lat)?, text(lng)?) {
+            (Some(lat), Some(lng)) if lat.is_empty() || lng.is_empty() => Ok(vec![]),
+            (Some(lat), Some(lng)) => {
+                let lat =
+                    parse_number(lat).map_err(|e| format!("Invalid latitude format: {}", e))?;
+                let lng =
+                    parse_number(lng).map_err(|e| format!("Invalid longitude format: {}", e))?;
+                Ok(vec![Point::new(lng, lat)])
+            }
+            _ => Err("Coordinates field not found".to_string()),
+        },
     }?;
-    let separator = match raw_coordinates.clone() {
-        s if s.contains(",") => ",",
-        s if s.contains(";") => ";",
-        _ => " ",
-    };
-    let scoordinates: Vec<String> = raw_coordinates
-        .split(separator)
-        .map(|s| s.trim().to_string())
-        .collect();
-    if scoordinates.len() != 2 {
-        return Ok(None);
-    }
-
-    let mut ilat = 1;
-    let mut ilng = 0;
-    if fields.flip_coordinates {
-        ilat = 0;
-        ilng = 1;
-    }
-
-    let lat = scoordinates[ilat]
-        .parse::<f64>()
-        .map_err(|e| format!("Invalid latitude format: {}", e.to_string()))?;
-    let lng = scoordinates[ilng]
-        .parse::<f64>()
-        .map_err(|e| format!("Invalid longitude format: {}", e.to_string()))?;
-
-    let time = match row.get(header.time) {
-        Some(d) => OffsetDateTime::parse(d, &well_known::Rfc3339)
-            .map_err(|e| format!("Failed on parse the time: {}", e.to_string())),
+    if points.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let time = match text(header.time)? {
+        Some(d) => fields.time_unit.parse_text(d),
         None => Err("Time field not found".to_string()),
     }?;
 
-    let mut dpos = DevicePosition::basic(device_id.clone(), Point::new(lng, lat), time);
+    let mut dpos = DevicePosition::basic(device_id, points[0], time);
 
-    if let Some(iroute) = header.route {
-        dpos.route_name = match row.get(iroute) {
-            Some(d) => {
-                if !d.trim().is_empty() {
-                    Some(d.trim().to_string())
-                } else {
-                    None
-                }
-            }
-            None => None,
-        };
-    }
+    dpos.route_name = optional(header.route)?.map(|d| shared.get(d));
+    dpos.tracker = optional(header.tracker)?.map(|d| shared.get(d));
+    dpos.group = optional(header.group)?.map(str::to_string);
+    dpos.track_type = optional(header.track_type)?.map(str::to_string);
 
-    if let Some(ispeed) = header.speed {
-        dpos.pos.speed = match row.get(ispeed) {
-            Some(d) => d.parse::<f64>().ok(),
-            None => None,
-        };
-    }
+    let number = |i: Option<usize>| -> Result<Option<f64>, String> {
+        Ok(optional(i)?.and_then(|d| parse_number(d).ok()))
+    };
+    dpos.pos.speed = number(header.speed)?.map(|speed| fields.speed_unit.to_mps(speed));
+    dpos.pos.altitude = number(header.elevation)?;
+    dpos.pos.baro_altitude = number(header.baro_elevation)?;
+    dpos.pos.precision = number(header.accuracy)?;
+    dpos.pos.hdop = number(header.hdop)?;
 
-    if let Some(ielevation) = header.elevation {
-        dpos.pos.altitude = match row.get(ielevation) {
-            Some(d) => d.parse::<f64>().ok(),
-            None => None,
-        };
-    }
+    let interval = match optional(header.interval)? {
+        Some(d) => Some(parse_number(d).map_err(|e| format!("Invalid interval format: {}", e))?),
+        None => None,
+    };
+    let times = line_times(time, points.len(), interval)?;
 
-    Ok(Some(dpos))
+    expand_positions(dpos, points, times)
 }
 
 #[cfg(test)]
@@ -219,8 +625,11 @@ pub mod tests {
     use geo::geometry::Point;
     use time::macros::datetime;
 
-    use super::CsvSource;
-    use crate::{SourceToTracks, TrackSegmentOptions};
+    use super::{CsvOptions, CsvSource};
+    use crate::{
+        ElevationOptions, ElevationSource, GroupingStrategy, SourceToTracks, TimeRange,
+        TrackOptions, TrackSegmentOptions,
+    };
 
     #[test]
     fn track() -> Result<(), String> {
@@ -289,6 +698,29 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn track_open_range() -> Result<(), String> {
+        let data = "\n
+            device,coordinates,time\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:00.000+00:00\"\n
+            AA251,\"-48.8802222 -26.31832\",\"2019-10-02T00:02:00.000+00:00\"\n
+            AA251,\"-48.8902222;-26.31832\",\"2019-10-03T00:03:00.000+00:00\"\n
+        ";
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let source = CsvSource::new(rdr, None);
+
+        let (tracks, _) =
+            SourceToTracks::new().run(source, TimeRange::since(datetime!(2019-10-02 0:00 UTC)))?;
+        assert_eq!(2, tracks.len());
+        assert_eq!(Some("2019-10-02".to_string()), tracks[0].name);
+        assert_eq!(Some("2019-10-03".to_string()), tracks[1].name);
+
+        Ok(())
+    }
+
     #[test]
     fn track_filter_out_failed_positions() -> Result<(), String> {
         let data = "\n
@@ -322,9 +754,9 @@ pub mod tests {
     #[test]
     fn extra_fields() -> Result<(), String> {
         let data = "\n
-            device,coordinates,time,route,speed,elevation\n
-            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:00.000+00:00\",\"JOI123\",0.2,200\n
-            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:10.000+00:00\",\"JOI123\",0.7,198.0\n
+            device,coordinates,time,route,speed,elevation,tracker\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:00.000+00:00\",\"JOI123\",0.2,200,OsmAnd\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:10.000+00:00\",\"JOI123\",0.7,198.0,OsmAnd\n
         ";
         let rdr = ReaderBuilder::new()
             .flexible(true)
@@ -343,6 +775,7 @@ pub mod tests {
         let track = &tracks[0];
         assert_eq!(1, track.segments.len());
         assert_eq!(Some("JOI123".to_string()), track.name);
+        assert_eq!(Some("OsmAnd".to_string()), track.source);
         let segment = &track.segments[0];
         assert_eq!(2, segment.points.len());
         let point = &segment.points[0];
@@ -351,4 +784,550 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn trips_grouping() -> Result<(), String> {
+        let data = "\n
+            device,coordinates,time,route,trip\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:00.000+00:00\",\"JOI123\",T1\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:10.000+00:00\",\"JOI123\",T1\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:09:00.000+00:00\",\"JOI123\",T2\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:10:00.000+00:00\",\"JOI123\",\n
+        ";
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let source = CsvSource::new(rdr, None);
+        let (tracks, _) = SourceToTracks::new()
+            .configure_tracks(&TrackOptions {
+                grouping: GroupingStrategy::Field,
+                ..Default::default()
+            })
+            .run(source, TimeRange::all())?;
+
+        let names: Vec<_> = tracks.iter().map(|t| t.name.clone()).collect();
+        assert_eq!(
+            vec![
+                Some("JOI123".to_string()),
+                Some("T1".to_string()),
+                Some("T2".to_string())
+            ],
+            names
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn baro_elevation() -> Result<(), String> {
+        let data = "\n
+            device,coordinates,time,elevation,baro_elevation\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:00.000+00:00\",220,200\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:10.000+00:00\",216,\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:20.000+00:00\",218,202\n
+        ";
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let source = CsvSource::new(rdr, None);
+        let op = TrackSegmentOptions {
+            elevation: ElevationOptions {
+                source: ElevationSource::Fused,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2010-10-01 0:00 UTC),
+            datetime!(2020-10-01 2:00 UTC),
+            op,
+        )?;
+        let points = &tracks[0].segments[0].points;
+        assert_eq!(Some(218.0), points[0].elevation);
+        assert_eq!(Some(216.0), points[1].elevation);
+        assert_eq!(Some(220.0), points[2].elevation);
+
+        Ok(())
+    }
+
+    #[test]
+    fn banner_and_comments() -> Result<(), String> {
+        let data = "Logger FW 2.1.4\nS/N 0042\ndevice,coordinates,time,speed\n\
+            # started\n\
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:01:00.000+00:00\",3.5\n\
+            AA251,\"-48.8802222 -26.31832\"\n\
+            AA251,\"-48.8902222;-26.31832\",\"2019-10-01T00:03:00.000+00:00\"\n\
+            AA251,\"-48.8912222;-26.31832\",\"2019-10-01T00:04:00.000+00:00\",4.0,extra\n\
+            # stopped\n";
+
+        let options = CsvOptions {
+            skip_lines: 2,
+            comment: Some("#".to_string()),
+            flexible: true,
+            ..Default::default()
+        };
+        let source = CsvSource::new(options.reader(data.as_bytes()), None);
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2010-10-01 0:00 UTC),
+            datetime!(2020-10-01 2:00 UTC),
+            TrackSegmentOptions::default(),
+        )?;
+        let points = &tracks[0].segments[0].points;
+        assert_eq!(3, points.len());
+        assert_eq!(Some(3.5), points[0].speed);
+        assert_eq!(Some(4.0), points[2].speed);
+
+        let options = CsvOptions {
+            skip_lines: 2,
+            ..Default::default()
+        };
+        let source = CsvSource::new(options.reader(data.as_bytes()), None);
+        assert!(SourceToTracks::build(
+            source,
+            datetime!(2010-10-01 0:00 UTC),
+            datetime!(2020-10-01 2:00 UTC),
+            TrackSegmentOptions::default(),
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn strict_rows() -> Result<(), String> {
+        use crate::PositionsSource;
+
+        let data = "device,coordinates,time,speed\n\
+            AA251 ,\"-48.87,-26.31\",2019-10-01T00:01:00Z,3.5\n\
+            AA251 ,\"-48.88,-26.31\"\n\
+            AA251 ,\"-48.89,-26.31\",2019-10-01T00:03:00Z,4.0\n";
+        let fields = Default::default();
+
+        let mut options = CsvOptions {
+            flexible: true,
+            ..Default::default()
+        };
+        let positions = options
+            .source(data.as_bytes(), &fields)
+            .fetch(TimeRange::all())?;
+        assert_eq!(2, positions.len());
+        assert_eq!("AA251", &*positions[0].device_id);
+
+        options.trim = false;
+        let positions = options
+            .source(data.as_bytes(), &fields)
+            .fetch(TimeRange::all())?;
+        assert_eq!("AA251 ", &*positions[0].device_id);
+
+        // The short row fails on the reader of the options, and
+        // on the source with a flexible reader
+        options.flexible = false;
+        assert!(options
+            .source(data.as_bytes(), &fields)
+            .fetch(TimeRange::all())
+            .is_err());
+
+        let error = CsvSource::new(
+            ReaderBuilder::new()
+                .flexible(true)
+                .from_reader(data.as_bytes()),
+            None,
+        )
+        .strict(true)
+        .fetch(TimeRange::all())
+        .err()
+        .ok_or("Short row parsed")?;
+        assert_eq!("The row 2 has 2 fields, but the header has 4", error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn header_aliases() -> Result<(), String> {
+        use crate::{FieldsConfiguration, PositionsSource};
+
+        let fields: FieldsConfiguration = serde_yaml::from_str(
+            "device_id: Device ID\n\
+            aliases:\n  \
+              time: [timestamp, datetime, fix_time]\n  \
+              coordinates: [position]",
+        )
+        .map_err(|e| e.to_string())?;
+
+        let fetch = |data: &str| {
+            CsvSource::new(
+                ReaderBuilder::new().from_reader(data.as_bytes()),
+                Some(fields.clone()),
+            )
+            .fetch(TimeRange::all())
+        };
+
+        // The first alias found wins, whatever the column order
+        let data = "DEVICEID, Fix_Time ,DateTime,Position\n\
+            AA251,2019-10-01T00:01:00Z,2019-10-01T00:05:00Z,\"-48.87,-26.31\"\n";
+        let positions = fetch(data)?;
+        assert_eq!("AA251", &*positions[0].device_id);
+        assert_eq!(datetime!(2019-10-01 0:05 UTC), positions[0].pos.time);
+        assert_eq!(Point::new(-48.87, -26.31), positions[0].pos.coordinates);
+
+        // The configured name comes before the aliases
+        let data = "device id,fix_time,time,coordinates\n\
+            AA251,2019-10-01T00:01:00Z,2019-10-01T00:05:00Z,\"-48.87,-26.31\"\n";
+        assert_eq!(datetime!(2019-10-01 0:05 UTC), fetch(data)?[0].pos.time);
+
+        let error = fetch("device id,recorded,coordinates\n")
+            .err()
+            .ok_or("Header without a time parsed")?;
+        assert_eq!("Time header not found", error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sniffed_lat_lng_columns() -> Result<(), String> {
+        let data = "\n
+            unit,lat,lon,fix\n
+            AA251,-26.31832,-48.8702222,2019-10-01T00:01:00.000+00:00\n
+            AA251,-26.31832,-48.8802222,2019-10-01T00:02:00.000+00:00\n
+        ";
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let mut source = CsvSource::new(rdr, None);
+        let sniffed = source.sniff(1)?;
+        assert_eq!(4, sniffed.len());
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2010-10-01 0:00 UTC),
+            datetime!(2020-10-01 2:00 UTC),
+            TrackSegmentOptions::default(),
+        )?;
+        assert_eq!(
+            Some("Tracked by `AA251`".to_string()),
+            tracks[0].description
+        );
+        let points = &tracks[0].segments[0].points;
+        assert_eq!(2, points.len());
+        assert_eq!(Point::new(-48.8802222, -26.31832), points[1].point());
+
+        Ok(())
+    }
+
+    #[test]
+    fn geojson_coordinates() -> Result<(), String> {
+        let data = r#"
+            device,coordinates,time
+            AA251,"{""type"":""Point"",""coordinates"":[-48.8702222,-26.31832]}","2019-10-01T00:01:00.000+00:00"
+            AA251,"{""type"":""Feature"",""geometry"":{""type"":""Point"",""coordinates"":[-48.8802222,-26.31832]},""properties"":{}}","2019-10-01T00:02:00.000+00:00"
+        "#;
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let source = CsvSource::new(rdr, None);
+        let op = TrackSegmentOptions::default();
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2010-10-01 0:00 UTC),
+            datetime!(2020-10-01 2:00 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+        let segment = &tracks[0].segments[0];
+        assert_eq!(2, segment.points.len());
+        assert_eq!(
+            Point::new(-48.8702222, -26.31832),
+            segment.points[0].point()
+        );
+        assert_eq!(
+            Point::new(-48.8802222, -26.31832),
+            segment.points[1].point()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn line_coordinates() -> Result<(), String> {
+        let data = r#"
+            device,coordinates,time,interval
+            AA251,"LINESTRING(-48.87 -26.31, -48.88 -26.31, -48.89 -26.31)","2019-10-01T00:01:00.000+00:00",30
+            AA251,"-48.90,-26.31","2019-10-01T00:03:00.000+00:00",
+        "#;
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let source = CsvSource::new(rdr, None);
+        let op = TrackSegmentOptions::default();
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2019-10-01 0:00 UTC),
+            datetime!(2019-10-01 0:02:30 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+        let segment = &tracks[0].segments[0];
+        assert_eq!(3, segment.points.len());
+        assert_eq!(Point::new(-48.88, -26.31), segment.points[1].point());
+        assert_eq!(
+            Some(datetime!(2019-10-01 0:02:00 UTC).into()),
+            segment.points[2].time
+        );
+
+        let data = r#"
+            device,coordinates,time
+            AA251,"LINESTRING(-48.87 -26.31, -48.88 -26.31)","2019-10-01T00:01:00.000+00:00"
+        "#;
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let source = CsvSource::new(rdr, None);
+        let res = SourceToTracks::build(
+            source,
+            datetime!(2019-10-01 0:00 UTC),
+            datetime!(2019-10-01 0:02:00 UTC),
+            TrackSegmentOptions::default(),
+        );
+        assert!(res.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn epoch_times() -> Result<(), String> {
+        let data = r#"
+            device,coordinates,time
+            AA251,"-48.8702222,-26.31832",1569888060
+            AA251,"-48.8802222,-26.31832",1569888120000
+            AA251,"-48.8902222,-26.31832",1569888180000000
+        "#;
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let source = CsvSource::new(rdr, None);
+        let op = TrackSegmentOptions::default();
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2019-10-01 0:00 UTC),
+            datetime!(2019-10-01 2:00 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+        let segment = &tracks[0].segments[0];
+        assert_eq!(3, segment.points.len());
+        assert_eq!(
+            Some(datetime!(2019-10-01 0:02:00 UTC).into()),
+            segment.points[1].time
+        );
+        assert_eq!(
+            Some(datetime!(2019-10-01 0:03:00 UTC).into()),
+            segment.points[2].time
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn positions_by_time() -> Result<(), String> {
+        use crate::PositionsSource;
+
+        let data = "\n
+            device,coordinates,time\n
+            AA251,\"-48.8702222,-26.31832\",\"2019-10-01T00:03:00.000+00:00\"\n
+            BB100,\"-48.8702222,-26.31832\",\"2019-10-01T00:02:00.000+00:00\"\n
+            AA251,\"-48.8802222,-26.31832\",\"2019-10-01T00:01:00.000+00:00\"\n
+            BB100,\"-48.8802222,-26.31832\",\"2019-10-01T00:02:00.000+00:00\"\n
+        ";
+        let rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let positions = CsvSource::new(rdr, None).fetch(TimeRange::all())?;
+
+        let order: Vec<_> = positions
+            .iter()
+            .map(|p| (&*p.device_id, p.pos.coordinates.x()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("AA251", -48.8802222),
+                ("BB100", -48.8702222),
+                ("BB100", -48.8802222),
+                ("AA251", -48.8702222)
+            ],
+            order
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_rows() -> Result<(), String> {
+        use crate::PositionsSource;
+
+        let mut data = "device,coordinates,time\n".to_string();
+        for i in 0..500 {
+            data.push_str(&format!(
+                "AA{},\"-48.87{:03},-26.31\",2019-10-01T00:{:02}:{:02}Z\n",
+                i % 3,
+                i,
+                i / 60 % 60,
+                i % 60
+            ));
+        }
+        data.push_str("AA1,\"-48.87,-26.31\",yesterday\n");
+
+        let fetch = |threads: usize, rows: &str| {
+            CsvSource::new(ReaderBuilder::new().from_reader(rows.as_bytes()), None)
+                .threads(threads)
+                .fetch(TimeRange::all())
+        };
+        let valid = data.lines().take(501).collect::<Vec<_>>().join("\n");
+
+        let single = fetch(1, &valid)?;
+        let parallel = fetch(4, &valid)?;
+        assert_eq!(500, parallel.len());
+        let order = |positions: &[crate::DevicePosition]| -> Vec<(std::sync::Arc<str>, f64)> {
+            positions
+                .iter()
+                .map(|p| (p.device_id.clone(), p.pos.coordinates.x()))
+                .collect()
+        };
+        assert_eq!(order(&single), order(&parallel));
+        // A single copy of each device, even from other threads
+        let dev1 = &parallel[0].device_id;
+        assert!(parallel
+            .iter()
+            .filter(|p| p.device_id == *dev1)
+            .all(|p| std::sync::Arc::ptr_eq(dev1, &p.device_id)));
+
+        let error = fetch(4, &data).err().ok_or("Invalid row parsed")?;
+        assert!(error.contains("yesterday"), "{}", error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn byte_rows() -> Result<(), String> {
+        use crate::PositionsSource;
+
+        // Padded fields and a note with a Latin-1 byte, never read
+        let mut data = b"device , latitude, longitude ,time,route,note\n\
+            AA251 , -26.31,-48.87 , 2019-10-01T00:01:00Z, 10 ,"
+            .to_vec();
+        data.extend(b"caf\xe9\n");
+        data.extend(b"AA251,-26.32,-48.88,2019-10-01T00:02:00Z,,\n");
+
+        let positions = CsvSource::new(ReaderBuilder::new().from_reader(&data[..]), None)
+            .fetch(TimeRange::all())?;
+        assert_eq!(2, positions.len());
+        assert_eq!("AA251", &*positions[0].device_id);
+        assert!(std::sync::Arc::ptr_eq(
+            &positions[0].device_id,
+            &positions[1].device_id
+        ));
+        assert_eq!(Point::new(-48.87, -26.31), positions[0].pos.coordinates);
+        assert_eq!(Some("10"), positions[0].route_name.as_deref());
+        assert_eq!(None, positions[1].route_name);
+
+        let invalid =
+            b"device,latitude,longitude,time\nAA\xe9,-26.31,-48.87,2019-10-01T00:01:00Z\n";
+        let error = CsvSource::new(ReaderBuilder::new().from_reader(&invalid[..]), None)
+            .fetch(TimeRange::all())
+            .err()
+            .ok_or("Invalid device parsed")?;
+        assert!(error.contains("UTF-8"), "{}", error);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "raw")]
+    #[test]
+    fn raw_rows() -> Result<(), String> {
+        use crate::Sentinels;
+        use serde_json::json;
+
+        let data = "device,coordinates,time,note\n\
+            AA251,\"-48.88,-26.32\",2019-10-01T00:01:00Z,fix\n\
+            AA251,\"0,0\",2019-10-01T00:01:00Z, boot \n\
+            AA251,\"-48.87,-26.31\",2019-10-01T00:02:00Z,ok\n";
+        let rejected = |trim: bool| -> Result<Option<serde_json::Value>, String> {
+            let mut source =
+                CsvSource::new(ReaderBuilder::new().from_reader(data.as_bytes()), None);
+            source.keep_raw(true).trim(trim);
+
+            let (_, report) = SourceToTracks::new()
+                .configure_segments(&TrackSegmentOptions {
+                    sentinels: Sentinels {
+                        coordinates: vec![[0.0, 0.0]],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .run(source, TimeRange::all())?;
+
+            assert_eq!(1, report.rejects.len());
+            Ok(report.rejects[0].raw.clone())
+        };
+
+        let raw = |note: &str| {
+            Some(json!({
+                "device": "AA251",
+                "coordinates": "0,0",
+                "time": "2019-10-01T00:01:00Z",
+                "note": note,
+            }))
+        };
+        assert_eq!(raw("boot"), rejected(true)?);
+        assert_eq!(raw(" boot "), rejected(false)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn traccar_export() -> Result<(), String> {
+        use super::CsvPreset;
+        use crate::PositionsSource;
+
+        let data = "\"ID\",\"PROTOCOL\",\"DEVICEID\",\"SERVERTIME\",\"DEVICETIME\",\"FIXTIME\",\"VALID\",\"LATITUDE\",\"LONGITUDE\",\"ALTITUDE\",\"SPEED\",\"COURSE\",\"ADDRESS\",\"ATTRIBUTES\",\"ACCURACY\",\"NETWORK\"\n\
+            \"1\",\"osmand\",\"3\",\"2021-05-24 08:00:02.120\",\"2021-05-24 08:00:00\",\"2021-05-24 08:00:00\",\"TRUE\",\"-26.31\",\"-48.87\",\"12.5\",\"19.4384\",\"90.0\",,\"{\"\"batteryLevel\"\":80.0,\"\"distance\"\":0.0}\",\"4.0\",\n\
+            \"2\",\"osmand\",\"3\",\"2021-05-24 08:01:02.340\",\"2021-05-24 08:01:00\",\"2021-05-24 08:01:00.500\",\"TRUE\",\"-26.30\",\"-48.86\",\"13.0\",\"0.0\",\"90.0\",,\"{}\",\"4.0\",\n";
+
+        let options = CsvOptions {
+            preset: Some(CsvPreset::Traccar),
+            ..Default::default()
+        };
+        let fields = options.fields(&Default::default());
+        let positions =
+            CsvSource::new(options.reader(data.as_bytes()), Some(fields)).fetch(TimeRange::all())?;
+
+        assert_eq!(2, positions.len());
+        let first = &positions[0];
+        assert_eq!("3", &*first.device_id);
+        assert_eq!(Point::new(-48.87, -26.31), first.pos.coordinates);
+        assert_eq!(datetime!(2021-05-24 8:00 UTC), first.pos.time);
+        assert_eq!(Some(12.5), first.pos.altitude);
+        assert_eq!(Some(4.0), first.pos.precision);
+        assert_eq!(Some("osmand"), first.tracker.as_deref());
+        // 19.4384 knots
+        let speed = first.pos.speed.ok_or("No speed")?;
+        assert!((speed - 10.0).abs() < 1e-3, "{}", speed);
+        assert_eq!(datetime!(2021-05-24 8:01:00.5 UTC), positions[1].pos.time);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,317 @@
+//! Detection of the CSV columns by their headers and values
+
+use csv::StringRecord;
+
+use super::csv_file::header_key;
+use super::geometry::parse_points;
+use super::{EpochUnit, FieldsConfiguration};
+
+/// Column guessed for a field
+#[derive(Clone, Debug, PartialEq)]
+pub struct SniffedField {
+    /// Name of the field, like `time`
+    pub field: &'static str,
+    /// Header of the column
+    pub column: String,
+}
+
+/// Kinds of the fields values
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Device,
+    Time,
+    Coordinates,
+    Latitude,
+    Longitude,
+    Number,
+}
+
+/// Fields detected, with the usual names of their columns
+const FIELDS: &[(&str, Kind, &[&str])] = &[
+    (
+        "coordinates",
+        Kind::Coordinates,
+        &[
+            "coordinates",
+            "coords",
+            "coordinate",
+            "position",
+            "location",
+            "geometry",
+            "geom",
+            "point",
+            "wkt",
+        ],
+    ),
+    ("latitude", Kind::Latitude, &["latitude", "lat", "y"]),
+    (
+        "longitude",
+        Kind::Longitude,
+        &["longitude", "lon", "lng", "long", "x"],
+    ),
+    (
+        "time",
+        Kind::Time,
+        &[
+            "time",
+            "timestamp",
+            "datetime",
+            "date_time",
+            "gps_time",
+            "fix_time",
+            "utc",
+            "ts",
+            "date",
+        ],
+    ),
+    (
+        "device_id",
+        Kind::Device,
+        &[
+            "device",
+            "device_id",
+            "deviceid",
+            "imei",
+            "unit",
+            "unit_id",
+            "vehicle",
+            "vehicle_id",
+            "tracker",
+            "tracker_id",
+            "id",
+            "name",
+        ],
+    ),
+    ("speed", Kind::Number, &["speed", "velocity", "spd"]),
+    (
+        "elevation",
+        Kind::Number,
+        &["elevation", "altitude", "alt", "ele", "height"],
+    ),
+];
+
+/// Guess the columns of the fields by the header and the sample rows.
+/// The fields already found on the header, by the configured names or
+/// their aliases, are kept, so the config overrides the guesses
+pub fn sniff_fields(
+    header: &StringRecord,
+    samples: &[StringRecord],
+    fields: &mut FieldsConfiguration,
+) -> Vec<SniffedField> {
+    let names: Vec<String> = header.iter().map(|h| h.trim().to_lowercase()).collect();
+    let keys: Vec<String> = header.iter().map(header_key).collect();
+    let mut taken = vec![false; names.len()];
+    let mut missing = vec![];
+
+    for (field, kind, _) in FIELDS {
+        let configured = std::iter::once(configured(fields, field))
+            .chain(configured_aliases(fields, field).iter().map(String::as_str))
+            .map(header_key)
+            .collect::<Vec<_>>();
+        match keys.iter().position(|key| configured.contains(key)) {
+            Some(i) => taken[i] = true,
+            None => missing.push((*field, *kind)),
+        }
+    }
+
+    let values = |i: usize| -> Vec<&str> {
+        samples
+            .iter()
+            .filter_map(|row| row.get(i))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .collect()
+    };
+
+    let mut sniffed = vec![];
+    let mut pick = |field: &'static str, i: usize, taken: &mut Vec<bool>| {
+        taken[i] = true;
+        *configured_mut(fields, field) = names[i].clone();
+        sniffed.push(SniffedField {
+            field,
+            column: header.get(i).unwrap_or_default().trim().to_string(),
+        });
+    };
+
+    // By the name of the columns, like `gps_latitude`
+    let mut unsolved = vec![];
+    for (field, kind) in missing {
+        let keywords = FIELDS
+            .iter()
+            .find(|(f, _, _)| *f == field)
+            .map(|(_, _, k)| *k)
+            .unwrap_or_default();
+
+        let by_name = |exact: bool| {
+            (0..names.len()).find(|i| {
+                let matches = keywords.iter().any(|k| {
+                    if exact {
+                        names[*i] == *k
+                    } else {
+                        tokens(&names[*i]).any(|t| t == *k)
+                    }
+                });
+                !taken[*i] && matches && accepts(kind, &values(*i))
+            })
+        };
+
+        match by_name(true).or_else(|| by_name(false)) {
+            Some(i) => pick(field, i, &mut taken),
+            None => unsolved.push((field, kind)),
+        }
+    }
+
+    // By the values only, for the required fields
+    for (field, kind) in unsolved {
+        let found = (0..names.len()).find(|i| {
+            let values = values(*i);
+            let textual = values.iter().all(|v| v.parse::<f64>().is_err());
+            !taken[*i]
+                && textual
+                && match kind {
+                    Kind::Time | Kind::Coordinates | Kind::Device => accepts(kind, &values),
+                    _ => false,
+                }
+        });
+
+        if let Some(i) = found {
+            pick(field, i, &mut taken);
+        }
+    }
+
+    sniffed
+}
+
+/// Words of the column name
+fn tokens(name: &str) -> impl Iterator<Item = &str> {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| !t.is_empty())
+}
+
+/// If all the values are of the kind
+fn accepts(kind: Kind, values: &[&str]) -> bool {
+    if values.is_empty() {
+        return false;
+    }
+
+    let number = |v: &str| v.parse::<f64>().ok().filter(|n| n.is_finite());
+    let defaults = FieldsConfiguration::default();
+
+    values.iter().all(|v| match kind {
+        Kind::Device => true,
+        // Numeric times only from 1973, so the coordinates
+        // and small numbers are not taken as epochs
+        Kind::Time => match number(v) {
+            Some(n) => n >= 1e8 && EpochUnit::Auto.to_time(n).is_ok(),
+            None => EpochUnit::Auto.parse_text(v).is_ok(),
+        },
+        Kind::Coordinates => {
+            number(v).is_none() && parse_points(v, &defaults).is_ok_and(|p| !p.is_empty())
+        }
+        Kind::Latitude => number(v).is_some_and(|n| (-90.0..=90.0).contains(&n)),
+        Kind::Longitude => number(v).is_some_and(|n| (-180.0..=180.0).contains(&n)),
+        Kind::Number => number(v).is_some(),
+    })
+}
+
+fn configured<'a>(fields: &'a FieldsConfiguration, field: &str) -> &'a str {
+    match field {
+        "coordinates" => &fields.coordinates,
+        "latitude" => &fields.latitude,
+        "longitude" => &fields.longitude,
+        "time" => &fields.time,
+        "device_id" => &fields.device_id,
+        "speed" => &fields.speed,
+        _ => &fields.elevation,
+    }
+}
+
+fn configured_aliases<'a>(fields: &'a FieldsConfiguration, field: &str) -> &'a [String] {
+    let aliases = &fields.aliases;
+    match field {
+        "coordinates" => &aliases.coordinates,
+        "latitude" => &aliases.latitude,
+        "longitude" => &aliases.longitude,
+        "time" => &aliases.time,
+        "device_id" => &aliases.device_id,
+        "speed" => &aliases.speed,
+        _ => &aliases.elevation,
+    }
+}
+
+fn configured_mut<'a>(fields: &'a mut FieldsConfiguration, field: &str) -> &'a mut String {
+    match field {
+        "coordinates" => &mut fields.coordinates,
+        "latitude" => &mut fields.latitude,
+        "longitude" => &mut fields.longitude,
+        "time" => &mut fields.time,
+        "device_id" => &mut fields.device_id,
+        "speed" => &mut fields.speed,
+        _ => &mut fields.elevation,
+    }
+}
+
+#[test]
+fn sniff_columns() {
+    let header = StringRecord::from(vec![
+        "Unit", "GPS Lat", "GPS Lon", "Fix", "Spd", "Alt", "Note",
+    ]);
+    let samples = vec![
+        StringRecord::from(vec![
+            "TRK-1",
+            "-26.31832",
+            "-48.8702222",
+            "2019-10-01T00:01:00Z",
+            "3.5",
+            "12",
+            "",
+        ]),
+        StringRecord::from(vec![
+            "TRK-1",
+            "-26.31833",
+            "-48.8702223",
+            "2019-10-01T00:02:00Z",
+            "",
+            "13",
+            "stop",
+        ]),
+    ];
+
+    let mut fields = FieldsConfiguration::default();
+    let sniffed = sniff_fields(&header, &samples, &mut fields);
+
+    let found = |field: &str| {
+        sniffed
+            .iter()
+            .find(|s| s.field == field)
+            .map(|s| s.column.clone())
+    };
+    assert_eq!(Some("GPS Lat".to_string()), found("latitude"));
+    assert_eq!(Some("GPS Lon".to_string()), found("longitude"));
+    assert_eq!(Some("Fix".to_string()), found("time"));
+    assert_eq!(Some("Unit".to_string()), found("device_id"));
+    assert_eq!(Some("Spd".to_string()), found("speed"));
+    assert_eq!(Some("Alt".to_string()), found("elevation"));
+    assert_eq!(None, found("coordinates"));
+    assert_eq!("gps lat", fields.latitude);
+    assert_eq!("fix", fields.time);
+
+    // The configured names override the guesses
+    let header = StringRecord::from(vec!["device", "position", "recorded", "time"]);
+    let samples = vec![StringRecord::from(vec![
+        "AA251",
+        "-48.8702222,-26.31832",
+        "2019-10-01T00:01:00Z",
+        "2019-10-01T00:01:05Z",
+    ])];
+    let mut fields = FieldsConfiguration::default();
+    let sniffed = sniff_fields(&header, &samples, &mut fields);
+    assert_eq!(
+        vec![SniffedField {
+            field: "coordinates",
+            column: "position".to_string()
+        }],
+        sniffed
+    );
+    assert_eq!("time", fields.time);
+}
@@ -0,0 +1,148 @@
+//! Numeric times, as unix epochs
+
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+/// Timestamps of the SQL databases, without offset
+const SQL_TIMESTAMP: &[FormatItem] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second][optional [.[subsecond]]]");
+
+/// Unit of the numeric time fields
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EpochUnit {
+    /// Detect the unit of each value by its magnitude
+    #[default]
+    Auto,
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+
+impl EpochUnit {
+    /// Units that a value of this configuration can have
+    pub fn candidates(&self) -> Vec<EpochUnit> {
+        match self {
+            Self::Auto => vec![Self::Seconds, Self::Milliseconds, Self::Microseconds],
+            unit => vec![*unit],
+        }
+    }
+
+    /// Unit of the value. With the auto detection the seconds
+    /// go until the year 5138, after that are milliseconds
+    /// and, in the same way, microseconds
+    pub fn detect(&self, value: f64) -> EpochUnit {
+        match self {
+            Self::Auto if value.abs() < 1e11 => Self::Seconds,
+            Self::Auto if value.abs() < 1e14 => Self::Milliseconds,
+            Self::Auto => Self::Microseconds,
+            unit => *unit,
+        }
+    }
+
+    /// Time of the numeric value
+    pub fn to_time(&self, value: f64) -> Result<OffsetDateTime, String> {
+        if !value.is_finite() {
+            return Err("Invalid epoch time".to_string());
+        }
+
+        // The integral epochs without the rounding of the floats,
+        // the others on the nearest microsecond
+        let unit = self.detect(value);
+        let nanos = match value.fract() == 0.0 {
+            true => (value as i128).checked_mul((1e9 / unit.per_second()) as i128),
+            false => ((value * (1e6 / unit.per_second())).round() as i128).checked_mul(1_000),
+        }
+        .ok_or("Invalid epoch time")?;
+
+        OffsetDateTime::from_unix_timestamp_nanos(nanos)
+            .map_err(|e| format!("Failed on parse the epoch time: {}", e))
+    }
+
+    /// Numeric value of the time, in this unit. The auto
+    /// detection gives the value in seconds
+    pub fn from_time(&self, time: OffsetDateTime) -> f64 {
+        let unit = match self {
+            Self::Auto => Self::Seconds,
+            unit => *unit,
+        };
+
+        time.unix_timestamp_nanos() as f64 / (1e9 / unit.per_second())
+    }
+
+    /// Parse a RFC3339 or numeric text time. The SQL timestamps without
+    /// an offset, like `2021-05-24 08:00:00.000`, are read as UTC
+    pub fn parse_text(&self, raw: &str) -> Result<OffsetDateTime, String> {
+        let raw = raw.trim();
+
+        match raw.parse::<f64>() {
+            Ok(value) => self.to_time(value),
+            Err(_) => OffsetDateTime::parse(raw, &well_known::Rfc3339)
+                .or_else(|e| {
+                    PrimitiveDateTime::parse(raw, SQL_TIMESTAMP)
+                        .map(PrimitiveDateTime::assume_utc)
+                        .map_err(|_| e)
+                })
+                .map_err(|e| format!("Failed on parse the time: {}", e)),
+        }
+    }
+
+    fn per_second(&self) -> f64 {
+        match self {
+            Self::Auto | Self::Seconds => 1.0,
+            Self::Milliseconds => 1e3,
+            Self::Microseconds => 1e6,
+        }
+    }
+}
+
+#[test]
+fn epoch_units() -> Result<(), String> {
+    use time::macros::datetime;
+
+    let expected = datetime!(2019-10-03 0:01:00 UTC);
+
+    assert_eq!(expected, EpochUnit::Auto.to_time(1570060860.0)?);
+    assert_eq!(expected, EpochUnit::Auto.to_time(1570060860000.0)?);
+    assert_eq!(expected, EpochUnit::Auto.to_time(1570060860000000.0)?);
+    assert_eq!(expected, EpochUnit::Auto.parse_text("1570060860000")?);
+    assert_eq!(
+        expected,
+        EpochUnit::Auto.parse_text("2019-10-03T00:01:00.000+00:00")?
+    );
+    assert_eq!(expected, EpochUnit::Auto.parse_text("2019-10-03 00:01:00")?);
+    assert_eq!(
+        datetime!(2019-10-03 0:01:00.25 UTC),
+        EpochUnit::Auto.parse_text("2019-10-03 00:01:00.250")?
+    );
+    assert!(EpochUnit::Auto.parse_text("2019-10-03 00:01").is_err());
+    assert_eq!(
+        datetime!(2019-10-03 0:01:00.5 UTC),
+        EpochUnit::Auto.to_time(1570060860.5)?
+    );
+    assert_eq!(
+        datetime!(1970-01-19 4:07:40.86 UTC),
+        EpochUnit::Milliseconds.to_time(1570060860.0)?
+    );
+    assert_eq!(1570060860000.0, EpochUnit::Milliseconds.from_time(expected));
+    assert!(EpochUnit::Seconds.to_time(f64::NAN).is_err());
+    assert!(EpochUnit::Seconds.to_time(1e300).is_err());
+
+    assert_eq!(
+        datetime!(2019-10-01 0:02:00.001 UTC),
+        EpochUnit::Auto.parse_text("1569888120001")?
+    );
+    assert_eq!(
+        datetime!(2019-10-01 0:02:00.000_001 UTC),
+        EpochUnit::Microseconds.to_time(1569888120000001.0)?
+    );
+    assert_eq!(
+        datetime!(2019-10-01 0:02:00.001 UTC),
+        EpochUnit::Seconds.to_time(1569888120.001)?
+    );
+
+    Ok(())
+}
@@ -0,0 +1,268 @@
+//! Teltonika AVL JSON source integration, as decoded by flespi
+//! and similar gateways
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+
+use geo::geometry::Point;
+use serde_json::{Deserializer, Value};
+use time::OffsetDateTime;
+
+use super::numeric::json_number;
+use super::{sort_by_time, PositionsSource, TimeRange};
+use crate::{DevicePosition, Names};
+
+/// Io elements reported as events when their state changes, with
+/// the keys of the element and the bit of the `din` mask
+const IO_EVENTS: [(&str, &str, Option<u64>); 2] = [
+    ("ignition", "engine.ignition.status", Some(1)),
+    ("movement", "movement.status", None),
+];
+
+/// Decoded Teltonika messages source
+///
+/// Accepts a JSON array of messages or one message per line. The
+/// ignition and movement changes are named on their positions, like
+/// `ignition_on`, and the invalid messages are skipped.
+pub struct FlespiSource<T>
+where
+    T: Read,
+{
+    rdr: T,
+    keep_raw: bool,
+    skipped: Vec<String>,
+    /// Shared names of all the messages
+    names: Names,
+}
+
+impl<T> FlespiSource<T>
+where
+    T: Read,
+{
+    pub fn new(rdr: T) -> Self {
+        Self {
+            rdr,
+            keep_raw: false,
+            skipped: vec![],
+            names: Names::default(),
+        }
+    }
+
+    /// Keep the message of each position, on the builds with the `raw` feature
+    pub fn keep_raw(&mut self, keep: bool) -> &mut Self {
+        self.keep_raw = keep;
+
+        self
+    }
+}
+
+impl<T> PositionsSource for FlespiSource<T>
+where
+    T: Read,
+{
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+        let mut pos = vec![];
+
+        let mut data = String::new();
+        self.rdr
+            .read_to_string(&mut data)
+            .map_err(|e| format!("Failed on read the messages: {}", e))?;
+
+        let mut messages = vec![];
+        for rvalue in Deserializer::from_str(&data).into_iter::<Value>() {
+            match rvalue.map_err(|e| format!("Failed on parse the messages: {}", e))? {
+                Value::Array(values) => messages.extend(values),
+                value => messages.push(value),
+            }
+        }
+
+        // Last state of the io elements of each device
+        let mut states: HashMap<(Arc<str>, &str), bool> = HashMap::new();
+        for msg in messages {
+            let (device_id, msg_pos) = match parse_message(&msg, &mut self.names) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    self.skipped
+                        .push(format!("Error with message {}: {}", msg, e));
+                    continue;
+                }
+            };
+
+            let mut event = None;
+            for (name, key, din_bit) in IO_EVENTS {
+                if let Some(on) = io_state(&msg, key, din_bit) {
+                    let last = states.insert((device_id.clone(), name), on);
+                    if last != Some(on) && event.is_none() {
+                        event = Some(format!("{}_{}", name, if on { "on" } else { "off" }));
+                    }
+                }
+            }
+
+            if let Some(mut dpos) = msg_pos {
+                dpos.pos.event = event;
+                if self.keep_raw {
+                    dpos.keep_raw(|| msg.clone());
+                }
+                if range.contains(dpos.pos.time) {
+                    pos.push(dpos);
+                }
+            }
+        }
+
+        sort_by_time(&mut pos);
+
+        Ok(pos)
+    }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
+    }
+
+    fn take_skipped(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.skipped)
+    }
+}
+
+/// State of the io element on the message, from its key or the `din` mask
+fn io_state(msg: &Value, key: &str, din_bit: Option<u64>) -> Option<bool> {
+    match msg.get(key) {
+        Some(Value::Bool(on)) => Some(*on),
+        Some(value) => json_number(value).map(|n| n != 0.0),
+        None => din_bit.and_then(|bit| {
+            msg.get("din")
+                .and_then(|din| din.as_u64())
+                .map(|din| din & bit != 0)
+        }),
+    }
+}
+
+/// Device of the message, with its position when the message has coordinates
+fn parse_message(
+    msg: &Value,
+    names: &mut Names,
+) -> Result<(Arc<str>, Option<DevicePosition>), String> {
+    let device_id = match msg.get("ident") {
+        Some(Value::String(di)) => Ok(names.get(di)),
+        Some(Value::Number(di)) => Ok(names.get(&di.to_string())),
+        Some(_) => Err("Ident field type not supported"),
+        None => Err("Ident field not found"),
+    }?;
+
+    let (lat, lng) = match (
+        msg.get("position.latitude").and_then(json_number),
+        msg.get("position.longitude").and_then(json_number),
+    ) {
+        (Some(lat), Some(lng)) => (lat, lng),
+        _ => return Ok((device_id, None)),
+    };
+
+    let time = match msg.get("timestamp").and_then(|v| v.as_f64()) {
+        Some(tm) => OffsetDateTime::from_unix_timestamp_nanos((tm * 1e9) as i128)
+            .map_err(|e| format!("Failed on parse the timestamp: {}", e)),
+        None => Err("Timestamp field not found".to_string()),
+    }?;
+
+    let mut dpos = DevicePosition::basic(device_id.clone(), Point::new(lng, lat), time);
+
+    // Teltonika reports the speed in km/h
+    dpos.pos.speed = msg
+        .get("position.speed")
+        .and_then(json_number)
+        .map(|sp| sp / 3.6);
+    dpos.pos.altitude = msg.get("position.altitude").and_then(json_number);
+    dpos.pos.satellites = msg.get("position.satellites").and_then(|v| v.as_u64());
+    dpos.pos.hdop = msg.get("position.hdop").and_then(json_number);
+
+    Ok((device_id, Some(dpos)))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use geo::geometry::Point;
+    use time::macros::datetime;
+
+    use super::FlespiSource;
+    use crate::sources::{PositionsSource, TimeRange};
+    use crate::{SourceToTracks, TrackSegmentOptions};
+
+    #[test]
+    fn track() -> Result<(), String> {
+        let data = r#"[
+            {"ident": "352093081429150", "timestamp": 1570060860.0, "position.latitude": -26.31832, "position.longitude": -48.8702222, "position.speed": 36, "position.altitude": 12, "position.satellites": 9, "engine.ignition.status": true},
+            {"ident": "352093081429150", "timestamp": 1570060920.5, "position.latitude": -26.31832, "position.longitude": -48.8802222},
+            {"ident": "352093081429150", "timestamp": 1570060980.0, "din": 1},
+            {"timestamp": 1570061000.0, "position.latitude": -26.31832, "position.longitude": -48.8902222},
+            {"ident": "352093081429150", "timestamp": 1570061040.0, "position.latitude": -26.31832, "position.longitude": -48.8902222, "din": 0}
+        ]"#;
+
+        let mut source = FlespiSource::new(data.as_bytes());
+        assert_eq!(3, source.fetch(TimeRange::all())?.len());
+        let skipped = source.take_skipped();
+        assert_eq!(1, skipped.len());
+        assert!(skipped[0].ends_with("Ident field not found"));
+
+        let source = FlespiSource::new(data.as_bytes());
+        let op = TrackSegmentOptions::default();
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2010-05-24 0:00 UTC),
+            datetime!(2023-05-24 0:00 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+
+        let track = &tracks[0];
+        assert_eq!(1, track.segments.len());
+        assert_eq!(Some("2019-10-03".to_string()), track.name);
+        assert_eq!(
+            Some("Tracked by `352093081429150`".to_string()),
+            track.description
+        );
+        let segment = &track.segments[0];
+        assert_eq!(3, segment.points.len());
+        let point = &segment.points[0];
+        assert_eq!(Point::new(-48.8702222, -26.31832), point.point());
+        assert_eq!(Some(10.0), point.speed);
+        assert_eq!(Some(12.0), point.elevation);
+        assert_eq!(Some(9), point.sat);
+        let events: Vec<Option<&str>> = segment.points.iter().map(|p| p.name.as_deref()).collect();
+        assert_eq!(
+            vec![Some("ignition_on"), None, Some("ignition_off")],
+            events
+        );
+        assert_eq!(Some("ignition_off".to_string()), segment.points[2]._type);
+        assert_eq!(
+            Some(datetime!(2019-10-03 0:02:00.5 UTC).into()),
+            segment.points[1].time
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn track_lines() -> Result<(), String> {
+        let data = r#"
+            {"ident": 251, "timestamp": 1570060860, "position.latitude": -26.31832, "position.longitude": -48.8702222}
+            {"ident": 251, "timestamp": 1570060920, "position.latitude": -26.31832, "position.longitude": -48.8802222}
+        "#;
+
+        let source = FlespiSource::new(data.as_bytes());
+        let op = TrackSegmentOptions::default();
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2019-10-03 0:00 UTC),
+            datetime!(2019-10-03 0:01:30 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+        let track = &tracks[0];
+        assert_eq!(Some("Tracked by `251`".to_string()), track.description);
+        let segment = &track.segments[0];
+        assert_eq!(1, segment.points.len());
+
+        Ok(())
+    }
+}
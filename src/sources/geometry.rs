@@ -0,0 +1,434 @@
+//! Geometries serialized as text, shared by the sources
+
+use geo::geometry::Point;
+use serde_json::Value;
+use time::{Duration, OffsetDateTime};
+
+use super::numeric::parse_number;
+use super::FieldsConfiguration;
+use crate::DevicePosition;
+
+/// Parse the points from a `lng,lat` pair, GeoJSON, a `{lat, lng}`
+/// object, WKT or hex encoded WKB
+///
+/// Points give one item and LineStrings or MultiPoints give all
+/// their points. Other geometries give nothing. The `flip_coordinates`
+/// is only applied on the pairs, the other formats already define the
+/// axis order.
+pub fn parse_points(raw: &str, fields: &FieldsConfiguration) -> Result<Vec<Point>, String> {
+    let raw = raw.trim();
+
+    if raw.starts_with('{') {
+        return parse_json(raw, fields);
+    }
+
+    if raw.len() >= 42 && raw.len().is_multiple_of(2) && raw.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return parse_wkb(raw);
+    }
+
+    if raw.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return parse_wkt(&raw.to_uppercase());
+    }
+
+    parse_pair(raw, fields.flip_coordinates)
+}
+
+/// Keys of the latitude on the coordinates objects, the
+/// configured one and then the usual abbreviation
+pub fn latitude_keys(fields: &FieldsConfiguration) -> [&str; 2] {
+    [&fields.latitude, "lat"]
+}
+
+/// Keys of the longitude on the coordinates objects, the
+/// configured one and then the usual abbreviations
+pub fn longitude_keys(fields: &FieldsConfiguration) -> [&str; 3] {
+    [&fields.longitude, "lng", "lon"]
+}
+
+/// Times of each point of a line, starting at the record
+/// time and spaced by the interval, in seconds
+pub fn line_times(
+    start: OffsetDateTime,
+    count: usize,
+    interval: Option<f64>,
+) -> Result<Vec<OffsetDateTime>, String> {
+    if count == 1 {
+        return Ok(vec![start]);
+    }
+
+    let interval = match interval {
+        Some(i) if i >= 0.0 => Duration::checked_seconds_f64(i).ok_or("Interval invalid")?,
+        Some(_) => return Err("Interval invalid".to_string()),
+        None => return Err("Interval or times required for line geometries".to_string()),
+    };
+
+    (0..count)
+        .map(|i| {
+            i32::try_from(i)
+                .ok()
+                .and_then(|i| interval.checked_mul(i))
+                .and_then(|offset| start.checked_add(offset))
+                .ok_or_else(|| "Interval invalid".to_string())
+        })
+        .collect()
+}
+
+/// Expand a record with many points into one position per point
+pub fn expand_positions(
+    dpos: DevicePosition,
+    points: Vec<Point>,
+    times: Vec<OffsetDateTime>,
+) -> Result<Vec<DevicePosition>, String> {
+    if points.len() != times.len() {
+        return Err(format!(
+            "The line has {} points but {} times",
+            points.len(),
+            times.len()
+        ));
+    }
+
+    Ok(points
+        .into_iter()
+        .zip(times)
+        .map(|(point, time)| {
+            let mut pos = dpos.clone();
+            pos.pos.coordinates = point;
+            pos.pos.time = time;
+            pos
+        })
+        .collect())
+}
+
+fn parse_pair(raw: &str, flip: bool) -> Result<Vec<Point>, String> {
+    let separator = match raw {
+        s if s.contains(',') => ",",
+        s if s.contains(';') => ";",
+        _ => " ",
+    };
+    let scoordinates: Vec<&str> = raw.split(separator).map(|s| s.trim()).collect();
+    if scoordinates.len() != 2 {
+        return Ok(vec![]);
+    }
+
+    let mut ilat = 1;
+    let mut ilng = 0;
+    if flip {
+        ilat = 0;
+        ilng = 1;
+    }
+
+    let lat =
+        parse_number(scoordinates[ilat]).map_err(|e| format!("Invalid latitude format: {}", e))?;
+    let lng =
+        parse_number(scoordinates[ilng]).map_err(|e| format!("Invalid longitude format: {}", e))?;
+
+    Ok(vec![Point::new(lng, lat)])
+}
+
+/// Parse a JSON object with the latitude and longitude keys, or a GeoJSON
+fn parse_json(raw: &str, fields: &FieldsConfiguration) -> Result<Vec<Point>, String> {
+    let value: Value =
+        serde_json::from_str(raw).map_err(|e| format!("Invalid GeoJSON format: {}", e))?;
+
+    let number = |keys: &[&str]| {
+        keys.iter().find_map(|key| value.get(key)).map(|v| match v {
+            Value::Number(n) => n.as_f64().ok_or(()),
+            Value::String(s) => parse_number(s).map_err(|_| ()),
+            _ => Err(()),
+        })
+    };
+
+    match (
+        number(&latitude_keys(fields)),
+        number(&longitude_keys(fields)),
+    ) {
+        (Some(Ok(lat)), Some(Ok(lng))) => Ok(vec![Point::new(lng, lat)]),
+        (Some(_), Some(_)) => Err("Invalid coordinates object".to_string()),
+        _ => parse_geojson(value),
+    }
+}
+
+/// Parse a GeoJSON geometry, or a Feature of it. The coordinates
+/// order is always `lng,lat`, as defined by the GeoJSON spec
+fn parse_geojson(value: Value) -> Result<Vec<Point>, String> {
+    let geometry = match value.get("type").and_then(|t| t.as_str()) {
+        Some("Feature") => value.get("geometry").unwrap_or(&Value::Null),
+        _ => &value,
+    };
+
+    let coordinates = geometry.get("coordinates").unwrap_or(&Value::Null);
+
+    let positions = match geometry.get("type").and_then(|t| t.as_str()) {
+        Some("Point") => vec![coordinates],
+        Some("LineString") | Some("MultiPoint") => match coordinates.as_array() {
+            Some(c) => c.iter().collect(),
+            None => return Ok(vec![]),
+        },
+        _ => return Ok(vec![]),
+    };
+
+    let mut points = vec![];
+    for position in positions {
+        let position = match position.as_array() {
+            Some(p) if p.len() >= 2 => p,
+            _ => return Ok(vec![]),
+        };
+
+        match (position[0].as_f64(), position[1].as_f64()) {
+            (Some(lng), Some(lat)) => points.push(Point::new(lng, lat)),
+            _ => return Err("Invalid GeoJSON coordinates".to_string()),
+        }
+    }
+
+    Ok(points)
+}
+
+/// Parse a WKT geometry, like `POINT(-48.87 -26.31)`, also
+/// accepting the Z/M variants and the EWKT `SRID=4326;` prefix
+fn parse_wkt(raw: &str) -> Result<Vec<Point>, String> {
+    let wkt = match raw.split_once(';') {
+        Some((srid, wkt)) if srid.starts_with("SRID=") => wkt.trim(),
+        _ => raw,
+    };
+
+    if !wkt.starts_with("POINT") && !wkt.starts_with("LINESTRING") && !wkt.starts_with("MULTIPOINT")
+    {
+        return Ok(vec![]);
+    }
+
+    let (open, close) = match (wkt.find('('), wkt.rfind(')')) {
+        (Some(o), Some(c)) if o < c => (o, c),
+        _ if wkt.ends_with("EMPTY") => return Ok(vec![]),
+        _ => return Err("Invalid WKT format".to_string()),
+    };
+
+    let mut points = vec![];
+    for position in wkt[open + 1..close].split(',') {
+        // MULTIPOINT can also have each point inside of parentheses
+        let values: Vec<&str> = position
+            .trim_matches(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .split_whitespace()
+            .collect();
+        if values.len() < 2 {
+            return Err("Invalid WKT coordinates".to_string());
+        }
+
+        let lng = values[0]
+            .parse::<f64>()
+            .map_err(|e| format!("Invalid longitude format: {}", e))?;
+        let lat = values[1]
+            .parse::<f64>()
+            .map_err(|e| format!("Invalid latitude format: {}", e))?;
+
+        points.push(Point::new(lng, lat));
+    }
+
+    Ok(points)
+}
+
+/// Parse a hex encoded WKB point or linestring, also accepting
+/// the PostGIS EWKB flags for SRID and Z/M dimensions
+fn parse_wkb(raw: &str) -> Result<Vec<Point>, String> {
+    let bytes = (0..raw.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&raw[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|e| format!("Invalid WKB format: {}", e))?;
+
+    let little = match bytes[0] {
+        0 => false,
+        1 => true,
+        _ => return Err("Invalid WKB byte order".to_string()),
+    };
+
+    let read_u32 = |at: usize| -> Result<u32, String> {
+        let b: [u8; 4] = bytes
+            .get(at..at + 4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or("WKB too short")?;
+        Ok(if little {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    };
+    let read_f64 = |at: usize| -> Result<f64, String> {
+        let b: [u8; 8] = bytes
+            .get(at..at + 8)
+            .and_then(|b| b.try_into().ok())
+            .ok_or("WKB too short")?;
+        Ok(if little {
+            f64::from_le_bytes(b)
+        } else {
+            f64::from_be_bytes(b)
+        })
+    };
+
+    let gtype = read_u32(1)?;
+    let mut offset = 5;
+    if gtype & 0x2000_0000 != 0 {
+        // SRID of EWKB
+        offset += 4;
+    }
+
+    // ISO WKB uses the 1000, 2000 and 3000 ranges for the Z/M types
+    let iso = gtype & 0x0fff_ffff;
+    let mut dims = 2;
+    if gtype & 0x8000_0000 != 0 || iso / 1000 == 1 || iso / 1000 == 3 {
+        dims += 1;
+    }
+    if gtype & 0x4000_0000 != 0 || iso / 1000 == 2 || iso / 1000 == 3 {
+        dims += 1;
+    }
+
+    let count = match iso % 1000 {
+        1 => 1,
+        2 => {
+            offset += 4;
+            read_u32(offset - 4)? as usize
+        }
+        _ => return Ok(vec![]),
+    };
+
+    let mut points = vec![];
+    for i in 0..count {
+        let at = offset + i * dims * 8;
+        let lng = read_f64(at)?;
+        let lat = read_f64(at + 8)?;
+
+        if lng.is_nan() && lat.is_nan() {
+            // POINT EMPTY
+            return Ok(vec![]);
+        }
+
+        points.push(Point::new(lng, lat));
+    }
+
+    Ok(points)
+}
+
+#[test]
+fn parse_text_points() -> Result<(), String> {
+    let expected = vec![Point::new(-48.87, -26.31)];
+    let fields = FieldsConfiguration::default();
+    let flipped = FieldsConfiguration {
+        flip_coordinates: true,
+        ..Default::default()
+    };
+
+    assert_eq!(expected, parse_points("-48.87, -26.31", &fields)?);
+    assert_eq!(expected, parse_points("-26.31;-48.87", &flipped)?);
+    assert_eq!(
+        expected,
+        parse_points(
+            r#"{"type":"Point","coordinates":[-48.87,-26.31]}"#,
+            &flipped
+        )?
+    );
+    assert_eq!(expected, parse_points("POINT(-48.87 -26.31)", &flipped)?);
+    assert_eq!(
+        expected,
+        parse_points("point z (-48.87 -26.31 12)", &fields)?
+    );
+    assert_eq!(
+        expected,
+        parse_points("SRID=4326;POINT(-48.87 -26.31)", &fields)?
+    );
+    assert_eq!(0, parse_points("POINT EMPTY", &fields)?.len());
+    assert_eq!(
+        0,
+        parse_points("POLYGON((0 0, 1 1, 1 0, 0 0))", &fields)?.len()
+    );
+    // Objects, by the configured or the usual keys
+    assert_eq!(
+        expected,
+        parse_points(r#"{"lat": -26.31, "lng": "-48.87"}"#, &fields)?
+    );
+    let keys = FieldsConfiguration {
+        latitude: "y".to_string(),
+        longitude: "x".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        expected,
+        parse_points(r#"{"y": -26.31, "x": -48.87, "alt": 12}"#, &keys)?
+    );
+    assert!(parse_points(r#"{"lat": "north", "lon": -48.87}"#, &fields).is_err());
+    // WKB, little and big endian
+    assert_eq!(
+        expected,
+        parse_points("01010000008fc2f5285c6f48c08fc2f5285c4f3ac0", &fields)?
+    );
+    assert_eq!(
+        expected,
+        parse_points("0000000001c0486f5c28f5c28fc03a4f5c28f5c28f", &fields)?
+    );
+    // EWKB with SRID, as PostGIS outputs
+    assert_eq!(
+        expected,
+        parse_points(
+            "0101000020e61000008fc2f5285c6f48c08fc2f5285c4f3ac0",
+            &fields
+        )?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parse_text_lines() -> Result<(), String> {
+    let expected = vec![Point::new(-48.87, -26.31), Point::new(-48.88, -26.32)];
+    let fields = FieldsConfiguration::default();
+
+    assert_eq!(
+        expected,
+        parse_points(
+            r#"{"type":"LineString","coordinates":[[-48.87,-26.31],[-48.88,-26.32]]}"#,
+            &fields
+        )?
+    );
+    assert_eq!(
+        expected,
+        parse_points("LINESTRING(-48.87 -26.31, -48.88 -26.32)", &fields)?
+    );
+    assert_eq!(
+        expected,
+        parse_points("MULTIPOINT((-48.87 -26.31), (-48.88 -26.32))", &fields)?
+    );
+    // ISO WKB linestring with Z
+    assert_eq!(
+        expected,
+        parse_points(
+            "01ea030000020000008fc2f5285c6f48c08fc2f5285c4f3ac00000000000002840\
+             713d0ad7a37048c052b81e85eb513ac00000000000002840",
+            &fields
+        )?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn line_intervals() -> Result<(), String> {
+    use time::macros::datetime;
+
+    let start = datetime!(2021-05-24 8:00 UTC);
+
+    assert_eq!(
+        vec![
+            start,
+            start + Duration::seconds(30),
+            start + Duration::minutes(1)
+        ],
+        line_times(start, 3, Some(30.0))?
+    );
+    assert_eq!(vec![start], line_times(start, 1, None)?);
+    assert!(line_times(start, 2, None).is_err());
+    assert!(line_times(start, 2, Some(-1.0)).is_err());
+    assert!(line_times(start, 2, Some(f64::NAN)).is_err());
+    assert!(line_times(start, 2, Some(1e300)).is_err());
+    assert!(line_times(start, 2, Some(1e15)).is_err());
+
+    Ok(())
+}
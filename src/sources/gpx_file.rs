@@ -0,0 +1,324 @@
+//! GPX files source, to reprocess existing tracks
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::Arc;
+
+use gpx::{Gpx, Track};
+
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use xml::reader::{EventReader, XmlEvent};
+use xml::writer::EmitterConfig;
+
+use super::{sort_by_time, PositionsSource, TimeRange};
+use crate::{DevicePosition, SpeedUnit};
+
+/// Tracks of GPX files source
+///
+/// Each track is read as a device, with its name as the device and the
+/// route, so the reprocessed tracks keep their names. The points without
+/// a time are skipped.
+pub struct GpxSource<T>
+where
+    T: Read,
+{
+    readers: Vec<T>,
+}
+
+impl<T> GpxSource<T>
+where
+    T: Read,
+{
+    pub fn new(rdr: T) -> Self {
+        Self { readers: vec![rdr] }
+    }
+
+    /// Read also the tracks of other file
+    pub fn add(&mut self, rdr: T) -> &mut Self {
+        self.readers.push(rdr);
+
+        self
+    }
+}
+
+impl<T> PositionsSource for GpxSource<T>
+where
+    T: Read,
+{
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+        let mut pos = vec![];
+        let mut count = 0;
+
+        for rdr in self.readers.drain(..) {
+            let (doc, accuracies) =
+                read_with_accuracies(rdr).map_err(|e| format!("Failed on read the GPX: {}", e))?;
+
+            for (track, accuracies) in doc.tracks.iter().zip(&accuracies) {
+                count += 1;
+                let name = track
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("track {}", count));
+
+                pos.extend(track_positions(track, &name, range, accuracies)?);
+            }
+        }
+
+        sort_by_time(&mut pos);
+
+        Ok(pos)
+    }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
+    }
+}
+
+/// Horizontal accuracies of the points of each track
+type TracksAccuracies = Vec<Vec<Option<f64>>>;
+
+/// Element of a point read by the generation, skipped by the `gpx` crate
+enum Capture {
+    Speed(SpeedUnit),
+    Accuracy,
+}
+
+/// Read the GPX document with the speeds of the points, also the ones
+/// that the `gpx` crate skips: the GPX 1.0 `<speed>` of the GPX 1.1
+/// documents and the `<extensions>`, like `<gpxtpx:speed>` and the
+/// `<l2g:speed>` in its unit
+pub fn read_gpx<R: Read>(rdr: R) -> Result<Gpx, String> {
+    read_with_accuracies(rdr).map(|(doc, _)| doc)
+}
+
+/// Read the GPX document like the `read_gpx`, also with the horizontal
+/// accuracy, in m, of the points of each track, like the Apple `<hAcc>`
+pub(super) fn read_with_accuracies<R: Read>(rdr: R) -> Result<(Gpx, TracksAccuracies), String> {
+    // The document without the captured elements, read again by the crate
+    let mut stripped = vec![];
+    let mut speeds: BTreeMap<String, Vec<Option<f64>>> = BTreeMap::new();
+    let mut accuracies = vec![];
+    {
+        let mut writer = EmitterConfig::new().create_writer(&mut stripped);
+        // Element, speed and accuracy of the current point
+        let mut point: Option<(String, Option<f64>, Option<f64>)> = None;
+        let mut capture: Option<(Capture, String, usize)> = None;
+        let mut depth = 0;
+
+        for event in EventReader::new(rdr) {
+            let event = event.map_err(|e| e.to_string())?;
+            match &event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    depth += 1;
+                    let local = name.local_name.as_str();
+                    if capture.is_some() {
+                        continue;
+                    }
+                    if matches!(local, "wpt" | "rtept" | "trkpt") {
+                        point = Some((local.to_string(), None, None));
+                    } else if local == "speed" && point.is_some() {
+                        let unit = match attributes.iter().find(|a| a.name.local_name == "unit") {
+                            Some(unit) => SpeedUnit::from_name(&unit.value)
+                                .ok_or_else(|| format!("Unknown speed unit {}", unit.value))?,
+                            None => SpeedUnit::MetersPerSecond,
+                        };
+                        capture = Some((Capture::Speed(unit), String::new(), depth));
+                        continue;
+                    } else if local == "hAcc" && point.is_some() {
+                        capture = Some((Capture::Accuracy, String::new(), depth));
+                        continue;
+                    }
+                }
+                XmlEvent::Characters(text) | XmlEvent::CData(text) if capture.is_some() => {
+                    if let Some((_, value, _)) = &mut capture {
+                        value.push_str(text);
+                    }
+                    continue;
+                }
+                XmlEvent::EndElement { name } => {
+                    depth -= 1;
+                    match capture.take() {
+                        Some((element, value, start)) if start == depth + 1 => {
+                            let value: f64 = value
+                                .trim()
+                                .parse()
+                                .map_err(|e| format!("Invalid number {}: {}", value.trim(), e))?;
+                            if let Some((_, speed, accuracy)) = &mut point {
+                                match element {
+                                    Capture::Speed(unit) => speed.get_or_insert(unit.to_mps(value)),
+                                    Capture::Accuracy => accuracy.get_or_insert(value),
+                                };
+                            }
+                            continue;
+                        }
+                        Some(inside) => {
+                            capture = Some(inside);
+                            continue;
+                        }
+                        None => {}
+                    }
+                    if matches!(name.local_name.as_str(), "wpt" | "rtept" | "trkpt") {
+                        if let Some((element, speed, accuracy)) = point.take() {
+                            if element == "trkpt" {
+                                accuracies.push(accuracy);
+                            }
+                            speeds.entry(element).or_default().push(speed);
+                        }
+                    }
+                }
+                _ if capture.is_some() => continue,
+                _ => {}
+            }
+
+            if let Some(event) = event.as_writer_event() {
+                writer.write(event).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    let mut doc = gpx::read(&stripped[..]).map_err(|e| e.to_string())?;
+
+    let mut points = |element: &str| speeds.remove(element).unwrap_or_default().into_iter();
+    let waypoints = doc.waypoints.iter_mut().zip(points("wpt"));
+    let routes = doc
+        .routes
+        .iter_mut()
+        .flat_map(|r| &mut r.points)
+        .zip(points("rtept"));
+    let tracks = doc
+        .tracks
+        .iter_mut()
+        .flat_map(|t| &mut t.segments)
+        .flat_map(|s| &mut s.points)
+        .zip(points("trkpt"));
+    for (wp, speed) in waypoints.chain(routes).chain(tracks) {
+        if speed.is_some() {
+            wp.speed = speed;
+        }
+    }
+
+    let mut accuracies = accuracies.into_iter();
+    let tracks_accuracies = doc
+        .tracks
+        .iter()
+        .map(|track| {
+            let count = track.segments.iter().map(|s| s.points.len()).sum();
+            accuracies.by_ref().take(count).collect()
+        })
+        .collect();
+
+    Ok((doc, tracks_accuracies))
+}
+
+/// Timed points of the track inside of the period, with the name as
+/// the device and the route, and the accuracies of the points, if any
+pub(super) fn track_positions(
+    track: &Track,
+    name: &str,
+    range: TimeRange,
+    accuracies: &[Option<f64>],
+) -> Result<Vec<DevicePosition>, String> {
+    let mut pos = vec![];
+    let name: Arc<str> = Arc::from(name);
+    let tracker: Option<Arc<str>> = track.source.as_deref().map(Arc::from);
+
+    for (i, wp) in track.segments.iter().flat_map(|s| &s.points).enumerate() {
+        let time = match wp.time {
+            Some(time) => time.format().map_err(|e| e.to_string())?,
+            None => continue,
+        };
+        let time = OffsetDateTime::parse(&time, &Rfc3339)
+            .map_err(|e| format!("Failed on parse the point time: {}", e))?;
+        if !range.contains(time) {
+            continue;
+        }
+
+        let mut dpos = DevicePosition::basic(name.clone(), wp.point(), time);
+        dpos.route_name = Some(name.clone());
+        dpos.tracker = tracker.clone();
+        dpos.track_type = track._type.clone();
+        dpos.pos.altitude = wp.elevation;
+        dpos.pos.speed = wp.speed;
+        dpos.pos.satellites = wp.sat;
+        dpos.pos.hdop = wp.hdop;
+        dpos.pos.precision = accuracies.get(i).copied().flatten();
+
+        pos.push(dpos);
+    }
+
+    Ok(pos)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use geo::geometry::Point;
+    use time::macros::datetime;
+
+    use super::GpxSource;
+    use crate::{SourceToTracks, TrackSegmentOptions};
+
+    #[test]
+    fn track() -> Result<(), String> {
+        let data = r#"<?xml version="1.0" encoding="utf-8"?>
+<gpx version="1.1" creator="location2gpx" xmlns="http://www.topografix.com/GPX/1/1">
+  <trk>
+    <name>morning ride</name>
+    <type>cycling</type>
+    <trkseg>
+      <trkpt lat="-26.31832" lon="-48.8702222"><ele>12</ele><time>2021-05-24T00:00:00Z</time></trkpt>
+      <trkpt lat="-26.31832" lon="-48.8712222"><time>2021-05-24T00:01:00Z</time></trkpt>
+      <trkpt lat="-26.31832" lon="-48.8722222"></trkpt>
+    </trkseg>
+    <trkseg>
+      <trkpt lat="-26.31832" lon="-48.8802222"><time>2021-05-24T00:09:00Z</time></trkpt>
+    </trkseg>
+  </trk>
+  <trk>
+    <trkseg>
+      <trkpt lat="-26.31832" lon="-48.8702222"><time>2021-05-25T00:00:00Z</time></trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+        let source = GpxSource::new(data.as_bytes());
+        let op = TrackSegmentOptions {
+            max_duration: 3600,
+            ..Default::default()
+        };
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2021-05-24 0:00 UTC),
+            datetime!(2021-05-24 23:59 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+
+        let track = &tracks[0];
+        assert_eq!(Some("morning ride".to_string()), track.name);
+        assert_eq!(Some("cycling".to_string()), track._type);
+        assert_eq!(1, track.segments.len());
+        let segment = &track.segments[0];
+        assert_eq!(3, segment.points.len());
+        assert_eq!(
+            Point::new(-48.8702222, -26.31832),
+            segment.points[0].point()
+        );
+        assert_eq!(Some(12.0), segment.points[0].elevation);
+
+        let mut source = GpxSource::new(data.as_bytes());
+        source.add(data.as_bytes());
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2021-05-24 0:00 UTC),
+            datetime!(2021-05-26 0:00 UTC),
+            TrackSegmentOptions::default(),
+        )?;
+        assert_eq!(3, tracks.len());
+
+        Ok(())
+    }
+}
@@ -1,34 +1,121 @@
 //! Positions sources API
 
-use serde::Deserialize;
-use time::OffsetDateTime;
-
-use crate::DevicePosition;
+use crate::{DevicePosition, SpeedUnit};
+use serde::{Deserialize, Serialize};
 
 /// Position source
 pub trait PositionsSource {
-    /// Fetch the raw positing during the period
-    fn fetch(
+    /// Fetch the raw positing during the period. The positions are
+    /// ordered by time, keeping the source order of the same times, so
+    /// the positions of each device can be read in a single pass
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String>;
+
+    /// Fetch the positions during the period in batches, not ordered by
+    /// time, so the caller can spill them without all of them in memory.
+    /// By default, a single batch with all the positions of the `fetch`
+    fn fetch_batches(
         &mut self,
-        start: OffsetDateTime,
-        end: OffsetDateTime,
-    ) -> Result<Vec<DevicePosition>, String>;
+        range: TimeRange,
+        each: &mut dyn FnMut(Vec<DevicePosition>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        each(self.fetch(range)?)
+    }
+
+    /// If the fetched positions are always inside of the period, like
+    /// with a query by time. The positions of the other sources are
+    /// filtered again by the generation
+    fn supports_time_pushdown(&self) -> bool {
+        false
+    }
+
+    /// Records that failed to parse since the previous call, with the
+    /// reason. The sources that skip the invalid records, instead of
+    /// failing the whole fetch, keep them here
+    fn take_skipped(&mut self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Positions of the source during the period, filtered again
+/// when the source does not apply the period by itself
+pub fn fetch_range<SU>(source: &mut SU, range: TimeRange) -> Result<Vec<DevicePosition>, String>
+where
+    SU: PositionsSource + ?Sized,
+{
+    let mut positions = source.fetch(range)?;
+    if !source.supports_time_pushdown() {
+        positions.retain(|dpos| range.contains(dpos.pos.time));
+    }
+
+    Ok(positions)
+}
+
+/// Order the fetched positions by time, as required by the sources
+pub fn sort_by_time(positions: &mut [DevicePosition]) {
+    positions.sort_by_key(|dpos| dpos.pos.time);
 }
 
 /// Fields of source customization
-#[derive(Clone, Debug, PartialEq, Deserialize)]
-#[serde(default)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct FieldsConfiguration {
     /// Device name or ID
     pub device_id: String,
     pub time: String,
+    /// Unit of the numeric times
+    pub time_unit: EpochUnit,
     /// Route name or ID
     pub route: String,
     pub coordinates: String,
     pub speed: String,
+    /// Unit of the speed values
+    pub speed_unit: SpeedUnit,
     pub elevation: String,
+    /// Barometric altitude
+    pub baro_elevation: String,
+    /// Accuracy reported by the device, in m
+    pub accuracy: String,
+    /// Horizontal dilution of precision
+    pub hdop: String,
+    /// Latitude and longitude, for the CSV files without a
+    /// coordinates column, and the keys of the coordinates objects
+    pub latitude: String,
+    pub longitude: String,
+    /// Seconds between the points of a line geometry
+    pub interval: String,
+    /// Activity of the track, eg.: cycling, driving
+    pub track_type: String,
+    /// Tracker app, software or model, used as the track source
+    pub tracker: String,
+    /// Trip ID, job number or other group of the positions, used as the
+    /// track key and name with the `field` grouping
+    pub group_by: String,
     /// Flip the lat,lng coordinates order
     pub flip_coordinates: bool,
+    /// Other names of the fields on the CSV headers
+    pub aliases: FieldAliases,
+}
+
+/// Other accepted names of each field, like `[timestamp, fix_time]` for
+/// the time. The configured name is looked up first, then the aliases
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FieldAliases {
+    pub device_id: Vec<String>,
+    pub time: Vec<String>,
+    pub route: Vec<String>,
+    pub coordinates: Vec<String>,
+    pub speed: Vec<String>,
+    pub elevation: Vec<String>,
+    pub baro_elevation: Vec<String>,
+    pub accuracy: Vec<String>,
+    pub hdop: Vec<String>,
+    pub latitude: Vec<String>,
+    pub longitude: Vec<String>,
+    pub interval: Vec<String>,
+    pub track_type: Vec<String>,
+    pub tracker: Vec<String>,
+    pub group_by: Vec<String>,
 }
 
 impl Default for FieldsConfiguration {
@@ -36,57 +123,133 @@ impl Default for FieldsConfiguration {
         Self {
             device_id: "device".to_string(),
             time: "time".to_string(),
+            time_unit: EpochUnit::Auto,
             route: "route".to_string(),
             coordinates: "coordinates".to_string(),
             speed: "speed".to_string(),
+            speed_unit: SpeedUnit::MetersPerSecond,
             elevation: "elevation".to_string(),
+            baro_elevation: "baro_elevation".to_string(),
+            accuracy: "accuracy".to_string(),
+            hdop: "hdop".to_string(),
+            latitude: "latitude".to_string(),
+            longitude: "longitude".to_string(),
+            interval: "interval".to_string(),
+            track_type: "track_type".to_string(),
+            tracker: "tracker".to_string(),
+            group_by: "trip".to_string(),
             flip_coordinates: false,
+            aliases: FieldAliases::default(),
         }
     }
 }
 
+mod epoch;
+pub use epoch::EpochUnit;
+
+mod range;
+pub use range::{IntervalMode, TimeRange};
+
+mod gpx_file;
+pub use gpx_file::{read_gpx, GpxSource};
+
+mod plain_text;
+pub use plain_text::PlainTextSource;
+
+mod synthetic;
+pub use synthetic::{SyntheticOptions, SyntheticSource};
+
+#[cfg(any(feature = "csv", feature = "mongo"))]
+mod geometry;
+
+#[cfg(any(feature = "csv", feature = "mongo", feature = "flespi"))]
+mod numeric;
+
 #[cfg(feature = "mongo")]
 mod mongo;
 #[cfg(feature = "mongo")]
-pub use mongo::MongoDbSource;
+pub use mongo::{DevicesLookup, MongoChangeStreamSource, MongoDbSource, MongoOptions, QueryPlan};
 
 #[cfg(feature = "csv")]
 mod csv_file;
 #[cfg(feature = "csv")]
-pub use csv_file::CsvSource;
+pub use csv_file::{CsvLines, CsvOptions, CsvPreset, CsvSource};
+#[cfg(feature = "csv")]
+mod csv_sniff;
+#[cfg(feature = "csv")]
+pub use csv_sniff::SniffedField;
+
+#[cfg(feature = "flespi")]
+mod flespi;
+#[cfg(feature = "flespi")]
+pub use flespi::FlespiSource;
+
+#[cfg(feature = "strava")]
+mod strava;
+#[cfg(feature = "strava")]
+pub use strava::{StravaActivity, StravaSource};
+
+#[cfg(feature = "apple-health")]
+mod apple_health;
+#[cfg(feature = "apple-health")]
+pub use apple_health::{AppleHealthSource, HealthWorkout};
 
 #[test]
 fn parse_fields() -> Result<(), String> {
     let yaml = "";
 
-    let fb: FieldsConfiguration = serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?;
+    let fb: FieldsConfiguration = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
 
     assert_eq!(
         FieldsConfiguration {
             device_id: "device".to_string(),
             time: "time".to_string(),
+            time_unit: EpochUnit::Auto,
             route: "route".to_string(),
             coordinates: "coordinates".to_string(),
             speed: "speed".to_string(),
+            speed_unit: SpeedUnit::MetersPerSecond,
             elevation: "elevation".to_string(),
+            baro_elevation: "baro_elevation".to_string(),
+            accuracy: "accuracy".to_string(),
+            hdop: "hdop".to_string(),
+            latitude: "latitude".to_string(),
+            longitude: "longitude".to_string(),
+            interval: "interval".to_string(),
+            track_type: "track_type".to_string(),
+            tracker: "tracker".to_string(),
+            group_by: "trip".to_string(),
             flip_coordinates: false,
+            aliases: FieldAliases::default(),
         },
         fb
     );
 
-    let yaml = "\ndevice_id: dev\ntime: time\ncoordinates: coords";
+    let yaml = "\ndevice_id: dev\ntime: time\ntime_unit: milliseconds\ncoordinates: coords";
 
-    let fb: FieldsConfiguration = serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?;
+    let fb: FieldsConfiguration = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
 
     assert_eq!(
         FieldsConfiguration {
             device_id: "dev".to_string(),
             time: "time".to_string(),
+            time_unit: EpochUnit::Milliseconds,
             route: "route".to_string(),
             coordinates: "coords".to_string(),
             speed: "speed".to_string(),
+            speed_unit: SpeedUnit::MetersPerSecond,
             elevation: "elevation".to_string(),
+            baro_elevation: "baro_elevation".to_string(),
+            accuracy: "accuracy".to_string(),
+            hdop: "hdop".to_string(),
+            latitude: "latitude".to_string(),
+            longitude: "longitude".to_string(),
+            interval: "interval".to_string(),
+            track_type: "track_type".to_string(),
+            tracker: "tracker".to_string(),
+            group_by: "trip".to_string(),
             flip_coordinates: false,
+            aliases: FieldAliases::default(),
         },
         fb
     );
@@ -1,126 +1,831 @@
 //! Mongodb source integration
 
-use bson::{doc, Bson, DateTime, Document};
+use std::collections::{BTreeMap, BTreeSet};
+
+use bson::oid::ObjectId;
+use bson::{doc, Bson, DateTime, Decimal128, Document};
 use geo::geometry::Point;
-use mongodb::sync::Collection;
-use time::format_description::well_known;
-use time::OffsetDateTime;
+use mongodb::change_stream::event::{ChangeStreamEvent, ResumeToken};
+use mongodb::options::{ChangeStreamOptions, FindOptions, Hint};
+use mongodb::sync::{ChangeStream, Collection, Database};
+use serde::{Deserialize, Serialize};
+use time::{Date, Month, OffsetDateTime};
+
+use super::geometry::{expand_positions, latitude_keys, line_times, longitude_keys, parse_points};
+use super::numeric::parse_number;
+use super::{sort_by_time, EpochUnit, FieldsConfiguration, PositionsSource, TimeRange};
+use crate::{DevicePosition, Names};
 
-use super::{FieldsConfiguration, PositionsSource};
-use crate::DevicePosition;
+/// Positions of the docs passed on each batch
+const BATCH_DOCS: usize = 100_000;
 
 /// MongoDB tracks source
 pub struct MongoDbSource {
-    collection: Collection<Document>,
+    collections: Collections,
     fields: FieldsConfiguration,
+    devices: Option<Devices>,
+    options: MongoOptions,
+    keep_raw: bool,
+    /// Shared names of all the docs
+    names: Names,
+}
+
+/// Query options of the positions collections
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MongoOptions {
+    /// Name of the index used by the query, like `time_1`
+    pub hint: Option<String>,
+    /// Time of the positions from the `_id` ObjectId, for the collections
+    /// without a time field. Also filters the period by the `_id`
+    pub object_id_time: bool,
+}
+
+/// Plan of the query of a collection, as chosen by the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryPlan {
+    pub collection: String,
+    /// Stages of the winning plan, like `FETCH` and `IXSCAN`
+    pub stages: Vec<String>,
+    /// Indexes used by the plan
+    pub indexes: Vec<String>,
+    /// Output of the `explain` command
+    pub explain: Document,
+}
+
+impl QueryPlan {
+    fn new(collection: String, explain: Document) -> Self {
+        let mut plan = Self {
+            collection,
+            stages: vec![],
+            indexes: vec![],
+            explain,
+        };
+
+        if let Ok(planner) = plan.explain.get_document("queryPlanner") {
+            if let Some(winning) = planner.get("winningPlan") {
+                let (stages, indexes) = plan_stages(winning);
+                plan.stages = stages;
+                plan.indexes = indexes;
+            }
+        }
+
+        plan
+    }
+
+    /// If the whole collection is read, without an index
+    pub fn is_collection_scan(&self) -> bool {
+        self.stages.iter().any(|stage| stage == "COLLSCAN")
+    }
+}
+
+/// Stages and indexes of the plan, from the outer to the inner ones
+fn plan_stages(plan: &Bson) -> (Vec<String>, Vec<String>) {
+    let (mut stages, mut indexes) = (vec![], vec![]);
+
+    match plan {
+        Bson::Document(doc) => {
+            if let Ok(stage) = doc.get_str("stage") {
+                stages.push(stage.to_string());
+            }
+            if let Ok(index) = doc.get_str("indexName") {
+                indexes.push(index.to_string());
+            }
+            for (key, value) in doc {
+                if key != "stage" && key != "indexName" {
+                    let (inner, inner_indexes) = plan_stages(value);
+                    stages.extend(inner);
+                    indexes.extend(inner_indexes);
+                }
+            }
+        }
+        Bson::Array(plans) => {
+            for value in plans {
+                let (inner, inner_indexes) = plan_stages(value);
+                stages.extend(inner);
+                indexes.extend(inner_indexes);
+            }
+        }
+        _ => {}
+    }
+
+    (stages, indexes)
+}
+
+/// Fields of the devices collection, with the metadata
+/// attached on the positions of each device
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DevicesLookup {
+    /// Devices collection name
+    pub collection: String,
+    /// Device ID, the same of the positions `device_id` field
+    pub id: String,
+    /// Friendly name, used on the track descriptions
+    pub name: String,
+    /// Vehicle plate, used on the track descriptions
+    pub plate: String,
+    /// Tracker model, used as the track source
+    pub model: String,
+}
+
+impl Default for DevicesLookup {
+    fn default() -> Self {
+        Self {
+            collection: "devices".to_string(),
+            id: "_id".to_string(),
+            name: "name".to_string(),
+            plate: "plate".to_string(),
+            model: "model".to_string(),
+        }
+    }
+}
+
+/// Collections with the positions
+enum Collections {
+    List(Vec<Collection<Document>>),
+    /// Collections named with the `{year}`, `{month}` and `{day}`
+    /// placeholders, like `positions_{year}_{month}`
+    Pattern(Database, String),
+}
+
+/// Metadata of a device
+#[derive(Clone, Debug, Default, PartialEq)]
+struct DeviceInfo {
+    name: Option<String>,
+    plate: Option<String>,
+    model: Option<String>,
+}
+
+/// Devices collection of the lookup
+struct Devices {
+    collection: Collection<Document>,
+    lookup: DevicesLookup,
+}
+
+impl Devices {
+    /// Metadata of the devices, by their IDs
+    fn fetch(&self, ids: BTreeSet<&str>) -> Result<BTreeMap<String, DeviceInfo>, String> {
+        let lookup = &self.lookup;
+
+        // The IDs of the positions are converted to text, so we
+        // also look for the numeric versions of them
+        let mut candidates = vec![];
+        for id in ids {
+            if let Ok(number) = id.parse::<i64>() {
+                candidates.push(Bson::Int64(number));
+                if let Ok(number) = i32::try_from(number) {
+                    candidates.push(Bson::Int32(number));
+                }
+            }
+            candidates.push(Bson::String(id.to_string()));
+        }
+
+        let cursor = self
+            .collection
+            .find(doc! { lookup.id.clone(): { "$in": candidates } }, None)
+            .map_err(|e| format!("Failed on fetch the devices: {}", e))?;
+
+        let mut devices = BTreeMap::new();
+        for rdoc in cursor {
+            let doc = rdoc.map_err(|e| format!("Failed on read some device: {}", e))?;
+
+            let id = match parse_device_id(doc.get(lookup.id.clone())) {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            let text = |field: &str| match doc.get(field) {
+                Some(Bson::String(v)) if !v.is_empty() => Some(v.clone()),
+                _ => None,
+            };
+
+            devices.insert(
+                id,
+                DeviceInfo {
+                    name: text(&lookup.name),
+                    plate: text(&lookup.plate),
+                    model: text(&lookup.model),
+                },
+            );
+        }
+
+        Ok(devices)
+    }
+
+    /// Attach the metadata of the devices to the positions
+    fn describe(&self, pos: &mut [DevicePosition], names: &mut Names) -> Result<(), String> {
+        let ids = pos.iter().map(|dpos| &*dpos.device_id).collect();
+        let devices = self.fetch(ids)?;
+        for dpos in pos.iter_mut() {
+            if let Some(info) = devices.get(&*dpos.device_id) {
+                dpos.device_name = info.name.clone();
+                dpos.vehicle_plate = info.plate.clone();
+                if dpos.tracker.is_none() {
+                    dpos.tracker = info.model.as_deref().map(|model| names.get(model));
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl MongoDbSource {
     pub fn new(collection: Collection<Document>, fields: Option<FieldsConfiguration>) -> Self {
+        Self::with_collections(vec![collection], fields)
+    }
+
+    /// Source of the positions of all the collections
+    pub fn with_collections(
+        collections: Vec<Collection<Document>>,
+        fields: Option<FieldsConfiguration>,
+    ) -> Self {
         Self {
-            collection,
-            fields: match fields {
-                Some(f) => f,
-                None => FieldsConfiguration::default(),
-            },
+            collections: Collections::List(collections),
+            fields: fields.unwrap_or_default(),
+            devices: None,
+            options: MongoOptions::default(),
+            keep_raw: false,
+            names: Names::default(),
         }
     }
+
+    /// Source of the collections named by the pattern, with the `{year}`,
+    /// `{month}` and `{day}` placeholders, like `positions_{year}_{month}`.
+    /// Only the collections of the periods inside of the fetch window are read
+    pub fn with_pattern(db: Database, pattern: &str, fields: Option<FieldsConfiguration>) -> Self {
+        Self {
+            collections: Collections::Pattern(db, pattern.to_string()),
+            fields: fields.unwrap_or_default(),
+            devices: None,
+            options: MongoOptions::default(),
+            keep_raw: false,
+            names: Names::default(),
+        }
+    }
+
+    /// Collections with positions of the period
+    fn collections(&self, range: TimeRange) -> Result<Vec<Collection<Document>>, String> {
+        let (db, pattern) = match &self.collections {
+            Collections::List(collections) => return Ok(collections.clone()),
+            Collections::Pattern(db, pattern) => (db, pattern),
+        };
+
+        let mut names = db
+            .list_collection_names(None)
+            .map_err(|e| format!("Failed on list the collections: {}", e))?;
+        names.sort();
+
+        Ok(names
+            .into_iter()
+            .filter(|name| {
+                pattern_period(pattern, name).is_some_and(|(start, end)| {
+                    range.start.is_none_or(|rs| rs < end) && range.end.is_none_or(|re| start <= re)
+                })
+            })
+            .map(|name| db.collection::<Document>(&name))
+            .collect())
+    }
+
+    /// Query options, like the index hint
+    pub fn options(&mut self, options: MongoOptions) -> &mut Self {
+        self.options = options;
+
+        self
+    }
+
+    /// Keep the document of each position, as relaxed extended
+    /// JSON, on the builds with the `raw` feature
+    pub fn keep_raw(&mut self, keep: bool) -> &mut Self {
+        self.keep_raw = keep;
+
+        self
+    }
+
+    /// Filter and options of the positions query of the period
+    fn query(&self, range: TimeRange) -> (Document, FindOptions) {
+        let fields = doc_fields(&self.fields, &self.options);
+        let time_filters = if self.options.object_id_time {
+            vec![object_id_filter(range)]
+        } else {
+            time_filter(&fields, range)
+        };
+
+        let filter = doc! {
+            "$and": [
+                { "$or": time_filters },
+                {
+                    "$or": [
+                        { self.fields.coordinates.clone(): { "$size": 2 } },
+                        { self.fields.coordinates.clone(): { "$type": "string" } },
+                        // Also matches the arrays with objects
+                        { self.fields.coordinates.clone(): { "$type": "object" } },
+                        {
+                            self.fields.coordinates.clone():
+                                { "$elemMatch": { "$type": "array" } }
+                        },
+                    ]
+                },
+            ]
+        };
+
+        // Ordered by the database, using an index of the time, so the
+        // final sort only merges the collections and the line points
+        let options = FindOptions::builder()
+            .sort(doc! { fields.time.clone(): 1 })
+            .hint(self.options.hint.clone().map(Hint::Name))
+            .build();
+
+        (filter, options)
+    }
+
+    /// Plans of the positions query of the period, for each collection.
+    /// The collections must be of the database
+    pub fn explain(&self, db: &Database, range: TimeRange) -> Result<Vec<QueryPlan>, String> {
+        let (filter, options) = self.query(range);
+
+        let mut plans = vec![];
+        for collection in self.collections(range)? {
+            if collection.namespace().db != db.name() {
+                return Err(format!(
+                    "The collection {} is not of the database {}",
+                    collection.namespace(),
+                    db.name()
+                ));
+            }
+
+            let mut find = doc! {
+                "find": collection.name(),
+                "filter": filter.clone(),
+                "sort": options.sort.clone(),
+            };
+            if let Some(hint) = &self.options.hint {
+                find.insert("hint", hint.clone());
+            }
+
+            let explain = db
+                .run_command(doc! { "explain": find, "verbosity": "queryPlanner" }, None)
+                .map_err(|e| format!("Failed on explain the query: {}", e))?;
+
+            plans.push(QueryPlan::new(collection.name().to_string(), explain));
+        }
+
+        Ok(plans)
+    }
+
+    /// Attach the metadata of the devices collection on the positions
+    pub fn lookup_devices(
+        &mut self,
+        devices: Collection<Document>,
+        lookup: DevicesLookup,
+    ) -> &mut Self {
+        self.devices = Some(Devices {
+            collection: devices,
+            lookup,
+        });
+
+        self
+    }
 }
 
 impl PositionsSource for MongoDbSource {
-    fn fetch(
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+        let mut pos = vec![];
+        self.fetch_batches(range, &mut |batch| {
+            pos.extend(batch);
+            Ok(())
+        })?;
+
+        sort_by_time(&mut pos);
+
+        Ok(pos)
+    }
+
+    /// Batches of the docs of the cursors, with the devices metadata
+    fn fetch_batches(
         &mut self,
-        start: OffsetDateTime,
-        end: OffsetDateTime,
-    ) -> Result<Vec<DevicePosition>, String> {
+        range: TimeRange,
+        each: &mut dyn FnMut(Vec<DevicePosition>) -> Result<(), String>,
+    ) -> Result<(), String> {
         let mut pos = vec![];
 
-        let filter = doc! {
-            self.fields.time.clone(): doc! {
-                "$gte": DateTime::from_time_0_3(start),
-                "$lte": DateTime::from_time_0_3(end),
-            },
-            self.fields.coordinates.clone(): doc! {
-                "$size": 2,
+        let fields = doc_fields(&self.fields, &self.options);
+        let (filter, options) = self.query(range);
+        for collection in self.collections(range)? {
+            let cursor = collection
+                .find(filter.clone(), options.clone())
+                .map_err(|e| format!("Failed on fetch the docs: {}", e))?;
+
+            for rdoc in cursor {
+                let doc = rdoc.map_err(|e| format!("Failed on read some doc: {}", e))?;
+
+                let id = doc
+                    .get_object_id("_id")
+                    .map_err(|e| format!("Failed on access the doc id: {}", e))?;
+
+                let doc_pos = match parse_doc(&fields, &doc, &mut self.names) {
+                    Ok(dpos) => Ok(dpos),
+                    Err(e) => Err(format!("Error with doc {0}: {1}", id, e)),
+                }?;
+
+                for mut dpos in doc_pos {
+                    if self.keep_raw {
+                        dpos.keep_raw(|| Bson::Document(doc.clone()).into_relaxed_extjson());
+                    }
+                    if range.contains(dpos.pos.time) {
+                        pos.push(dpos);
+                    }
+                }
+
+                if pos.len() >= BATCH_DOCS {
+                    if let Some(devices) = &self.devices {
+                        devices.describe(&mut pos, &mut self.names)?;
+                    }
+                    each(std::mem::take(&mut pos))?;
+                }
             }
-        };
-        let cursor = self
-            .collection
-            .find(filter, None)
-            .map_err(|e| format!("Failed on fetch the docs: {}", e.to_string()))?;
+        }
 
-        for rdoc in cursor {
-            let doc = rdoc.map_err(|e| format!("Failed on read some doc: {}", e.to_string()))?;
+        if !pos.is_empty() {
+            if let Some(devices) = &self.devices {
+                devices.describe(&mut pos, &mut self.names)?;
+            }
+            each(pos)?;
+        }
+
+        Ok(())
+    }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
+    }
+}
+
+/// MongoDB live source, tailing the collection change stream
+///
+/// Each fetch returns the positions inserted since the previous one.
+/// The positions after the fetch period are kept for the next fetches.
+/// Requires a replica set or sharded cluster.
+pub struct MongoChangeStreamSource {
+    stream: ChangeStream<ChangeStreamEvent<Document>>,
+    fields: FieldsConfiguration,
+    devices: Option<Devices>,
+    options: MongoOptions,
+    /// Docs of the stream that failed to parse
+    skipped: Vec<String>,
+    /// Positions read after the period of the previous fetches
+    pending: Vec<DevicePosition>,
+    /// Shared names of all the docs of the stream
+    names: Names,
+}
+
+impl MongoChangeStreamSource {
+    /// Start watching the collection for new positions
+    pub fn new(
+        collection: Collection<Document>,
+        fields: Option<FieldsConfiguration>,
+    ) -> Result<Self, String> {
+        Self::watch(collection, fields, None)
+    }
+
+    /// Resume watching the collection after the provided token
+    pub fn resume_after(
+        collection: Collection<Document>,
+        fields: Option<FieldsConfiguration>,
+        token: ResumeToken,
+    ) -> Result<Self, String> {
+        Self::watch(collection, fields, Some(token))
+    }
+
+    fn watch(
+        collection: Collection<Document>,
+        fields: Option<FieldsConfiguration>,
+        token: Option<ResumeToken>,
+    ) -> Result<Self, String> {
+        let fields = fields.unwrap_or_default();
+
+        let pipeline = vec![doc! {
+            "$match": {
+                "operationType": "insert",
+                "$or": [
+                    { format!("fullDocument.{}", fields.coordinates): { "$size": 2 } },
+                    { format!("fullDocument.{}", fields.coordinates): { "$type": "string" } },
+                    { format!("fullDocument.{}", fields.coordinates): { "$type": "object" } },
+                    {
+                        format!("fullDocument.{}", fields.coordinates):
+                            { "$elemMatch": { "$type": "array" } }
+                    },
+                ]
+            }
+        }];
+        let options = ChangeStreamOptions::builder().resume_after(token).build();
+
+        let stream = collection
+            .watch(pipeline, options)
+            .map_err(|e| format!("Failed on watch the collection: {}", e))?;
+
+        Ok(Self {
+            stream,
+            fields,
+            devices: None,
+            options: MongoOptions::default(),
+            skipped: vec![],
+            pending: vec![],
+            names: Names::default(),
+        })
+    }
+
+    /// Options of the docs, like the `object_id_time`. The
+    /// index hint is not used by the change stream
+    pub fn options(&mut self, options: MongoOptions) -> &mut Self {
+        self.options = options;
+
+        self
+    }
+
+    /// Attach the metadata of the devices collection on the positions
+    pub fn lookup_devices(
+        &mut self,
+        devices: Collection<Document>,
+        lookup: DevicesLookup,
+    ) -> &mut Self {
+        self.devices = Some(Devices {
+            collection: devices,
+            lookup,
+        });
+
+        self
+    }
 
-            let id = doc
-                .get_object_id("_id")
-                .map_err(|e| format!("Failed on access the doc id: {}", e.to_string()))?;
+    /// Token to resume the stream from the last event read
+    pub fn resume_token(&self) -> Option<ResumeToken> {
+        self.stream.resume_token()
+    }
+}
 
-            let dpos = match parse_doc(&self.fields, &doc) {
-                Ok(dpos) => Ok(dpos),
-                Err(e) => Err(format!("Error with doc {0}: {1}", id, e)),
-            }?;
+impl PositionsSource for MongoChangeStreamSource {
+    fn take_skipped(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.skipped)
+    }
+
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+        let mut read = std::mem::take(&mut self.pending);
+
+        let fields = doc_fields(&self.fields, &self.options);
+        while let Some(event) = self
+            .stream
+            .next_if_any()
+            .map_err(|e| format!("Failed on read the change stream: {}", e))?
+        {
+            let doc = match event.full_document {
+                Some(doc) => doc,
+                None => continue,
+            };
+
+            // The events are already consumed, so a bad doc is skipped
+            // to keep the positions of the others
+            let doc_pos = match parse_doc(&fields, &doc, &mut self.names) {
+                Ok(dpos) => dpos,
+                Err(e) => {
+                    self.skipped
+                        .push(format!("Error with doc {0}: {1}", doc, e));
+                    continue;
+                }
+            };
+            read.extend(doc_pos);
+        }
 
-            pos.push(dpos);
+        // Only the positions before the period are dropped, the
+        // ones after it are inside of the period of a next fetch
+        let (mut pos, after, before) = split_period(read, range);
+        self.pending = after;
+        for dpos in before {
+            self.skipped.push(format!(
+                "Position of {} at {} before the period",
+                dpos.device_id, dpos.pos.time
+            ));
         }
 
+        if let Some(devices) = &self.devices {
+            devices.describe(&mut pos, &mut self.names)?;
+        }
+        sort_by_time(&mut pos);
+
         Ok(pos)
     }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
+    }
 }
 
-fn parse_doc(fields: &FieldsConfiguration, doc: &Document) -> Result<DevicePosition, String> {
-    let device_id = match doc.get(fields.device_id.clone()) {
+/// Positions inside of the period, after it and before it
+fn split_period(
+    positions: Vec<DevicePosition>,
+    range: TimeRange,
+) -> (
+    Vec<DevicePosition>,
+    Vec<DevicePosition>,
+    Vec<DevicePosition>,
+) {
+    let (inside, outside): (Vec<_>, Vec<_>) = positions
+        .into_iter()
+        .partition(|dpos| range.contains(dpos.pos.time));
+    let (before, after) = outside
+        .into_iter()
+        .partition(|dpos| range.start.is_some_and(|start| dpos.pos.time < start));
+
+    (inside, after, before)
+}
+
+/// Fields of the documents, with the `_id` as the time field of the
+/// `object_id_time` option
+fn doc_fields(fields: &FieldsConfiguration, options: &MongoOptions) -> FieldsConfiguration {
+    let mut fields = fields.clone();
+    if options.object_id_time {
+        fields.time = "_id".to_string();
+    }
+
+    fields
+}
+
+/// Period of the collection named by the pattern, from the most
+/// specific placeholder. None when the name is not of the pattern
+fn pattern_period(pattern: &str, name: &str) -> Option<(OffsetDateTime, OffsetDateTime)> {
+    let (mut year, mut month, mut day) = (None, None, None);
+    let (mut pattern, mut name) = (pattern, name);
+
+    while !pattern.is_empty() {
+        let placeholder = ["{year}", "{month}", "{day}"]
+            .into_iter()
+            .find(|p| pattern.starts_with(p));
+
+        match placeholder {
+            Some(placeholder) => {
+                let digits = if placeholder == "{year}" { 4 } else { 2 };
+                let value = name.get(..digits)?;
+                if !value.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                let value: u16 = value.parse().ok()?;
+                match placeholder {
+                    "{year}" => year = Some(value),
+                    "{month}" => month = Some(value),
+                    _ => day = Some(value),
+                }
+
+                pattern = &pattern[placeholder.len()..];
+                name = &name[digits..];
+            }
+            None => {
+                let c = pattern.chars().next()?;
+                name = name.strip_prefix(c)?;
+                pattern = &pattern[c.len_utf8()..];
+            }
+        }
+    }
+    if !name.is_empty() {
+        return None;
+    }
+
+    let year = i32::from(year?);
+    let first = Month::try_from(month.unwrap_or(1) as u8).ok()?;
+    let start = Date::from_calendar_date(year, first, day.unwrap_or(1) as u8).ok()?;
+    let end = match (month, day) {
+        (_, Some(_)) => start.next_day()?,
+        (Some(_), None) => match first {
+            Month::December => Date::from_calendar_date(year + 1, Month::January, 1).ok()?,
+            _ => Date::from_calendar_date(year, first.next(), 1).ok()?,
+        },
+        (None, None) => Date::from_calendar_date(year + 1, Month::January, 1).ok()?,
+    };
+
+    Some((start.midnight().assume_utc(), end.midnight().assume_utc()))
+}
+
+/// Period filter for dates and for each unit of the numeric times
+fn time_filter(fields: &FieldsConfiguration, range: TimeRange) -> Vec<Document> {
+    let end_operator = if range.includes_end() { "$lte" } else { "$lt" };
+
+    let mut date = doc! { "$type": "date" };
+    if let Some(start) = range.start {
+        date.insert("$gte", DateTime::from_time_0_3(start));
+    }
+    if let Some(end) = range.end {
+        date.insert(end_operator, DateTime::from_time_0_3(end));
+    }
+
+    let mut filters = vec![doc! { fields.time.clone(): date }];
+
+    for unit in fields.time_unit.candidates() {
+        let mut number = doc! { "$type": "number" };
+        if let Some(start) = range.start {
+            number.insert("$gte", unit.from_time(start));
+        }
+        if let Some(end) = range.end {
+            number.insert(end_operator, unit.from_time(end));
+        }
+
+        filters.push(doc! { fields.time.clone(): number });
+    }
+
+    filters
+}
+
+/// Period filter by the creation time of the `_id` ObjectIds
+fn object_id_filter(range: TimeRange) -> Document {
+    let mut id = doc! { "$type": "objectId" };
+    if let Some(start) = range.start {
+        id.insert("$gte", object_id_bound(start.unix_timestamp()));
+    }
+    // ObjectIds have seconds, so the fractions of the end second and the
+    // closed periods are filtered after the fetch
+    if let Some(end) = range.end {
+        id.insert("$lt", object_id_bound(end.unix_timestamp() + 1));
+    }
+
+    doc! { "_id": id }
+}
+
+/// Lowest ObjectId created at the second
+fn object_id_bound(seconds: i64) -> ObjectId {
+    let seconds = seconds.clamp(0, u32::MAX.into()) as u32;
+
+    let mut bytes = [0; 12];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+
+    ObjectId::from_bytes(bytes)
+}
+
+fn parse_device_id(value: Option<&Bson>) -> Result<String, String> {
+    match value {
         Some(Bson::String(di)) => Ok(di.clone()),
         Some(Bson::Int32(di)) => Ok(di.to_string()),
         Some(Bson::Int64(di)) => Ok(di.to_string()),
         Some(Bson::Double(di)) => Ok(di.to_string()),
-        Some(_) => Err("Device field type not supported"),
-        None => Err("Device field not found"),
-    }?;
-
-    let coordinates = doc
-        .get_array(fields.coordinates.clone())
-        .map_err(|e| format!("Failed on access the `coordinates`: {}", e.to_string()))?;
-    if coordinates.len() != 2 {
-        return Err("Coordinates size invalid".to_string());
-    }
-
-    let mut ilat = 1;
-    let mut ilng = 0;
-    if fields.flip_coordinates {
-        ilat = 0;
-        ilng = 1;
+        Some(_) => Err("Device field type not supported".to_string()),
+        None => Err("Device field not found".to_string()),
     }
+}
 
-    let lat = match coordinates[ilat] {
-        Bson::Double(l) => Ok(l),
-        _ => Err("Invalid type of latitude".to_string()),
-    }?;
-    let lng = match coordinates[ilng] {
-        Bson::Double(l) => Ok(l),
-        _ => Err("Invalid type of longitude".to_string()),
+fn parse_doc(
+    fields: &FieldsConfiguration,
+    doc: &Document,
+    names: &mut Names,
+) -> Result<Vec<DevicePosition>, String> {
+    let device_id = names.get(&parse_device_id(doc.get(fields.device_id.clone()))?);
+
+    let points = match doc.get(fields.coordinates.clone()) {
+        Some(Bson::Array(coordinates)) => match coordinates.first() {
+            // Line of `[lng, lat]` arrays or `{lat, lng}` objects
+            Some(Bson::Array(_)) | Some(Bson::Document(_)) => coordinates
+                .iter()
+                .map(|c| match c {
+                    Bson::Array(c) => parse_coordinates_array(fields, c),
+                    Bson::Document(c) => parse_coordinates_object(fields, c),
+                    _ => Err("Invalid type of line coordinates".to_string()),
+                })
+                .collect(),
+            _ => Ok(vec![parse_coordinates_array(fields, coordinates)?]),
+        },
+        Some(Bson::Document(coordinates)) => {
+            Ok(vec![parse_coordinates_object(fields, coordinates)?])
+        }
+        Some(Bson::String(raw)) => match parse_points(raw, fields)? {
+            points if points.is_empty() => Err("Coordinates format invalid".to_string()),
+            points => Ok(points),
+        },
+        Some(_) => Err("Coordinates field type not supported".to_string()),
+        None => Err("Coordinates field not found".to_string()),
     }?;
 
-    let time = match doc.get(fields.time.clone()) {
-        Some(Bson::String(tm)) => OffsetDateTime::parse(tm, &well_known::Rfc3339)
-            .map_err(|e| format!("Failed on parse the time: {}", e.to_string())),
-        Some(Bson::DateTime(tm)) => Ok(tm.to_time_0_3()),
-        Some(Bson::Timestamp(tm)) => OffsetDateTime::from_unix_timestamp(tm.time.into())
-            .map_err(|e| format!("Failed on parse the time tiemstamp: {}", e.to_string())),
-        Some(_) => Err("Time field type not supported".to_string()),
+    let times = match doc.get(fields.time.clone()) {
+        // One time per point of the line
+        Some(Bson::Array(times)) => times
+            .iter()
+            .map(|tm| parse_time(tm, fields.time_unit))
+            .collect(),
+        Some(tm) => {
+            let interval = doc.get(fields.interval.clone()).and_then(bson_number);
+            line_times(parse_time(tm, fields.time_unit)?, points.len(), interval)
+        }
         None => Err("Time field not found".to_string()),
     }?;
 
-    let mut dpos = DevicePosition::basic(device_id.clone(), Point::new(lng, lat), time);
+    let (point, time) = match (points.first(), times.first()) {
+        (Some(point), Some(time)) => (*point, *time),
+        _ => return Err("No coordinates or times on the doc".to_string()),
+    };
+    let mut dpos = DevicePosition::basic(device_id, point, time);
 
     dpos.route_name = match doc.get(fields.route.clone()) {
-        Some(Bson::String(ro)) => Some(ro.clone()),
-        Some(Bson::Int32(ro)) => Some(ro.to_string()),
+        Some(Bson::String(ro)) => Some(names.get(ro)),
+        Some(Bson::Int32(ro)) => Some(names.get(&ro.to_string())),
         Some(Bson::Array(ro)) => {
-            if ro.len() > 0 {
+            if !ro.is_empty() {
                 match &ro[0] {
-                    Bson::String(di) => Some(di.clone()),
-                    Bson::Int32(di) => Some(di.to_string()),
+                    Bson::String(di) => Some(names.get(di)),
+                    Bson::Int32(di) => Some(names.get(&di.to_string())),
                     _ => None,
                 }
             } else {
@@ -130,19 +835,353 @@ fn parse_doc(fields: &FieldsConfiguration, doc: &Document) -> Result<DevicePosit
         _ => None,
     };
 
-    dpos.pos.speed = match doc.get(fields.speed.clone()) {
-        Some(Bson::Int32(sp)) => Some((*sp).into()),
-        Some(Bson::Double(sp)) => Some((*sp).into()),
+    dpos.track_type = match doc.get(fields.track_type.clone()) {
+        Some(Bson::String(tt)) if !tt.is_empty() => Some(tt.clone()),
+        _ => None,
+    };
+
+    dpos.tracker = match doc.get(fields.tracker.clone()) {
+        Some(Bson::String(trk)) if !trk.is_empty() => Some(names.get(trk)),
+        _ => None,
+    };
+
+    dpos.group = match doc.get(fields.group_by.clone()) {
+        Some(Bson::String(group)) if !group.is_empty() => Some(group.clone()),
+        Some(Bson::Int32(group)) => Some(group.to_string()),
+        Some(Bson::Int64(group)) => Some(group.to_string()),
+        Some(Bson::ObjectId(group)) => Some(group.to_hex()),
         _ => None,
     };
 
-    dpos.pos.altitude = match doc.get(fields.elevation.clone()) {
-        Some(Bson::Int32(sp)) => Some((*sp).into()),
-        Some(Bson::Double(sp)) => Some((*sp).into()),
+    dpos.pos.speed = doc
+        .get(fields.speed.clone())
+        .and_then(bson_number)
+        .map(|speed| fields.speed_unit.to_mps(speed));
+    dpos.pos.altitude = doc.get(fields.elevation.clone()).and_then(bson_number);
+    dpos.pos.baro_altitude = doc.get(fields.baro_elevation.clone()).and_then(bson_number);
+    dpos.pos.precision = doc.get(fields.accuracy.clone()).and_then(bson_number);
+    dpos.pos.hdop = doc.get(fields.hdop.clone()).and_then(bson_number);
+
+    expand_positions(dpos, points, times)
+}
+
+fn parse_time(value: &Bson, unit: EpochUnit) -> Result<OffsetDateTime, String> {
+    match value {
+        Bson::String(tm) => unit.parse_text(tm),
+        Bson::Int32(tm) => unit.to_time((*tm).into()),
+        Bson::Int64(tm) => unit.to_time(*tm as f64),
+        Bson::Double(tm) => unit.to_time(*tm),
+        Bson::DateTime(tm) => Ok(tm.to_time_0_3()),
+        Bson::Timestamp(tm) => OffsetDateTime::from_unix_timestamp(tm.time.into())
+            .map_err(|e| format!("Failed on parse the time tiemstamp: {}", e)),
+        Bson::ObjectId(id) => Ok(id.timestamp().to_time_0_3()),
+        _ => Err("Time field type not supported".to_string()),
+    }
+}
+
+/// Parse the `[lng, lat]` array
+fn parse_coordinates_array(
+    fields: &FieldsConfiguration,
+    coordinates: &[Bson],
+) -> Result<Point, String> {
+    if coordinates.len() != 2 {
+        return Err("Coordinates size invalid".to_string());
+    }
+
+    let mut ilat = 1;
+    let mut ilng = 0;
+    if fields.flip_coordinates {
+        ilat = 0;
+        ilng = 1;
+    }
+
+    let lat = bson_number(&coordinates[ilat]).ok_or("Invalid type of latitude")?;
+    let lng = bson_number(&coordinates[ilng]).ok_or("Invalid type of longitude")?;
+
+    Ok(Point::new(lng, lat))
+}
+
+/// Parse the `{lat, lng}` object, by the configured or the usual keys
+fn parse_coordinates_object(
+    fields: &FieldsConfiguration,
+    coordinates: &Document,
+) -> Result<Point, String> {
+    let number = |keys: &[&str]| keys.iter().find_map(|key| coordinates.get(key));
+
+    let lat = number(&latitude_keys(fields)).ok_or("Latitude not found")?;
+    let lng = number(&longitude_keys(fields)).ok_or("Longitude not found")?;
+
+    let lat = bson_number(lat).ok_or("Invalid type of latitude")?;
+    let lng = bson_number(lng).ok_or("Invalid type of longitude")?;
+
+    Ok(Point::new(lng, lat))
+}
+
+/// Number of the value, from any numeric type or a numeric string
+fn bson_number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Double(number) => Some(*number),
+        Bson::Int32(number) => Some((*number).into()),
+        Bson::Int64(number) => Some(*number as f64),
+        Bson::Decimal128(number) => parse_decimal128(number),
+        Bson::String(raw) => parse_number(raw).ok(),
         _ => None,
+    }
+}
+
+/// Nearest float of the decimal, without the infinities and NaNs
+///
+/// The bson crate only stores the bytes, a 113 bits coefficient and a 14
+/// bits exponent of the IEEE 754 binary integer decimal encoding
+fn parse_decimal128(number: &Decimal128) -> Option<f64> {
+    let bits = u128::from_le_bytes(number.bytes());
+
+    let negative = bits >> 127 == 1;
+    let (exponent, coefficient) = if (bits >> 125) & 0b11 == 0b11 {
+        // Infinities, NaNs or the non-canonical coefficients, always zero
+        if (bits >> 122) & 0b11110 == 0b11110 {
+            return None;
+        }
+        ((bits >> 111) & 0x3fff, 0)
+    } else {
+        ((bits >> 113) & 0x3fff, bits & ((1 << 113) - 1))
     };
 
-    Ok(dpos)
+    let text = format!(
+        "{}{}e{}",
+        if negative { "-" } else { "" },
+        coefficient,
+        exponent as i64 - 6176
+    );
+    parse_number(&text).ok()
+}
+
+#[test]
+fn collection_periods() {
+    use time::macros::datetime;
+
+    assert_eq!(
+        Some((
+            datetime!(2023-01-01 0:00 UTC),
+            datetime!(2023-02-01 0:00 UTC)
+        )),
+        pattern_period("positions_{year}_{month}", "positions_2023_01")
+    );
+    assert_eq!(
+        Some((
+            datetime!(2023-12-01 0:00 UTC),
+            datetime!(2024-01-01 0:00 UTC)
+        )),
+        pattern_period("positions_{year}_{month}", "positions_2023_12")
+    );
+    assert_eq!(
+        Some((
+            datetime!(2023-02-28 0:00 UTC),
+            datetime!(2023-03-01 0:00 UTC)
+        )),
+        pattern_period("pos{year}{month}{day}", "pos20230228")
+    );
+    assert_eq!(
+        Some((
+            datetime!(2023-01-01 0:00 UTC),
+            datetime!(2024-01-01 0:00 UTC)
+        )),
+        pattern_period("{year}", "2023")
+    );
+    assert_eq!(
+        None,
+        pattern_period("positions_{year}_{month}", "positions_2023_1")
+    );
+    assert_eq!(
+        None,
+        pattern_period("positions_{year}_{month}", "positions_2023_13")
+    );
+    assert_eq!(None, pattern_period("positions_{year}_{month}", "devices"));
+    assert_eq!(
+        None,
+        pattern_period("positions_{year}", "positions_2023_01")
+    );
+}
+
+#[test]
+fn object_id_times() -> Result<(), String> {
+    use time::macros::datetime;
+
+    let id = ObjectId::parse_str("60ab5e60000000000000002a").map_err(|e| e.to_string())?;
+    assert_eq!(
+        datetime!(2021-05-24 8:05:52 UTC),
+        parse_time(&Bson::ObjectId(id), EpochUnit::Auto)?
+    );
+
+    let range = TimeRange::between(
+        datetime!(2021-05-24 8:05:52 UTC),
+        datetime!(2021-05-24 8:05:52.5 UTC),
+    );
+    let filter = object_id_filter(range);
+    let id_filter = filter.get_document("_id").map_err(|e| e.to_string())?;
+    assert_eq!(
+        Ok(&Bson::ObjectId(object_id_bound(1621843552))),
+        id_filter.get("$gte").ok_or("no start")
+    );
+    assert_eq!(
+        Ok(&Bson::ObjectId(object_id_bound(1621843553))),
+        id_filter.get("$lt").ok_or("no end")
+    );
+    assert!(object_id_bound(1621843552) <= id && id < object_id_bound(1621843553));
+
+    Ok(())
+}
+
+#[test]
+fn stream_periods() {
+    use geo::Point;
+    use time::macros::datetime;
+
+    let position = |hour: u8| {
+        DevicePosition::basic(
+            "dev 1".to_string(),
+            Point::new(-48.87, -26.31),
+            datetime!(2021-05-24 0:00 UTC).replace_hour(hour).unwrap(),
+        )
+    };
+    let range = TimeRange::between(
+        datetime!(2021-05-24 8:00 UTC),
+        datetime!(2021-05-24 10:00 UTC),
+    );
+
+    let times = |positions: Vec<DevicePosition>| -> Vec<u8> {
+        positions.iter().map(|dpos| dpos.pos.time.hour()).collect()
+    };
+    let (inside, after, before) = split_period(
+        vec![position(11), position(9), position(7), position(10)],
+        range,
+    );
+    assert_eq!(vec![9], times(inside));
+    // The end is not inside of the default period
+    assert_eq!(vec![11, 10], times(after));
+    assert_eq!(vec![7], times(before));
+}
+
+#[test]
+fn numeric_values() {
+    let decimal = |coefficient: u128, exponent: u128, negative: bool| {
+        let bits = (u128::from(negative) << 127) | (exponent << 113) | coefficient;
+        Bson::Decimal128(Decimal128::from_bytes(bits.to_le_bytes()))
+    };
+    assert_eq!(Some(-26.31), bson_number(&decimal(2631, 6176 - 2, true)));
+    assert_eq!(Some(1.5e300), bson_number(&decimal(15, 6176 + 299, false)));
+    assert_eq!(Some(0.0), bson_number(&decimal(0, 6176, false)));
+
+    let infinity = Bson::Decimal128(Decimal128::from_bytes((0b11110u128 << 122).to_le_bytes()));
+    assert_eq!(None, bson_number(&infinity));
+
+    assert_eq!(Some(42.0), bson_number(&Bson::Int64(42)));
+    assert_eq!(
+        Some(-48.87),
+        bson_number(&Bson::String(" -48.87".to_string()))
+    );
+    assert_eq!(None, bson_number(&Bson::String("north".to_string())));
+    assert_eq!(None, bson_number(&Bson::Null));
+
+    let coordinates = [Bson::String("-48.87".to_string()), Bson::Int32(-26)];
+    assert_eq!(
+        Ok(Point::new(-48.87, -26.0)),
+        parse_coordinates_array(&FieldsConfiguration::default(), &coordinates)
+    );
+}
+
+#[test]
+fn coordinates_objects() -> Result<(), String> {
+    use time::macros::datetime;
+
+    let fields = FieldsConfiguration::default();
+
+    let doc = doc! {
+        "device": "dev 1",
+        "time": DateTime::from_time_0_3(datetime!(2021-05-24 8:00 UTC)),
+        "coordinates": { "lat": -26.31, "lng": "-48.87" },
+    };
+    let dpos = parse_doc(&fields, &doc, &mut Names::default())?;
+    assert_eq!(Point::new(-48.87, -26.31), dpos[0].pos.coordinates);
+
+    let doc = doc! {
+        "device": "dev 1",
+        "time": DateTime::from_time_0_3(datetime!(2021-05-24 8:00 UTC)),
+        "interval": 10,
+        "coordinates": [
+            { "latitude": -26.31, "longitude": -48.87 },
+            { "latitude": -26.32, "longitude": -48.88 },
+        ],
+    };
+    let dpos = parse_doc(&fields, &doc, &mut Names::default())?;
+    assert_eq!(2, dpos.len());
+    assert_eq!(Point::new(-48.88, -26.32), dpos[1].pos.coordinates);
+
+    let doc = doc! {
+        "device": "dev 1",
+        "time": [],
+        "coordinates": [[-48.87, -26.31], [-48.88, -26.32]],
+    };
+    assert_eq!(
+        Some("No coordinates or times on the doc".to_string()),
+        parse_doc(&fields, &doc, &mut Names::default()).err()
+    );
+
+    let doc = doc! {
+        "device": "dev 1",
+        "time": DateTime::from_time_0_3(datetime!(2021-05-24 8:00 UTC)),
+        "coordinates": [],
+    };
+    assert!(parse_doc(&fields, &doc, &mut Names::default()).is_err());
+
+    let doc = doc! { "location": { "y": -26.31, "x": -48.87 } };
+    let fields = FieldsConfiguration {
+        latitude: "y".to_string(),
+        longitude: "x".to_string(),
+        ..Default::default()
+    };
+    let location = doc.get_document("location").map_err(|e| e.to_string())?;
+    assert_eq!(
+        Ok(Point::new(-48.87, -26.31)),
+        parse_coordinates_object(&fields, location)
+    );
+    assert_eq!(
+        Err("Longitude not found".to_string()),
+        parse_coordinates_object(&fields, &doc! { "y": -26.31 })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn query_plan_stages() {
+    let explain = doc! {
+        "queryPlanner": {
+            "namespace": "fleet.positions",
+            "winningPlan": {
+                "stage": "SORT",
+                "inputStage": {
+                    "stage": "FETCH",
+                    "inputStage": { "stage": "IXSCAN", "indexName": "time_1" }
+                }
+            },
+            "rejectedPlans": [{ "stage": "COLLSCAN" }]
+        }
+    };
+
+    let plan = QueryPlan::new("positions".to_string(), explain);
+    assert_eq!(vec!["SORT", "FETCH", "IXSCAN"], plan.stages);
+    assert_eq!(vec!["time_1"], plan.indexes);
+    assert!(!plan.is_collection_scan());
+
+    let explain = doc! {
+        "queryPlanner": {
+            "winningPlan": {
+                "queryPlan": { "stage": "COLLSCAN", "direction": "forward" }
+            }
+        }
+    };
+    assert!(QueryPlan::new("positions".to_string(), explain).is_collection_scan());
 }
 
 #[cfg(test)]
@@ -152,9 +1191,110 @@ pub mod tests {
     use mongodb::sync::Client;
     use time::macros::datetime;
 
-    use super::MongoDbSource;
+    use super::{DevicesLookup, MongoDbSource};
     use crate::{FieldsConfiguration, SourceToTracks, TrackSegmentOptions};
 
+    #[test]
+    fn track_devices_lookup() -> Result<(), String> {
+        let client =
+            Client::with_uri_str("mongodb://localhost:27017").map_err(|e| e.to_string())?;
+        let db = client.database("location2gpx_tests");
+        let collection = db.collection::<Document>("tracks");
+        collection.drop(None).map_err(|e| e.to_string())?;
+        let devices = db.collection::<Document>("devices");
+        devices.drop(None).map_err(|e| e.to_string())?;
+
+        let docs = vec![
+            doc! { "device": "AA251", "coordinates": [-48.8702222, -26.31832], "time": datetime!(2022-02-07 0:01 UTC) },
+            doc! { "device": 42, "coordinates": [-48.8802222, -26.31832], "time": datetime!(2022-02-07 0:02 UTC) },
+        ];
+        collection
+            .insert_many(docs, None)
+            .map_err(|e| e.to_string())?;
+        let docs = vec![
+            doc! { "_id": "AA251", "name": "Truck 12", "plate": "MKZ-2041", "model": "FMB920" },
+            doc! { "_id": 42, "name": "Van 3" },
+        ];
+        devices.insert_many(docs, None).map_err(|e| e.to_string())?;
+
+        let mut source = MongoDbSource::new(collection, None);
+        source.lookup_devices(devices, DevicesLookup::default());
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2021-05-24 0:00 UTC),
+            datetime!(2023-05-24 0:00 UTC),
+            TrackSegmentOptions::default(),
+        )?;
+        assert_eq!(2, tracks.len());
+        assert_eq!(
+            Some("Tracked by `Van 3`".to_string()),
+            tracks[0].description
+        );
+        assert_eq!(None, tracks[0].source);
+        assert_eq!(
+            Some("Tracked by `Truck 12`, plate MKZ-2041".to_string()),
+            tracks[1].description
+        );
+        assert_eq!(Some("FMB920".to_string()), tracks[1].source);
+
+        Ok(())
+    }
+
+    #[test]
+    fn track_monthly_collections() -> Result<(), String> {
+        let client =
+            Client::with_uri_str("mongodb://localhost:27017").map_err(|e| e.to_string())?;
+        let db = client.database("location2gpx_tests");
+
+        for (name, time) in [
+            ("positions_2022_01", datetime!(2022-01-31 23:59 UTC)),
+            ("positions_2022_02", datetime!(2022-02-01 0:01 UTC)),
+            ("positions_2022_03", datetime!(2022-03-01 0:01 UTC)),
+        ] {
+            let collection = db.collection::<Document>(name);
+            collection.drop(None).map_err(|e| e.to_string())?;
+            collection
+                .insert_one(
+                    doc! { "device": "AA251", "coordinates": [-48.8702222, -26.31832], "time": time },
+                    None,
+                )
+                .map_err(|e| e.to_string())?;
+        }
+
+        let source = MongoDbSource::with_pattern(db.clone(), "positions_{year}_{month}", None);
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2022-01-31 0:00 UTC),
+            datetime!(2022-02-28 0:00 UTC),
+            TrackSegmentOptions::default(),
+        )?;
+        assert_eq!(1, tracks.len());
+        assert_eq!(2, tracks[0].segments[0].points.len());
+
+        let source = MongoDbSource::with_collections(
+            vec![
+                db.collection::<Document>("positions_2022_02"),
+                db.collection::<Document>("positions_2022_03"),
+            ],
+            None,
+        );
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2021-05-24 0:00 UTC),
+            datetime!(2023-05-24 0:00 UTC),
+            TrackSegmentOptions::default(),
+        )?;
+        let points: usize = tracks
+            .iter()
+            .flat_map(|t| &t.segments)
+            .map(|s| s.points.len())
+            .sum();
+        assert_eq!(2, points);
+
+        Ok(())
+    }
+
     #[test]
     fn track() -> Result<(), String> {
         let client =
@@ -215,8 +1355,10 @@ pub mod tests {
             .map_err(|e| e.to_string())?;
 
         let op = TrackSegmentOptions::default();
-        let mut fields = FieldsConfiguration::default();
-        fields.flip_coordinates = true;
+        let fields = FieldsConfiguration {
+            flip_coordinates: true,
+            ..Default::default()
+        };
 
         let source = MongoDbSource::new(collection, Some(fields));
 
@@ -262,7 +1404,7 @@ pub mod tests {
         let tracks = SourceToTracks::build(
             source,
             datetime!(2021-05-24 0:00 UTC),
-            datetime!(2023-05-24 0:00 UTC),
+            datetime!(2023-05-24 0:01 UTC),
             op,
         )?;
         assert_eq!(1, tracks.len());
@@ -326,10 +1468,12 @@ pub mod tests {
             .insert_many(docs, None)
             .map_err(|e| e.to_string())?;
 
-        let mut fields = FieldsConfiguration::default();
-        fields.device_id = "dev".to_string();
-        fields.coordinates = "coords".to_string();
-        fields.time = "dev_time".to_string();
+        let fields = FieldsConfiguration {
+            device_id: "dev".to_string(),
+            coordinates: "coords".to_string(),
+            time: "dev_time".to_string(),
+            ..Default::default()
+        };
 
         let op = TrackSegmentOptions::default();
         let source = MongoDbSource::new(collection, Some(fields));
@@ -443,4 +1587,116 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn track_text_coordinates() -> Result<(), String> {
+        let client =
+            Client::with_uri_str("mongodb://localhost:27017").map_err(|e| e.to_string())?;
+        let db = client.database("location2gpx_tests");
+        let collection = db.collection::<Document>("tracks");
+        collection.drop(None).map_err(|e| e.to_string())?;
+
+        let docs = vec![
+            doc! { "device": "AA251", "coordinates": "POINT(-48.8702222 -26.31832)", "time": datetime!(2022-02-07 0:01 UTC) },
+            doc! { "device": "AA251", "coordinates": "SRID=4326;POINT(-48.8802222 -26.31832)", "time": datetime!(2022-02-07 0:02 UTC) },
+            doc! { "device": "AA251", "coordinates": [-48.8902222, -26.31832], "time": datetime!(2022-02-07 0:03 UTC) },
+        ];
+        collection
+            .insert_many(docs, None)
+            .map_err(|e| e.to_string())?;
+
+        let op = TrackSegmentOptions::default();
+        let source = MongoDbSource::new(collection, None);
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2021-05-24 0:00 UTC),
+            datetime!(2023-05-24 0:00 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+        let segment = &tracks[0].segments[0];
+        assert_eq!(3, segment.points.len());
+        assert_eq!(
+            Point::new(-48.8702222, -26.31832),
+            segment.points[0].point()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn track_line_coordinates() -> Result<(), String> {
+        let client =
+            Client::with_uri_str("mongodb://localhost:27017").map_err(|e| e.to_string())?;
+        let db = client.database("location2gpx_tests");
+        let collection = db.collection::<Document>("tracks");
+        collection.drop(None).map_err(|e| e.to_string())?;
+
+        let docs = vec![
+            doc! { "device": "AA251", "coordinates": [[-48.87, -26.31], [-48.88, -26.31], [-48.89, -26.31]], "time": datetime!(2022-02-07 0:01 UTC), "interval": 30 },
+            doc! { "device": "AA251", "coordinates": "LINESTRING(-48.90 -26.31, -48.91 -26.31)", "time": [datetime!(2022-02-07 0:03 UTC), datetime!(2022-02-07 0:04 UTC)] },
+        ];
+        collection
+            .insert_many(docs, None)
+            .map_err(|e| e.to_string())?;
+
+        let op = TrackSegmentOptions::default();
+        let source = MongoDbSource::new(collection, None);
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2021-05-24 0:00 UTC),
+            datetime!(2023-05-24 0:00 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+        let segment = &tracks[0].segments[0];
+        assert_eq!(5, segment.points.len());
+        assert_eq!(
+            Some(datetime!(2022-02-07 0:01:30 UTC).into()),
+            segment.points[1].time
+        );
+        assert_eq!(Point::new(-48.91, -26.31), segment.points[4].point());
+
+        Ok(())
+    }
+
+    #[test]
+    fn track_epoch_times() -> Result<(), String> {
+        let client =
+            Client::with_uri_str("mongodb://localhost:27017").map_err(|e| e.to_string())?;
+        let db = client.database("location2gpx_tests");
+        let collection = db.collection::<Document>("tracks");
+        collection.drop(None).map_err(|e| e.to_string())?;
+
+        let docs = vec![
+            doc! { "device": "AA251", "coordinates": [-48.8702222, -26.31832], "time": 1644192060_i64 },
+            doc! { "device": "AA251", "coordinates": [-48.8802222, -26.31832], "time": 1644192120000_i64 },
+            doc! { "device": "AA251", "coordinates": [-48.8902222, -26.31832], "time": datetime!(2022-02-07 0:03 UTC) },
+            doc! { "device": "AA251", "coordinates": [-48.9002222, -26.31832], "time": 1644192240000_i64 },
+        ];
+        collection
+            .insert_many(docs, None)
+            .map_err(|e| e.to_string())?;
+
+        let op = TrackSegmentOptions::default();
+        let source = MongoDbSource::new(collection, None);
+
+        let tracks = SourceToTracks::build(
+            source,
+            datetime!(2022-02-07 0:00 UTC),
+            datetime!(2022-02-07 0:03:30 UTC),
+            op,
+        )?;
+        assert_eq!(1, tracks.len());
+        let segment = &tracks[0].segments[0];
+        assert_eq!(3, segment.points.len());
+        assert_eq!(
+            Some(datetime!(2022-02-07 0:02 UTC).into()),
+            segment.points[1].time
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,49 @@
+//! Numeric values stored as numbers or text, shared by the sources
+
+#[cfg(feature = "flespi")]
+use serde_json::Value;
+
+/// Parse a number from the text, like the values of `mongoimport`
+/// from CSV files. Surrounding spaces are ignored and the infinite
+/// and NaN values rejected
+pub fn parse_number(raw: &str) -> Result<f64, String> {
+    let number = raw.trim().parse::<f64>().map_err(|e| e.to_string())?;
+
+    if number.is_finite() {
+        Ok(number)
+    } else {
+        Err(format!("{} is not a finite number", raw.trim()))
+    }
+}
+
+/// Number of the JSON value, also from a numeric string
+#[cfg(feature = "flespi")]
+pub fn json_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(raw) => parse_number(raw).ok(),
+        _ => None,
+    }
+}
+
+#[test]
+fn parse_numbers() {
+    assert_eq!(Ok(-26.31), parse_number("-26.31"));
+    assert_eq!(Ok(12.0), parse_number(" 12 "));
+    assert_eq!(Ok(1500.0), parse_number("1.5e3"));
+    assert!(parse_number("").is_err());
+    assert!(parse_number("12,5").is_err());
+    assert!(parse_number("NaN").is_err());
+    assert!(parse_number("inf").is_err());
+}
+
+#[cfg(feature = "flespi")]
+#[test]
+fn json_numbers() {
+    use serde_json::json;
+
+    assert_eq!(Some(-48.87), json_number(&json!(-48.87)));
+    assert_eq!(Some(90.0), json_number(&json!("90")));
+    assert_eq!(None, json_number(&json!("ninety")));
+    assert_eq!(None, json_number(&json!(null)));
+}
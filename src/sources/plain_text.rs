@@ -0,0 +1,228 @@
+//! Plain text source, with a position per line, like field notes
+//! and the GPS receivers in text mode
+
+use std::io::{BufRead, BufReader, Read};
+use std::sync::Arc;
+
+use geo::Point;
+use time::{Duration, OffsetDateTime};
+
+use super::{sort_by_time, EpochUnit, PositionsSource, TimeRange};
+use crate::DevicePosition;
+
+/// Positions of `lat,lon[,time]` lines or `geo:` URIs
+///
+/// The times can be RFC3339, unix epochs or SQL timestamps. The lines
+/// without a time are placed an interval after the previous one, from
+/// the configured start. The empty and the `#` lines are ignored
+pub struct PlainTextSource<T>
+where
+    T: Read,
+{
+    rdr: BufReader<T>,
+    device: Arc<str>,
+    start: Option<OffsetDateTime>,
+    interval: Duration,
+}
+
+impl<T> PlainTextSource<T>
+where
+    T: Read,
+{
+    pub fn new(rdr: T) -> Self {
+        Self {
+            rdr: BufReader::new(rdr),
+            device: Arc::from("text"),
+            start: None,
+            interval: Duration::seconds(1),
+        }
+    }
+
+    /// Device of all the positions
+    pub fn device(&mut self, device: &str) -> &mut Self {
+        self.device = Arc::from(device);
+
+        self
+    }
+
+    /// Time of the first lines without a time, and the
+    /// seconds between the next ones
+    pub fn untimed(&mut self, start: OffsetDateTime, interval: u32) -> &mut Self {
+        self.start = Some(start);
+        self.interval = Duration::seconds(interval.into());
+
+        self
+    }
+}
+
+impl<T> PositionsSource for PlainTextSource<T>
+where
+    T: Read,
+{
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+        let mut pos = vec![];
+        let mut last: Option<OffsetDateTime> = None;
+
+        for (number, line) in (&mut self.rdr).lines().enumerate() {
+            let line =
+                line.map_err(|e| format!("Failed on read the line {}: {}", number + 1, e))?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parsed = parse_line(line).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            let time = match (parsed.time, last, self.start) {
+                (Some(time), _, _) => time,
+                (None, Some(last), _) => last + self.interval,
+                (None, None, Some(start)) => start,
+                (None, None, None) => {
+                    return Err(format!(
+                        "Line {}: no time, and no start of the untimed positions",
+                        number + 1
+                    ))
+                }
+            };
+            last = Some(time);
+
+            if !range.contains(time) {
+                continue;
+            }
+
+            let mut dpos = DevicePosition::basic(self.device.clone(), parsed.coordinates, time);
+            dpos.pos.altitude = parsed.altitude;
+            dpos.pos.precision = parsed.uncertainty;
+            pos.push(dpos);
+        }
+
+        sort_by_time(&mut pos);
+
+        Ok(pos)
+    }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
+    }
+}
+
+/// Values of a line
+#[derive(Debug, PartialEq)]
+struct TextPosition {
+    coordinates: Point,
+    time: Option<OffsetDateTime>,
+    altitude: Option<f64>,
+    /// Uncertainty of the `geo:` URIs, in meters
+    uncertainty: Option<f64>,
+}
+
+fn parse_line(line: &str) -> Result<TextPosition, String> {
+    let number = |raw: &str, name: &str| -> Result<f64, String> {
+        raw.trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| format!("Invalid {} `{}`", name, raw.trim()))
+    };
+    let coordinates = |lat: f64, lng: f64| -> Result<Point, String> {
+        match (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng) {
+            true => Ok(Point::new(lng, lat)),
+            false => Err(format!("Coordinates {},{} out of range", lat, lng)),
+        }
+    };
+
+    // RFC 5870, like `geo:-26.31,-48.87,12;u=5`
+    if let Some(uri) = line
+        .get(..4)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("geo:"))
+        .map(|_| &line[4..])
+    {
+        let mut parts = uri.split(';');
+        let values: Vec<&str> = parts.next().unwrap_or_default().split(',').collect();
+        if values.len() < 2 || values.len() > 3 {
+            return Err(format!("Invalid geo URI `{}`", line));
+        }
+
+        let mut uncertainty = None;
+        for param in parts {
+            if let Some(("u", value)) = param.split_once('=') {
+                uncertainty = Some(number(value, "uncertainty")?);
+            }
+        }
+
+        return Ok(TextPosition {
+            coordinates: coordinates(
+                number(values[0], "latitude")?,
+                number(values[1], "longitude")?,
+            )?,
+            time: None,
+            altitude: values
+                .get(2)
+                .map(|alt| number(alt, "altitude"))
+                .transpose()?,
+            uncertainty,
+        });
+    }
+
+    let values: Vec<&str> = line.splitn(3, ',').collect();
+    if values.len() < 2 {
+        return Err(format!("Expected `lat,lon[,time]`, found `{}`", line));
+    }
+
+    Ok(TextPosition {
+        coordinates: coordinates(
+            number(values[0], "latitude")?,
+            number(values[1], "longitude")?,
+        )?,
+        time: values
+            .get(2)
+            .map(|time| EpochUnit::Auto.parse_text(time))
+            .transpose()?,
+        altitude: None,
+        uncertainty: None,
+    })
+}
+
+#[test]
+fn text_lines() -> Result<(), String> {
+    use time::macros::datetime;
+
+    let data = "# Notes of the survey\n\
+        -26.31,-48.87,2021-05-24T08:00:00Z\n\
+        \n\
+        -26.3101, -48.8702, 1621843260\n\
+        geo:-26.3102,-48.8704,12.5;u=8\n\
+        GEO:-26.3103,-48.8706\n";
+
+    let positions = PlainTextSource::new(data.as_bytes())
+        .device("surveyor")
+        .fetch(TimeRange::all())?;
+    assert_eq!(4, positions.len());
+    assert_eq!("surveyor", &*positions[0].device_id);
+    assert_eq!(Point::new(-48.87, -26.31), positions[0].pos.coordinates);
+    assert_eq!(datetime!(2021-05-24 8:01 UTC), positions[1].pos.time);
+    // The URIs follow the previous time
+    assert_eq!(datetime!(2021-05-24 8:01:01 UTC), positions[2].pos.time);
+    assert_eq!(Some(12.5), positions[2].pos.altitude);
+    assert_eq!(Some(8.0), positions[2].pos.precision);
+    assert_eq!(datetime!(2021-05-24 8:01:02 UTC), positions[3].pos.time);
+
+    let untimed = "-26.31,-48.87\n-26.3101,-48.8702\n";
+    let error = PlainTextSource::new(untimed.as_bytes())
+        .fetch(TimeRange::all())
+        .err()
+        .ok_or("Untimed line without a start")?;
+    assert!(error.starts_with("Line 1"), "{}", error);
+
+    let positions = PlainTextSource::new(untimed.as_bytes())
+        .untimed(datetime!(2021-05-24 8:00 UTC), 60)
+        .fetch(TimeRange::all())?;
+    assert_eq!(datetime!(2021-05-24 8:01 UTC), positions[1].pos.time);
+
+    let error = PlainTextSource::new("-26.31,-248.87,1621843260".as_bytes())
+        .fetch(TimeRange::all())
+        .err()
+        .ok_or("Invalid longitude parsed")?;
+    assert!(error.contains("out of range"), "{}", error);
+
+    Ok(())
+}
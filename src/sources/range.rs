@@ -0,0 +1,92 @@
+//! Period of the positions fetched
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// If the end of the period is part of it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntervalMode {
+    /// `[start, end)`, so back-to-back periods do not share positions
+    #[default]
+    HalfOpen,
+    /// `[start, end]`
+    Closed,
+}
+
+/// Period to fetch, without limit on the missing ends
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimeRange {
+    pub start: Option<OffsetDateTime>,
+    pub end: Option<OffsetDateTime>,
+    pub mode: IntervalMode,
+}
+
+impl TimeRange {
+    /// Positions between the times
+    pub fn between(start: OffsetDateTime, end: OffsetDateTime) -> Self {
+        Self {
+            start: Some(start),
+            end: Some(end),
+            ..Default::default()
+        }
+    }
+
+    /// Positions after the time
+    pub fn since(start: OffsetDateTime) -> Self {
+        Self {
+            start: Some(start),
+            ..Default::default()
+        }
+    }
+
+    /// Positions before the time
+    pub fn until(end: OffsetDateTime) -> Self {
+        Self {
+            end: Some(end),
+            ..Default::default()
+        }
+    }
+
+    /// All the positions
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Change the interval mode
+    pub fn with_mode(mut self, mode: IntervalMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// If the end time is part of the period
+    pub fn includes_end(&self) -> bool {
+        self.mode == IntervalMode::Closed
+    }
+
+    /// If the time is inside of the period
+    pub fn contains(&self, time: OffsetDateTime) -> bool {
+        self.start.is_none_or(|start| start <= time)
+            && self.end.is_none_or(|end| match self.mode {
+                IntervalMode::HalfOpen => time < end,
+                IntervalMode::Closed => time <= end,
+            })
+    }
+}
+
+#[test]
+fn range_contains() {
+    use time::macros::datetime;
+
+    let time = datetime!(2021-05-24 0:00 UTC);
+
+    assert!(TimeRange::all().contains(time));
+    assert!(TimeRange::since(time).contains(time));
+    assert!(!TimeRange::since(datetime!(2021-05-24 0:01 UTC)).contains(time));
+    assert!(!TimeRange::until(time).contains(time));
+    assert!(TimeRange::until(time)
+        .with_mode(IntervalMode::Closed)
+        .contains(time));
+    assert!(!TimeRange::until(datetime!(2021-05-23 23:59 UTC)).contains(time));
+    assert!(TimeRange::between(time, datetime!(2021-05-25 0:00 UTC)).contains(time));
+}
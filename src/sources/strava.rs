@@ -0,0 +1,243 @@
+//! Strava bulk export source, with the activities of the ZIP archive
+
+use std::io::{Read, Seek};
+
+use csv::ReaderBuilder;
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use super::gpx_file::track_positions;
+use super::{sort_by_time, PositionsSource, TimeRange};
+use crate::DevicePosition;
+
+/// Activity of the export with a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct StravaActivity {
+    pub id: String,
+    pub name: String,
+    /// Sport of the activity, like Ride or Run
+    pub activity_type: Option<String>,
+    /// Path of the file, relative to the `activities.csv`
+    pub file: String,
+}
+
+impl StravaActivity {
+    /// If the file is a GPX, gzipped or not
+    pub fn is_gpx(&self) -> bool {
+        let file = self.file.to_lowercase();
+        file.ends_with(".gpx") || file.ends_with(".gpx.gz")
+    }
+}
+
+/// Activities of a Strava bulk export ZIP
+///
+/// The `activities.csv` of the archive names the file of each activity,
+/// read as a device and route with the activity name, so each activity
+/// keeps its name. The GPX files are read, also the gzipped ones, and
+/// the FIT and TCX ones are not supported
+pub struct StravaSource<R>
+where
+    R: Read + Seek,
+{
+    archive: ZipArchive<R>,
+    /// Directory of the `activities.csv`, the base of the files
+    base: String,
+    activities: Vec<StravaActivity>,
+}
+
+impl<R> StravaSource<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(rdr: R) -> Result<Self, String> {
+        let mut archive =
+            ZipArchive::new(rdr).map_err(|e| format!("Failed on open the ZIP archive: {}", e))?;
+
+        // Some exports have all the files inside of a directory
+        let index = archive
+            .file_names()
+            .filter(|name| *name == "activities.csv" || name.ends_with("/activities.csv"))
+            .min_by_key(|name| name.len())
+            .ok_or("The activities.csv was not found on the archive")?
+            .to_string();
+        let base = index.trim_end_matches("activities.csv").to_string();
+
+        let activities = parse_activities(
+            archive
+                .by_name(&index)
+                .map_err(|e| format!("Failed on read the activities.csv: {}", e))?,
+        )?;
+
+        Ok(Self {
+            archive,
+            base,
+            activities,
+        })
+    }
+
+    /// Activities with a file, also the unsupported ones
+    pub fn activities(&self) -> &[StravaActivity] {
+        &self.activities
+    }
+
+    /// Activities with the files not supported, like FIT and TCX
+    pub fn unsupported(&self) -> impl Iterator<Item = &StravaActivity> {
+        self.activities.iter().filter(|a| !a.is_gpx())
+    }
+}
+
+impl<R> PositionsSource for StravaSource<R>
+where
+    R: Read + Seek,
+{
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+        let mut pos = vec![];
+
+        for activity in self.activities.iter().filter(|a| a.is_gpx()) {
+            let path = format!("{}{}", self.base, activity.file);
+            let file = self.archive.by_name(&path).map_err(|e| {
+                format!(
+                    "Failed on read the file {} of the activity {}: {}",
+                    path, activity.id, e
+                )
+            })?;
+
+            let doc = match activity.file.to_lowercase().ends_with(".gz") {
+                true => gpx::read(GzDecoder::new(file)),
+                false => gpx::read(file),
+            }
+            .map_err(|e| format!("Failed on read the GPX {}: {}", path, e))?;
+
+            for track in &doc.tracks {
+                let mut track_pos = track_positions(track, &activity.name, range, &[])?;
+                if activity.activity_type.is_some() {
+                    for dpos in &mut track_pos {
+                        dpos.track_type = activity.activity_type.clone();
+                    }
+                }
+                pos.extend(track_pos);
+            }
+        }
+
+        sort_by_time(&mut pos);
+
+        Ok(pos)
+    }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
+    }
+}
+
+/// Activities of the index with a file, the manual ones have none
+fn parse_activities<T: Read>(index: T) -> Result<Vec<StravaActivity>, String> {
+    let mut rdr = ReaderBuilder::new().flexible(true).from_reader(index);
+
+    let header = rdr
+        .headers()
+        .map_err(|e| format!("Failed on read the activities header: {}", e))?
+        .clone();
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+    let (id, name, file) = match (
+        column("Activity ID"),
+        column("Activity Name"),
+        column("Filename"),
+    ) {
+        (Some(id), Some(name), Some(file)) => (id, name, file),
+        _ => return Err("The activities.csv has no ID, name or file columns".to_string()),
+    };
+    let activity_type = column("Activity Type");
+
+    let mut activities = vec![];
+    for rec in rdr.records() {
+        let rec = rec.map_err(|e| format!("Failed on read some activity: {}", e))?;
+        let field = |i: usize| rec.get(i).map(str::trim).filter(|v| !v.is_empty());
+
+        let file = match field(file) {
+            Some(file) => file.to_string(),
+            None => continue,
+        };
+        let id = field(id).unwrap_or_default().to_string();
+
+        activities.push(StravaActivity {
+            name: field(name)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("activity {}", id)),
+            activity_type: activity_type.and_then(field).map(str::to_string),
+            id,
+            file,
+        });
+    }
+
+    Ok(activities)
+}
+
+#[test]
+fn bulk_export() -> Result<(), String> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{Cursor, Write};
+    use time::macros::datetime;
+    use zip::write::{FileOptions, ZipWriter};
+
+    let gpx = |lng: f64, minute: u32| {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="StravaGPX" xmlns="http://www.topografix.com/GPX/1/1">
+ <trk>
+  <name>Ride</name>
+  <type>1</type>
+  <trkseg>
+   <trkpt lat="-26.31" lon="{}"><ele>12.0</ele><time>2021-05-24T08:{:02}:00Z</time></trkpt>
+   <trkpt lat="-26.31" lon="{}"><ele>13.0</ele><time>2021-05-24T08:{:02}:00Z</time></trkpt>
+  </trkseg>
+ </trk>
+</gpx>"#,
+            lng,
+            minute,
+            lng + 0.001,
+            minute + 1
+        )
+    };
+    let index = "Activity ID,Activity Date,Activity Name,Activity Type,Elapsed Time,Filename\n\
+        1,\"May 24, 2021, 8:00:00 AM\",Morning Ride,Ride,60,activities/1.gpx\n\
+        2,\"May 24, 2021, 8:30:00 AM\",Lunch Run,Run,60,activities/2.gpx.gz\n\
+        3,\"May 24, 2021, 9:00:00 AM\",Evening Ride,Ride,60,activities/3.fit.gz\n\
+        4,\"May 24, 2021, 9:30:00 AM\",Manual Swim,Swim,60,\n";
+
+    let mut gzipped = GzEncoder::new(vec![], Compression::default());
+    gzipped
+        .write_all(gpx(-48.86, 30).as_bytes())
+        .map_err(|e| e.to_string())?;
+    let gzipped = gzipped.finish().map_err(|e| e.to_string())?;
+
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    for (path, content) in [
+        ("export_1/activities.csv", index.as_bytes().to_vec()),
+        ("export_1/activities/1.gpx", gpx(-48.87, 0).into_bytes()),
+        ("export_1/activities/2.gpx.gz", gzipped),
+        ("export_1/activities/3.fit.gz", vec![0, 1, 2]),
+    ] {
+        zip.start_file(path, FileOptions::default())
+            .map_err(|e| e.to_string())?;
+        zip.write_all(&content).map_err(|e| e.to_string())?;
+    }
+    let archive = zip.finish().map_err(|e| e.to_string())?;
+
+    let mut source = StravaSource::new(archive)?;
+    assert_eq!(3, source.activities().len());
+    let unsupported: Vec<&str> = source.unsupported().map(|a| a.id.as_str()).collect();
+    assert_eq!(vec!["3"], unsupported);
+
+    let positions = source.fetch(TimeRange::all())?;
+    assert_eq!(4, positions.len());
+    assert_eq!("Morning Ride", &*positions[0].device_id);
+    assert_eq!(Some("Ride".to_string()), positions[0].track_type);
+    assert_eq!(Some(12.0), positions[0].pos.altitude);
+    assert_eq!("Lunch Run", &*positions[2].device_id);
+    assert_eq!(Some("Lunch Run"), positions[2].route_name.as_deref());
+    assert_eq!(Some("Run".to_string()), positions[2].track_type);
+    assert_eq!(datetime!(2021-05-24 8:30 UTC), positions[2].pos.time);
+
+    Ok(())
+}
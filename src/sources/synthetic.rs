@@ -0,0 +1,190 @@
+//! Synthetic source, with random walk tracks for benchmarks and tests
+
+use std::sync::Arc;
+
+use geo::Point;
+use time::macros::datetime;
+use time::{Duration, OffsetDateTime};
+
+use super::{sort_by_time, PositionsSource, TimeRange};
+use crate::geoutil::METERS_PER_DEGREE;
+use crate::DevicePosition;
+
+/// Shape of the generated tracks
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntheticOptions {
+    pub devices: usize,
+    /// Positions of each device
+    pub points: usize,
+    /// Seconds between the positions
+    pub interval: u32,
+    /// Mean speed of the devices, in m/s
+    pub speed: f64,
+    /// Max GPS error added to the positions, in meters
+    pub noise_m: f64,
+    /// Same seed, same tracks
+    pub seed: u64,
+    /// Time of the first positions
+    pub start: OffsetDateTime,
+    /// Place of the first position of the first device
+    pub origin: Point,
+}
+
+impl Default for SyntheticOptions {
+    fn default() -> Self {
+        Self {
+            devices: 10,
+            points: 1000,
+            interval: 5,
+            speed: 10.0,
+            noise_m: 5.0,
+            seed: 1,
+            start: datetime!(2021-05-24 0:00 UTC),
+            origin: Point::new(-48.87, -26.31),
+        }
+    }
+}
+
+/// Random walk tracks of many devices, with some noise, so the
+/// generation can be measured and the configs tried without real data
+pub struct SyntheticSource {
+    options: SyntheticOptions,
+}
+
+impl SyntheticSource {
+    pub fn new(options: SyntheticOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl PositionsSource for SyntheticSource {
+    fn fetch(&mut self, range: TimeRange) -> Result<Vec<DevicePosition>, String> {
+        let mut pos = Vec::with_capacity(self.options.devices * self.options.points);
+        self.fetch_batches(range, &mut |batch| {
+            pos.extend(batch);
+            Ok(())
+        })?;
+
+        sort_by_time(&mut pos);
+
+        Ok(pos)
+    }
+
+    /// A batch with the positions of each device
+    fn fetch_batches(
+        &mut self,
+        range: TimeRange,
+        each: &mut dyn FnMut(Vec<DevicePosition>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let opts = &self.options;
+        let mut random = SplitMix(opts.seed);
+        let tracker: Arc<str> = Arc::from("synthetic");
+
+        for device in 0..opts.devices {
+            let name: Arc<str> = Arc::from(format!("synthetic {}", device + 1));
+            // The devices start about 1km apart
+            let (mut x, mut y) = (device as f64 * 1000.0, 0.0);
+            let mut heading = random.next() * std::f64::consts::TAU;
+            let mut elevation = 20.0 + random.next() * 100.0;
+            let lng_meters = METERS_PER_DEGREE * opts.origin.y().to_radians().cos();
+
+            let mut pos = Vec::with_capacity(opts.points);
+            for i in 0..opts.points {
+                heading += (random.next() - 0.5) * 0.6;
+                let speed = opts.speed * (0.5 + random.next());
+                let step = speed * f64::from(opts.interval);
+                x += heading.cos() * step;
+                y += heading.sin() * step;
+                elevation = (elevation + (random.next() - 0.5) * 2.0).max(0.0);
+
+                let noise_x = (random.next() - 0.5) * 2.0 * opts.noise_m;
+                let noise_y = (random.next() - 0.5) * 2.0 * opts.noise_m;
+
+                // Same walks whatever the range
+                let time = opts.start + Duration::seconds(i as i64 * i64::from(opts.interval));
+                if !range.contains(time) {
+                    continue;
+                }
+                let coordinates = Point::new(
+                    opts.origin.x() + (x + noise_x) / lng_meters,
+                    opts.origin.y() + (y + noise_y) / METERS_PER_DEGREE,
+                );
+
+                let mut dpos = DevicePosition::basic(name.clone(), coordinates, time);
+                dpos.pos.speed = Some(speed);
+                dpos.pos.altitude = Some(elevation);
+                dpos.tracker = Some(tracker.clone());
+                pos.push(dpos);
+            }
+            each(pos)?;
+        }
+
+        Ok(())
+    }
+
+    fn supports_time_pushdown(&self) -> bool {
+        true
+    }
+}
+
+/// SplitMix64 generator, enough for repeatable walks
+struct SplitMix(u64);
+
+impl SplitMix {
+    /// Next value, from 0 to 1
+    fn next(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[test]
+fn random_walks() -> Result<(), String> {
+    use crate::geoutil::distance;
+
+    let options = SyntheticOptions {
+        devices: 3,
+        points: 100,
+        ..Default::default()
+    };
+
+    let coordinates = |positions: &[DevicePosition]| -> Vec<Point> {
+        positions.iter().map(|dpos| dpos.pos.coordinates).collect()
+    };
+
+    let positions = SyntheticSource::new(options.clone()).fetch(TimeRange::all())?;
+    assert_eq!(300, positions.len());
+    assert!(positions.windows(2).all(|w| w[0].pos.time <= w[1].pos.time));
+
+    let again = SyntheticSource::new(options.clone()).fetch(TimeRange::all())?;
+    assert_eq!(coordinates(&positions), coordinates(&again));
+
+    let other = SyntheticSource::new(SyntheticOptions {
+        seed: 2,
+        ..options.clone()
+    })
+    .fetch(TimeRange::all())?;
+    assert_ne!(coordinates(&positions), coordinates(&other));
+
+    // Steps of up to 1.5 times the speed, plus the noise on both ends
+    let device: Vec<&DevicePosition> = positions
+        .iter()
+        .filter(|dpos| &*dpos.device_id == "synthetic 1")
+        .collect();
+    assert_eq!(100, device.len());
+    for w in device.windows(2) {
+        let meters = distance(w[0].pos.coordinates, w[1].pos.coordinates);
+        assert!(meters < 15.0 * 5.0 + 4.0 * 5.0, "{}", meters);
+    }
+
+    let range = TimeRange::until(options.start + Duration::seconds(50));
+    let positions = SyntheticSource::new(options).fetch(range)?;
+    assert_eq!(30, positions.len());
+
+    Ok(())
+}